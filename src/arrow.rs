@@ -0,0 +1,395 @@
+use crate::{
+    bulk::{BulkLoader, BulkOptions, LoadReport},
+    connection::{connect, ConnectionConfig},
+    convert::{convert, decimal_value, Value},
+    error::{BulkError, Result, ResultExt},
+    schema::{Column, SqlType, TableSchema},
+    source::{ColumnMap, IngestOptions},
+};
+use arrow_array::{cast::AsArray, timezone::Tz, types::*, Array, PrimitiveArray, RecordBatch};
+use arrow_cast::display::array_value_to_string;
+use arrow_schema::{DataType, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Offset, TimeZone};
+use futures_util::{pin_mut, Stream, StreamExt};
+use tiberius::{ColumnData, TokenRow};
+
+/// Loads Arrow record batches into a table.
+///
+/// The Arrow schema is checked against the table and the time zones of its
+/// timestamps are parsed when the sink is created, before anything is sent
+/// (see [`check_schema`]). Rows are then converted column by column straight
+/// from the Arrow arrays: numbers, decimals, dates and timestamps need no
+/// allocation, strings, binary values and GUIDs one each. Only values stored
+/// in a column of another kind, such as numbers in a string column, go
+/// through text.
+pub struct RecordBatchSink {
+    loader: BulkLoader,
+    arrow_schema: SchemaRef,
+    columns: ColumnMap,
+    time_zones: Vec<Option<Tz>>,
+    rows: u64,
+}
+
+impl RecordBatchSink {
+    /// A sink for batches of `arrow_schema` into the table of `schema`,
+    /// loaded with `loader`.
+    pub fn new(
+        loader: BulkLoader,
+        schema: &TableSchema,
+        arrow_schema: SchemaRef,
+        options: &IngestOptions,
+    ) -> Result<RecordBatchSink> {
+        let columns = check_schema(schema, &arrow_schema, options)?;
+        let time_zones = time_zones(&arrow_schema)?;
+
+        Ok(RecordBatchSink::with_columns(
+            loader,
+            arrow_schema,
+            columns,
+            time_zones,
+        ))
+    }
+
+    fn with_columns(
+        loader: BulkLoader,
+        arrow_schema: SchemaRef,
+        columns: ColumnMap,
+        time_zones: Vec<Option<Tz>>,
+    ) -> RecordBatchSink {
+        RecordBatchSink {
+            loader,
+            arrow_schema,
+            columns,
+            time_zones,
+            rows: 0,
+        }
+    }
+
+    /// Sends the rows of `batch`, which must have the schema of the sink.
+    pub async fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        if batch.schema().fields() != self.arrow_schema.fields() {
+            return Err(BulkError::Config(format!(
+                "a record batch for table {} does not have the schema of the sink",
+                self.loader.table()
+            )));
+        }
+
+        for row in 0..batch.num_rows() {
+            let values = token_row(&self.columns, &self.time_zones, batch, row)
+                .in_table(self.loader.table())
+                .at_row(self.rows)?;
+            self.loader.send_row(values).await?;
+            self.rows += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the last batch and reports what was loaded.
+    pub async fn finish(self) -> Result<LoadReport> {
+        self.loader.finish().await
+    }
+}
+
+/// Loads a stream of record batches of `arrow_schema` into `table`. The
+/// schema is checked against the table before the first row is sent.
+pub async fn load_record_batches<S, E>(
+    config: &ConnectionConfig,
+    table: &str,
+    arrow_schema: SchemaRef,
+    batches: S,
+    options: BulkOptions,
+    ingest: &IngestOptions,
+) -> Result<LoadReport>
+where
+    S: Stream<Item = std::result::Result<RecordBatch, E>>,
+    E: Into<BulkError>,
+{
+    let (mut client, _) = connect(config).await?;
+    let schema = TableSchema::load(&mut client, table).await?;
+    client.close().await?;
+    let columns = check_schema(&schema, &arrow_schema, ingest)?;
+    let time_zones = time_zones(&arrow_schema)?;
+
+    let loader = BulkLoader::connect(config.clone(), table, options).await?;
+    let mut sink = RecordBatchSink::with_columns(loader, arrow_schema, columns, time_zones);
+
+    pin_mut!(batches);
+    while let Some(batch) = batches.next().await {
+        sink.write(&batch.map_err(Into::into)?).await?;
+    }
+
+    sink.finish().await
+}
+
+/// Matches the fields of `arrow_schema` with the insertable columns of
+/// `schema` like a [`ColumnMap`], and fails with the first field whose type
+/// cannot be loaded into its column (see [`is_compatible`]).
+pub fn check_schema(
+    schema: &TableSchema,
+    arrow_schema: &Schema,
+    options: &IngestOptions,
+) -> Result<ColumnMap> {
+    let fields: Vec<String> = arrow_schema
+        .fields()
+        .iter()
+        .map(|field| field.name().clone())
+        .collect();
+    let columns = ColumnMap::new(schema, Some(&fields), options)?;
+
+    for (column, position) in columns.columns() {
+        let Some(position) = position else { continue };
+        let data_type = arrow_schema.field(*position).data_type();
+        if !is_compatible(data_type, &column.sql_type) {
+            return Err(BulkError::conversion(format!(
+                "{} values cannot be stored in {}",
+                data_type, column.sql_type
+            ))
+            .with_table(&schema.table)
+            .with_column(&column.name));
+        }
+    }
+
+    Ok(columns)
+}
+
+/// The time zone of every field of `arrow_schema` that is a timestamp with
+/// one, parsed once for all of its values.
+pub fn time_zones(arrow_schema: &Schema) -> Result<Vec<Option<Tz>>> {
+    arrow_schema
+        .fields()
+        .iter()
+        .map(|field| match field.data_type() {
+            DataType::Timestamp(_, Some(tz)) => {
+                parse_time_zone(tz).map(Some).in_column(field.name())
+            }
+            _ => Ok(None),
+        })
+        .collect()
+}
+
+/// True, if values of the Arrow type can be loaded into columns of
+/// `sql_type`. Strings can be loaded into any column and are parsed; lists,
+/// structs and other nested types only into string columns, as text.
+pub fn is_compatible(data_type: &DataType, sql_type: &SqlType) -> bool {
+    let integer = matches!(
+        sql_type,
+        SqlType::Bit | SqlType::TinyInt | SqlType::SmallInt | SqlType::Int | SqlType::BigInt
+    );
+    let float = matches!(sql_type, SqlType::Real | SqlType::Float);
+    let decimal = matches!(sql_type, SqlType::Decimal { .. });
+    let string = matches!(
+        sql_type,
+        SqlType::Char(_)
+            | SqlType::VarChar(_)
+            | SqlType::NChar(_)
+            | SqlType::NVarChar(_)
+            | SqlType::Text
+            | SqlType::NText
+            | SqlType::Xml
+    );
+    let binary = matches!(sql_type, SqlType::Binary(_) | SqlType::VarBinary(_));
+    let datetime = matches!(
+        sql_type,
+        SqlType::DateTime | SqlType::SmallDateTime | SqlType::DateTime2(_)
+    );
+
+    match data_type {
+        DataType::Null | DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => true,
+        DataType::Boolean => *sql_type == SqlType::Bit,
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64 => integer || float || decimal,
+        DataType::Float16 | DataType::Float32 | DataType::Float64 => float,
+        DataType::Decimal128(..) | DataType::Decimal256(..) => float || decimal,
+        DataType::Binary | DataType::LargeBinary | DataType::BinaryView => binary,
+        DataType::FixedSizeBinary(16) => binary || *sql_type == SqlType::UniqueIdentifier,
+        DataType::FixedSizeBinary(_) => binary,
+        DataType::Date32 | DataType::Date64 => datetime || *sql_type == SqlType::Date,
+        DataType::Time32(_) | DataType::Time64(_) => matches!(sql_type, SqlType::Time(_)),
+        DataType::Timestamp(_, None) => datetime,
+        DataType::Timestamp(_, Some(_)) => {
+            datetime || matches!(sql_type, SqlType::DateTimeOffset(_))
+        }
+        _ => string,
+    }
+}
+
+/// The row at `row` of `batch`, with a value for every insertable column
+/// of `columns` and the `time_zones` of the fields, which come from
+/// [`check_schema`] and [`time_zones`].
+pub fn token_row(
+    columns: &ColumnMap,
+    time_zones: &[Option<Tz>],
+    batch: &RecordBatch,
+    row: usize,
+) -> Result<TokenRow<'static>> {
+    let mut values = TokenRow::with_capacity(columns.columns().len());
+
+    for (column, position) in columns.columns() {
+        let value = match position {
+            Some(position) => column_value(
+                batch.column(*position).as_ref(),
+                time_zones[*position].as_ref(),
+                row,
+                column,
+                columns,
+            )
+            .in_column(&column.name)?,
+            None => column.sql_type.null(),
+        };
+        values.push(value);
+    }
+
+    Ok(values)
+}
+
+fn column_value(
+    array: &dyn Array,
+    time_zone: Option<&Tz>,
+    row: usize,
+    column: &Column,
+    columns: &ColumnMap,
+) -> Result<ColumnData<'static>> {
+    if array.is_null(row) {
+        if !column.nullable {
+            return Err(BulkError::conversion(
+                "NULL in a column that does not allow NULLs",
+            ));
+        }
+        return Ok(column.sql_type.null());
+    }
+
+    match (array.data_type(), &column.sql_type) {
+        (
+            DataType::Decimal128(_, scale),
+            SqlType::Decimal { .. } | SqlType::Float | SqlType::Real,
+        ) => decimal_value(
+            array.as_primitive::<Decimal128Type>().value(row),
+            *scale,
+            &column.sql_type,
+        ),
+        _ => convert(
+            arrow_value(array, time_zone, row)?,
+            &column.sql_type,
+            columns.binary_encoding(),
+        ),
+    }
+}
+
+/// The value of `array` at `row`. Timestamps with a time zone are read with
+/// the offset of `time_zone`, the zone of the array from [`time_zones`].
+pub fn arrow_value(array: &dyn Array, time_zone: Option<&Tz>, row: usize) -> Result<Value> {
+    if array.is_null(row) {
+        return Ok(Value::Null);
+    }
+
+    let value = match array.data_type() {
+        DataType::Null => Value::Null,
+        DataType::Boolean => Value::Bool(array.as_boolean().value(row)),
+        DataType::Int8 => Value::Int(array.as_primitive::<Int8Type>().value(row).into()),
+        DataType::Int16 => Value::Int(array.as_primitive::<Int16Type>().value(row).into()),
+        DataType::Int32 => Value::Int(array.as_primitive::<Int32Type>().value(row).into()),
+        DataType::Int64 => Value::Int(array.as_primitive::<Int64Type>().value(row)),
+        DataType::UInt8 => Value::Int(array.as_primitive::<UInt8Type>().value(row).into()),
+        DataType::UInt16 => Value::Int(array.as_primitive::<UInt16Type>().value(row).into()),
+        DataType::UInt32 => Value::Int(array.as_primitive::<UInt32Type>().value(row).into()),
+        DataType::UInt64 => {
+            let value = array.as_primitive::<UInt64Type>().value(row);
+            i64::try_from(value).map_or_else(|_| Value::Text(value.to_string()), Value::Int)
+        }
+        DataType::Float16 => Value::Float(array.as_primitive::<Float16Type>().value(row).to_f64()),
+        DataType::Float32 => Value::Float(array.as_primitive::<Float32Type>().value(row).into()),
+        DataType::Float64 => Value::Float(array.as_primitive::<Float64Type>().value(row)),
+        DataType::Utf8 => Value::Text(array.as_string::<i32>().value(row).to_owned()),
+        DataType::LargeUtf8 => Value::Text(array.as_string::<i64>().value(row).to_owned()),
+        DataType::Utf8View => Value::Text(array.as_string_view().value(row).to_owned()),
+        DataType::Binary => Value::Bytes(array.as_binary::<i32>().value(row).to_vec()),
+        DataType::LargeBinary => Value::Bytes(array.as_binary::<i64>().value(row).to_vec()),
+        DataType::BinaryView => Value::Bytes(array.as_binary_view().value(row).to_vec()),
+        DataType::FixedSizeBinary(_) => {
+            Value::Bytes(array.as_fixed_size_binary().value(row).to_vec())
+        }
+        DataType::Date32 => Value::Date(temporal(array.as_primitive::<Date32Type>(), row)?.date()),
+        DataType::Date64 => Value::Date(temporal(array.as_primitive::<Date64Type>(), row)?.date()),
+        DataType::Time32(TimeUnit::Second) => time(array.as_primitive::<Time32SecondType>(), row)?,
+        DataType::Time32(_) => time(array.as_primitive::<Time32MillisecondType>(), row)?,
+        DataType::Time64(TimeUnit::Microsecond) => {
+            time(array.as_primitive::<Time64MicrosecondType>(), row)?
+        }
+        DataType::Time64(_) => time(array.as_primitive::<Time64NanosecondType>(), row)?,
+        DataType::Timestamp(unit, tz) => {
+            let value = match unit {
+                TimeUnit::Second => temporal(array.as_primitive::<TimestampSecondType>(), row)?,
+                TimeUnit::Millisecond => {
+                    temporal(array.as_primitive::<TimestampMillisecondType>(), row)?
+                }
+                TimeUnit::Microsecond => {
+                    temporal(array.as_primitive::<TimestampMicrosecondType>(), row)?
+                }
+                TimeUnit::Nanosecond => {
+                    temporal(array.as_primitive::<TimestampNanosecondType>(), row)?
+                }
+            };
+            match (tz, time_zone) {
+                (Some(_), Some(tz)) => Value::DateTimeOffset(with_time_zone(value, tz)),
+                (Some(tz), None) => {
+                    Value::DateTimeOffset(with_time_zone(value, &parse_time_zone(tz)?))
+                }
+                (None, _) => Value::DateTime(value),
+            }
+        }
+        _ => Value::Text(
+            array_value_to_string(array, row).map_err(|e| BulkError::conversion(e.to_string()))?,
+        ),
+    };
+
+    Ok(value)
+}
+
+fn temporal<T>(array: &PrimitiveArray<T>, row: usize) -> Result<NaiveDateTime>
+where
+    T: ArrowTemporalType,
+    i64: From<T::Native>,
+{
+    array.value_as_datetime(row).ok_or_else(|| {
+        BulkError::conversion(format!(
+            "{} is out of range for {}",
+            i64::from(array.value(row)),
+            array.data_type()
+        ))
+    })
+}
+
+fn time<T>(array: &PrimitiveArray<T>, row: usize) -> Result<Value>
+where
+    T: ArrowTemporalType,
+    i64: From<T::Native>,
+{
+    array.value_as_time(row).map(Value::Time).ok_or_else(|| {
+        BulkError::conversion(format!(
+            "{} is out of range for {}",
+            i64::from(array.value(row)),
+            array.data_type()
+        ))
+    })
+}
+
+/// A time zone of a timestamp type, a fixed offset like `+02:00` or a name
+/// like `Europe/Paris`.
+fn parse_time_zone(tz: &str) -> Result<Tz> {
+    tz.parse()
+        .map_err(|e: arrow_schema::ArrowError| BulkError::conversion(e.to_string()))
+}
+
+/// The UTC timestamp `utc` with the offset it has in the time zone `tz`.
+fn with_time_zone(utc: NaiveDateTime, tz: &Tz) -> DateTime<FixedOffset> {
+    let offset = tz.offset_from_utc_datetime(&utc).fix();
+
+    DateTime::from_naive_utc_and_offset(utc, offset)
+}
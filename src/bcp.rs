@@ -0,0 +1,1121 @@
+use crate::{
+    compression::{self, InputFile},
+    convert::{column_text, convert, decimal_text, BinaryEncoding, Value},
+    error::{BulkError, Result, ResultExt},
+    schema::{Column, SqlType, TableSchema},
+    source::Source,
+};
+use chrono::{Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use std::{
+    fmt, fs,
+    io::{BufRead, Read, Write},
+    path::Path,
+    str::FromStr,
+};
+use tiberius::{ColumnData, Uuid};
+
+/// Namespace of the `xsi:type` attributes of XML format files.
+const XSI: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// Version written in non-XML format files, that of SQL Server 2017.
+const FORMAT_VERSION: &str = "14.0";
+
+/// The kinds of data files of `bcp`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BcpMode {
+    /// `-n`: values in their binary form and text in the code page of the
+    /// file, which has to be UTF-8 (`-C 65001`).
+    #[default]
+    Native,
+    /// `-N`: like `Native`, but with all text in UTF-16.
+    WideNative,
+    /// `-c`: values as text, fields ending in a tab and rows in a newline.
+    /// The text has to be UTF-8 (`-C 65001`).
+    Character,
+}
+
+impl FromStr for BcpMode {
+    type Err = BulkError;
+
+    fn from_str(s: &str) -> Result<BcpMode> {
+        match s.to_lowercase().as_str() {
+            "native" => Ok(BcpMode::Native),
+            "wide-native" => Ok(BcpMode::WideNative),
+            "char" | "character" => Ok(BcpMode::Character),
+            _ => Err(BulkError::Config(format!("unknown bcp mode: {}", s))),
+        }
+    }
+}
+
+/// The type of a field in a data file, named as in format files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostType {
+    /// Text, read as UTF-8.
+    Char,
+    /// UTF-16 text.
+    NChar,
+    Binary,
+    Bit,
+    TinyInt,
+    SmallInt,
+    Int,
+    BigInt,
+    Real,
+    Float,
+    Decimal,
+    Money,
+    SmallMoney,
+    UniqueIdentifier,
+    DateTime,
+    SmallDateTime,
+    Date,
+    Time,
+    DateTime2,
+    DateTimeOffset,
+}
+
+impl HostType {
+    /// Bytes of the values of types of a fixed length.
+    fn size(self) -> Option<usize> {
+        match self {
+            HostType::Bit | HostType::TinyInt => Some(1),
+            HostType::SmallInt => Some(2),
+            HostType::Int | HostType::Real | HostType::SmallMoney | HostType::SmallDateTime => {
+                Some(4)
+            }
+            HostType::BigInt | HostType::Float | HostType::Money | HostType::DateTime => Some(8),
+            HostType::Decimal => Some(19),
+            HostType::UniqueIdentifier => Some(16),
+            HostType::Date => Some(3),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HostType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HostType::Char => "SQLCHAR",
+            HostType::NChar => "SQLNCHAR",
+            HostType::Binary => "SQLBINARY",
+            HostType::Bit => "SQLBIT",
+            HostType::TinyInt => "SQLTINYINT",
+            HostType::SmallInt => "SQLSMALLINT",
+            HostType::Int => "SQLINT",
+            HostType::BigInt => "SQLBIGINT",
+            HostType::Real => "SQLFLT4",
+            HostType::Float => "SQLFLT8",
+            HostType::Decimal => "SQLDECIMAL",
+            HostType::Money => "SQLMONEY",
+            HostType::SmallMoney => "SQLMONEY4",
+            HostType::UniqueIdentifier => "SQLUNIQUEID",
+            HostType::DateTime => "SQLDATETIME",
+            HostType::SmallDateTime => "SQLDATETIM4",
+            HostType::Date => "SQLDATE",
+            HostType::Time => "SQLTIME",
+            HostType::DateTime2 => "SQLDATETIME2",
+            HostType::DateTimeOffset => "SQLDATETIMEOFFSET",
+        };
+        f.pad(name)
+    }
+}
+
+impl FromStr for HostType {
+    type Err = BulkError;
+
+    /// Reads the types of non-XML format files and the column types of XML
+    /// ones.
+    fn from_str(s: &str) -> Result<HostType> {
+        let host_type = match s.to_uppercase().as_str() {
+            "SQLCHAR" | "SQLVARYCHAR" | "SQLTEXT" => HostType::Char,
+            "SQLNCHAR" | "SQLNVARCHAR" | "SQLNTEXT" | "SQLXML" => HostType::NChar,
+            "SQLBINARY" | "SQLVARYBIN" | "SQLIMAGE" | "SQLUDT" => HostType::Binary,
+            "SQLBIT" => HostType::Bit,
+            "SQLTINYINT" => HostType::TinyInt,
+            "SQLSMALLINT" => HostType::SmallInt,
+            "SQLINT" => HostType::Int,
+            "SQLBIGINT" => HostType::BigInt,
+            "SQLFLT4" => HostType::Real,
+            "SQLFLT8" => HostType::Float,
+            "SQLDECIMAL" | "SQLNUMERIC" => HostType::Decimal,
+            "SQLMONEY" => HostType::Money,
+            "SQLMONEY4" => HostType::SmallMoney,
+            "SQLUNIQUEID" => HostType::UniqueIdentifier,
+            "SQLDATETIME" => HostType::DateTime,
+            "SQLDATETIM4" => HostType::SmallDateTime,
+            "SQLDATE" => HostType::Date,
+            "SQLTIME" => HostType::Time,
+            "SQLDATETIME2" => HostType::DateTime2,
+            "SQLDATETIMEOFFSET" | "SQLDATETIMOFFSET" => HostType::DateTimeOffset,
+            _ => return Err(BulkError::Config(format!("unknown bcp data type: {}", s))),
+        };
+        Ok(host_type)
+    }
+}
+
+/// A field of the records of a data file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BcpField {
+    pub host_type: HostType,
+    /// Bytes of the length before the data: 0, 1, 2, 4 or 8. A length of all
+    /// ones is NULL.
+    pub prefix_length: u8,
+    /// Bytes of data of fields without prefix and terminator, or the most
+    /// bytes of other fields; 0 when there is no limit.
+    pub length: u32,
+    /// Characters after the data, empty for none. `SQLNCHAR` fields end in
+    /// the UTF-16 characters.
+    pub terminator: String,
+    /// Position of the column of the table the field is loaded into, from 1,
+    /// or 0 when the field is skipped.
+    pub column: usize,
+    /// Name of the column, only for the reader of the file.
+    pub name: String,
+}
+
+impl BcpField {
+    /// The terminator as written in the data file.
+    fn terminator_bytes(&self) -> Vec<u8> {
+        match self.host_type {
+            HostType::NChar => utf16_bytes(&self.terminator),
+            _ => self.terminator.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// The layout of the records of a data file, as in a `bcp` format file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FormatFile {
+    pub fields: Vec<BcpField>,
+}
+
+impl FormatFile {
+    /// Reads an XML or non-XML format file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<FormatFile> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// The layout `bcp` uses for the data files of `schema` in `mode`: a
+    /// field for every column of the table, in the order of the table.
+    pub fn for_table(schema: &TableSchema, mode: BcpMode) -> FormatFile {
+        let count = schema.columns.len();
+        let fields = schema
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                let (host_type, prefix_length, length) = match mode {
+                    BcpMode::Character => (HostType::Char, 0, char_length(&column.sql_type)),
+                    BcpMode::Native => native_field(column, false),
+                    BcpMode::WideNative => native_field(column, true),
+                };
+                let terminator = match mode {
+                    BcpMode::Character if i + 1 == count => "\r\n",
+                    BcpMode::Character => "\t",
+                    _ => "",
+                };
+
+                BcpField {
+                    host_type,
+                    prefix_length,
+                    length,
+                    terminator: terminator.to_owned(),
+                    column: i + 1,
+                    name: column.name.clone(),
+                }
+            })
+            .collect();
+
+        FormatFile { fields }
+    }
+
+    fn parse_non_xml(text: &str) -> Result<FormatFile> {
+        let invalid = |what: &str| BulkError::Config(format!("invalid format file: {}", what));
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+
+        // The version of bcp does not change the layout.
+        lines.next().ok_or_else(|| invalid("no version"))?;
+        let count: usize = lines
+            .next()
+            .and_then(|line| line.trim().parse().ok())
+            .ok_or_else(|| invalid("no number of fields"))?;
+
+        let mut fields = Vec::with_capacity(count);
+        for line in lines {
+            let tokens = tokens(line).ok_or_else(|| invalid(line))?;
+            if tokens.len() < 7 || tokens[0] != (fields.len() + 1).to_string() {
+                return Err(invalid(line));
+            }
+
+            fields.push(BcpField {
+                host_type: tokens[1].parse()?,
+                prefix_length: tokens[2]
+                    .parse()
+                    .ok()
+                    .filter(|len| matches!(len, 0 | 1 | 2 | 4 | 8))
+                    .ok_or_else(|| invalid(line))?,
+                length: tokens[3].parse().map_err(|_| invalid(line))?,
+                terminator: tokens[4].clone(),
+                column: tokens[5].parse().map_err(|_| invalid(line))?,
+                name: tokens[6].clone(),
+            });
+        }
+
+        if fields.len() != count {
+            return Err(invalid(&format!(
+                "{} fields instead of {}",
+                fields.len(),
+                count
+            )));
+        }
+        Ok(FormatFile { fields })
+    }
+
+    fn parse_xml(text: &str) -> Result<FormatFile> {
+        let invalid = |what: String| BulkError::Config(format!("invalid format file: {}", what));
+        let document = roxmltree::Document::parse(text).map_err(|e| invalid(e.to_string()))?;
+        let elements = |name: &'static str| {
+            document
+                .descendants()
+                .filter(move |node| node.has_tag_name(name))
+        };
+        let number = |node: roxmltree::Node, name: &str| -> Result<Option<u32>> {
+            node.attribute(name)
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| invalid(format!("{}={}", name, value)))
+                })
+                .transpose()
+        };
+
+        let mut fields = Vec::new();
+        for field in elements("FIELD") {
+            let id = field.attribute("ID").unwrap_or_default();
+            let kind = field.attribute((XSI, "type")).unwrap_or_default();
+            let (host_type, fixed) = match kind {
+                "CharTerm" | "CharFixed" | "CharPrefix" => (Some(HostType::Char), "CharFixed"),
+                "NCharTerm" | "NCharFixed" | "NCharPrefix" => (Some(HostType::NChar), "NCharFixed"),
+                // The column of the field tells the type of native data.
+                "NativeFixed" | "NativePrefix" => (None, "NativeFixed"),
+                _ => return Err(invalid(format!("field {} of type {}", id, kind))),
+            };
+
+            // Columns are numbered by their position in the row.
+            let column = elements("COLUMN")
+                .enumerate()
+                .find(|(_, column)| column.attribute("SOURCE") == Some(id));
+            let host_type = match (host_type, column) {
+                (Some(host_type), _) => host_type,
+                (None, Some((_, column))) => column
+                    .attribute((XSI, "type"))
+                    .unwrap_or_default()
+                    .parse()?,
+                // Skipped native fields are read as bytes.
+                (None, None) => HostType::Binary,
+            };
+
+            fields.push(BcpField {
+                host_type,
+                prefix_length: number(field, "PREFIX_LENGTH")?
+                    .unwrap_or(0)
+                    .try_into()
+                    .map_err(|_| invalid(format!("prefix length of field {}", id)))?,
+                length: match kind == fixed {
+                    true => number(field, "LENGTH")?,
+                    false => number(field, "MAX_LENGTH")?,
+                }
+                .unwrap_or(0),
+                terminator: unescape(field.attribute("TERMINATOR").unwrap_or_default())
+                    .ok_or_else(|| invalid(format!("terminator of field {}", id)))?,
+                column: column.map_or(0, |(i, _)| i + 1),
+                name: column
+                    .and_then(|(_, column)| column.attribute("NAME"))
+                    .unwrap_or_default()
+                    .to_owned(),
+            });
+        }
+
+        if fields.is_empty() {
+            return Err(invalid("no fields".to_owned()));
+        }
+        Ok(FormatFile { fields })
+    }
+}
+
+impl FromStr for FormatFile {
+    type Err = BulkError;
+
+    /// Reads an XML or non-XML format file.
+    fn from_str(s: &str) -> Result<FormatFile> {
+        let text = s.trim_start_matches('\u{feff}').trim_start();
+        match text.starts_with('<') {
+            true => FormatFile::parse_xml(text),
+            false => FormatFile::parse_non_xml(text),
+        }
+    }
+}
+
+impl fmt::Display for FormatFile {
+    /// Writes the format file in the non-XML format. Tokens are aligned in
+    /// columns, and one longer than its column is still followed by a space.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", FORMAT_VERSION)?;
+        writeln!(f, "{}", self.fields.len())?;
+        for (i, field) in self.fields.iter().enumerate() {
+            writeln!(
+                f,
+                "{:<7} {:<19} {:<7} {:<7} {:<7} {:<5} {:<29} \"\"",
+                i + 1,
+                field.host_type,
+                field.prefix_length,
+                field.length,
+                format!("\"{}\"", escape(&field.terminator)),
+                field.column,
+                name_token(&field.name)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the records of a `bcp` data file into the insertable columns of a
+/// table. Fields of identity, computed and `rowversion` columns are skipped,
+/// as `bcp` does without `-E`.
+pub struct BcpSource<R> {
+    reader: R,
+    fields: Vec<BcpField>,
+    /// Fractional second digits of the values of each field.
+    scales: Vec<u8>,
+    /// Whether each field is loaded.
+    loaded: Vec<bool>,
+    names: Vec<String>,
+}
+
+impl BcpSource<InputFile> {
+    /// Reads a data file, decompressing it if its extension is that of a
+    /// compression format. Native data may start with any bytes, so they do
+    /// not tell.
+    pub fn from_path(
+        path: impl AsRef<Path>,
+        format: &FormatFile,
+        schema: &TableSchema,
+    ) -> Result<BcpSource<InputFile>> {
+        BcpSource::from_reader(compression::open_by_extension(path)?, format, schema)
+    }
+}
+
+impl<R: BufRead> BcpSource<R> {
+    /// Reads the records of `reader` laid out as in `format`, whose fields
+    /// are loaded into the columns of `schema` by their position.
+    pub fn from_reader(
+        reader: R,
+        format: &FormatFile,
+        schema: &TableSchema,
+    ) -> Result<BcpSource<R>> {
+        let mut columns = Vec::with_capacity(format.fields.len());
+        for field in &format.fields {
+            let column = match field.column {
+                0 => None,
+                i => Some(schema.columns.get(i - 1).ok_or_else(|| {
+                    BulkError::Config(format!(
+                        "the format file loads a field into column {} of table {}, which has {} \
+                         columns",
+                        i,
+                        schema.table,
+                        schema.columns.len()
+                    ))
+                })?),
+            };
+            columns.push(column);
+        }
+
+        Ok(BcpSource {
+            reader,
+            fields: format.fields.clone(),
+            scales: columns
+                .iter()
+                .map(|column| match column.map(|column| &column.sql_type) {
+                    Some(
+                        SqlType::Time(scale)
+                        | SqlType::DateTime2(scale)
+                        | SqlType::DateTimeOffset(scale),
+                    ) => *scale,
+                    _ => 7,
+                })
+                .collect(),
+            loaded: columns
+                .iter()
+                .map(|column| column.is_some_and(Column::is_insertable))
+                .collect(),
+            names: columns
+                .iter()
+                .flatten()
+                .filter(|column| column.is_insertable())
+                .map(|column| column.name.clone())
+                .collect(),
+        })
+    }
+}
+
+impl<R: BufRead> Source for BcpSource<R> {
+    fn fields(&self) -> Option<&[String]> {
+        Some(&self.names)
+    }
+
+    fn next_record(&mut self) -> Result<Option<Vec<Value>>> {
+        // The whole record is read before its values are converted, so that
+        // a bad value does not leave the rest of the record to the next one.
+        let mut data = Vec::with_capacity(self.fields.len());
+        for (i, field) in self.fields.iter().enumerate() {
+            match read_field(&mut self.reader, field)? {
+                Some(bytes) => data.push(bytes),
+                None if i == 0 => return Ok(None),
+                None => return Err(truncated()),
+            }
+        }
+
+        let mut values = Vec::with_capacity(self.names.len());
+        for (i, bytes) in data.into_iter().enumerate() {
+            if self.loaded[i] {
+                let field = &self.fields[i];
+                let value = field_value(field.host_type, bytes, self.scales[i]);
+                values.push(value.in_column(&self.names[values.len()])?);
+            }
+        }
+
+        Ok(Some(values))
+    }
+}
+
+/// Writes records of every column of a table to a `bcp` data file, in the
+/// order of the table. `bcp in` loads the file with `-n`, `-N` or `-c` like
+/// the mode of the writer, or with the [`format_file`](BcpWriter::format_file).
+pub struct BcpWriter<W> {
+    writer: W,
+    format: FormatFile,
+    columns: Vec<Column>,
+    record: Vec<u8>,
+}
+
+impl<W: Write> BcpWriter<W> {
+    pub fn new(writer: W, schema: &TableSchema, mode: BcpMode) -> BcpWriter<W> {
+        BcpWriter {
+            writer,
+            format: FormatFile::for_table(schema, mode),
+            columns: schema.columns.clone(),
+            record: Vec::new(),
+        }
+    }
+
+    /// The layout of the records.
+    pub fn format_file(&self) -> &FormatFile {
+        &self.format
+    }
+
+    /// Writes a record with a value for every column of the table. Values
+    /// are converted to the types of the columns first; a record that cannot
+    /// be converted is not written.
+    pub fn write_record(&mut self, values: Vec<Value>) -> Result<()> {
+        if values.len() != self.columns.len() {
+            return Err(BulkError::Config(format!(
+                "a record of {} values for {} columns",
+                values.len(),
+                self.columns.len()
+            )));
+        }
+
+        self.record.clear();
+        for ((value, column), field) in values
+            .into_iter()
+            .zip(&self.columns)
+            .zip(&self.format.fields)
+        {
+            if value == Value::Null && !column.nullable {
+                return Err(
+                    BulkError::conversion("NULL in a column that does not allow NULLs")
+                        .with_column(&column.name),
+                );
+            }
+            convert(value, &column.sql_type, BinaryEncoding::Hex)
+                .and_then(|data| write_field(&mut self.record, field, column, &data))
+                .in_column(&column.name)?;
+        }
+
+        self.writer.write_all(&self.record)?;
+        Ok(())
+    }
+
+    /// Flushes the file and returns the writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// The type, prefix length and length of the native field of `column`.
+/// Values of fixed length have a prefix only when they may be NULL.
+fn native_field(column: &Column, wide: bool) -> (HostType, u8, u32) {
+    let fixed = |host_type: HostType| {
+        let size = host_type.size().unwrap_or_default() as u32;
+        (host_type, u8::from(column.nullable), size)
+    };
+    let text = |len: Option<u16>, prefix: u8| match wide {
+        true => (
+            HostType::NChar,
+            prefix,
+            len.map_or(0, |len| u32::from(len) * 2),
+        ),
+        false => (HostType::Char, prefix, len.map_or(0, u32::from)),
+    };
+    let wide_text = |len: Option<u16>, prefix: u8| {
+        (
+            HostType::NChar,
+            prefix,
+            len.map_or(0, |len| u32::from(len) * 2),
+        )
+    };
+    let binary =
+        |len: Option<u16>, prefix: u8| (HostType::Binary, prefix, len.map_or(0, u32::from));
+    let time = |scale: u8| time_length(scale) as u32;
+
+    match &column.sql_type {
+        SqlType::Bit => fixed(HostType::Bit),
+        SqlType::TinyInt => fixed(HostType::TinyInt),
+        SqlType::SmallInt => fixed(HostType::SmallInt),
+        SqlType::Int => fixed(HostType::Int),
+        SqlType::BigInt => fixed(HostType::BigInt),
+        SqlType::Real => fixed(HostType::Real),
+        SqlType::Float => fixed(HostType::Float),
+        SqlType::Money => fixed(HostType::Money),
+        SqlType::SmallMoney => fixed(HostType::SmallMoney),
+        SqlType::DateTime => fixed(HostType::DateTime),
+        SqlType::SmallDateTime => fixed(HostType::SmallDateTime),
+        SqlType::Decimal { .. } => (HostType::Decimal, 1, 19),
+        SqlType::UniqueIdentifier => (HostType::UniqueIdentifier, 1, 16),
+        SqlType::Date => (HostType::Date, 1, 3),
+        SqlType::Time(scale) => (HostType::Time, 1, time(*scale)),
+        SqlType::DateTime2(scale) => (HostType::DateTime2, 1, time(*scale) + 3),
+        SqlType::DateTimeOffset(scale) => (HostType::DateTimeOffset, 1, time(*scale) + 5),
+        SqlType::Char(len) => text(Some(*len), 2),
+        SqlType::VarChar(Some(len)) => text(Some(*len), 2),
+        SqlType::VarChar(None) => text(None, 8),
+        SqlType::Text => text(None, 4),
+        SqlType::NChar(len) => wide_text(Some(*len), 2),
+        SqlType::NVarChar(Some(len)) => wide_text(Some(*len), 2),
+        SqlType::NVarChar(None) | SqlType::Xml | SqlType::Other(_) => wide_text(None, 8),
+        SqlType::NText => wide_text(None, 4),
+        SqlType::Binary(len) => binary(Some(*len), 2),
+        SqlType::VarBinary(Some(len)) => binary(Some(*len), 2),
+        SqlType::VarBinary(None) => binary(None, 8),
+        SqlType::Image => binary(None, 4),
+        SqlType::RowVersion => (HostType::Binary, 0, 8),
+    }
+}
+
+/// The most characters of the text of a value of `sql_type`, as in the
+/// format files of `bcp -c`; 0 when there is no limit.
+fn char_length(sql_type: &SqlType) -> u32 {
+    match sql_type {
+        SqlType::Bit => 1,
+        SqlType::TinyInt => 5,
+        SqlType::SmallInt => 7,
+        SqlType::Int => 12,
+        SqlType::BigInt => 19,
+        SqlType::Real | SqlType::Float | SqlType::Money | SqlType::SmallMoney => 30,
+        SqlType::Decimal { precision, .. } => u32::from(*precision) + 2,
+        SqlType::Char(len) | SqlType::VarChar(Some(len)) => u32::from(*len),
+        SqlType::NChar(len) | SqlType::NVarChar(Some(len)) => u32::from(*len) * 2,
+        SqlType::Binary(len) | SqlType::VarBinary(Some(len)) => u32::from(*len) * 2,
+        SqlType::RowVersion => 16,
+        SqlType::UniqueIdentifier => 37,
+        SqlType::Date => 11,
+        SqlType::Time(_) => 16,
+        SqlType::DateTime | SqlType::SmallDateTime => 24,
+        SqlType::DateTime2(_) => 27,
+        SqlType::DateTimeOffset(_) => 34,
+        SqlType::VarChar(None)
+        | SqlType::NVarChar(None)
+        | SqlType::VarBinary(None)
+        | SqlType::Text
+        | SqlType::NText
+        | SqlType::Image
+        | SqlType::Xml
+        | SqlType::Other(_) => 0,
+    }
+}
+
+/// Bytes of the time of day of a value with `scale` fractional second digits.
+fn time_length(scale: u8) -> usize {
+    match scale {
+        0..=2 => 3,
+        3..=4 => 4,
+        _ => 5,
+    }
+}
+
+fn truncated() -> BulkError {
+    BulkError::conversion("the file ends within a record")
+}
+
+/// The data of the next field, `Some(None)` for NULL, or `None` at the end of
+/// the file.
+fn read_field(reader: &mut impl BufRead, field: &BcpField) -> Result<Option<Option<Vec<u8>>>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let terminator = field.terminator_bytes();
+
+    let data = if field.prefix_length > 0 {
+        let mut prefix = [0; 8];
+        read_exact(reader, &mut prefix[..field.prefix_length as usize])?;
+        let data = match prefix[..field.prefix_length as usize]
+            .iter()
+            .all(|b| *b == 0xFF)
+        {
+            true => None,
+            false => Some(read_bytes(reader, u64::from_le_bytes(prefix))?),
+        };
+        if !terminator.is_empty() {
+            let mut end = vec![0; terminator.len()];
+            read_exact(reader, &mut end)?;
+            if end != terminator {
+                return Err(BulkError::conversion(format!(
+                    "a field of type {} does not end in its terminator",
+                    field.host_type
+                )));
+            }
+        }
+        data
+    } else if !terminator.is_empty() {
+        let data = read_terminated(reader, &terminator, field.host_type == HostType::NChar)?;
+        // Character files write NULL as an empty field and empty text as a
+        // NUL character.
+        match data.as_slice() {
+            [] => None,
+            [0] | [0, 0] => Some(Vec::new()),
+            _ => Some(data),
+        }
+    } else {
+        let len = field.host_type.size().unwrap_or(field.length as usize);
+        Some(read_bytes(reader, len as u64)?)
+    };
+
+    Ok(Some(data))
+}
+
+fn read_exact(reader: &mut impl BufRead, buf: &mut [u8]) -> Result<()> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => truncated(),
+        _ => e.into(),
+    })
+}
+
+/// Reads `len` bytes without trusting `len` with the size of the buffer.
+fn read_bytes(reader: &mut impl BufRead, len: u64) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(len).read_to_end(&mut data)?;
+    match data.len() as u64 == len {
+        true => Ok(data),
+        false => Err(truncated()),
+    }
+}
+
+/// Reads up to `terminator`, which is left out. UTF-16 text ends only at a
+/// terminator on a character boundary. A `\r\n` terminator also matches a
+/// bare `\n`, as `bcp` writes rows on Linux, and the last field of the file
+/// may go without terminator.
+fn read_terminated(reader: &mut impl BufRead, terminator: &[u8], wide: bool) -> Result<Vec<u8>> {
+    let newline = terminator == b"\r\n";
+    let last = *terminator.last().expect("not empty");
+    let mut data = Vec::new();
+
+    loop {
+        if reader.read_until(last, &mut data)? == 0 || !data.ends_with(&[last]) {
+            return match data.is_empty() {
+                true => Err(truncated()),
+                false => Ok(data),
+            };
+        }
+        if data.ends_with(terminator)
+            && (!wide || (data.len() - terminator.len()).is_multiple_of(2))
+        {
+            data.truncate(data.len() - terminator.len());
+            return Ok(data);
+        }
+        if newline {
+            data.pop();
+            return Ok(data);
+        }
+    }
+}
+
+/// Reads the data of a field of `host_type`. Time of day is read with `scale`
+/// fractional second digits.
+fn field_value(host_type: HostType, data: Option<Vec<u8>>, scale: u8) -> Result<Value> {
+    let Some(data) = data else {
+        return Ok(Value::Null);
+    };
+    let invalid = || {
+        BulkError::conversion(format!(
+            "{} bytes are no valid {} value",
+            data.len(),
+            host_type
+        ))
+    };
+    let bytes = |len: usize| -> Result<&[u8]> {
+        match data.len() == len {
+            true => Ok(&data),
+            false => Err(invalid()),
+        }
+    };
+    let array = |len: usize| bytes(len).map(|bytes| bytes.to_vec());
+
+    let value = match host_type {
+        HostType::Char => Value::Text(
+            String::from_utf8(data).map_err(|_| BulkError::conversion("the text is no UTF-8"))?,
+        ),
+        HostType::NChar => Value::Text(utf16_text(&data).ok_or_else(invalid)?),
+        HostType::Binary => Value::Bytes(data),
+        HostType::Bit => Value::Bool(bytes(1)?[0] != 0),
+        HostType::TinyInt => Value::Int(i64::from(bytes(1)?[0])),
+        HostType::SmallInt => Value::Int(i16::from_le_bytes(fixed(&array(2)?)).into()),
+        HostType::Int => Value::Int(i32::from_le_bytes(fixed(&array(4)?)).into()),
+        HostType::BigInt => Value::Int(i64::from_le_bytes(fixed(&array(8)?))),
+        HostType::Real => Value::Float(f32::from_le_bytes(fixed(&array(4)?)).into()),
+        HostType::Float => Value::Float(f64::from_le_bytes(fixed(&array(8)?))),
+        HostType::Decimal => {
+            let bytes = bytes(19)?;
+            let magnitude = i128::from_le_bytes(fixed(&bytes[3..]));
+            let value = match bytes[2] {
+                0 => -magnitude,
+                _ => magnitude,
+            };
+            Value::Text(decimal_text(value, bytes[1]))
+        }
+        HostType::Money => {
+            let bytes = bytes(8)?;
+            let high = i32::from_le_bytes(fixed(&bytes[..4]));
+            let low = u32::from_le_bytes(fixed(&bytes[4..]));
+            Value::Text(decimal_text((i128::from(high) << 32) | i128::from(low), 4))
+        }
+        HostType::SmallMoney => Value::Text(decimal_text(
+            i32::from_le_bytes(fixed(&array(4)?)).into(),
+            4,
+        )),
+        HostType::UniqueIdentifier => Value::Guid(Uuid::from_bytes_le(fixed(&array(16)?))),
+        HostType::DateTime => {
+            let bytes = bytes(8)?;
+            let days = i32::from_le_bytes(fixed(&bytes[..4]));
+            // Three hundredths of a second, rounded to milliseconds.
+            let ticks = u32::from_le_bytes(fixed(&bytes[4..]));
+            let millis = (i64::from(ticks) * 10 + 1) / 3;
+            Value::DateTime(
+                epoch(1900)
+                    .and_time(NaiveTime::MIN)
+                    .checked_add_signed(Duration::days(days.into()))
+                    .and_then(|value| value.checked_add_signed(Duration::milliseconds(millis)))
+                    .ok_or_else(invalid)?,
+            )
+        }
+        HostType::SmallDateTime => {
+            let bytes = bytes(4)?;
+            let days = u16::from_le_bytes(fixed(&bytes[..2]));
+            let minutes = u16::from_le_bytes(fixed(&bytes[2..]));
+            Value::DateTime(
+                epoch(1900).and_time(NaiveTime::MIN)
+                    + Duration::days(days.into())
+                    + Duration::minutes(minutes.into()),
+            )
+        }
+        HostType::Date => Value::Date(date(bytes(3)?).ok_or_else(invalid)?),
+        HostType::Time => Value::Time(time(bytes(time_length(scale))?, scale).ok_or_else(invalid)?),
+        HostType::DateTime2 => {
+            let bytes = bytes(time_length(scale) + 3)?;
+            Value::DateTime(datetime2(bytes, scale).ok_or_else(invalid)?)
+        }
+        HostType::DateTimeOffset => {
+            let bytes = bytes(time_length(scale) + 5)?;
+            let (utc, offset) = bytes.split_at(bytes.len() - 2);
+            let offset = i16::from_le_bytes(fixed(offset));
+            let offset = FixedOffset::east_opt(i32::from(offset) * 60).ok_or_else(invalid)?;
+            let utc = datetime2(utc, scale).ok_or_else(invalid)?;
+            Value::DateTimeOffset(offset.from_utc_datetime(&utc))
+        }
+    };
+
+    Ok(value)
+}
+
+/// The array of a slice whose length was checked.
+fn fixed<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    bytes.try_into().expect("length checked")
+}
+
+fn epoch(year: i32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, 1, 1).unwrap()
+}
+
+/// A date of three bytes: days since 0001-01-01.
+fn date(bytes: &[u8]) -> Option<NaiveDate> {
+    let days = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+    epoch(1).checked_add_signed(Duration::days(days.into()))
+}
+
+/// A time of day in units of 10^-`scale` seconds.
+fn time(bytes: &[u8], scale: u8) -> Option<NaiveTime> {
+    let mut increments = [0; 8];
+    increments[..bytes.len()].copy_from_slice(bytes);
+    let nanos = u64::from_le_bytes(increments).checked_mul(10u64.pow(9 - u32::from(scale)))?;
+    NaiveTime::from_num_seconds_from_midnight_opt(
+        (nanos / 1_000_000_000).try_into().ok()?,
+        (nanos % 1_000_000_000) as u32,
+    )
+}
+
+/// A time of day followed by a date.
+fn datetime2(bytes: &[u8], scale: u8) -> Option<NaiveDateTime> {
+    let (time_bytes, date_bytes) = bytes.split_at(bytes.len() - 3);
+    Some(date(date_bytes)?.and_time(time(time_bytes, scale)?))
+}
+
+fn utf16_bytes(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn utf16_text(bytes: &[u8]) -> Option<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes
+        .chunks(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .collect::<std::result::Result<_, _>>()
+        .ok()
+}
+
+/// Writes the field of a converted value to `record`.
+fn write_field(
+    record: &mut Vec<u8>,
+    field: &BcpField,
+    column: &Column,
+    data: &ColumnData<'static>,
+) -> Result<()> {
+    let terminator = field.terminator_bytes();
+
+    if field.prefix_length == 0 && !terminator.is_empty() {
+        // Text of a character file: NULL is an empty field, empty text a NUL.
+        match column_text(data) {
+            None => {}
+            Some(text) if text.is_empty() => record.extend(match field.host_type {
+                HostType::NChar => &[0, 0][..],
+                _ => &[0][..],
+            }),
+            Some(text) if field.host_type == HostType::NChar => record.extend(utf16_bytes(&text)),
+            Some(text) => record.extend(text.as_bytes()),
+        }
+        record.extend(terminator);
+        return Ok(());
+    }
+
+    let bytes = native_bytes(data, field.host_type, &column.sql_type)?;
+    match (field.prefix_length, bytes) {
+        (0, None) => {
+            return Err(BulkError::conversion(
+                "NULL in a column that does not allow NULLs",
+            ))
+        }
+        (0, Some(bytes)) => record.extend(bytes),
+        (prefix, None) => record.extend(std::iter::repeat_n(0xFF, prefix as usize)),
+        (prefix, Some(bytes)) => {
+            let len = (bytes.len() as u64).to_le_bytes();
+            if prefix < 8 && bytes.len() as u64 >= 1 << (8 * u32::from(prefix)) {
+                return Err(BulkError::conversion(format!(
+                    "a value of {} bytes does not fit in a field of type {}",
+                    bytes.len(),
+                    field.host_type
+                )));
+            }
+            record.extend(&len[..prefix as usize]);
+            record.extend(bytes);
+        }
+    }
+    record.extend(terminator);
+
+    Ok(())
+}
+
+/// The native data of a value, or `None` for NULL.
+fn native_bytes(
+    data: &ColumnData<'static>,
+    host_type: HostType,
+    sql_type: &SqlType,
+) -> Result<Option<Vec<u8>>> {
+    let scale = match sql_type {
+        SqlType::Time(scale) | SqlType::DateTime2(scale) | SqlType::DateTimeOffset(scale) => *scale,
+        _ => 7,
+    };
+    let time_bytes = |time: tiberius::time::Time| {
+        let increments = match time.scale().cmp(&scale) {
+            std::cmp::Ordering::Greater => {
+                time.increments() / 10u64.pow(u32::from(time.scale() - scale))
+            }
+            _ => time.increments() * 10u64.pow(u32::from(scale - time.scale())),
+        };
+        increments.to_le_bytes()[..time_length(scale)].to_vec()
+    };
+    let date_bytes = |date: tiberius::time::Date| date.days().to_le_bytes()[..3].to_vec();
+
+    let bytes = match (data, host_type) {
+        (ColumnData::Bit(value), HostType::Bit) => value.map(|value| vec![u8::from(value)]),
+        (ColumnData::U8(value), HostType::TinyInt) => value.map(|value| vec![value]),
+        (ColumnData::I16(value), HostType::SmallInt) => value.map(|v| v.to_le_bytes().to_vec()),
+        (ColumnData::I32(value), HostType::Int) => value.map(|v| v.to_le_bytes().to_vec()),
+        (ColumnData::I64(value), HostType::BigInt) => value.map(|v| v.to_le_bytes().to_vec()),
+        (ColumnData::F32(value), HostType::Real) => value.map(|v| v.to_le_bytes().to_vec()),
+        (ColumnData::F64(value), HostType::Float) => value.map(|v| v.to_le_bytes().to_vec()),
+        (ColumnData::F64(value), HostType::Money) => value.map(|value| {
+            let value = (value * 10_000.0).round() as i64;
+            let mut bytes = ((value >> 32) as i32).to_le_bytes().to_vec();
+            bytes.extend((value as u32).to_le_bytes());
+            bytes
+        }),
+        (ColumnData::F64(value), HostType::SmallMoney) => {
+            value.map(|value| ((value * 10_000.0).round() as i32).to_le_bytes().to_vec())
+        }
+        (ColumnData::Numeric(value), HostType::Decimal) => value.map(|value| {
+            let precision = match sql_type {
+                SqlType::Decimal { precision, .. } => *precision,
+                _ => value.precision(),
+            };
+            let mut bytes = vec![precision, value.scale(), u8::from(value.value() >= 0)];
+            bytes.extend(value.value().unsigned_abs().to_le_bytes());
+            bytes
+        }),
+        (ColumnData::String(value), HostType::Char) => {
+            value.as_ref().map(|value| value.as_bytes().to_vec())
+        }
+        (ColumnData::String(value), HostType::NChar) => {
+            value.as_ref().map(|value| utf16_bytes(value))
+        }
+        (ColumnData::Xml(value), HostType::NChar) => {
+            value.as_ref().map(|xml| utf16_bytes(&xml.to_string()))
+        }
+        (ColumnData::Binary(value), HostType::Binary) => value.as_ref().map(|value| value.to_vec()),
+        (ColumnData::Guid(value), HostType::UniqueIdentifier) => {
+            value.map(|value| value.to_bytes_le().to_vec())
+        }
+        (ColumnData::DateTime(value), HostType::DateTime) => value.map(|value| {
+            let mut bytes = value.days().to_le_bytes().to_vec();
+            bytes.extend(value.seconds_fragments().to_le_bytes());
+            bytes
+        }),
+        (ColumnData::SmallDateTime(value), HostType::SmallDateTime) => value.map(|value| {
+            let mut bytes = value.days().to_le_bytes().to_vec();
+            bytes.extend(value.seconds_fragments().to_le_bytes());
+            bytes
+        }),
+        (ColumnData::Date(value), HostType::Date) => value.map(date_bytes),
+        (ColumnData::Time(value), HostType::Time) => value.map(time_bytes),
+        (ColumnData::DateTime2(value), HostType::DateTime2) => value.map(|value| {
+            let mut bytes = time_bytes(value.time());
+            bytes.extend(date_bytes(value.date()));
+            bytes
+        }),
+        (ColumnData::DateTimeOffset(value), HostType::DateTimeOffset) => value.map(|value| {
+            let mut bytes = time_bytes(value.datetime2().time());
+            bytes.extend(date_bytes(value.datetime2().date()));
+            bytes.extend(value.offset().to_le_bytes());
+            bytes
+        }),
+        _ => {
+            return Err(BulkError::conversion(format!(
+                "{} values cannot be written as {}",
+                sql_type, host_type
+            )))
+        }
+    };
+
+    Ok(bytes)
+}
+
+/// Splits a line of a non-XML format file into its tokens. Quoted tokens
+/// are unescaped; `None` if a quote is not closed.
+fn tokens(line: &str) -> Option<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut end = None;
+            let mut escaped = false;
+            for (i, c) in quoted.char_indices() {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => {
+                        end = Some(i);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            let end = end?;
+            tokens.push(unescape(&quoted[..end])?);
+            rest = quoted[end + 1..].trim_start();
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            tokens.push(rest[..end].to_owned());
+            rest = rest[end..].trim_start();
+        }
+    }
+
+    Some(tokens)
+}
+
+/// The name of a column as a token of a non-XML format file: quoted, if it
+/// is empty or has whitespace or quotes, so that it stays one token.
+fn name_token(name: &str) -> String {
+    match name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '"') {
+        true => format!("\"{}\"", escape(name)),
+        false => name.to_owned(),
+    }
+}
+
+/// Reads the escapes of terminators: `\t`, `\n`, `\r`, `\0`, `\\`, `\"` and
+/// `\'`.
+fn unescape(text: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        unescaped.push(match c {
+            '\\' => match chars.next()? {
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                '0' => '\0',
+                c @ ('\\' | '"' | '\'') => c,
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+
+    Some(unescaped)
+}
+
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\t' => "\\t".to_owned(),
+            '\n' => "\\n".to_owned(),
+            '\r' => "\\r".to_owned(),
+            '\0' => "\\0".to_owned(),
+            '\\' => "\\\\".to_owned(),
+            '"' => "\\\"".to_owned(),
+            c => c.to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,868 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+use tiberius_bulk_insert_sqlserver::{
+    bcp::{BcpMode, BcpSource, FormatFile},
+    bulk::{BulkLoader, BulkOptions, LoadReport},
+    compression::{open, uncompressed_path},
+    connection::{connect, ConnectionConfig},
+    convert::BinaryEncoding,
+    copy::copy_query,
+    error::{BulkError, Result, ResultExt},
+    export::{export, CsvWriter, ExportOptions, JsonlWriter, ParquetWriter, RowWriter},
+    generate::RowGenerator,
+    infer::{infer_schema, InferOptions, DEFAULT_SAMPLE_ROWS},
+    profile::{Profiles, DEFAULT_CONFIG_FILE},
+    retry::RetryPolicy,
+    runtime::Runtime,
+    schema::{quote_object_name, TableSchema},
+    secret::{password_from_env, redact},
+    source::{
+        csv::{CsvOptions, CsvSource},
+        excel::{CellRange, ExcelOptions, ExcelSource},
+        fixed::{FixedWidthLayout, FixedWidthSource},
+        ingest,
+        jsonl::{default_fields, sample_fields, JsonPath, JsonlSource},
+        parquet::ParquetSource,
+        sqlite::SqliteDatabase,
+        IngestOptions, IngestReport, Source,
+    },
+};
+
+/// Bulk loads data into SQL Server.
+#[derive(Parser)]
+#[command(name = "bulkload", version)]
+struct Cli {
+    #[command(flatten)]
+    connection: ConnectionArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct ConnectionArgs {
+    /// Configuration file with connection profiles.
+    #[arg(long, global = true, env = "BULK_CONFIG")]
+    config: Option<PathBuf>,
+    /// Connection profile to use instead of the default one.
+    #[arg(long, short, global = true, env = "BULK_PROFILE")]
+    profile: Option<String>,
+    /// ADO.NET connection string, used when no profile is selected. The
+    /// password may be kept apart in SQL_AUTH_PASSWORD, SQL_AUTH_PASSWORD_FILE
+    /// or SQL_AUTH_PASSWORD_COMMAND.
+    #[arg(
+        long,
+        global = true,
+        env = "SQL_AUTH_CONN_STRING",
+        hide_env_values = true
+    )]
+    connection_string: Option<String>,
+}
+
+#[derive(Args)]
+struct LoadArgs {
+    /// Rows per batch; every batch is committed on its own.
+    #[arg(long, default_value_t = 10_000)]
+    batch_size: usize,
+    /// Attempts to connect, including the first one.
+    #[arg(long, default_value_t = 4)]
+    attempts: u32,
+    /// Attempts per batch, including the first one. A batch sent again after
+    /// a dropped connection may be loaded twice.
+    #[arg(long, default_value_t = 1)]
+    batch_attempts: u32,
+}
+
+/// Formats of the files `load` reads.
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    /// JSON Lines, one object per line.
+    Jsonl,
+    Parquet,
+    /// Data file of bcp.
+    Bcp,
+    /// Fixed-width records described by --layout.
+    Fixed,
+    /// Excel workbook or OpenDocument spreadsheet.
+    Excel,
+}
+
+impl Format {
+    /// The format of a file by its extension, after that of its compression
+    /// as in `data.jsonl.gz`; CSV unless it is a JSON, Parquet, bcp or
+    /// spreadsheet one.
+    fn of(path: &Path) -> Format {
+        let extension = uncompressed_path(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase);
+
+        match extension.as_deref() {
+            Some("jsonl" | "ndjson" | "json") => Format::Jsonl,
+            Some("parquet" | "parq") => Format::Parquet,
+            Some("bcp") => Format::Bcp,
+            Some("xlsx" | "xlsm" | "xlsb" | "xls" | "ods") => Format::Excel,
+            _ => Format::Csv,
+        }
+    }
+}
+
+/// Formats of the files `export` writes.
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Csv,
+    /// JSON Lines, one object per line.
+    Jsonl,
+    Parquet,
+}
+
+impl ExportFormat {
+    /// The format of a file by its extension; CSV unless it is a JSON or
+    /// Parquet one.
+    fn of(path: &Path) -> ExportFormat {
+        match Format::of(path) {
+            Format::Jsonl => ExportFormat::Jsonl,
+            Format::Parquet => ExportFormat::Parquet,
+            _ => ExportFormat::Csv,
+        }
+    }
+}
+
+#[derive(Args)]
+struct CsvArgs {
+    /// Field delimiter.
+    #[arg(long, default_value_t = ',')]
+    delimiter: char,
+    /// Quote character.
+    #[arg(long, default_value_t = '"')]
+    quote: char,
+    /// Escape character of quotes in quoted fields; by default quotes are
+    /// doubled.
+    #[arg(long)]
+    escape: Option<char>,
+    /// The file has no header; fields are loaded into the columns by position.
+    #[arg(long)]
+    no_header: bool,
+    /// Field text read as NULL, may be given several times [default: empty
+    /// fields].
+    #[arg(long = "null", value_name = "MARKER")]
+    null_markers: Vec<String>,
+    /// Removes the spaces around fields.
+    #[arg(long)]
+    trim: bool,
+}
+
+#[derive(Args)]
+struct BcpArgs {
+    /// Format file of the bcp data file, XML or not [default: the layout bcp
+    /// uses for the table in --bcp-mode].
+    #[arg(long, value_name = "FMT")]
+    format_file: Option<PathBuf>,
+    /// Kind of bcp data file: -n, -N or -c.
+    #[arg(long, default_value = "native", value_name = "native|wide-native|char")]
+    bcp_mode: BcpMode,
+}
+
+#[derive(Args)]
+struct FixedArgs {
+    /// TOML layout of the fields of fixed-width records; implies --format
+    /// fixed.
+    #[arg(long, value_name = "TOML")]
+    layout: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct ExcelArgs {
+    /// Sheet of the workbook [default: the first one].
+    #[arg(long)]
+    sheet: Option<String>,
+    /// Cells of the sheet, such as B2:F100, or B2 for every used cell from
+    /// B2; the header, unless --no-header, is the first row.
+    #[arg(long, value_name = "CELLS")]
+    range: Option<CellRange>,
+}
+
+#[derive(Args)]
+struct MappingArgs {
+    /// Loads a field into a column of another name. Fields of JSON Lines
+    /// are paths such as $.device.id; by default every column is loaded from
+    /// the top-level key of its name.
+    #[arg(long = "map", value_name = "FIELD=COLUMN", value_parser = parse_rename)]
+    rename: Vec<(String, String)>,
+    /// Skips fields without a column instead of failing.
+    #[arg(long)]
+    ignore_extra_fields: bool,
+    /// Encoding of binary values that do not start with 0x.
+    #[arg(long, default_value = "hex", value_name = "hex|base64")]
+    binary: BinaryEncoding,
+    /// Records that may be rejected before the load fails.
+    #[arg(long, default_value_t = 0)]
+    max_errors: u64,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Shows the columns of a table.
+    Schema {
+        /// Destination table.
+        table: String,
+    },
+    /// Loads rows of random values into a table.
+    Generate {
+        /// Destination table.
+        table: String,
+        /// Number of rows.
+        #[arg(long, default_value_t = 10_000)]
+        rows: u64,
+        /// Seed of the random values; the same seed generates the same rows.
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Share of NULL values in nullable columns, from 0 to 1.
+        #[arg(long, default_value_t = 0.0)]
+        null_ratio: f64,
+        #[command(flatten)]
+        load: LoadArgs,
+    },
+    /// Loads a CSV, JSON Lines, Parquet, bcp, fixed-width or Excel file into
+    /// a table, matching the fields with the columns by name.
+    Load {
+        /// Destination table.
+        table: String,
+        /// CSV, JSON Lines, Parquet, bcp, fixed-width or Excel file.
+        file: PathBuf,
+        /// Format of the file [default: by the extension of the file].
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+        #[command(flatten)]
+        csv: CsvArgs,
+        #[command(flatten)]
+        bcp: BcpArgs,
+        #[command(flatten)]
+        fixed: FixedArgs,
+        #[command(flatten)]
+        excel: ExcelArgs,
+        #[command(flatten)]
+        mapping: MappingArgs,
+        #[command(flatten)]
+        load: LoadArgs,
+    },
+    /// Infers the columns of a table from a sample of a CSV, JSON Lines,
+    /// Parquet, fixed-width or Excel file and prints the CREATE TABLE
+    /// statement, without connecting unless --create is given.
+    Infer {
+        /// Table to create.
+        table: String,
+        /// CSV, JSON Lines, Parquet, fixed-width or Excel file.
+        file: PathBuf,
+        /// Format of the file [default: by the extension of the file].
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+        /// Records read to infer the types.
+        #[arg(long, default_value_t = DEFAULT_SAMPLE_ROWS)]
+        sample_rows: u64,
+        /// Reads every record instead of a sample.
+        #[arg(long, conflicts_with = "sample_rows")]
+        all_rows: bool,
+        /// Creates the table as well.
+        #[arg(long)]
+        create: bool,
+        #[command(flatten)]
+        csv: CsvArgs,
+        #[command(flatten)]
+        fixed: FixedArgs,
+        #[command(flatten)]
+        excel: ExcelArgs,
+    },
+    /// Writes the rows of a table or query to a CSV, JSON Lines or Parquet
+    /// file.
+    Export {
+        /// CSV, JSON Lines or Parquet file to write.
+        file: PathBuf,
+        /// Table to export.
+        #[arg(long, required_unless_present = "query")]
+        table: Option<String>,
+        /// Query whose rows are exported instead of a table.
+        #[arg(long, conflicts_with = "table")]
+        query: Option<String>,
+        /// Format of the file [default: by the extension of the file].
+        #[arg(long, value_enum)]
+        format: Option<ExportFormat>,
+        /// Field delimiter of CSV files.
+        #[arg(long, default_value_t = ',')]
+        delimiter: char,
+        /// Field text written for NULL in CSV files.
+        #[arg(long = "null", default_value = "", value_name = "MARKER")]
+        null_marker: String,
+        /// Encoding of binary values in CSV and JSON Lines files.
+        #[arg(long, default_value = "hex", value_name = "hex|base64")]
+        binary: BinaryEncoding,
+    },
+    /// Copies the rows a query returns on a source server into a table,
+    /// matching the columns of the result with those of the table by name.
+    Copy {
+        /// Destination table.
+        table: String,
+        /// Query run on the source server, such as "SELECT * FROM dbo.orders".
+        query: String,
+        /// Connection profile of the source server [default: the connection
+        /// of the destination].
+        #[arg(long, value_name = "PROFILE")]
+        source_profile: Option<String>,
+        /// ADO.NET connection string of the source server, used when no
+        /// source profile is given.
+        #[arg(long, value_name = "ADO", conflicts_with = "source_profile")]
+        source_connection_string: Option<String>,
+        #[command(flatten)]
+        mapping: MappingArgs,
+        #[command(flatten)]
+        load: LoadArgs,
+    },
+    /// Copies the tables of a SQLite database into tables of the same name.
+    Sqlite {
+        /// SQLite database file.
+        file: PathBuf,
+        /// Table to copy, may be given several times [default: every table].
+        #[arg(long = "table", value_name = "TABLE")]
+        tables: Vec<String>,
+        /// Creates the tables before loading them, with the types the
+        /// columns of SQLite map to.
+        #[arg(long)]
+        create: bool,
+        /// Prints the CREATE TABLE statements of the tables instead of
+        /// loading them.
+        #[arg(long, conflicts_with = "create")]
+        print_create: bool,
+        #[command(flatten)]
+        mapping: MappingArgs,
+        #[command(flatten)]
+        load: LoadArgs,
+    },
+    /// Measures the throughput of loading random rows with several batch sizes.
+    ///
+    /// The rows stay in the table unless --truncate is given.
+    Bench {
+        /// Destination table.
+        table: String,
+        /// Number of rows per run.
+        #[arg(long, default_value_t = 100_000)]
+        rows: u64,
+        /// Batch sizes to compare.
+        #[arg(long, value_delimiter = ',', default_value = "1000,10000,50000")]
+        batch_sizes: Vec<usize>,
+        /// Empties the table before every run.
+        #[arg(long)]
+        truncate: bool,
+        /// Seed of the random values.
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+}
+
+impl ConnectionArgs {
+    /// The selected profile, or the connection string when no profile is
+    /// selected, or the default profile of the configuration file.
+    fn connection_config(&self) -> Result<ConnectionConfig> {
+        if let (None, Some(connection_string)) = (&self.profile, &self.connection_string) {
+            return match password_from_env()? {
+                Some(password) => {
+                    ConnectionConfig::from_ado_string_with_password(connection_string, &password)
+                }
+                None => ConnectionConfig::from_ado_string(connection_string),
+            };
+        }
+
+        self.profile_config(self.profile.as_deref())
+    }
+
+    /// The profile `name` of the configuration file, or its default profile.
+    fn profile_config(&self, name: Option<&str>) -> Result<ConnectionConfig> {
+        let path = self
+            .config
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE));
+        Profiles::load(path)?.get(name)?.connection_config()
+    }
+}
+
+impl LoadArgs {
+    fn options(&self) -> BulkOptions {
+        BulkOptions {
+            batch_size: self.batch_size.max(1),
+            retry: RetryPolicy {
+                max_attempts: self.attempts.max(1),
+                ..Default::default()
+            },
+            batch_retry: RetryPolicy {
+                max_attempts: self.batch_attempts.max(1),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl CsvArgs {
+    fn options(&self) -> Result<CsvOptions> {
+        let null_markers = match self.null_markers.is_empty() {
+            true => vec![String::new()],
+            false => self.null_markers.clone(),
+        };
+
+        Ok(CsvOptions {
+            delimiter: ascii(self.delimiter, "delimiter")?,
+            quote: ascii(self.quote, "quote")?,
+            escape: self.escape.map(|c| ascii(c, "escape")).transpose()?,
+            has_header: !self.no_header,
+            null_markers,
+            trim: self.trim,
+        })
+    }
+}
+
+impl ExcelArgs {
+    fn options(&self, csv: &CsvArgs) -> ExcelOptions {
+        ExcelOptions {
+            sheet: self.sheet.clone(),
+            range: self.range,
+            has_header: !csv.no_header,
+        }
+    }
+}
+
+impl MappingArgs {
+    fn options(&self) -> IngestOptions {
+        IngestOptions {
+            rename: self.rename.clone(),
+            ignore_extra_fields: self.ignore_extra_fields,
+            binary_encoding: self.binary,
+            max_errors: self.max_errors,
+        }
+    }
+}
+
+fn ascii(c: char, option: &str) -> Result<u8> {
+    u8::try_from(c)
+        .ok()
+        .filter(u8::is_ascii)
+        .ok_or_else(|| BulkError::Config(format!("the {} must be an ASCII character", option)))
+}
+
+fn parse_rename(s: &str) -> std::result::Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(field, column)| (field.trim().to_owned(), column.trim().to_owned()))
+        .ok_or_else(|| format!("expected FIELD=COLUMN, found {}", s))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = Runtime::new()
+        .map_err(Into::into)
+        .and_then(|runtime| runtime.block_on(run(cli)));
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", redact(&e.to_string()));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    // Printing statements needs no server.
+    if let Command::Sqlite {
+        file,
+        tables,
+        print_create: true,
+        ..
+    } = &cli.command
+    {
+        let database = SqliteDatabase::open(file)?;
+        for table in sqlite_tables(&database, tables)? {
+            println!("{};\n", database.schema(&table)?.create_table_sql()?);
+        }
+        return Ok(());
+    }
+
+    if let Command::Infer {
+        table,
+        file,
+        format,
+        sample_rows,
+        all_rows,
+        create,
+        csv,
+        fixed,
+        excel,
+    } = &cli.command
+    {
+        let options = InferOptions {
+            sample_rows: (!all_rows).then_some(*sample_rows),
+        };
+        let schema = infer(file, *format, csv, fixed, excel, table, &options)?;
+        let create_table = schema.create_table_sql()?;
+        println!("{};", create_table);
+        if !create {
+            return Ok(());
+        }
+
+        let config = cli.connection.connection_config()?;
+        let (mut client, _) = connect(&config).await?;
+        client.execute(create_table, &[]).await.in_table(table)?;
+        client.close().await?;
+        return Ok(());
+    }
+
+    let config = cli.connection.connection_config()?;
+
+    match cli.command {
+        Command::Schema { table } => schema(&config, &table).await,
+        Command::Generate {
+            table,
+            rows,
+            seed,
+            null_ratio,
+            load,
+        } => {
+            let report = generate(&config, &table, rows, seed, null_ratio, load.options()).await?;
+            println!("{}", report);
+            Ok(())
+        }
+        Command::Load {
+            table,
+            file,
+            format,
+            csv,
+            bcp,
+            fixed,
+            excel,
+            mapping,
+            load,
+        } => {
+            let schema = load_schema(&config, &table).await?;
+            let format = format.unwrap_or_else(|| match fixed.layout {
+                Some(_) => Format::Fixed,
+                None => Format::of(&file),
+            });
+            let mut source: Box<dyn Source> = match format {
+                Format::Csv => Box::new(CsvSource::from_path(&file, &csv.options()?)?),
+                Format::Jsonl => {
+                    let fields = default_fields(&schema, &mapping.rename)?;
+                    Box::new(JsonlSource::with_paths(open(&file)?, fields))
+                }
+                Format::Parquet => Box::new(ParquetSource::from_path(&file)?),
+                Format::Bcp => {
+                    let format = match &bcp.format_file {
+                        Some(path) => FormatFile::from_path(path)?,
+                        None => FormatFile::for_table(&schema, bcp.bcp_mode),
+                    };
+                    Box::new(BcpSource::from_path(&file, &format, &schema)?)
+                }
+                Format::Fixed => {
+                    let path = fixed.layout.as_ref().ok_or_else(|| {
+                        BulkError::Config("fixed-width files need a --layout".to_owned())
+                    })?;
+                    let layout = FixedWidthLayout::from_path(path)?;
+                    Box::new(FixedWidthSource::from_path(&file, &layout)?)
+                }
+                Format::Excel => Box::new(ExcelSource::from_path(&file, &excel.options(&csv))?),
+            };
+
+            let mut loader = BulkLoader::connect(config, &table, load.options()).await?;
+            let ingested =
+                ingest(source.as_mut(), &schema, &mut loader, &mapping.options()).await?;
+            let report = loader.finish().await?;
+
+            print_report(&report, &ingested);
+            Ok(())
+        }
+        Command::Infer { .. } => unreachable!("inferred before connecting"),
+        Command::Bench {
+            table,
+            rows,
+            batch_sizes,
+            truncate,
+            seed,
+        } => bench(&config, &table, rows, &batch_sizes, truncate, seed).await,
+        Command::Export {
+            file,
+            table,
+            query,
+            format,
+            delimiter,
+            null_marker,
+            binary,
+        } => {
+            // The table name is checked before the output file is created,
+            // so that a bad name leaves no empty file behind.
+            let query = match (query, table) {
+                (Some(query), _) => query,
+                (None, Some(table)) => format!("SELECT * FROM {}", quote_object_name(&table)?),
+                (None, None) => unreachable!("clap requires a table or a query"),
+            };
+            let options = ExportOptions {
+                delimiter: ascii(delimiter, "delimiter")?,
+                null_marker,
+                binary_encoding: binary,
+            };
+
+            let (mut client, _) = connect(&config).await?;
+            let schema = TableSchema::describe(&mut client, &query).await?;
+            let output = BufWriter::new(File::create(&file)?);
+            let mut writer: Box<dyn RowWriter> =
+                match format.unwrap_or_else(|| ExportFormat::of(&file)) {
+                    ExportFormat::Csv => Box::new(CsvWriter::new(output, &schema, &options)),
+                    ExportFormat::Jsonl => Box::new(JsonlWriter::new(output, &schema, &options)),
+                    ExportFormat::Parquet => Box::new(ParquetWriter::new(output, &schema)?),
+                };
+            let started = Instant::now();
+            let rows = export(&mut client, &query, writer.as_mut()).await?;
+            client.close().await?;
+            println!(
+                "{} rows exported to {} in {:.2?}",
+                rows,
+                file.display(),
+                started.elapsed()
+            );
+            Ok(())
+        }
+        Command::Copy {
+            table,
+            query,
+            source_profile,
+            source_connection_string,
+            mapping,
+            load,
+        } => {
+            let source = match (&source_profile, &source_connection_string) {
+                (Some(profile), _) => cli.connection.profile_config(Some(profile))?,
+                (None, Some(connection_string)) => {
+                    ConnectionConfig::from_ado_string(connection_string)?
+                }
+                (None, None) => config.clone(),
+            };
+            let (report, copied) = copy_query(
+                &source,
+                &query,
+                &config,
+                &table,
+                load.options(),
+                &mapping.options(),
+                |progress| {
+                    eprintln!(
+                        "{} rows read, {} loaded in {:.2?}",
+                        progress.rows_read, progress.rows_loaded, progress.elapsed
+                    )
+                },
+            )
+            .await?;
+            print_report(&report, &copied);
+            Ok(())
+        }
+        Command::Sqlite {
+            file,
+            tables,
+            create,
+            mapping,
+            load,
+            ..
+        } => {
+            let database = SqliteDatabase::open(&file)?;
+            for table in sqlite_tables(&database, &tables)? {
+                let source_schema = database.schema(&table)?;
+                let destination = source_schema.table.clone();
+                if create {
+                    let (mut client, _) = connect(&config).await?;
+                    client
+                        .execute(source_schema.create_table_sql()?, &[])
+                        .await
+                        .in_table(&destination)?;
+                    client.close().await?;
+                }
+
+                let schema = load_schema(&config, &destination).await?;
+                let mut source = database.source(&table)?;
+                let mut loader =
+                    BulkLoader::connect(config.clone(), &destination, load.options()).await?;
+                let ingested =
+                    ingest(&mut source, &schema, &mut loader, &mapping.options()).await?;
+                let report = loader.finish().await?;
+
+                print_report(&report, &ingested);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The columns of a table holding the records of `file`, inferred from a
+/// sample of them.
+fn infer(
+    file: &Path,
+    format: Option<Format>,
+    csv: &CsvArgs,
+    fixed: &FixedArgs,
+    excel: &ExcelArgs,
+    table: &str,
+    options: &InferOptions,
+) -> Result<TableSchema> {
+    let format = format.unwrap_or_else(|| match fixed.layout {
+        Some(_) => Format::Fixed,
+        None => Format::of(file),
+    });
+    let mut source: Box<dyn Source> = match format {
+        Format::Csv => Box::new(CsvSource::from_path(file, &csv.options()?)?),
+        Format::Jsonl => {
+            let fields = sample_fields(open(file)?, options.sample_rows)?
+                .into_iter()
+                .map(|field| (field.clone(), JsonPath::key(field)))
+                .collect();
+            Box::new(JsonlSource::with_paths(open(file)?, fields))
+        }
+        Format::Parquet => Box::new(ParquetSource::from_path(file)?),
+        Format::Bcp => {
+            return Err(BulkError::Config(
+                "the columns of bcp files cannot be inferred".to_owned(),
+            ))
+        }
+        Format::Fixed => {
+            let path = fixed
+                .layout
+                .as_ref()
+                .ok_or_else(|| BulkError::Config("fixed-width files need a --layout".to_owned()))?;
+            let layout = FixedWidthLayout::from_path(path)?;
+            Box::new(FixedWidthSource::from_path(file, &layout)?)
+        }
+        Format::Excel => Box::new(ExcelSource::from_path(file, &excel.options(csv))?),
+    };
+
+    infer_schema(source.as_mut(), table, options)
+}
+
+fn print_report(report: &LoadReport, ingested: &IngestReport) {
+    for error in &ingested.rejected {
+        eprintln!("rejected: {}", redact(&error.to_string()));
+    }
+    println!(
+        "{} ({} of {} records rejected)",
+        report,
+        ingested.rejected.len(),
+        ingested.records
+    );
+}
+
+/// The tables of `database` to copy: `tables`, or every table.
+fn sqlite_tables(database: &SqliteDatabase, tables: &[String]) -> Result<Vec<String>> {
+    if tables.is_empty() {
+        database.tables()
+    } else {
+        Ok(tables.to_vec())
+    }
+}
+
+async fn load_schema(config: &ConnectionConfig, table: &str) -> Result<TableSchema> {
+    let (mut client, _) = connect(config).await?;
+    let schema = TableSchema::load(&mut client, table).await?;
+    client.close().await?;
+
+    Ok(schema)
+}
+
+async fn schema(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let schema = load_schema(config, table).await?;
+
+    let width = schema
+        .columns
+        .iter()
+        .map(|column| column.name.len())
+        .max()
+        .unwrap_or_default();
+    for column in &schema.columns {
+        let mut notes = Vec::new();
+        if !column.nullable {
+            notes.push("not null");
+        }
+        if column.identity {
+            notes.push("identity");
+        }
+        if column.computed {
+            notes.push("computed");
+        }
+        if column.is_insertable() && !column.sql_type.is_bulk_loadable() {
+            notes.push("cannot be bulk loaded");
+        }
+
+        println!(
+            "{:width$}  {:20}  {}",
+            column.name,
+            column.sql_type.to_string(),
+            notes.join(", "),
+            width = width
+        );
+    }
+
+    Ok(())
+}
+
+async fn generate(
+    config: &ConnectionConfig,
+    table: &str,
+    rows: u64,
+    seed: u64,
+    null_ratio: f64,
+    options: BulkOptions,
+) -> Result<LoadReport> {
+    let schema = load_schema(config, table).await?;
+    let mut generator = RowGenerator::new(&schema, seed)?.null_ratio(null_ratio);
+
+    let mut loader = BulkLoader::connect(config.clone(), table, options).await?;
+    for _ in 0..rows {
+        loader.send_row(generator.next_row()).await?;
+    }
+
+    loader.finish().await
+}
+
+async fn bench(
+    config: &ConnectionConfig,
+    table: &str,
+    rows: u64,
+    batch_sizes: &[usize],
+    truncate: bool,
+    seed: u64,
+) -> Result<()> {
+    for &batch_size in batch_sizes {
+        if truncate {
+            let (mut client, _) = connect(config).await?;
+            client
+                .execute(format!("TRUNCATE TABLE {}", quote_object_name(table)?), &[])
+                .await
+                .in_table(table)?;
+            client.close().await?;
+        }
+
+        let options = BulkOptions {
+            batch_size: batch_size.max(1),
+            ..Default::default()
+        };
+        let report = generate(config, table, rows, seed, 0.0, options).await?;
+        println!(
+            "batch size {:>7}: {:>10.0} rows/s  ({})",
+            batch_size,
+            rows_per_second(report.rows, report.elapsed),
+            report
+        );
+    }
+
+    Ok(())
+}
+
+fn rows_per_second(rows: u64, elapsed: Duration) -> f64 {
+    rows as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+}
@@ -0,0 +1,59 @@
+use crate::{
+    bulk::{self, BulkOptions, LoadReport},
+    connection::ConnectionConfig,
+    error::Result,
+    runtime::Runtime,
+    tds::ServerInfo,
+};
+use tiberius::TokenRow;
+
+/// A synchronous [`bulk::BulkLoader`] for code that does not run on an async
+/// runtime. It owns a runtime and blocks the calling thread on every call, so
+/// it must not be used from async code.
+pub struct BulkLoader {
+    inner: bulk::BulkLoader,
+    runtime: Runtime,
+}
+
+impl BulkLoader {
+    /// Connect to SQL Server to load rows into `table`.
+    pub fn connect(config: ConnectionConfig, table: &str, options: BulkOptions) -> Result<Self> {
+        let runtime = Runtime::new()?;
+        let inner = runtime.block_on(bulk::BulkLoader::connect(config, table, options))?;
+
+        Ok(BulkLoader { inner, runtime })
+    }
+
+    /// The server the loader is connected to.
+    pub fn server(&self) -> &ServerInfo {
+        self.inner.server()
+    }
+
+    /// The destination table.
+    pub fn table(&self) -> &str {
+        self.inner.table()
+    }
+
+    /// Number of rows committed so far.
+    pub fn rows_loaded(&self) -> u64 {
+        self.inner.rows_loaded()
+    }
+
+    /// Adds a row to the current batch and sends the batch when it is full.
+    pub fn send_row(&mut self, row: TokenRow<'static>) -> Result<()> {
+        self.runtime.block_on(self.inner.send_row(row))
+    }
+
+    /// Sends the rows of the current batch. A batch that fails with a
+    /// transient error is only sent again when
+    /// [`BulkOptions::batch_retry`]
+    /// allows it, which it does not by default.
+    pub fn flush_batch(&mut self) -> Result<()> {
+        self.runtime.block_on(self.inner.flush_batch())
+    }
+
+    /// Sends the last batch, closes the connection and reports what was loaded.
+    pub fn finish(self) -> Result<LoadReport> {
+        self.runtime.block_on(self.inner.finish())
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{
+    error::{BulkError, Result},
+    runtime::{self, UdpSocket},
+};
+use std::{io, time::Duration};
+
+/// UDP port SQL Server Browser listens on.
+pub const SQL_BROWSER_PORT: u16 = 1434;
+
+/// How long to wait for SQL Server Browser to answer by default.
+pub const DEFAULT_BROWSER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// `CLNT_UCAST_INST`: asks for the details of a single instance.
+const CLNT_UCAST_INST: u8 = 0x04;
+
+/// `SVR_RESP`: the answer of SQL Server Browser.
+const SVR_RESP: u8 = 0x05;
+
+/// Asks the SQL Server Browser service running on `host` for the TCP port of
+/// a named instance, using the SQL Server Resolution Protocol.
+pub async fn resolve_instance(
+    host: &str,
+    instance: &str,
+    browser_port: u16,
+    timeout: Duration,
+) -> Result<u16> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect((host, browser_port)).await?;
+
+    let mut request = Vec::with_capacity(instance.len() + 2);
+    request.push(CLNT_UCAST_INST);
+    request.extend_from_slice(instance.as_bytes());
+    request.push(0);
+    socket.send(&request).await?;
+
+    // The answer is limited to 1024 bytes of data plus a 3 byte header.
+    let mut buffer = [0u8; 1027];
+    let len = runtime::timeout(timeout, socket.recv(&mut buffer))
+        .await
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "SQL Server Browser on {}:{} did not answer within {:?}",
+                    host, browser_port, timeout
+                ),
+            )
+        })??;
+
+    parse_response(&buffer[..len], instance)
+}
+
+/// Reads the TCP port of `instance` from a `SVR_RESP` message, which looks
+/// like `ServerName;HOST;InstanceName;SQL2022D;IsClustered;No;Version;16.0.1000.6;tcp;22828;;`.
+pub fn parse_response(response: &[u8], instance: &str) -> Result<u16> {
+    if response.len() < 3 || response[0] != SVR_RESP {
+        return Err(BulkError::Protocol(
+            "unexpected answer from SQL Server Browser".to_owned(),
+        ));
+    }
+
+    let size = u16::from_le_bytes([response[1], response[2]]) as usize;
+    let data = &response[3..response.len().min(3 + size)];
+    let data = String::from_utf8_lossy(data);
+
+    // Several instances are separated by `;;`.
+    for description in data.split(";;").filter(|d| !d.is_empty()) {
+        let fields: Vec<&str> = description.split(';').collect();
+        let value = |key: &str| {
+            fields
+                .chunks(2)
+                .find(|pair| pair[0].eq_ignore_ascii_case(key))
+                .and_then(|pair| pair.get(1).copied())
+        };
+
+        if !value("InstanceName").is_some_and(|name| name.eq_ignore_ascii_case(instance)) {
+            continue;
+        }
+
+        return match value("tcp") {
+            Some(port) => port.parse().map_err(|_| {
+                BulkError::Protocol(format!(
+                    "SQL Server Browser sent an invalid port for instance {}: {}",
+                    instance, port
+                ))
+            }),
+            None => Err(BulkError::Config(format!(
+                "instance {} does not accept TCP connections",
+                instance
+            ))),
+        };
+    }
+
+    Err(BulkError::Config(format!(
+        "SQL Server Browser does not know instance {}",
+        instance
+    )))
+}
@@ -0,0 +1,216 @@
+use crate::{
+    connection::{connect, connect_with_retry, ConnectionConfig, SqlClient},
+    error::{Result, ResultExt},
+    retry::RetryPolicy,
+    runtime,
+    secret::redact,
+    tds::{adapt_row, ServerInfo},
+};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+use tiberius::TokenRow;
+
+/// Options of a [`BulkLoader`].
+#[derive(Clone, Debug)]
+pub struct BulkOptions {
+    /// Number of rows sent in one `INSERT BULK` statement. Every batch is
+    /// committed on its own.
+    pub batch_size: usize,
+    /// Applied to the first connection.
+    pub retry: RetryPolicy,
+    /// Applied to every batch, including the connections made to send it
+    /// again. Batches are not retried by default, see [`BulkLoader`].
+    pub batch_retry: RetryPolicy,
+}
+
+impl Default for BulkOptions {
+    fn default() -> Self {
+        BulkOptions {
+            batch_size: 10_000,
+            retry: RetryPolicy::default(),
+            batch_retry: RetryPolicy::never(),
+        }
+    }
+}
+
+/// Summary of a finished load.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    pub table: String,
+    /// Rows committed to the table.
+    pub rows: u64,
+    /// Batches committed to the table.
+    pub batches: u64,
+    /// Batches sent again after a transient error.
+    pub retries: u64,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} rows loaded into {} in {} batches ({} retries) in {:.2?}",
+            self.rows,
+            redact(&self.table),
+            self.batches,
+            self.retries,
+            self.elapsed
+        )
+    }
+}
+
+/// Loads rows into a table in batches, built around `client.bulk_insert`.
+///
+/// Rows are buffered until a batch is full and then sent in their own
+/// `INSERT BULK` statement. Because the buffered batch can be replayed, a
+/// batch that fails with a transient error (a deadlock, a dropped connection)
+/// can be sent again over a new connection according to
+/// [`BulkOptions::batch_retry`]. A connection lost after the server committed
+/// a batch but before it confirmed it would load that batch twice, so
+/// batches are only retried when that policy allows more than one attempt,
+/// which should be kept to tables with a unique key.
+pub struct BulkLoader {
+    config: ConnectionConfig,
+    client: Option<SqlClient>,
+    server: ServerInfo,
+    table: String,
+    options: BulkOptions,
+    batch: Vec<TokenRow<'static>>,
+    report: LoadReport,
+    started: Instant,
+}
+
+impl BulkLoader {
+    /// Connect to SQL Server to load rows into `table`.
+    pub async fn connect(
+        config: ConnectionConfig,
+        table: &str,
+        options: BulkOptions,
+    ) -> Result<Self> {
+        let (client, server) = connect_with_retry(&config, &options.retry).await?;
+
+        Ok(BulkLoader {
+            config,
+            client: Some(client),
+            server,
+            table: table.to_owned(),
+            batch: Vec::with_capacity(options.batch_size),
+            options,
+            report: LoadReport {
+                table: table.to_owned(),
+                ..Default::default()
+            },
+            started: Instant::now(),
+        })
+    }
+
+    /// The server the loader is connected to.
+    pub fn server(&self) -> &ServerInfo {
+        &self.server
+    }
+
+    /// The destination table.
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Number of rows committed so far.
+    pub fn rows_loaded(&self) -> u64 {
+        self.report.rows
+    }
+
+    /// Adds a row to the current batch and sends the batch when it is full.
+    pub async fn send_row(&mut self, row: TokenRow<'static>) -> Result<()> {
+        self.batch.push(adapt_row(row, &self.server));
+
+        if self.batch.len() >= self.options.batch_size {
+            self.flush_batch().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the rows of the current batch, retrying the whole batch when
+    /// it fails with a transient error and [`BulkOptions::batch_retry`]
+    /// allows it; by default it does not.
+    pub async fn flush_batch(&mut self) -> Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let mut attempt = 1;
+        loop {
+            let e = match self.send_batch().await {
+                Ok(()) => break,
+                Err(e) => e,
+            };
+
+            // The connection is in an unknown state after a failed batch, so
+            // it is never used again.
+            self.client = None;
+            if !self.options.batch_retry.should_retry(attempt, &e) {
+                return Err(e);
+            }
+            println!(
+                "Batch failed on attempt {}, retrying: {}",
+                attempt,
+                redact(&e.to_string())
+            );
+            runtime::sleep(self.options.batch_retry.backoff(attempt)).await;
+            self.report.retries += 1;
+            attempt += 1;
+        }
+
+        self.report.rows += self.batch.len() as u64;
+        self.report.batches += 1;
+        self.batch.clear();
+
+        Ok(())
+    }
+
+    /// Sends the last batch, closes the connection and reports what was loaded.
+    pub async fn finish(mut self) -> Result<LoadReport> {
+        self.flush_batch().await?;
+
+        if let Some(client) = self.client.take() {
+            client.close().await?;
+        }
+
+        self.report.elapsed = self.started.elapsed();
+        Ok(self.report)
+    }
+
+    /// Sends the batch once. A new connection is made when the last one was
+    /// dropped, as one attempt of the batch.
+    async fn send_batch(&mut self) -> Result<()> {
+        if self.client.is_none() {
+            let (client, server) = connect(&self.config).await?;
+            self.client = Some(client);
+            self.server = server;
+        }
+
+        let BulkLoader {
+            client,
+            table,
+            batch,
+            report,
+            ..
+        } = self;
+        let client = client.as_mut().expect("connected above");
+
+        let mut request = client.bulk_insert(table).await.in_table(table)?;
+        for (i, row) in batch.iter().enumerate() {
+            request
+                .send(row.clone())
+                .await
+                .in_table(table)
+                .at_row(report.rows + i as u64)?;
+        }
+        request.finalize().await.in_table(table)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use crate::error::Result;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+/// Bytes of the buffers around files and decompressors.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// A file opened for reading, decompressed on the fly if it is compressed.
+pub type InputFile = Box<dyn BufRead + Send>;
+
+/// The compression formats of input files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// The compression of data starting with `magic`, its first bytes.
+    pub fn detect(magic: &[u8]) -> Compression {
+        match magic {
+            [0x1F, 0x8B, ..] => Compression::Gzip,
+            [0x28, 0xB5, 0x2F, 0xFD, ..] => Compression::Zstd,
+            [b'B', b'Z', b'h', ..] => Compression::Bzip2,
+            [0xFD, b'7', b'z', b'X', b'Z', 0x00, ..] => Compression::Xz,
+            _ => Compression::None,
+        }
+    }
+
+    /// The compression of a file by its extension.
+    pub fn from_extension(path: &Path) -> Compression {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase);
+
+        match extension.as_deref() {
+            Some("gz" | "gzip") => Compression::Gzip,
+            Some("zst" | "zstd") => Compression::Zstd,
+            Some("bz2") => Compression::Bzip2,
+            Some("xz") => Compression::Xz,
+            _ => Compression::None,
+        }
+    }
+
+    /// The compression of a file, told by its first bytes or else by its
+    /// extension.
+    pub fn of_file(path: impl AsRef<Path>) -> Result<Compression> {
+        let path = path.as_ref();
+        let mut reader = BufReader::new(File::open(path)?);
+        Compression::of_reader(&mut reader, path)
+    }
+
+    fn of_reader(reader: &mut impl BufRead, path: &Path) -> Result<Compression> {
+        let compression = match Compression::detect(reader.fill_buf()?) {
+            Compression::None => Compression::from_extension(path),
+            compression => compression,
+        };
+        Ok(compression)
+    }
+
+    /// Decompresses `reader` while it is read.
+    pub fn decoder<'a, R: BufRead + Send + 'a>(
+        self,
+        reader: R,
+    ) -> Result<Box<dyn BufRead + Send + 'a>> {
+        let buffered = |reader: Box<dyn Read + Send + 'a>| -> Box<dyn BufRead + Send + 'a> {
+            Box::new(BufReader::with_capacity(BUFFER_SIZE, reader))
+        };
+
+        // Files may hold several compressed streams one after the other, as
+        // `cat a.gz b.gz` writes; they are read as one.
+        let reader = match self {
+            Compression::None => Box::new(reader),
+            Compression::Gzip => buffered(Box::new(flate2::bufread::MultiGzDecoder::new(reader))),
+            Compression::Zstd => {
+                buffered(Box::new(zstd::stream::read::Decoder::with_buffer(reader)?))
+            }
+            Compression::Bzip2 => buffered(Box::new(bzip2::bufread::MultiBzDecoder::new(reader))),
+            Compression::Xz => buffered(Box::new(liblzma::bufread::XzDecoder::new_multi_decoder(
+                reader,
+            ))),
+        };
+
+        Ok(reader)
+    }
+}
+
+/// Decompresses `reader` while it is read, if its first bytes are those of
+/// a compressed format.
+pub fn decompress<'a, R: BufRead + Send + 'a>(
+    mut reader: R,
+) -> Result<Box<dyn BufRead + Send + 'a>> {
+    let compression = Compression::detect(reader.fill_buf()?);
+    compression.decoder(reader)
+}
+
+/// Opens a file for reading, decompressing it on the fly when it is
+/// compressed with gzip, zstd, bzip2 or xz, so nothing is decompressed to
+/// disk. The compression is told by the first bytes of the file, or else by
+/// its extension; a `.gz` file that is no gzip fails when it is read.
+pub fn open(path: impl AsRef<Path>) -> Result<InputFile> {
+    let path = path.as_ref();
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, File::open(path)?);
+    Compression::of_reader(&mut reader, path)?.decoder(reader)
+}
+
+/// Opens a file for reading like [`open`], with the compression told by its
+/// extension only. Binary data may start with the bytes of any format, so a
+/// file without the extension of one is read as it is.
+pub fn open_by_extension(path: impl AsRef<Path>) -> Result<InputFile> {
+    let path = path.as_ref();
+    let reader = BufReader::with_capacity(BUFFER_SIZE, File::open(path)?);
+    Compression::from_extension(path).decoder(reader)
+}
+
+/// Reads the whole of a file, decompressed, for readers that need to seek.
+pub fn read_all(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    open(path)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// The path without the extension of its compression, such as `data.csv`
+/// for `data.csv.gz`, to tell the format of the file.
+pub fn uncompressed_path(path: &Path) -> PathBuf {
+    match Compression::from_extension(path) {
+        Compression::None => path.to_owned(),
+        _ => path.with_extension(""),
+    }
+}
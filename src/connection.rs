@@ -0,0 +1,253 @@
+use crate::{
+    browser::{resolve_instance, DEFAULT_BROWSER_TIMEOUT, SQL_BROWSER_PORT},
+    error::{BulkError, Result},
+    retry::RetryPolicy,
+    runtime::{connect_tcp, TcpStream},
+    secret::{redact, Secret},
+    tds::ServerInfo,
+    tls::{TlsSettings, TlsStream},
+};
+use futures_util::io::{AsyncRead, AsyncWrite};
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tiberius::{AuthMethod, Client, Config};
+
+/// A client connected through a [`SqlStream`].
+pub type SqlClient = Client<SqlStream>;
+
+/// The stream of a connection: a TCP stream of the runtime, which Tiberius
+/// encrypts if needed, or a stream the crate encrypts to validate the
+/// certificate of the server itself (see [`TlsSettings`]).
+pub enum SqlStream {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream>),
+}
+
+impl AsyncRead for SqlStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            SqlStream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            SqlStream::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for SqlStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            SqlStream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            SqlStream::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            SqlStream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            SqlStream::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            SqlStream::Tcp(stream) => Pin::new(stream).poll_close(cx),
+            SqlStream::Tls(stream) => Pin::new(stream.as_mut()).poll_close(cx),
+        }
+    }
+}
+
+/// The `server` part of a connection string: `tcp:host`, `tcp:host,port` or
+/// `tcp:host\instance`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerAddress {
+    pub host: String,
+    pub instance: Option<String>,
+    pub port: Option<u16>,
+}
+
+impl ServerAddress {
+    /// Parses the value of the `server` key of a connection string.
+    pub fn parse(server: &str) -> Result<ServerAddress> {
+        let server = server.trim();
+        let server = server
+            .strip_prefix("tcp:")
+            .or_else(|| server.strip_prefix("TCP:"))
+            .unwrap_or(server);
+
+        let (server, port) = match server.split_once(',') {
+            Some((server, port)) => {
+                let port = port
+                    .trim()
+                    .parse()
+                    .map_err(|_| BulkError::Config(format!("invalid port: {}", port)))?;
+                (server, Some(port))
+            }
+            None => (server, None),
+        };
+
+        let (host, instance) = match server.split_once('\\') {
+            Some((host, instance)) => (host, Some(instance.trim().to_owned())),
+            None => (server, None),
+        };
+
+        let host = match host.trim() {
+            "" | "." | "(local)" => "localhost".to_owned(),
+            host => host.to_owned(),
+        };
+
+        Ok(ServerAddress {
+            host,
+            instance,
+            port,
+        })
+    }
+
+    /// Finds and parses the server of an ADO.NET connection string.
+    pub fn from_ado_string(s: &str) -> Result<ServerAddress> {
+        let server = ado_value(
+            s,
+            &[
+                "server",
+                "data source",
+                "address",
+                "addr",
+                "network address",
+            ],
+        )
+        .ok_or_else(|| BulkError::Config("the connection string has no server".to_owned()))?;
+
+        ServerAddress::parse(server)
+    }
+}
+
+/// The value of the first of `keys` found in an ADO.NET connection string.
+pub(crate) fn ado_value<'a>(s: &'a str, keys: &[&str]) -> Option<&'a str> {
+    s.split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| keys.contains(&key.trim().to_lowercase().as_str()))
+        .map(|(_, value)| value.trim())
+}
+
+/// Everything the connection factory needs to open a connection.
+#[derive(Clone)]
+pub struct ConnectionConfig {
+    /// Settings passed to Tiberius.
+    pub config: Config,
+    /// The server as written in the connection string.
+    pub server: ServerAddress,
+    /// UDP port of SQL Server Browser, used for named instances without a port.
+    pub browser_port: u16,
+    /// How long to wait for SQL Server Browser to answer.
+    pub browser_timeout: Duration,
+    /// Encryption and certificate validation, already applied to `config`.
+    pub tls: TlsSettings,
+}
+
+impl ConnectionConfig {
+    /// Creates the configuration from an ADO.NET connection string.
+    pub fn from_ado_string(s: &str) -> Result<ConnectionConfig> {
+        let mut config =
+            Config::from_ado_string(s).map_err(|e| BulkError::Config(redact(&e.to_string())))?;
+        let tls = TlsSettings::from_ado_string(s)?;
+        tls.apply(&mut config)?;
+
+        Ok(ConnectionConfig {
+            config,
+            server: ServerAddress::from_ado_string(s)?,
+            browser_port: SQL_BROWSER_PORT,
+            browser_timeout: DEFAULT_BROWSER_TIMEOUT,
+            tls,
+        })
+    }
+
+    /// Creates the configuration from an ADO.NET connection string without
+    /// password and a password kept apart from it.
+    pub fn from_ado_string_with_password(s: &str, password: &Secret) -> Result<ConnectionConfig> {
+        let mut config = ConnectionConfig::from_ado_string(s)?;
+        let user = ado_value(s, &["uid", "username", "user", "user id"]).unwrap_or_default();
+        config
+            .config
+            .authentication(AuthMethod::sql_server(user, password.expose()));
+
+        Ok(config)
+    }
+
+    /// The Tiberius configuration with the port of a named instance
+    /// resolved through SQL Server Browser, when the connection string does
+    /// not give a port.
+    pub async fn resolve(&self) -> Result<Config> {
+        let mut config = self.config.clone();
+
+        if let (Some(instance), None) = (&self.server.instance, self.server.port) {
+            let port = resolve_instance(
+                &self.server.host,
+                instance,
+                self.browser_port,
+                self.browser_timeout,
+            )
+            .await?;
+            config.port(port);
+        }
+
+        Ok(config)
+    }
+}
+
+impl fmt::Debug for ConnectionConfig {
+    /// The Tiberius configuration holds the password, so it is redacted.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionConfig")
+            .field("config", &redact(&format!("{:?}", self.config)))
+            .field("server", &self.server)
+            .field("browser_port", &self.browser_port)
+            .field("browser_timeout", &self.browser_timeout)
+            .field("tls", &self.tls)
+            .finish()
+    }
+}
+
+/// Connect to SQL Server and detect the version of the server, so callers can
+/// choose encodings supported by the negotiated TDS version.
+///
+/// The connection is tried once. [`connect_with_retry`] tries again with a
+/// retry policy, as [`BulkLoader::connect`](crate::bulk::BulkLoader::connect)
+/// does with [`BulkOptions::retry`](crate::bulk::BulkOptions::retry).
+pub async fn connect(config: &ConnectionConfig) -> Result<(SqlClient, ServerInfo)> {
+    let tls = &config.tls;
+    let host = config.server.host.clone();
+    let config = config.resolve().await?;
+
+    // Create a `TCPStream` of the runtime with
+    // a address that contains the hostname/IP and port number.
+    let stream = match tls.encrypted_by_crate() {
+        true => SqlStream::Tls(Box::new(
+            TlsStream::connect(&config.get_addr(), &host, tls).await?,
+        )),
+        false => SqlStream::Tcp(connect_tcp(&config.get_addr()).await?),
+    };
+
+    let mut client = Client::connect(config, stream).await?;
+    let server = ServerInfo::detect(&mut client).await?;
+
+    Ok((client, server))
+}
+
+/// Connect to SQL Server like [`connect`], trying again with the given policy
+/// when the connection is refused, dropped or times out.
+pub async fn connect_with_retry(
+    config: &ConnectionConfig,
+    policy: &RetryPolicy,
+) -> Result<(SqlClient, ServerInfo)> {
+    policy.run(|_| connect(config)).await
+}
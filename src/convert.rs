@@ -0,0 +1,592 @@
+use crate::{
+    error::{BulkError, Result},
+    schema::SqlType,
+    tds::to_datetime,
+};
+use base64::Engine;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use std::{borrow::Cow, fmt, str::FromStr};
+use tiberius::{
+    numeric::Numeric, time::SmallDateTime, xml::XmlData, ColumnData, FromSql, IntoSql, Uuid,
+};
+
+/// Formats of `datetime`, `smalldatetime` and `datetime2` values.
+const DATETIME_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// Formats of `datetimeoffset` values besides RFC 3339.
+const DATETIMEOFFSET_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S%.f %:z", "%Y-%m-%d %H:%M:%S%.f%:z"];
+
+/// A value read from a source file, before it is converted to the type of
+/// its destination column. Decimals are read as text, which keeps all their
+/// digits.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Bytes(Vec<u8>),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    DateTime(NaiveDateTime),
+    DateTimeOffset(DateTime<FixedOffset>),
+    Guid(Uuid),
+}
+
+impl fmt::Display for Value {
+    /// Writes the value as text; bytes are written as hex with a `0x` prefix.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Text(value) => write!(f, "{}", value),
+            Value::Bytes(bytes) => {
+                write!(f, "0x")?;
+                bytes.iter().try_for_each(|b| write!(f, "{:02X}", b))
+            }
+            Value::Date(value) => write!(f, "{}", value),
+            Value::Time(value) => write!(f, "{}", value),
+            Value::DateTime(value) => write!(f, "{}", value),
+            Value::DateTimeOffset(value) => write!(f, "{}", value),
+            Value::Guid(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// How binary values are written in text files. Values starting with `0x`
+/// are always read as hex.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BinaryEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+impl FromStr for BinaryEncoding {
+    type Err = BulkError;
+
+    fn from_str(s: &str) -> Result<BinaryEncoding> {
+        match s.to_lowercase().as_str() {
+            "hex" => Ok(BinaryEncoding::Hex),
+            "base64" => Ok(BinaryEncoding::Base64),
+            _ => Err(BulkError::Config(format!("unknown binary encoding: {}", s))),
+        }
+    }
+}
+
+/// Converts a value to the variant a bulk load expects for `sql_type`.
+/// Text is parsed, numbers are checked against the range of the type and
+/// decimals are rounded to the scale of the column. A `datetimeoffset` value
+/// stored in a type without offset keeps its local time, as in SQL Server.
+pub fn convert(
+    value: Value,
+    sql_type: &SqlType,
+    binary: BinaryEncoding,
+) -> Result<ColumnData<'static>> {
+    match (value, sql_type) {
+        (Value::Null, _) => Ok(sql_type.null()),
+        (Value::Bytes(bytes), SqlType::Binary(_) | SqlType::VarBinary(_) | SqlType::Image) => {
+            binary_value(bytes, sql_type)
+        }
+        (Value::Bytes(bytes), SqlType::UniqueIdentifier) if bytes.len() == 16 => {
+            Ok(ColumnData::Guid(Uuid::from_slice(&bytes).ok()))
+        }
+        (Value::Bytes(_), _) => Err(BulkError::conversion(format!(
+            "binary values cannot be stored in {}",
+            sql_type
+        ))),
+        (Value::Float(value), _) if !value.is_finite() => Err(BulkError::conversion(format!(
+            "`{}` cannot be stored in {}",
+            value, sql_type
+        ))),
+        (Value::Date(value), SqlType::Date) => Ok(value.into_sql()),
+        (
+            Value::Date(value),
+            SqlType::DateTime | SqlType::SmallDateTime | SqlType::DateTime2(_),
+        ) => convert(
+            Value::DateTime(value.and_time(NaiveTime::MIN)),
+            sql_type,
+            binary,
+        ),
+        (Value::Time(value), SqlType::Time(_)) => Ok(value.into_sql()),
+        (Value::DateTime(value), SqlType::DateTime2(_)) => Ok(value.into_sql()),
+        (Value::DateTime(value), SqlType::DateTime | SqlType::SmallDateTime) => {
+            datetime_value(value, sql_type).ok_or_else(|| {
+                BulkError::conversion(format!("`{}` is out of range for {}", value, sql_type))
+            })
+        }
+        // As a cast to `date`, the time of day is dropped.
+        (Value::DateTime(value), SqlType::Date) => Ok(value.date().into_sql()),
+        (Value::DateTimeOffset(value), SqlType::DateTimeOffset(_)) => Ok(value.into_sql()),
+        (
+            Value::DateTimeOffset(value),
+            SqlType::Date | SqlType::DateTime | SqlType::SmallDateTime | SqlType::DateTime2(_),
+        ) => convert(Value::DateTime(value.naive_local()), sql_type, binary),
+        (Value::Guid(value), SqlType::UniqueIdentifier) => Ok(ColumnData::Guid(Some(value))),
+        (Value::Bool(value), SqlType::Bit) => Ok(ColumnData::Bit(Some(value))),
+        (
+            Value::Int(value),
+            SqlType::TinyInt | SqlType::SmallInt | SqlType::Int | SqlType::BigInt,
+        ) => int_value(value, sql_type),
+        (Value::Int(value @ (0 | 1)), SqlType::Bit) => Ok(ColumnData::Bit(Some(value == 1))),
+        (Value::Int(value), SqlType::Real) => Ok(ColumnData::F32(Some(value as f32))),
+        (Value::Int(value), SqlType::Float | SqlType::Money | SqlType::SmallMoney) => {
+            Ok(ColumnData::F64(Some(value as f64)))
+        }
+        (Value::Int(value), SqlType::Decimal { .. }) => decimal_value(value.into(), 0, sql_type),
+        (Value::Float(value), SqlType::Float | SqlType::Money | SqlType::SmallMoney) => {
+            Ok(ColumnData::F64(Some(value)))
+        }
+        (Value::Float(value), SqlType::Real) => match value as f32 {
+            real if real.is_finite() => Ok(ColumnData::F32(Some(real))),
+            _ => Err(BulkError::conversion(format!(
+                "`{}` is out of range for {}",
+                value, sql_type
+            ))),
+        },
+        // Text is moved into string columns rather than copied.
+        (Value::Text(text), SqlType::Char(len) | SqlType::NChar(len)) => {
+            string_value(text, Some(*len), sql_type)
+        }
+        (Value::Text(text), SqlType::VarChar(len) | SqlType::NVarChar(len)) => {
+            string_value(text, *len, sql_type)
+        }
+        (Value::Text(text), SqlType::Text | SqlType::NText) => string_value(text, None, sql_type),
+        (Value::Text(text), _) => parse(&text, sql_type, binary),
+        (value, _) => parse(&value.to_string(), sql_type, binary),
+    }
+}
+
+/// Parses the text of a value of `sql_type`.
+pub fn parse(
+    text: &str,
+    sql_type: &SqlType,
+    binary: BinaryEncoding,
+) -> Result<ColumnData<'static>> {
+    let trimmed = text.trim();
+    let invalid = || BulkError::conversion(format!("`{}` is not a valid {}", text, sql_type));
+    let out_of_range =
+        || BulkError::conversion(format!("`{}` is out of range for {}", text, sql_type));
+
+    let value = match sql_type {
+        SqlType::Bit => match trimmed.to_lowercase().as_str() {
+            "1" | "true" | "t" | "yes" | "y" => ColumnData::Bit(Some(true)),
+            "0" | "false" | "f" | "no" | "n" => ColumnData::Bit(Some(false)),
+            _ => return Err(invalid()),
+        },
+        SqlType::TinyInt => ColumnData::U8(Some(parse_int(trimmed, invalid, out_of_range)?)),
+        SqlType::SmallInt => ColumnData::I16(Some(parse_int(trimmed, invalid, out_of_range)?)),
+        SqlType::Int => ColumnData::I32(Some(parse_int(trimmed, invalid, out_of_range)?)),
+        SqlType::BigInt => ColumnData::I64(Some(parse_int(trimmed, invalid, out_of_range)?)),
+        SqlType::Real => {
+            let value: f32 = trimmed.parse().map_err(|_| invalid())?;
+            if !value.is_finite() {
+                return Err(out_of_range());
+            }
+            ColumnData::F32(Some(value))
+        }
+        SqlType::Float | SqlType::Money | SqlType::SmallMoney => {
+            let value: f64 = trimmed.parse().map_err(|_| invalid())?;
+            if !value.is_finite() {
+                return Err(out_of_range());
+            }
+            ColumnData::F64(Some(value))
+        }
+        SqlType::Decimal { precision, scale } => {
+            let value = parse_decimal(trimmed, *scale).ok_or_else(invalid)?;
+            numeric(value, *precision, *scale).ok_or_else(out_of_range)?
+        }
+        SqlType::Char(len) | SqlType::NChar(len) => {
+            string_value(text.to_owned(), Some(*len), sql_type)?
+        }
+        SqlType::VarChar(len) | SqlType::NVarChar(len) => {
+            string_value(text.to_owned(), *len, sql_type)?
+        }
+        SqlType::Text | SqlType::NText | SqlType::Other(_) => {
+            string_value(text.to_owned(), None, sql_type)?
+        }
+        SqlType::Binary(_) | SqlType::VarBinary(_) | SqlType::Image | SqlType::RowVersion => {
+            binary_value(
+                decode_binary(trimmed, binary).ok_or_else(invalid)?,
+                sql_type,
+            )?
+        }
+        SqlType::UniqueIdentifier => {
+            ColumnData::Guid(Some(Uuid::parse_str(trimmed).map_err(|_| invalid())?))
+        }
+        SqlType::Date => NaiveDate::parse_from_str(trimmed, "%Y-%m-%d")
+            .map_err(|_| invalid())?
+            .into_sql(),
+        SqlType::Time(_) => ["%H:%M:%S%.f", "%H:%M"]
+            .iter()
+            .find_map(|format| NaiveTime::parse_from_str(trimmed, format).ok())
+            .ok_or_else(invalid)?
+            .into_sql(),
+        SqlType::DateTime | SqlType::SmallDateTime => {
+            datetime_value(parse_datetime(trimmed).ok_or_else(invalid)?, sql_type)
+                .ok_or_else(out_of_range)?
+        }
+        SqlType::DateTime2(_) => parse_datetime(trimmed).ok_or_else(invalid)?.into_sql(),
+        SqlType::DateTimeOffset(_) => DateTime::parse_from_rfc3339(trimmed)
+            .ok()
+            .or_else(|| {
+                DATETIMEOFFSET_FORMATS
+                    .iter()
+                    .find_map(|format| DateTime::parse_from_str(trimmed, format).ok())
+            })
+            .ok_or_else(invalid)?
+            .into_sql(),
+        SqlType::Xml => ColumnData::Xml(Some(Cow::Owned(XmlData::new(text)))),
+    };
+
+    Ok(value)
+}
+
+/// Converts a value read from SQL Server to the variant a bulk load expects
+/// for `sql_type`, as [`convert`] does. Numbers, dates and times are
+/// converted without going through text, except for decimals stored in
+/// columns of other types; `datetime` and `smalldatetime` values copied into
+/// columns of their own type are kept as they are.
+pub fn convert_column(
+    value: ColumnData<'static>,
+    sql_type: &SqlType,
+) -> Result<ColumnData<'static>> {
+    match (value, sql_type) {
+        (ColumnData::Numeric(Some(value)), SqlType::Decimal { .. }) => {
+            decimal_value(value.value(), value.scale() as i8, sql_type)
+        }
+        (value @ ColumnData::DateTime(Some(_)), SqlType::DateTime)
+        | (value @ ColumnData::SmallDateTime(Some(_)), SqlType::SmallDateTime)
+        | (value @ ColumnData::Xml(Some(_)), SqlType::Xml) => Ok(value),
+        (value, _) => convert(column_value(value), sql_type, BinaryEncoding::Hex),
+    }
+}
+
+/// The value of a column read from SQL Server. Decimals and XML are read as
+/// their text.
+pub fn column_value(value: ColumnData<'static>) -> Value {
+    let value = match value {
+        ColumnData::Bit(value) => value.map(Value::Bool),
+        ColumnData::U8(value) => value.map(|value| Value::Int(value.into())),
+        ColumnData::I16(value) => value.map(|value| Value::Int(value.into())),
+        ColumnData::I32(value) => value.map(|value| Value::Int(value.into())),
+        ColumnData::I64(value) => value.map(Value::Int),
+        ColumnData::F32(value) => value.map(|value| Value::Float(value.into())),
+        ColumnData::F64(value) => value.map(Value::Float),
+        ColumnData::String(value) => value.map(|text| Value::Text(text.into_owned())),
+        ColumnData::Guid(value) => value.map(Value::Guid),
+        ColumnData::Binary(value) => value.map(|bytes| Value::Bytes(bytes.into_owned())),
+        ColumnData::Date(_) => NaiveDate::from_sql(&value).ok().flatten().map(Value::Date),
+        ColumnData::Time(_) => NaiveTime::from_sql(&value).ok().flatten().map(Value::Time),
+        ColumnData::DateTime(_) | ColumnData::SmallDateTime(_) | ColumnData::DateTime2(_) => {
+            NaiveDateTime::from_sql(&value)
+                .ok()
+                .flatten()
+                .map(Value::DateTime)
+        }
+        ColumnData::DateTimeOffset(_) => DateTime::<FixedOffset>::from_sql(&value)
+            .ok()
+            .flatten()
+            .map(Value::DateTimeOffset),
+        ColumnData::Numeric(_) | ColumnData::Xml(_) => column_text(&value).map(Value::Text),
+    };
+
+    value.unwrap_or(Value::Null)
+}
+
+/// A decimal with the unscaled `value` at `scale`, rounded to the scale of
+/// the `decimal` type `sql_type`, or to the nearest `float` or `real`.
+pub fn decimal_value(value: i128, scale: i8, sql_type: &SqlType) -> Result<ColumnData<'static>> {
+    let text = || match u8::try_from(scale) {
+        Ok(scale) => decimal_text(value, scale),
+        Err(_) => format!("{}e{}", value, -i32::from(scale)),
+    };
+    let out_of_range =
+        || BulkError::conversion(format!("`{}` is out of range for {}", text(), sql_type));
+
+    // Both the unscaled value and the power of ten are exact as floats in
+    // these ranges, so the quotient is rounded once, like the parsed text.
+    match sql_type {
+        SqlType::Decimal {
+            precision,
+            scale: to,
+        } => numeric(rescale(value, scale, *to), *precision, *to).ok_or_else(out_of_range),
+        SqlType::Float if (0..=22).contains(&scale) && value.unsigned_abs() <= 1 << 53 => Ok(
+            ColumnData::F64(Some(value as f64 / 10f64.powi(scale.into()))),
+        ),
+        SqlType::Real if (0..=10).contains(&scale) && value.unsigned_abs() <= 1 << 24 => Ok(
+            ColumnData::F32(Some(value as f32 / 10f32.powi(scale.into()))),
+        ),
+        SqlType::Float | SqlType::Real => parse(&text(), sql_type, BinaryEncoding::Hex),
+        _ => Err(BulkError::conversion(format!(
+            "decimals cannot be stored in {}",
+            sql_type
+        ))),
+    }
+}
+
+/// The unscaled value `value` at `from` moved to the scale `to`, rounded
+/// half away from zero. `None` if it does not fit in an `i128`.
+fn rescale(value: i128, from: i8, to: u8) -> Option<i128> {
+    let shift = i32::from(to) - i32::from(from);
+    if shift >= 0 {
+        return 10i128.checked_pow(shift as u32)?.checked_mul(value);
+    }
+
+    let divisor = match 10i128.checked_pow(shift.unsigned_abs()) {
+        Some(divisor) => divisor,
+        None => return Some(0),
+    };
+    let (quotient, remainder) = (value / divisor, value % divisor);
+    match remainder.unsigned_abs() * 2 >= divisor.unsigned_abs() {
+        true => Some(quotient + value.signum()),
+        false => Some(quotient),
+    }
+}
+
+/// A `numeric` of `precision` digits, or `None` if the unscaled value has
+/// more digits.
+fn numeric(value: Option<i128>, precision: u8, scale: u8) -> Option<ColumnData<'static>> {
+    let max = 10i128
+        .checked_pow(u32::from(precision))
+        .unwrap_or(i128::MAX);
+
+    value
+        .filter(|value| value.unsigned_abs() < max.unsigned_abs())
+        .map(|value| ColumnData::Numeric(Some(Numeric::new_with_scale(value, scale))))
+}
+
+fn int_value(value: i64, sql_type: &SqlType) -> Result<ColumnData<'static>> {
+    let out_of_range =
+        || BulkError::conversion(format!("`{}` is out of range for {}", value, sql_type));
+
+    let value = match sql_type {
+        SqlType::TinyInt => ColumnData::U8(Some(value.try_into().map_err(|_| out_of_range())?)),
+        SqlType::SmallInt => ColumnData::I16(Some(value.try_into().map_err(|_| out_of_range())?)),
+        SqlType::Int => ColumnData::I32(Some(value.try_into().map_err(|_| out_of_range())?)),
+        _ => ColumnData::I64(Some(value)),
+    };
+
+    Ok(value)
+}
+
+fn parse_int<T: FromStr + TryFrom<i128>>(
+    text: &str,
+    invalid: impl Fn() -> BulkError,
+    out_of_range: impl Fn() -> BulkError,
+) -> Result<T> {
+    if let Ok(value) = text.parse() {
+        return Ok(value);
+    }
+
+    // Tell values that are too large apart from values that are no number.
+    let value: i128 = text.parse().map_err(|_| invalid())?;
+    T::try_from(value).map_err(|_| out_of_range())
+}
+
+/// The unscaled value of a decimal number at `scale`, rounded half away from
+/// zero like SQL Server does. `None` if the text is no number, `Some(None)`
+/// if it does not fit in 38 digits.
+fn parse_decimal(text: &str, scale: u8) -> Option<Option<i128>> {
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().ok()?),
+        None => (unsigned, 0),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+    let digits: Vec<u8> = integer
+        .bytes()
+        .chain(fraction.bytes())
+        .map(|b| b.is_ascii_digit().then(|| b - b'0'))
+        .collect::<Option<_>>()?;
+    if digits.is_empty() {
+        return None;
+    }
+
+    let digits = match digits.iter().position(|d| *d != 0) {
+        Some(first) => &digits[first..],
+        None => return Some(Some(0)),
+    };
+
+    // Digits to move left of the decimal point of the unscaled value.
+    let shift = i64::from(exponent) - fraction.len() as i64 + i64::from(scale);
+    let kept = digits.len() as i64 + shift;
+    if kept > 38 {
+        return Some(None);
+    }
+
+    let (kept_digits, round_up) = if shift >= 0 {
+        (digits, false)
+    } else if kept <= 0 {
+        (&digits[..0], kept == 0 && digits[0] >= 5)
+    } else {
+        let kept = kept as usize;
+        (&digits[..kept], digits[kept] >= 5)
+    };
+
+    let mut value = kept_digits
+        .iter()
+        .fold(0i128, |value, d| value * 10 + i128::from(*d));
+    if shift > 0 {
+        value *= 10i128.pow(shift as u32);
+    }
+    if round_up {
+        value += 1;
+    }
+
+    Some(Some(if negative { -value } else { value }))
+}
+
+fn parse_datetime(text: &str) -> Option<NaiveDateTime> {
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+            Some(date.and_time(NaiveTime::MIN))
+        })
+}
+
+/// A `datetime` or `smalldatetime` of `sql_type`, or `None` outside of the
+/// range of the type.
+fn datetime_value(value: NaiveDateTime, sql_type: &SqlType) -> Option<ColumnData<'static>> {
+    match sql_type {
+        SqlType::SmallDateTime => Some(ColumnData::SmallDateTime(Some(small_datetime(value)?))),
+        _ if value.date() < NaiveDate::from_ymd_opt(1753, 1, 1).unwrap() => None,
+        _ => Some(to_datetime(value.into_sql())),
+    }
+}
+
+/// A `smalldatetime`, rounded to the minute, or `None` outside of
+/// 1900-01-01 to 2079-06-06.
+fn small_datetime(value: NaiveDateTime) -> Option<SmallDateTime> {
+    let start = NaiveDate::from_ymd_opt(1900, 1, 1).unwrap();
+    let seconds = value.time().num_seconds_from_midnight();
+    let rounded = (seconds + 30) / 60;
+    let days = (value.date() - start).num_days() + i64::from(rounded / (24 * 60));
+
+    Some(SmallDateTime::new(
+        u16::try_from(days).ok()?,
+        (rounded % (24 * 60)) as u16,
+    ))
+}
+
+fn string_value(text: String, len: Option<u16>, sql_type: &SqlType) -> Result<ColumnData<'static>> {
+    if let Some(len) = len {
+        let chars = text.chars().count();
+        if chars > len as usize {
+            return Err(BulkError::conversion(format!(
+                "a value of {} characters does not fit in {}",
+                chars, sql_type
+            )));
+        }
+    }
+
+    Ok(ColumnData::String(Some(Cow::Owned(text))))
+}
+
+fn binary_value(bytes: Vec<u8>, sql_type: &SqlType) -> Result<ColumnData<'static>> {
+    let len = match sql_type {
+        SqlType::Binary(len) => Some(*len),
+        SqlType::VarBinary(len) => *len,
+        _ => None,
+    };
+    if let Some(len) = len {
+        if bytes.len() > len as usize {
+            return Err(BulkError::conversion(format!(
+                "a value of {} bytes does not fit in {}",
+                bytes.len(),
+                sql_type
+            )));
+        }
+    }
+
+    Ok(ColumnData::Binary(Some(Cow::Owned(bytes))))
+}
+
+/// Bytes written as hex, with or without a `0x` prefix, or as base64.
+fn decode_binary(text: &str, encoding: BinaryEncoding) -> Option<Vec<u8>> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return decode_hex(hex);
+    }
+
+    match encoding {
+        BinaryEncoding::Hex => decode_hex(text),
+        BinaryEncoding::Base64 => base64::engine::general_purpose::STANDARD.decode(text).ok(),
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// The text of a value as SQL Server converts it to a string, or `None` for
+/// NULL. Bits are written as `1` and `0` and bytes as hex without prefix.
+pub fn column_text(value: &ColumnData<'static>) -> Option<String> {
+    match value {
+        ColumnData::Bit(value) => value.map(|value| u8::from(value).to_string()),
+        ColumnData::U8(value) => value.map(|value| value.to_string()),
+        ColumnData::I16(value) => value.map(|value| value.to_string()),
+        ColumnData::I32(value) => value.map(|value| value.to_string()),
+        ColumnData::I64(value) => value.map(|value| value.to_string()),
+        ColumnData::F32(value) => value.map(|value| value.to_string()),
+        ColumnData::F64(value) => value.map(|value| value.to_string()),
+        ColumnData::Numeric(value) => value.map(|value| decimal_text(value.value(), value.scale())),
+        ColumnData::String(value) => value.as_ref().map(|value| value.to_string()),
+        ColumnData::Guid(value) => value.map(|value| value.to_string().to_uppercase()),
+        ColumnData::Binary(value) => value
+            .as_ref()
+            .map(|bytes| bytes.iter().map(|b| format!("{:02X}", b)).collect()),
+        ColumnData::Xml(value) => value.as_ref().map(|xml| xml.to_string()),
+        ColumnData::Date(_) => NaiveDate::from_sql(value)
+            .ok()?
+            .map(|date| date.to_string()),
+        ColumnData::Time(_) => NaiveTime::from_sql(value)
+            .ok()?
+            .map(|time| time.format("%H:%M:%S%.f").to_string()),
+        ColumnData::DateTime(_) | ColumnData::SmallDateTime(_) | ColumnData::DateTime2(_) => {
+            NaiveDateTime::from_sql(value)
+                .ok()?
+                .map(|value| value.format("%Y-%m-%d %H:%M:%S%.f").to_string())
+        }
+        ColumnData::DateTimeOffset(_) => DateTime::<FixedOffset>::from_sql(value)
+            .ok()?
+            .map(|value| value.format("%Y-%m-%d %H:%M:%S%.f %:z").to_string()),
+    }
+}
+
+/// The unscaled decimal `value` at `scale` as text, such as `-0.50`.
+pub fn decimal_text(value: i128, scale: u8) -> String {
+    let digits = value.unsigned_abs().to_string();
+    let scale = scale as usize;
+    let digits = match digits.len() <= scale {
+        true => format!("{}{}", "0".repeat(scale + 1 - digits.len()), digits),
+        false => digits,
+    };
+    let (integer, fraction) = digits.split_at(digits.len() - scale);
+    let sign = if value < 0 { "-" } else { "" };
+
+    match fraction.is_empty() {
+        true => format!("{}{}", sign, integer),
+        false => format!("{}{}.{}", sign, integer, fraction),
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{
+    bulk::{BulkLoader, BulkOptions, LoadReport},
+    connection::{connect, ConnectionConfig, SqlClient},
+    error::{BulkError, Result, ResultExt},
+    schema::TableSchema,
+    source::{ColumnMap, IngestOptions, IngestReport},
+};
+use futures_util::TryStreamExt;
+use std::time::{Duration, Instant};
+use tiberius::{ColumnData, QueryItem};
+
+/// How far a copy has come, reported after every committed batch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CopyProgress {
+    /// Rows read from the source, including rejected ones.
+    pub rows_read: u64,
+    /// Rows committed to the destination table.
+    pub rows_loaded: u64,
+    pub elapsed: Duration,
+}
+
+/// Runs `query` on `source` and loads the rows it returns into the table of
+/// `schema` with `loader`, which is left open for the caller to finish.
+///
+/// The columns of the result are matched with the columns of the table by
+/// name, as the fields of a file are (see [`ColumnMap`]), so the query can
+/// rename them with `AS`. Values are converted to the types of their columns
+/// without going through text (see [`crate::convert::convert_column`]), and
+/// rows are sent while the query runs, so the result never has to fit in
+/// memory. Rows that cannot be converted are rejected like the records of a
+/// file, up to `max_errors`. Only a query with one result set can be copied;
+/// the load fails when a second one arrives, as its columns are not those of
+/// the first.
+pub async fn copy(
+    source: &mut SqlClient,
+    query: &str,
+    schema: &TableSchema,
+    loader: &mut BulkLoader,
+    options: &IngestOptions,
+    mut progress: impl FnMut(&CopyProgress),
+) -> Result<IngestReport> {
+    let started = Instant::now();
+    let mut stream = source.simple_query(query).await?;
+    let fields: Vec<String> = match stream.columns().await? {
+        Some(columns) => columns.iter().map(|c| c.name().to_owned()).collect(),
+        None => {
+            return Err(BulkError::Config(format!(
+                "the query returns no rows: {}",
+                query
+            )))
+        }
+    };
+    let columns = ColumnMap::new(schema, Some(&fields), options)?;
+    let mut report = IngestReport::default();
+
+    while let Some(item) = stream.try_next().await? {
+        let values: Vec<ColumnData<'static>> = match item {
+            QueryItem::Row(row) => row.into_iter().collect(),
+            QueryItem::Metadata(metadata) if metadata.result_index() == 0 => continue,
+            QueryItem::Metadata(_) => {
+                return Err(BulkError::Config(format!(
+                    "the query returns more than one result set: {}",
+                    query
+                )))
+            }
+        };
+        let record = report.records;
+        report.records += 1;
+
+        match columns
+            .column_row(values)
+            .in_table(&schema.table)
+            .at_row(record)
+        {
+            Ok(row) => {
+                let loaded = loader.rows_loaded();
+                loader.send_row(row).await?;
+                if loader.rows_loaded() != loaded {
+                    progress(&CopyProgress {
+                        rows_read: report.records,
+                        rows_loaded: loader.rows_loaded(),
+                        elapsed: started.elapsed(),
+                    });
+                }
+            }
+            Err(e @ BulkError::Conversion { .. }) => {
+                if report.rejected.len() as u64 >= options.max_errors {
+                    return Err(e);
+                }
+                report.rejected.push(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Copies the rows `query` returns on the server of `source` into `table`
+/// on the server of `destination`, which may be the same server, and
+/// reports the load and the rejected rows.
+pub async fn copy_query(
+    source: &ConnectionConfig,
+    query: &str,
+    destination: &ConnectionConfig,
+    table: &str,
+    options: BulkOptions,
+    ingest: &IngestOptions,
+    progress: impl FnMut(&CopyProgress),
+) -> Result<(LoadReport, IngestReport)> {
+    let (mut client, _) = connect(destination).await?;
+    let schema = TableSchema::load(&mut client, table).await?;
+    client.close().await?;
+
+    let (mut client, _) = connect(source).await?;
+    let mut loader = BulkLoader::connect(destination.clone(), table, options).await?;
+    let copied = copy(&mut client, query, &schema, &mut loader, ingest, progress).await?;
+    let report = loader.finish().await?;
+    client.close().await?;
+
+    Ok((report, copied))
+}
@@ -0,0 +1,308 @@
+use crate::secret::redact;
+use std::{fmt, io};
+use thiserror::Error;
+
+/// A specialized `Result` type for bulk operations.
+pub type Result<T, E = BulkError> = std::result::Result<T, E>;
+
+/// Server errors that usually go away when the operation is tried again:
+/// lock request timeouts, resource limits and the transient errors of
+/// Azure SQL Database.
+const TRANSIENT_SERVER_ERRORS: &[u32] = &[
+    1222, 4221, 10928, 10929, 40197, 40501, 40613, 49918, 49919, 49920,
+];
+
+/// Where in a load an error happened, as far as it is known.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// Destination table.
+    pub table: Option<String>,
+    /// Zero-based position of the row in the load.
+    pub row: Option<u64>,
+    /// Destination column.
+    pub column: Option<String>,
+}
+
+impl ErrorContext {
+    pub fn is_empty(&self) -> bool {
+        self.table.is_none() && self.row.is_none() && self.column.is_none()
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    /// Writes the context as ` (table `t`, row 3, column `c`)`, or nothing
+    /// when no context is known.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let mut parts = Vec::new();
+        if let Some(table) = &self.table {
+            parts.push(format!("table `{}`", table));
+        }
+        if let Some(row) = self.row {
+            parts.push(format!("row {}", row));
+        }
+        if let Some(column) = &self.column {
+            parts.push(format!("column `{}`", column));
+        }
+
+        write!(f, " ({})", parts.join(", "))
+    }
+}
+
+/// Errors returned by the functions of this crate.
+#[derive(Debug, Error)]
+pub enum BulkError {
+    /// A primary key or unique index rejected a duplicated key
+    /// (errors 2627 and 2601).
+    #[error("duplicate key{context}: {message}")]
+    DuplicateKey {
+        number: u32,
+        message: String,
+        context: ErrorContext,
+    },
+    /// String or binary data would be truncated (errors 8152 and 2628).
+    #[error("data would be truncated{context}: {message}")]
+    Truncation {
+        number: u32,
+        message: String,
+        context: ErrorContext,
+    },
+    /// The session was chosen as the victim of a deadlock (error 1205).
+    #[error("deadlock{context}: {message}")]
+    Deadlock {
+        message: String,
+        context: ErrorContext,
+    },
+    /// The server rejected the credentials (error 18456).
+    #[error("login failed: {message}")]
+    LoginFailed { message: String },
+    /// Any other error reported by the server.
+    #[error("server error {number}, state {state}, class {class}{context}: {message}")]
+    Server {
+        number: u32,
+        state: u8,
+        class: u8,
+        message: String,
+        context: ErrorContext,
+    },
+    /// Reading from or writing to the network or a file failed.
+    #[error("I/O error{context}: {source}")]
+    Io {
+        #[source]
+        source: io::Error,
+        context: ErrorContext,
+    },
+    /// The TLS handshake with the server failed.
+    #[error("TLS error: {0}")]
+    Tls(String),
+    /// The connection settings are invalid.
+    #[error("invalid configuration: {0}")]
+    Config(String),
+    /// A value could not be converted to or from its SQL Server type.
+    #[error("conversion error{context}: {message}")]
+    Conversion {
+        message: String,
+        context: ErrorContext,
+    },
+    /// The server sent something the driver did not expect.
+    #[error("protocol error: {0}")]
+    Protocol(String),
+    /// Rows read back from a table differ from the rows loaded into it.
+    #[error("values differ from those loaded{context}: {message}")]
+    Mismatch {
+        message: String,
+        context: ErrorContext,
+    },
+}
+
+impl BulkError {
+    /// Classifies an error reported by the server by its number.
+    pub fn from_server(number: u32, state: u8, class: u8, message: impl Into<String>) -> Self {
+        let message = redact(&message.into());
+        let context = ErrorContext::default();
+
+        match number {
+            2601 | 2627 => BulkError::DuplicateKey {
+                number,
+                message,
+                context,
+            },
+            2628 | 8152 => BulkError::Truncation {
+                number,
+                message,
+                context,
+            },
+            1205 => BulkError::Deadlock { message, context },
+            18456 => BulkError::LoginFailed { message },
+            _ => BulkError::Server {
+                number,
+                state,
+                class,
+                message,
+                context,
+            },
+        }
+    }
+
+    pub fn conversion(message: impl Into<String>) -> Self {
+        BulkError::Conversion {
+            message: redact(&message.into()),
+            context: ErrorContext::default(),
+        }
+    }
+
+    pub fn mismatch(message: impl Into<String>) -> Self {
+        BulkError::Mismatch {
+            message: redact(&message.into()),
+            context: ErrorContext::default(),
+        }
+    }
+
+    /// The SQL Server error number, if the error comes from the server.
+    pub fn number(&self) -> Option<u32> {
+        match self {
+            BulkError::DuplicateKey { number, .. }
+            | BulkError::Truncation { number, .. }
+            | BulkError::Server { number, .. } => Some(*number),
+            BulkError::Deadlock { .. } => Some(1205),
+            BulkError::LoginFailed { .. } => Some(18456),
+            _ => None,
+        }
+    }
+
+    /// True, if trying the same operation again may succeed: deadlocks,
+    /// transient server errors and dropped or refused connections.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BulkError::Deadlock { .. } => true,
+            BulkError::Server { number, .. } => TRANSIENT_SERVER_ERRORS.contains(number),
+            BulkError::Io { source, .. } => matches!(
+                source.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::UnexpectedEof
+                    | io::ErrorKind::Interrupted
+            ),
+            _ => false,
+        }
+    }
+
+    /// Where the error happened, for the errors that can carry a context.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            BulkError::DuplicateKey { context, .. }
+            | BulkError::Truncation { context, .. }
+            | BulkError::Deadlock { context, .. }
+            | BulkError::Server { context, .. }
+            | BulkError::Io { context, .. }
+            | BulkError::Conversion { context, .. }
+            | BulkError::Mismatch { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    fn context_mut(&mut self) -> Option<&mut ErrorContext> {
+        match self {
+            BulkError::DuplicateKey { context, .. }
+            | BulkError::Truncation { context, .. }
+            | BulkError::Deadlock { context, .. }
+            | BulkError::Server { context, .. }
+            | BulkError::Io { context, .. }
+            | BulkError::Conversion { context, .. }
+            | BulkError::Mismatch { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Records the destination table, unless a table is already known.
+    pub fn with_table(mut self, table: &str) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.table.get_or_insert_with(|| table.to_owned());
+        }
+        self
+    }
+
+    /// Records the position of the row, unless a row is already known.
+    pub fn with_row(mut self, row: u64) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.row.get_or_insert(row);
+        }
+        self
+    }
+
+    /// Records the destination column, unless a column is already known.
+    pub fn with_column(mut self, column: &str) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.column.get_or_insert_with(|| column.to_owned());
+        }
+        self
+    }
+}
+
+impl From<io::Error> for BulkError {
+    fn from(source: io::Error) -> Self {
+        BulkError::Io {
+            source,
+            context: ErrorContext::default(),
+        }
+    }
+}
+
+impl From<tiberius::error::Error> for BulkError {
+    fn from(error: tiberius::error::Error) -> Self {
+        use tiberius::error::Error;
+
+        match error {
+            Error::Server(token) => {
+                BulkError::from_server(token.code(), token.state(), token.class(), token.message())
+            }
+            Error::Io { kind, message } => io::Error::new(kind, redact(&message)).into(),
+            Error::Tls(message) => BulkError::Tls(redact(&message)),
+            Error::Conversion(message) | Error::Encoding(message) | Error::BulkInput(message) => {
+                BulkError::conversion(message)
+            }
+            Error::Utf8 | Error::Utf16 | Error::ParseInt(_) => {
+                BulkError::conversion(error.to_string())
+            }
+            Error::Protocol(message) => BulkError::Protocol(redact(&message)),
+            Error::Routing { .. } => BulkError::Protocol(error.to_string()),
+        }
+    }
+}
+
+impl From<arrow_schema::ArrowError> for BulkError {
+    fn from(error: arrow_schema::ArrowError) -> Self {
+        match error {
+            arrow_schema::ArrowError::IoError(_, source) => source.into(),
+            other => BulkError::conversion(other.to_string()),
+        }
+    }
+}
+
+/// Adds the table, row or column to the error of a result.
+pub trait ResultExt<T> {
+    fn in_table(self, table: &str) -> Result<T>;
+    fn at_row(self, row: u64) -> Result<T>;
+    fn in_column(self, column: &str) -> Result<T>;
+}
+
+impl<T, E: Into<BulkError>> ResultExt<T> for std::result::Result<T, E> {
+    fn in_table(self, table: &str) -> Result<T> {
+        self.map_err(|e| e.into().with_table(table))
+    }
+
+    fn at_row(self, row: u64) -> Result<T> {
+        self.map_err(|e| e.into().with_row(row))
+    }
+
+    fn in_column(self, column: &str) -> Result<T> {
+        self.map_err(|e| e.into().with_column(column))
+    }
+}
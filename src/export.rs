@@ -0,0 +1,585 @@
+use crate::{
+    connection::SqlClient,
+    convert::{column_text, column_value, decimal_text, decimal_value, BinaryEncoding, Value},
+    error::{BulkError, Result, ResultExt},
+    schema::{Column, SqlType, TableSchema},
+    source::parquet::DEFAULT_BATCH_SIZE,
+};
+use arrow_array::{
+    builder::{
+        BinaryBuilder, BooleanBuilder, Date32Builder, Decimal128Builder, FixedSizeBinaryBuilder,
+        Float32Builder, Float64Builder, Int16Builder, Int32Builder, Int64Builder, StringBuilder,
+        Time64NanosecondBuilder, TimestampMicrosecondBuilder, TimestampNanosecondBuilder,
+        UInt8Builder,
+    },
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{extension::Uuid, DataType, Field, Schema, SchemaRef, TimeUnit};
+use base64::Engine;
+use chrono::{NaiveDate, NaiveDateTime, Timelike};
+use futures_util::TryStreamExt;
+use parquet::arrow::ArrowWriter;
+use std::{io::Write, sync::Arc};
+use tiberius::ColumnData;
+
+/// How rows are written to CSV and JSON Lines files.
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    /// Field delimiter of CSV files.
+    pub delimiter: u8,
+    /// Field text written for NULL in CSV files.
+    pub null_marker: String,
+    /// How binary values are written in CSV and JSON Lines files. Hex is
+    /// written without the `0x` prefix, as the loader reads it back.
+    pub binary_encoding: BinaryEncoding,
+}
+
+impl Default for ExportOptions {
+    fn default() -> ExportOptions {
+        ExportOptions {
+            delimiter: b',',
+            null_marker: String::new(),
+            binary_encoding: BinaryEncoding::Hex,
+        }
+    }
+}
+
+/// A file that rows read from SQL Server are written to.
+pub trait RowWriter {
+    /// Writes a row with a value for every column of the schema the writer
+    /// was created for.
+    fn write_row(&mut self, values: Vec<ColumnData<'static>>) -> Result<()>;
+
+    /// Writes what is buffered and ends the file.
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// Runs `query` and writes the rows it returns with `writer`, which is
+/// finished at the end. Rows are written while the query runs, so the result
+/// never has to fit in memory. Returns the number of rows written.
+pub async fn export(
+    client: &mut SqlClient,
+    query: &str,
+    writer: &mut dyn RowWriter,
+) -> Result<u64> {
+    let mut rows = client.simple_query(query).await?.into_row_stream();
+    let mut count = 0;
+
+    while let Some(row) = rows.try_next().await? {
+        writer.write_row(row.into_iter().collect()).at_row(count)?;
+        count += 1;
+    }
+    writer.finish()?;
+
+    Ok(count)
+}
+
+/// Writes rows to a CSV file with a header of the column names. Values are
+/// written as SQL Server converts them to text, which the loader reads
+/// back: bits as `1` and `0`, decimals with all their digits, dates and
+/// times in ISO 8601 form and `datetimeoffset` values with their offset.
+pub struct CsvWriter<W: Write> {
+    writer: csv::Writer<W>,
+    options: ExportOptions,
+    columns: Vec<Column>,
+    header: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W, schema: &TableSchema, options: &ExportOptions) -> CsvWriter<W> {
+        CsvWriter {
+            writer: csv::WriterBuilder::new()
+                .delimiter(options.delimiter)
+                .from_writer(writer),
+            options: options.clone(),
+            columns: schema.columns.clone(),
+            header: false,
+        }
+    }
+
+    /// Flushes the file and returns the writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.finish()?;
+        self.writer
+            .into_inner()
+            .map_err(|e| BulkError::from(e.into_error()))
+    }
+
+    /// The header is written before the first row, or at the end of a file
+    /// without rows.
+    fn write_header(&mut self) -> Result<()> {
+        if !self.header {
+            self.writer
+                .write_record(self.columns.iter().map(|column| &column.name))
+                .map_err(csv_error)?;
+            self.header = true;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> RowWriter for CsvWriter<W> {
+    fn write_row(&mut self, values: Vec<ColumnData<'static>>) -> Result<()> {
+        check_length(&values, self.columns.len())?;
+        self.write_header()?;
+
+        let fields: Vec<String> = values
+            .iter()
+            .map(|value| {
+                text(value, self.options.binary_encoding)
+                    .unwrap_or_else(|| self.options.null_marker.clone())
+            })
+            .collect();
+        self.writer.write_record(&fields).map_err(csv_error)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.write_header()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes rows to a JSON Lines file, one object per row with the columns
+/// as keys in their order. Bits are booleans, integers and floats numbers,
+/// and decimals numbers with all their digits; other values are strings,
+/// written as in a [`CsvWriter`]. NULL is `null`.
+pub struct JsonlWriter<W: Write> {
+    writer: W,
+    binary_encoding: BinaryEncoding,
+    keys: Vec<String>,
+    line: String,
+}
+
+impl<W: Write> JsonlWriter<W> {
+    pub fn new(writer: W, schema: &TableSchema, options: &ExportOptions) -> JsonlWriter<W> {
+        JsonlWriter {
+            writer,
+            binary_encoding: options.binary_encoding,
+            keys: schema
+                .columns
+                .iter()
+                .map(|column| serde_json::Value::from(column.name.as_str()).to_string())
+                .collect(),
+            line: String::new(),
+        }
+    }
+
+    /// Flushes the file and returns the writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.finish()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> RowWriter for JsonlWriter<W> {
+    fn write_row(&mut self, values: Vec<ColumnData<'static>>) -> Result<()> {
+        check_length(&values, self.keys.len())?;
+
+        self.line.clear();
+        self.line.push('{');
+        for (i, (key, value)) in self.keys.iter().zip(&values).enumerate() {
+            if i > 0 {
+                self.line.push(',');
+            }
+            self.line.push_str(key);
+            self.line.push(':');
+            self.line
+                .push_str(&json_value(value, self.binary_encoding).to_string());
+        }
+        self.line.push_str("}\n");
+
+        self.writer.write_all(self.line.as_bytes())?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes rows to a Parquet file, a row group of rows at a time. Columns
+/// keep their types, as [`arrow_type`] maps them.
+pub struct ParquetWriter<W: Write + Send> {
+    /// The Arrow writer until the file is finished.
+    writer: Option<ArrowWriter<W>>,
+    /// The writer of the file once it is finished.
+    finished: Option<W>,
+    arrow_schema: SchemaRef,
+    columns: Vec<Column>,
+    rows: Vec<Vec<ColumnData<'static>>>,
+    batch_size: usize,
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    /// A writer of batches of [`DEFAULT_BATCH_SIZE`] rows.
+    pub fn new(writer: W, schema: &TableSchema) -> Result<ParquetWriter<W>> {
+        ParquetWriter::with_batch_size(writer, schema, DEFAULT_BATCH_SIZE)
+    }
+
+    /// A writer that converts rows to Arrow `batch_size` rows at a time;
+    /// the memory an export needs grows with it.
+    pub fn with_batch_size(
+        writer: W,
+        schema: &TableSchema,
+        batch_size: usize,
+    ) -> Result<ParquetWriter<W>> {
+        let arrow_schema = Arc::new(arrow_schema(schema));
+        let writer =
+            ArrowWriter::try_new(writer, arrow_schema.clone(), None).map_err(write_error)?;
+
+        Ok(ParquetWriter {
+            writer: Some(writer),
+            finished: None,
+            arrow_schema,
+            columns: schema.columns.clone(),
+            rows: Vec::new(),
+            batch_size: batch_size.max(1),
+        })
+    }
+
+    /// Ends the file and returns the writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.finish()?;
+        Ok(self.finished.take().expect("the file is finished"))
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let rows = std::mem::take(&mut self.rows);
+        let arrays = self
+            .columns
+            .iter()
+            .zip(self.arrow_schema.fields())
+            .enumerate()
+            .map(|(i, (column, field))| {
+                column_array(column, field.data_type(), rows.iter().map(|row| &row[i]))
+                    .in_column(&column.name)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let batch = RecordBatch::try_new(self.arrow_schema.clone(), arrays).map_err(write_error)?;
+
+        match &mut self.writer {
+            Some(writer) => writer.write(&batch).map_err(write_error),
+            None => Err(BulkError::Config(
+                "rows written to a finished Parquet file".to_owned(),
+            )),
+        }
+    }
+}
+
+impl<W: Write + Send> RowWriter for ParquetWriter<W> {
+    fn write_row(&mut self, values: Vec<ColumnData<'static>>) -> Result<()> {
+        check_length(&values, self.columns.len())?;
+        self.rows.push(values);
+        if self.rows.len() >= self.batch_size {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.write_batch()?;
+        if let Some(writer) = self.writer.take() {
+            // Writes the footer, which the Arrow writer does not flush.
+            let mut finished = writer.into_inner().map_err(write_error)?;
+            finished.flush()?;
+            self.finished = Some(finished);
+        }
+        Ok(())
+    }
+}
+
+/// The Arrow schema of the Parquet files of rows of `schema`.
+pub fn arrow_schema(schema: &TableSchema) -> Schema {
+    let fields: Vec<Field> = schema
+        .columns
+        .iter()
+        .map(|column| {
+            let field = Field::new(&column.name, arrow_type(&column.sql_type), column.nullable);
+            match column.sql_type {
+                SqlType::UniqueIdentifier => field.with_extension_type(Uuid),
+                _ => field,
+            }
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+/// The Arrow type values of `sql_type` are exported as:
+///
+/// - integers, floats and bits as integers, floats and booleans of the same
+///   size; `tinyint` is unsigned;
+/// - `decimal` as a decimal of the same precision and scale, and `money` and
+///   `smallmoney` as decimals of scale 4;
+/// - text and XML as strings, binary values as bytes and GUIDs as UUIDs;
+/// - `date` as a date, `time` as a time of day in nanoseconds;
+/// - `datetime`, `smalldatetime` and `datetime2` as timestamps without time
+///   zone in microseconds, or in nanoseconds for `datetime2(7)`, whose range
+///   is then limited to the years 1677 to 2262;
+/// - `datetimeoffset` as timestamps adjusted to UTC, which keep the instant
+///   but not the offset.
+pub fn arrow_type(sql_type: &SqlType) -> DataType {
+    let unit = |scale: u8| match scale {
+        7.. => TimeUnit::Nanosecond,
+        _ => TimeUnit::Microsecond,
+    };
+
+    match sql_type {
+        SqlType::Bit => DataType::Boolean,
+        SqlType::TinyInt => DataType::UInt8,
+        SqlType::SmallInt => DataType::Int16,
+        SqlType::Int => DataType::Int32,
+        SqlType::BigInt => DataType::Int64,
+        SqlType::Real => DataType::Float32,
+        SqlType::Float => DataType::Float64,
+        SqlType::Decimal { precision, scale } => DataType::Decimal128(*precision, *scale as i8),
+        SqlType::Money => DataType::Decimal128(19, 4),
+        SqlType::SmallMoney => DataType::Decimal128(10, 4),
+        SqlType::Binary(_) | SqlType::VarBinary(_) | SqlType::Image | SqlType::RowVersion => {
+            DataType::Binary
+        }
+        SqlType::UniqueIdentifier => DataType::FixedSizeBinary(16),
+        SqlType::Date => DataType::Date32,
+        SqlType::Time(_) => DataType::Time64(TimeUnit::Nanosecond),
+        SqlType::DateTime | SqlType::SmallDateTime => DataType::Timestamp(unit(3), None),
+        SqlType::DateTime2(scale) => DataType::Timestamp(unit(*scale), None),
+        SqlType::DateTimeOffset(scale) => DataType::Timestamp(unit(*scale), Some("UTC".into())),
+        SqlType::Char(_)
+        | SqlType::VarChar(_)
+        | SqlType::NChar(_)
+        | SqlType::NVarChar(_)
+        | SqlType::Text
+        | SqlType::NText
+        | SqlType::Xml
+        | SqlType::Other(_) => DataType::Utf8,
+    }
+}
+
+/// The array of the values of `column` in a batch of rows.
+fn column_array<'a>(
+    column: &Column,
+    data_type: &DataType,
+    values: impl Iterator<Item = &'a ColumnData<'static>>,
+) -> Result<ArrayRef> {
+    let mismatch = |value: &ColumnData<'static>| {
+        BulkError::conversion(format!("{:?} is no value of {}", value, column.sql_type))
+    };
+    let values = values.map(|value| (value, column_value(value.clone())));
+
+    macro_rules! build {
+        ($builder:expr, $convert:expr) => {{
+            let mut builder = $builder;
+            for (data, value) in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    value => builder.append_value($convert(value).ok_or_else(|| mismatch(data))?),
+                }
+            }
+            Arc::new(builder.finish()) as ArrayRef
+        }};
+    }
+
+    let array = match data_type {
+        DataType::Boolean => build!(BooleanBuilder::new(), |value| match value {
+            Value::Bool(value) => Some(value),
+            _ => None,
+        }),
+        DataType::UInt8 => build!(UInt8Builder::new(), |value| int(value)?.try_into().ok()),
+        DataType::Int16 => build!(Int16Builder::new(), |value| int(value)?.try_into().ok()),
+        DataType::Int32 => build!(Int32Builder::new(), |value| int(value)?.try_into().ok()),
+        DataType::Int64 => build!(Int64Builder::new(), int),
+        DataType::Float32 => build!(Float32Builder::new(), |value| float(value)
+            .map(|v| v as f32)),
+        DataType::Float64 => build!(Float64Builder::new(), float),
+        DataType::Decimal128(precision, scale) => {
+            let mut builder = Decimal128Builder::new()
+                .with_precision_and_scale(*precision, *scale)
+                .map_err(write_error)?;
+            let sql_type = SqlType::Decimal {
+                precision: *precision,
+                scale: *scale as u8,
+            };
+            for (data, _) in values {
+                match decimal(data, &sql_type)? {
+                    Some(value) => builder.append_value(value),
+                    None => builder.append_null(),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Binary => build!(BinaryBuilder::new(), |value| match value {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }),
+        DataType::FixedSizeBinary(size) => {
+            let mut builder = FixedSizeBinaryBuilder::new(*size);
+            for (data, value) in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Guid(guid) => {
+                        builder.append_value(guid.as_bytes()).map_err(write_error)?
+                    }
+                    _ => return Err(mismatch(data)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Date32 => build!(Date32Builder::new(), |value| match value {
+            Value::Date(date) => i32::try_from((date - epoch().date()).num_days()).ok(),
+            _ => None,
+        }),
+        DataType::Time64(_) => build!(Time64NanosecondBuilder::new(), |value| match value {
+            Value::Time(time) => Some(
+                i64::from(time.num_seconds_from_midnight()) * 1_000_000_000
+                    + i64::from(time.nanosecond())
+            ),
+            _ => None,
+        }),
+        DataType::Timestamp(TimeUnit::Microsecond, tz) => build!(
+            TimestampMicrosecondBuilder::new().with_timezone_opt(tz.clone()),
+            |value| (timestamp(value)? - epoch()).num_microseconds()
+        ),
+        DataType::Timestamp(_, tz) => {
+            let out_of_range = |value: &ColumnData<'static>| {
+                BulkError::conversion(format!(
+                    "{} is out of range for timestamps in nanoseconds",
+                    column_text(value).unwrap_or_default()
+                ))
+            };
+            let mut builder = TimestampNanosecondBuilder::new().with_timezone_opt(tz.clone());
+            for (data, value) in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    value => {
+                        let value = timestamp(value).ok_or_else(|| mismatch(data))?;
+                        let nanos = (value - epoch())
+                            .num_nanoseconds()
+                            .ok_or_else(|| out_of_range(data))?;
+                        builder.append_value(nanos)
+                    }
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        _ => {
+            let mut builder = StringBuilder::new();
+            for (data, _) in values {
+                builder.append_option(column_text(data));
+            }
+            Arc::new(builder.finish())
+        }
+    };
+
+    Ok(array)
+}
+
+fn epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(1970, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+}
+
+fn int(value: Value) -> Option<i64> {
+    match value {
+        Value::Int(value) => Some(value),
+        _ => None,
+    }
+}
+
+fn float(value: Value) -> Option<f64> {
+    match value {
+        Value::Float(value) => Some(value),
+        _ => None,
+    }
+}
+
+/// The date and time of a timestamp; `datetimeoffset` values in UTC.
+fn timestamp(value: Value) -> Option<NaiveDateTime> {
+    match value {
+        Value::DateTime(value) => Some(value),
+        Value::DateTimeOffset(value) => Some(value.naive_utc()),
+        _ => None,
+    }
+}
+
+/// The unscaled value of a decimal or money value at the scale of
+/// `sql_type`.
+fn decimal(value: &ColumnData<'static>, sql_type: &SqlType) -> Result<Option<i128>> {
+    let value = match value {
+        ColumnData::Numeric(None) | ColumnData::F64(None) => return Ok(None),
+        ColumnData::Numeric(Some(value)) => {
+            decimal_value(value.value(), value.scale() as i8, sql_type)?
+        }
+        // Money is read as a float of at most four decimals.
+        ColumnData::F64(Some(value)) => {
+            decimal_value((value * 10_000.0).round() as i128, 4, sql_type)?
+        }
+        value => {
+            return Err(BulkError::conversion(format!(
+                "{:?} is no value of {}",
+                value, sql_type
+            )))
+        }
+    };
+
+    match value {
+        ColumnData::Numeric(value) => Ok(value.map(|value| value.value())),
+        _ => unreachable!("decimal_value returns numerics"),
+    }
+}
+
+/// The text of a value in a CSV file, or `None` for NULL.
+fn text(value: &ColumnData<'static>, binary: BinaryEncoding) -> Option<String> {
+    match (value, binary) {
+        (ColumnData::Binary(Some(bytes)), BinaryEncoding::Base64) => {
+            Some(base64::engine::general_purpose::STANDARD.encode(bytes))
+        }
+        _ => column_text(value),
+    }
+}
+
+fn json_value(value: &ColumnData<'static>, binary: BinaryEncoding) -> serde_json::Value {
+    match value {
+        ColumnData::Bit(Some(value)) => (*value).into(),
+        ColumnData::U8(Some(value)) => (*value).into(),
+        ColumnData::I16(Some(value)) => (*value).into(),
+        ColumnData::I32(Some(value)) => (*value).into(),
+        ColumnData::I64(Some(value)) => (*value).into(),
+        ColumnData::F32(Some(value)) => f64::from(*value).into(),
+        ColumnData::F64(Some(value)) => (*value).into(),
+        // Numbers keep all their digits with `arbitrary_precision`.
+        ColumnData::Numeric(Some(value)) => decimal_text(value.value(), value.scale())
+            .parse::<serde_json::Number>()
+            .map_or(serde_json::Value::Null, serde_json::Value::Number),
+        value => text(value, binary).map_or(serde_json::Value::Null, serde_json::Value::String),
+    }
+}
+
+fn check_length(values: &[ColumnData<'static>], columns: usize) -> Result<()> {
+    if values.len() != columns {
+        return Err(BulkError::Config(format!(
+            "a row of {} values for {} columns",
+            values.len(),
+            columns
+        )));
+    }
+    Ok(())
+}
+
+fn csv_error(error: csv::Error) -> BulkError {
+    match error.into_kind() {
+        csv::ErrorKind::Io(error) => error.into(),
+        kind => BulkError::Config(format!("{:?}", kind)),
+    }
+}
+
+/// Failing to write the file ends the export; it is no error of a row.
+fn write_error(error: impl std::error::Error + Send + Sync + 'static) -> BulkError {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, error).into()
+}
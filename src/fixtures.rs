@@ -0,0 +1,394 @@
+use crate::{
+    connection::{connect, ConnectionConfig, SqlClient},
+    error::{BulkError, Result, ResultExt},
+    schema::{quote_name, unquote},
+};
+use futures_util::lock::{Mutex, OwnedMutexGuard};
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, env, sync::Arc};
+
+/// The tables of the test suite.
+pub const TABLES_SQL: &str = include_str!("../tables.sql");
+
+/// Schema of the tables of the test suite unless `BULK_TEST_SCHEMA` names
+/// another one.
+pub const DEFAULT_TEST_SCHEMA: &str = "bulk_test";
+
+/// Name of the extended property that marks the schemas fixtures create,
+/// the only ones whose tables they drop.
+const SCHEMA_PROPERTY: &str = "bulk_insert_fixtures";
+
+/// A table of a file of `CREATE TABLE` statements.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableDefinition {
+    /// The schema the statement names, if any, as `dbo`.
+    pub schema: Option<String>,
+    /// The name of the table without its schema, as `random_bit`.
+    pub name: String,
+    /// The text between the parentheses of the statement, without comments.
+    pub columns: String,
+}
+
+impl TableDefinition {
+    /// The statement that creates the table in `schema`.
+    pub fn create_table_sql(&self, schema: &str) -> String {
+        format!(
+            "CREATE TABLE {}.{} (\n    {}\n)",
+            quote_name(schema),
+            quote_name(&self.name),
+            self.columns
+        )
+    }
+}
+
+/// Parses the `CREATE TABLE` statements of `sql`, such as those of
+/// [`TABLES_SQL`]. Statements may end with `;` or be separated by `GO`, and
+/// `--` comments are left out. Any other statement is an error.
+pub fn parse_tables(sql: &str) -> Result<Vec<TableDefinition>> {
+    let sql: Vec<&str> = sql
+        .lines()
+        .map(|line| {
+            line.split_once("--")
+                .map_or(line, |(code, _)| code)
+                .trim_end()
+        })
+        .collect();
+    let sql = sql.join("\n");
+
+    let mut tables = Vec::new();
+    let mut rest = sql.as_str();
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ';');
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(after) = keyword(rest, "GO") {
+            rest = after;
+            continue;
+        }
+
+        let unexpected = || {
+            let statement = rest.lines().next().unwrap_or_default().trim();
+            BulkError::Config(format!("expected CREATE TABLE, found `{}`", statement))
+        };
+        let after = keyword(rest, "CREATE")
+            .and_then(|after| keyword(after.trim_start(), "TABLE"))
+            .ok_or_else(unexpected)?;
+        let open = after.find('(').ok_or_else(unexpected)?;
+        let close = closing_parenthesis(after, open).ok_or_else(unexpected)?;
+
+        let name = after[..open].trim();
+        let (schema, name) = match name.rsplit_once('.') {
+            Some((schema, name)) => (Some(unquote(schema)), unquote(name)),
+            None => (None, unquote(name)),
+        };
+        tables.push(TableDefinition {
+            schema,
+            name,
+            columns: after[open + 1..close].trim().to_owned(),
+        });
+        rest = &after[close + 1..];
+    }
+
+    Ok(tables)
+}
+
+/// The tables of a file of `CREATE TABLE` statements, created in a schema
+/// of their own, whatever schema the statements name, so that they can be
+/// dropped without touching other tables. The schema is marked when it is
+/// created, and fixtures refuse to create or drop tables in `dbo` or in a
+/// schema without the mark.
+#[derive(Clone, Debug)]
+pub struct Fixtures {
+    schema: String,
+    tables: Vec<TableDefinition>,
+}
+
+impl Fixtures {
+    /// The `tables` in `schema`.
+    pub fn new(schema: &str, tables: Vec<TableDefinition>) -> Fixtures {
+        Fixtures {
+            schema: schema.to_owned(),
+            tables,
+        }
+    }
+
+    /// The tables of [`TABLES_SQL`] in the schema of [`test_table`].
+    pub fn from_tables_sql() -> Result<Fixtures> {
+        Ok(Fixtures::new(&test_schema(), parse_tables(TABLES_SQL)?))
+    }
+
+    pub fn schema(&self) -> &str {
+        &self.schema
+    }
+
+    pub fn tables(&self) -> &[TableDefinition] {
+        &self.tables
+    }
+
+    /// `name` in the schema of the tables, as in `[bulk_test].[random_bit]`.
+    pub fn table(&self, name: &str) -> String {
+        format!("{}.{}", quote_name(&self.schema), quote_name(name))
+    }
+
+    /// Creates the schema and the tables, dropping tables left over from an
+    /// earlier run first. Fails with [`BulkError::Config`] if the schema
+    /// exists but was not created by fixtures.
+    pub async fn create(&self, client: &mut SqlClient) -> Result<()> {
+        match self.own_schema(client).await? {
+            true => self.drop_tables(client).await?,
+            false => {
+                client
+                    .execute(
+                        "IF SCHEMA_ID(@P1) IS NULL BEGIN \
+                         EXEC('CREATE SCHEMA ' + QUOTENAME(@P1)); \
+                         EXEC sys.sp_addextendedproperty @name = @P2, @value = N'', \
+                         @level0type = N'SCHEMA', @level0name = @P1; END",
+                        &[&self.schema, &SCHEMA_PROPERTY],
+                    )
+                    .await?;
+            }
+        }
+        for table in &self.tables {
+            client
+                .execute(table.create_table_sql(&self.schema), &[])
+                .await
+                .in_table(&table.name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the rows of the tables `names`.
+    pub async fn truncate(&self, client: &mut SqlClient, names: &[&str]) -> Result<()> {
+        for name in names {
+            if !self.tables.iter().any(|table| table.name == *name) {
+                return Err(BulkError::Config(format!(
+                    "table {} is not one of the fixtures",
+                    name
+                )));
+            }
+            client
+                .execute(format!("TRUNCATE TABLE {}", self.table(name)), &[])
+                .await
+                .in_table(name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Drops the tables and the schema, if it exists. Fails with
+    /// [`BulkError::Config`] if the schema was not created by fixtures.
+    pub async fn drop(&self, client: &mut SqlClient) -> Result<()> {
+        if !self.own_schema(client).await? {
+            return Ok(());
+        }
+
+        self.drop_tables(client).await?;
+        client
+            .execute(
+                "IF SCHEMA_ID(@P1) IS NOT NULL EXEC('DROP SCHEMA ' + QUOTENAME(@P1))",
+                &[&self.schema],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Whether the schema exists and was created by fixtures. Fails if it
+    /// is `dbo` or any other schema, whose tables are not theirs to drop.
+    async fn own_schema(&self, client: &mut SqlClient) -> Result<bool> {
+        let refused = || {
+            BulkError::Config(format!(
+                "schema {} was not created for the test tables, which are dropped; \
+                 name a new schema with BULK_TEST_SCHEMA",
+                self.schema
+            ))
+        };
+        if self.schema.eq_ignore_ascii_case("dbo") {
+            return Err(refused());
+        }
+
+        let found = client
+            .query(
+                "SELECT CASE WHEN SCHEMA_ID(@P1) IS NULL THEN 0 \
+                 WHEN EXISTS (SELECT * FROM sys.extended_properties \
+                 WHERE class = 3 AND major_id = SCHEMA_ID(@P1) AND name = @P2) THEN 1 \
+                 ELSE 2 END",
+                &[&self.schema, &SCHEMA_PROPERTY],
+            )
+            .await?
+            .into_row()
+            .await?
+            .and_then(|row| row.get::<i32, _>(0));
+
+        match found {
+            Some(0) => Ok(false),
+            Some(1) => Ok(true),
+            _ => Err(refused()),
+        }
+    }
+
+    async fn drop_tables(&self, client: &mut SqlClient) -> Result<()> {
+        for table in &self.tables {
+            client
+                .execute(
+                    format!("DROP TABLE IF EXISTS {}", self.table(&table.name)),
+                    &[],
+                )
+                .await
+                .in_table(&table.name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The schema of the tables of the test suite: `BULK_TEST_SCHEMA`, or
+/// [`DEFAULT_TEST_SCHEMA`].
+pub fn test_schema() -> String {
+    env::var("BULK_TEST_SCHEMA").unwrap_or_else(|_| DEFAULT_TEST_SCHEMA.to_owned())
+}
+
+/// The table `name` of [`TABLES_SQL`] in the schema of the test suite.
+pub fn test_table(name: &str) -> String {
+    format!("{}.{}", quote_name(&test_schema()), quote_name(name))
+}
+
+/// The fixtures shared by the tests of a run.
+struct Suite {
+    fixtures: Option<Fixtures>,
+    /// Tests holding tables.
+    users: usize,
+    /// A lock per table, so that tests using the same table take turns.
+    locks: HashMap<String, Arc<Mutex<()>>>,
+}
+
+static SUITE: Lazy<Mutex<Suite>> = Lazy::new(|| {
+    Mutex::new(Suite {
+        fixtures: None,
+        users: 0,
+        locks: HashMap::new(),
+    })
+});
+
+/// Tables of [`TABLES_SQL`] held by a test, empty when it starts. Release
+/// them with [`TestTables::release`].
+pub struct TestTables {
+    config: ConnectionConfig,
+    guards: Vec<OwnedMutexGuard<()>>,
+}
+
+impl TestTables {
+    /// Holds the tables `names` for a test, on the server of `config`, which
+    /// is the same for every test of a run.
+    ///
+    /// The first test of a run creates every table of [`TABLES_SQL`] in the
+    /// schema of [`test_table`], and the last one to release its tables
+    /// drops them, so the suite needs nothing but an empty database; tables
+    /// left over by a run that stopped halfway are dropped first. Tests
+    /// holding the same table wait for each other, and every test finds its
+    /// tables truncated.
+    pub async fn acquire(config: &ConnectionConfig, names: &[&str]) -> Result<TestTables> {
+        let mut names = names.to_vec();
+        names.sort_unstable();
+        names.dedup();
+
+        let (fixtures, locks) = {
+            let mut suite = SUITE.lock().await;
+            let fixtures = match &suite.fixtures {
+                Some(fixtures) => fixtures.clone(),
+                None => {
+                    let fixtures = Fixtures::from_tables_sql()?;
+                    let (mut client, _) = connect(config).await?;
+                    fixtures.create(&mut client).await?;
+                    client.close().await?;
+                    suite.fixtures = Some(fixtures.clone());
+                    fixtures
+                }
+            };
+            suite.users += 1;
+            let locks: Vec<Arc<Mutex<()>>> = names
+                .iter()
+                .map(|name| suite.locks.entry(name.to_string()).or_default().clone())
+                .collect();
+            (fixtures, locks)
+        };
+
+        // Locks are taken in the order of the names, so tests cannot wait
+        // for each other in a circle.
+        let mut guards = Vec::with_capacity(locks.len());
+        for lock in locks {
+            guards.push(lock.lock_owned().await);
+        }
+        let tables = TestTables {
+            config: config.clone(),
+            guards,
+        };
+
+        let result = async {
+            let (mut client, _) = connect(config).await?;
+            fixtures.truncate(&mut client, &names).await?;
+            client.close().await?;
+            Ok::<_, BulkError>(())
+        }
+        .await;
+        match result {
+            Ok(()) => Ok(tables),
+            Err(e) => {
+                tables.release().await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Lets other tests have the tables, and drops every table when no test
+    /// holds any.
+    pub async fn release(self) -> Result<()> {
+        drop(self.guards);
+
+        let mut suite = SUITE.lock().await;
+        suite.users -= 1;
+        if suite.users > 0 {
+            return Ok(());
+        }
+        if let Some(fixtures) = suite.fixtures.take() {
+            let (mut client, _) = connect(&self.config).await?;
+            fixtures.drop(&mut client).await?;
+            client.close().await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `s` without the keyword `word` it starts with, compared without regard
+/// to case.
+pub(crate) fn keyword<'a>(s: &'a str, word: &str) -> Option<&'a str> {
+    let rest = s
+        .get(..word.len())
+        .filter(|start| start.eq_ignore_ascii_case(word))
+        .map(|_| &s[word.len()..])?;
+    match rest.chars().next() {
+        Some(c) if c.is_alphanumeric() || c == '_' => None,
+        _ => Some(rest),
+    }
+}
+
+/// The position of the parenthesis that closes the one at `open`.
+pub(crate) fn closing_parenthesis(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
@@ -0,0 +1,150 @@
+use crate::{
+    error::Result,
+    schema::{Column, SqlType, TableSchema},
+    tds::to_datetime,
+};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
+use std::borrow::Cow;
+use tiberius::{
+    numeric::Numeric, time::SmallDateTime, xml::XmlData, ColumnData, IntoSql, TokenRow, Uuid,
+};
+
+/// Longest string or binary value generated for `max` and long columns.
+const MAX_GENERATED_LEN: usize = 64;
+
+/// Generates rows of random values for the insertable columns of a table,
+/// to fill test tables and to measure load throughput.
+pub struct RowGenerator {
+    columns: Vec<Column>,
+    rng: fastrand::Rng,
+    null_ratio: f64,
+}
+
+impl RowGenerator {
+    /// A generator for `schema`; the same seed generates the same rows.
+    pub fn new(schema: &TableSchema, seed: u64) -> Result<RowGenerator> {
+        schema.check_bulk_loadable()?;
+
+        Ok(RowGenerator {
+            columns: schema.insertable_columns().cloned().collect(),
+            rng: fastrand::Rng::with_seed(seed),
+            null_ratio: 0.0,
+        })
+    }
+
+    /// The share of NULL values in nullable columns, from 0 to 1.
+    pub fn null_ratio(mut self, null_ratio: f64) -> Self {
+        self.null_ratio = null_ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The next row, with a value for every insertable column.
+    pub fn next_row(&mut self) -> TokenRow<'static> {
+        let mut row = TokenRow::with_capacity(self.columns.len());
+
+        for column in &self.columns {
+            if column.nullable && self.rng.f64() < self.null_ratio {
+                row.push(column.sql_type.null());
+            } else {
+                row.push(random_value(&column.sql_type, &mut self.rng));
+            }
+        }
+
+        row
+    }
+}
+
+/// A random value of `sql_type`, in the variant a bulk load expects.
+pub fn random_value(sql_type: &SqlType, rng: &mut fastrand::Rng) -> ColumnData<'static> {
+    match sql_type {
+        SqlType::Bit => ColumnData::Bit(Some(rng.bool())),
+        SqlType::TinyInt => ColumnData::U8(Some(rng.u8(..))),
+        SqlType::SmallInt => ColumnData::I16(Some(rng.i16(..))),
+        SqlType::Int => ColumnData::I32(Some(rng.i32(..))),
+        SqlType::BigInt => ColumnData::I64(Some(rng.i64(..))),
+        SqlType::Real => ColumnData::F32(Some(rng.f32() * 1e6)),
+        SqlType::Float | SqlType::Money | SqlType::SmallMoney => {
+            ColumnData::F64(Some(rng.f64() * 1e9))
+        }
+        SqlType::Decimal { precision, scale } => {
+            // The scale of the value has to be the scale of the column.
+            let max = 10i64.pow(u32::from((*precision).clamp(1, 18))) - 1;
+            ColumnData::Numeric(Some(Numeric::new_with_scale(
+                rng.i64(-max..=max).into(),
+                *scale,
+            )))
+        }
+        SqlType::Char(len) | SqlType::NChar(len) => {
+            ColumnData::String(Some(random_string(rng, *len as usize).into()))
+        }
+        SqlType::VarChar(len) | SqlType::NVarChar(len) => {
+            let len = rng.usize(0..=max_len(len));
+            ColumnData::String(Some(random_string(rng, len).into()))
+        }
+        SqlType::Text | SqlType::NText | SqlType::Other(_) => {
+            let len = rng.usize(0..=MAX_GENERATED_LEN);
+            ColumnData::String(Some(random_string(rng, len).into()))
+        }
+        SqlType::Binary(len) => ColumnData::Binary(Some(random_bytes(rng, *len as usize).into())),
+        SqlType::VarBinary(len) => {
+            let len = rng.usize(0..=max_len(len));
+            ColumnData::Binary(Some(random_bytes(rng, len).into()))
+        }
+        SqlType::Image | SqlType::RowVersion => {
+            ColumnData::Binary(Some(random_bytes(rng, 8).into()))
+        }
+        SqlType::UniqueIdentifier => ColumnData::Guid(Some(Uuid::from_u128(rng.u128(..)))),
+        SqlType::Date => random_date(rng).into_sql(),
+        SqlType::Time(_) => random_time(rng).into_sql(),
+        SqlType::DateTime => to_datetime(random_datetime(rng).into_sql()),
+        SqlType::SmallDateTime => {
+            // Days since 1900 and minutes since midnight.
+            let start = NaiveDate::from_ymd_opt(1900, 1, 1).unwrap();
+            let days = (random_date(rng) - start).num_days() as u16;
+            ColumnData::SmallDateTime(Some(SmallDateTime::new(days, rng.u16(0..24 * 60))))
+        }
+        SqlType::DateTime2(_) => random_datetime(rng).into_sql(),
+        SqlType::DateTimeOffset(_) => {
+            // Offsets are whole quarters of an hour within ±14 hours.
+            let offset = FixedOffset::east_opt(rng.i32(-56..=56) * 15 * 60).unwrap();
+            let value: DateTime<FixedOffset> =
+                DateTime::from_naive_utc_and_offset(random_datetime(rng), offset);
+            value.into_sql()
+        }
+        SqlType::Xml => {
+            let xml = XmlData::new(format!("<value>{}</value>", rng.u32(..)));
+            ColumnData::Xml(Some(Cow::Owned(xml)))
+        }
+    }
+}
+
+fn max_len(len: &Option<u16>) -> usize {
+    len.map_or(MAX_GENERATED_LEN, |len| {
+        (len as usize).min(MAX_GENERATED_LEN)
+    })
+}
+
+fn random_string(rng: &mut fastrand::Rng, len: usize) -> String {
+    std::iter::repeat_with(|| rng.alphanumeric())
+        .take(len)
+        .collect()
+}
+
+fn random_bytes(rng: &mut fastrand::Rng, len: usize) -> Vec<u8> {
+    std::iter::repeat_with(|| rng.u8(..)).take(len).collect()
+}
+
+/// A date between 1900 and 2078, the range every date type supports.
+fn random_date(rng: &mut fastrand::Rng) -> NaiveDate {
+    let start = NaiveDate::from_ymd_opt(1900, 1, 1).unwrap();
+    start + Duration::days(rng.i64(0..65_000))
+}
+
+fn random_time(rng: &mut fastrand::Rng) -> NaiveTime {
+    let nanos = rng.u32(0..1_000) * 1_000_000;
+    NaiveTime::from_num_seconds_from_midnight_opt(rng.u32(0..86_400), nanos).unwrap()
+}
+
+fn random_datetime(rng: &mut fastrand::Rng) -> NaiveDateTime {
+    NaiveDateTime::new(random_date(rng), random_time(rng))
+}
@@ -0,0 +1,343 @@
+use crate::{
+    convert::{column_value, parse, BinaryEncoding, Value},
+    error::{BulkError, Result, ResultExt},
+    schema::{Column, SqlType, TableSchema},
+    source::Source,
+};
+use chrono::Timelike;
+use tiberius::Uuid;
+
+/// Records read when [`InferOptions::sample_rows`] is left at its default.
+pub const DEFAULT_SAMPLE_ROWS: u64 = 10_000;
+
+/// How the columns of a table are inferred from a file.
+#[derive(Clone, Debug)]
+pub struct InferOptions {
+    /// Records read to infer the types, or `None` to read every record.
+    pub sample_rows: Option<u64>,
+}
+
+impl Default for InferOptions {
+    fn default() -> InferOptions {
+        InferOptions {
+            sample_rows: Some(DEFAULT_SAMPLE_ROWS),
+        }
+    }
+}
+
+/// What the values of a field have in common so far; every value widens it
+/// as little as it can.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Bit,
+    Int {
+        min: i64,
+        max: i64,
+    },
+    /// Digits before and after the decimal point.
+    Decimal {
+        integer: usize,
+        scale: usize,
+    },
+    Float,
+    Guid,
+    Date,
+    /// Digits of the fractions of seconds of times and timestamps.
+    Time(u8),
+    DateTime(u8),
+    DateTimeOffset(u8),
+    /// Length in bytes.
+    Binary(usize),
+    Text,
+}
+
+/// The values of a field seen so far.
+#[derive(Clone, Debug, Default)]
+struct FieldStats {
+    /// `None` until a value other than NULL is seen.
+    kind: Option<Kind>,
+    nullable: bool,
+    /// The longest value as text, in characters.
+    chars: usize,
+    /// Some value as text is not ASCII.
+    unicode: bool,
+}
+
+impl FieldStats {
+    fn add(&mut self, value: &Value) {
+        let Some(kind) = kind(value) else {
+            self.nullable = true;
+            return;
+        };
+
+        let text = match value {
+            Value::Text(text) => text.clone(),
+            value => value.to_string(),
+        };
+        self.chars = self.chars.max(text.chars().count());
+        self.unicode |= !text.is_ascii();
+
+        self.kind = Some(match self.kind.take() {
+            Some(seen) => widen(seen, kind),
+            None => kind,
+        });
+    }
+
+    fn sql_type(&self) -> SqlType {
+        match self.kind {
+            // Nothing is known of a field that is always NULL.
+            None => SqlType::NVarChar(None),
+            Some(Kind::Bit) => SqlType::Bit,
+            Some(Kind::Int { min, max }) => match i32::try_from(min).and(i32::try_from(max)) {
+                Ok(_) => SqlType::Int,
+                Err(_) => SqlType::BigInt,
+            },
+            Some(Kind::Decimal { integer, scale }) if integer + scale <= 38 => SqlType::Decimal {
+                precision: (integer + scale).max(1) as u8,
+                scale: scale as u8,
+            },
+            Some(Kind::Decimal { .. }) | Some(Kind::Float) => SqlType::Float,
+            Some(Kind::Guid) => SqlType::UniqueIdentifier,
+            Some(Kind::Date) => SqlType::Date,
+            Some(Kind::Time(digits)) => SqlType::Time(digits),
+            Some(Kind::DateTime(digits)) => SqlType::DateTime2(digits),
+            Some(Kind::DateTimeOffset(digits)) => SqlType::DateTimeOffset(digits),
+            Some(Kind::Binary(len)) => SqlType::VarBinary(length(len, 8000)),
+            Some(Kind::Text) if self.unicode => SqlType::NVarChar(length(self.chars, 4000)),
+            Some(Kind::Text) => SqlType::VarChar(length(self.chars, 8000)),
+        }
+    }
+}
+
+/// Reads up to [`InferOptions::sample_rows`] records of `source` and infers
+/// the columns of a table named `table` that holds them, with the narrowest
+/// types that hold every value of the sample:
+///
+/// - integers are `int`, or `bigint` when one is out of range for `int`;
+/// - numbers with a decimal point are `decimal`, with as many digits before
+///   and after the point as the sample has, and `float` when there are more
+///   than 38 digits or the numbers have exponents;
+/// - `true` and `false` are `bit`;
+/// - GUIDs such as `6F9619FF-8B86-D011-B42D-00C04FC964FF` are
+///   `uniqueidentifier`;
+/// - dates, times and timestamps in ISO 8601 form are `date`, `time`,
+///   `datetime2`, or `datetimeoffset` when they have an offset, with as many
+///   digits of the fractions of seconds as the sample has;
+/// - other text is `varchar` of the longest value, or `nvarchar` when some
+///   value is not ASCII, and `max` beyond 8000 bytes.
+///
+/// Integers with leading zeros, such as zip codes, are text. Typed values,
+/// such as those of Parquet files, keep their types. Columns with a NULL in
+/// the sample allow NULLs, and columns without a value are `nvarchar(max)`.
+/// Fields of a source without field names are named `column1`, `column2`
+/// and so on. [`TableSchema::create_table_sql`] writes the statement that
+/// creates the table.
+pub fn infer_schema<S: Source + ?Sized>(
+    source: &mut S,
+    table: &str,
+    options: &InferOptions,
+) -> Result<TableSchema> {
+    let mut fields: Vec<FieldStats> = Vec::new();
+    let mut records = 0;
+
+    while options.sample_rows.is_none_or(|rows| records < rows) {
+        let values = match source.next_record().in_table(table).at_row(records)? {
+            Some(values) => values,
+            None => break,
+        };
+        records += 1;
+
+        if fields.len() < values.len() {
+            // Fields missing from the records before are NULL there.
+            let nullable = FieldStats {
+                nullable: records > 1,
+                ..Default::default()
+            };
+            fields.resize(values.len(), nullable);
+        }
+        for (i, stats) in fields.iter_mut().enumerate() {
+            stats.add(values.get(i).unwrap_or(&Value::Null));
+        }
+    }
+
+    let names: Vec<String> = match source.fields() {
+        Some(names) => names.to_vec(),
+        None => (1..=fields.len()).map(|i| format!("column{}", i)).collect(),
+    };
+    if names.is_empty() {
+        return Err(BulkError::Config(format!(
+            "no fields to infer the columns of table {} from",
+            table
+        )));
+    }
+    fields.resize(names.len(), FieldStats::default());
+
+    let columns = names
+        .into_iter()
+        .zip(&fields)
+        .map(|(name, stats)| Column {
+            name,
+            sql_type: stats.sql_type(),
+            nullable: stats.nullable || stats.kind.is_none(),
+            identity: false,
+            computed: false,
+        })
+        .collect();
+
+    Ok(TableSchema {
+        table: table.to_owned(),
+        columns,
+    })
+}
+
+/// The kind of `value`, or `None` for NULL.
+fn kind(value: &Value) -> Option<Kind> {
+    let kind = match value {
+        Value::Null => return None,
+        Value::Bool(_) => Kind::Bit,
+        Value::Int(value) => Kind::Int {
+            min: *value,
+            max: *value,
+        },
+        Value::Float(_) => Kind::Float,
+        Value::Text(text) => text_kind(text),
+        Value::Bytes(bytes) => Kind::Binary(bytes.len()),
+        Value::Date(_) => Kind::Date,
+        Value::Time(time) => Kind::Time(fraction_digits(time.nanosecond())),
+        Value::DateTime(value) => Kind::DateTime(fraction_digits(value.nanosecond())),
+        Value::DateTimeOffset(value) => Kind::DateTimeOffset(fraction_digits(value.nanosecond())),
+        Value::Guid(_) => Kind::Guid,
+    };
+    Some(kind)
+}
+
+/// The kind of a value read as text, as [`parse`] reads it.
+fn text_kind(text: &str) -> Kind {
+    let trimmed = text.trim();
+    if let Some(kind) = number_kind(trimmed) {
+        return kind;
+    }
+    if trimmed.eq_ignore_ascii_case("true") || trimmed.eq_ignore_ascii_case("false") {
+        return Kind::Bit;
+    }
+    if trimmed.len() == 36 && Uuid::parse_str(trimmed).is_ok() {
+        return Kind::Guid;
+    }
+
+    [
+        SqlType::Date,
+        SqlType::DateTimeOffset(7),
+        SqlType::DateTime2(7),
+        SqlType::Time(7),
+    ]
+    .iter()
+    .find_map(|sql_type| parse(trimmed, sql_type, BinaryEncoding::Hex).ok())
+    .and_then(|value| kind(&column_value(value)))
+    .unwrap_or(Kind::Text)
+}
+
+/// The kind of an integer, a number with a decimal point or a number with
+/// an exponent, or `None` for other text.
+fn number_kind(text: &str) -> Option<Kind> {
+    let digits = text.strip_prefix(['-', '+']).unwrap_or(text);
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+
+    if !is_digits(integer) || !is_digits(fraction) {
+        // Numbers with exponents are floats.
+        return match text.contains(['e', 'E']) && text.parse::<f64>().is_ok_and(f64::is_finite) {
+            true => Some(Kind::Float),
+            false => None,
+        };
+    }
+    if integer.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    // Leading zeros are part of codes, not numbers.
+    if integer.len() > 1 && integer.starts_with('0') {
+        return None;
+    }
+
+    if !digits.contains('.') {
+        if let Ok(value) = text.parse() {
+            return Some(Kind::Int {
+                min: value,
+                max: value,
+            });
+        }
+    }
+    Some(Kind::Decimal {
+        integer: integer.trim_start_matches('0').len(),
+        scale: fraction.len(),
+    })
+}
+
+/// The kind of the values of two kinds.
+fn widen(a: Kind, b: Kind) -> Kind {
+    use Kind::*;
+
+    match (a, b) {
+        (a, b) if a == b => a,
+        (
+            Int { min, max },
+            Int {
+                min: low,
+                max: high,
+            },
+        ) => Int {
+            min: min.min(low),
+            max: max.max(high),
+        },
+        (Int { min, max }, Decimal { integer, scale })
+        | (Decimal { integer, scale }, Int { min, max }) => Decimal {
+            integer: integer.max(integer_digits(min)).max(integer_digits(max)),
+            scale,
+        },
+        (
+            Decimal { integer, scale },
+            Decimal {
+                integer: i,
+                scale: s,
+            },
+        ) => Decimal {
+            integer: integer.max(i),
+            scale: scale.max(s),
+        },
+        (Int { .. } | Decimal { .. } | Float, Float) | (Float, Int { .. } | Decimal { .. }) => {
+            Float
+        }
+        (Time(a), Time(b)) => Time(a.max(b)),
+        (DateTime(a), DateTime(b)) => DateTime(a.max(b)),
+        (DateTime(digits), Date) | (Date, DateTime(digits)) => DateTime(digits),
+        (DateTimeOffset(a), DateTimeOffset(b)) => DateTimeOffset(a.max(b)),
+        (Binary(a), Binary(b)) => Binary(a.max(b)),
+        _ => Text,
+    }
+}
+
+fn integer_digits(value: i64) -> usize {
+    match value {
+        0 => 0,
+        value => value.unsigned_abs().ilog10() as usize + 1,
+    }
+}
+
+/// The digits of a fraction of a second of SQL Server, in units of 100
+/// nanoseconds, that hold `nanoseconds`.
+fn fraction_digits(nanoseconds: u32) -> u8 {
+    let mut ticks = nanoseconds % 1_000_000_000 / 100;
+    let mut digits = 7;
+    while ticks > 0 && ticks.is_multiple_of(10) {
+        ticks /= 10;
+        digits -= 1;
+    }
+    match ticks {
+        0 => 0,
+        _ => digits,
+    }
+}
+
+/// `Some(len)`, at least 1, up to `max`, and `None` for `max` beyond it.
+fn length(len: usize, max: u16) -> Option<u16> {
+    u16::try_from(len.max(1)).ok().filter(|len| *len <= max)
+}
@@ -1,132 +1,2444 @@
-mod sql_functions;
+pub mod arrow;
+pub mod bcp;
+pub mod blocking;
+pub mod browser;
+pub mod bulk;
+pub mod compression;
+pub mod connection;
+pub mod convert;
+pub mod copy;
+pub mod error;
+pub mod export;
+#[cfg(test)]
+pub mod fixtures;
+pub mod generate;
+pub mod infer;
+#[cfg(test)]
+pub mod mock_server;
+pub mod profile;
+pub mod retry;
+pub mod runtime;
+pub mod schema;
+pub mod secret;
+pub mod source;
+pub mod sql_functions;
+pub mod tds;
+pub mod tls;
+pub mod verify;
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
+    use crate::arrow::*;
+    use crate::bcp::*;
+    use crate::blocking;
+    use crate::browser::*;
+    use crate::bulk::*;
+    use crate::compression::*;
+    use crate::connection::*;
+    use crate::convert::*;
+    use crate::error::*;
+    use crate::fixtures::*;
+    use crate::generate::*;
+    use crate::mock_server::*;
+    use crate::profile::*;
+    use crate::retry::*;
+    use crate::schema::*;
+    use crate::secret::*;
+    use crate::source::{csv::*, excel::*, fixed::*, jsonl::*, sqlite::*, *};
     use crate::sql_functions::*;
+    use crate::tds::*;
+    use crate::tls::*;
+    use crate::verify::*;
+    use crate::runtime::{self, UdpSocket};
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use chrono::{FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+    use futures_util::future::{join_all, LocalBoxFuture};
+    use tiberius::{ColumnData, FromSql, IntoRow, IntoSql, TokenRow};
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_connect_through_port() {
-        let result = connect_through_port().await;
+        let result = connect_through_port(&test_config().unwrap()).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_bulk_insert_bit_column() {
-        let result = bulk_insert_bit_column().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_bit");
+        let result = with_tables(&config, &["random_bit"], bulk_insert_bit_column(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_bulk_insert_float_real_column() {
-        let result = bulk_insert_float_real_column().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_float");
+        let result = with_tables(&config, &["random_float"], bulk_insert_float_real_column(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_bulk_insert_integer_column() {
-        let result = bulk_insert_integer_column().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_integer");
+        let result = with_tables(&config, &["random_integer"], bulk_insert_integer_column(&config, &table)).await;
+        assert_eq!(result.is_ok(), true);
+    }
+
+    #[runtime::test]
+    async fn test_bulk_insert_integer_column_in_batches() {
+        let config = test_config().unwrap();
+        let table = test_table("random_integer");
+        let result = with_tables(&config, &["random_integer"], bulk_insert_integer_column_in_batches(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[cfg(feature="use_tds73")]
-    #[async_std::test]
+    #[test]
+    fn test_bulk_insert_integer_column_blocking() {
+        let runtime = runtime::Runtime::new().unwrap();
+        let config = test_config().unwrap();
+        let table = test_table("random_integer");
+        let tables = runtime.block_on(TestTables::acquire(&config, &["random_integer"])).unwrap();
+        let result = bulk_insert_integer_column_blocking(&config, &table).and_then(|_| {
+            runtime.block_on(read_back(&config, &table, 1000000, |i| integer_row(i as i32)))
+        });
+        runtime.block_on(tables.release()).unwrap();
+        assert_eq!(result.is_ok(), true);
+    }
+
+    #[runtime::test]
     async fn test_bulk_insert_decimal_column() {
-        let result = bulk_insert_decimal_column().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_decimal");
+        let result = with_tables(&config, &["random_decimal"], bulk_insert_decimal_column(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[cfg(feature="use_tds73")]
-    #[async_std::test]
+    #[runtime::test]
     async fn test_insert_precision_decimal() {
-        let result = insert_precision_decimal().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_precision_decimal");
+        let result = with_tables(&config, &["random_precision_decimal"], insert_precision_decimal(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_bulk_insert_char_column() {
-        let result = bulk_insert_char_column().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_string");
+        let result = with_tables(&config, &["random_string"], bulk_insert_char_column(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_bulk_insert_binary_column() {
-        let result = bulk_insert_binary_column().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_binary");
+        let result = with_tables(&config, &["random_binary"], bulk_insert_binary_column(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_bulk_insert_uniqueidentifier_column() {
-        let result = bulk_insert_uniqueidentifier_column().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_guid");
+        let result = with_tables(&config, &["random_guid"], bulk_insert_uniqueidentifier_column(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_insert_xml(){
-        let result = insert_xml().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_xml");
+        let result = with_tables(&config, &["random_xml"], insert_xml(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_insert_money(){
-        let result = insert_money().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_money");
+        let result = with_tables(&config, &["random_money"], insert_money(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }  
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_insert_text_image(){
-        let result = insert_text_image().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_text_image");
+        let result = with_tables(&config, &["random_text_image"], insert_text_image(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }  
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_bulk_insert_datetime_tds72(){
-        let result = bulk_insert_datetime_tds72().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_datetime");
+        let result = with_tables(&config, &["random_datetime"], bulk_insert_datetime_tds72(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_bulk_insert_datetime2_tds72(){
-        let result = bulk_insert_datetime2_tds72().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_datetime2");
+        let result = with_tables(&config, &["random_datetime2"], bulk_insert_datetime2_tds72(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[cfg(feature="use_tds73")]
-    #[async_std::test]
+    #[runtime::test]
     async fn test_bulk_insert_datetimeoffset_tds73(){
-        let result = bulk_insert_datetimeoffset_tds73().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_datetimeoffset");
+        let result = with_tables(&config, &["random_datetimeoffset"], bulk_insert_datetimeoffset_tds73(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_bulk_insert_smalldatetime_tds72(){
-        let result = bulk_insert_smalldatetime_tds72().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_smalldatetime");
+        let result = with_tables(&config, &["random_smalldatetime"], bulk_insert_smalldatetime_tds72(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[cfg(feature="use_tds73")]
-    #[async_std::test]
+    #[runtime::test]
     async fn test_insert_date(){
-        let result = insert_date().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_date");
+        let result = with_tables(&config, &["random_date"], insert_date(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[cfg(feature="use_tds73")]
-    #[async_std::test]
+    #[runtime::test]
     async fn test_bulk_insert_time_tds73(){
-        let result = bulk_insert_time_tds73().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_time");
+        let result = with_tables(&config, &["random_time"], bulk_insert_time_tds73(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[async_std::test]
+    #[runtime::test]
     async fn test_insert_datetime_datetime2(){
-        let result = insert_datetime_datetime2().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_datetime_datetime2");
+        let result = with_tables(&config, &["random_datetime_datetime2"], insert_datetime_datetime2(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
 
-    #[cfg(feature = "use_tds73")]
-    #[async_std::test]
+    #[runtime::test]
     async fn test_bulk_insert_more_than_10_columns(){
-        let result = bulk_insert_more_than_10_columns().await;
+        let config = test_config().unwrap();
+        let table = test_table("random_data_several_columns");
+        let result = with_tables(&config, &["random_data_several_columns"], bulk_insert_more_than_10_columns(&config, &table)).await;
         assert_eq!(result.is_ok(), true);
     }
+
+    /// Loads a table of every type with its example and reads the rows
+    /// back, so that a value the server rounds or truncates otherwise than
+    /// expected fails the test.
+    #[runtime::test]
+    async fn test_read_back_loaded_values() {
+        type Example = for<'a> fn(&'a ConnectionConfig, &'a str) -> LocalBoxFuture<'a, Result<()>>;
+        type Expected = fn(u64) -> TokenRow<'static>;
+        let cases: &[(&str, Example, u64, Expected)] = &[
+            ("random_bit", |config, table| Box::pin(bulk_insert_bit_column(config, table)), 100000, |i| bit_row(i as i32)),
+            ("random_float", |config, table| Box::pin(bulk_insert_float_real_column(config, table)), 1000000, |i| float_row(i as i32)),
+            ("random_integer", |config, table| Box::pin(bulk_insert_integer_column(config, table)), 1000000, |i| integer_row(i as i32)),
+            ("random_integer", |config, table| Box::pin(bulk_insert_integer_column_in_batches(config, table)), 1000000, |i| integer_row(i as i32)),
+            ("random_decimal", |config, table| Box::pin(bulk_insert_decimal_column(config, table)), 1000000, |i| decimal_row(i as i32)),
+            ("random_precision_decimal", |config, table| Box::pin(insert_precision_decimal(config, table)), 1, |_| precision_decimal_values().into_row()),
+            ("random_string", |config, table| Box::pin(bulk_insert_char_column(config, table)), 1000000, |i| char_row(i as i32)),
+            ("random_binary", |config, table| Box::pin(bulk_insert_binary_column(config, table)), 1000000, |i| binary_row(i as i32)),
+            ("random_guid", |config, table| Box::pin(bulk_insert_uniqueidentifier_column(config, table)), 1000000, |i| guid_row(i.into())),
+            ("random_xml", |config, table| Box::pin(insert_xml(config, table)), 1, |_| xml_value().into_row()),
+            ("random_money", |config, table| Box::pin(insert_money(config, table)), 1, |_| money_values().into_row()),
+            ("random_text_image", |config, table| Box::pin(insert_text_image(config, table)), 1, |_| text_image_values().into_row()),
+            ("random_datetime", |config, table| Box::pin(bulk_insert_datetime_tds72(config, table)), 1000000, |i| datetime_value(i as u32).into_row()),
+            ("random_datetime2", |config, table| Box::pin(bulk_insert_datetime2_tds72(config, table)), 1000000, |i| datetime2_value(i as u32).into_row()),
+            ("random_datetimeoffset", |config, table| Box::pin(bulk_insert_datetimeoffset_tds73(config, table)), 1000000, |i| datetimeoffset_row(i as i64)),
+            ("random_smalldatetime", |config, table| Box::pin(bulk_insert_smalldatetime_tds72(config, table)), 10000, |i| smalldatetime_value(i as u32).into_row()),
+            ("random_date", |config, table| Box::pin(insert_date(config, table)), 1, |_| date_value().into_row()),
+            ("random_time", |config, table| Box::pin(bulk_insert_time_tds73(config, table)), 1000000, |i| time_row(i as u32)),
+            ("random_datetime_datetime2", |config, table| Box::pin(insert_datetime_datetime2(config, table)), 1, |_| datetime_datetime2_values().into_row()),
+            ("random_data_several_columns", |config, table| Box::pin(bulk_insert_more_than_10_columns(config, table)), 1000000, |i| several_columns_row(i as i32)),
+        ];
+
+        // The types are loaded at the same time, like their own tests are.
+        let config = test_config().unwrap();
+        let results = join_all(cases.iter().map(|&(name, example, rows, expected)| {
+            let config = &config;
+            async move {
+                let table = test_table(name);
+                let result = with_tables(config, &[name], async {
+                    example(config, &table).await?;
+                    read_back(config, &table, rows, expected).await
+                })
+                .await;
+                result.map_err(|e| format!("{}: {}", name, e))
+            }
+        }))
+        .await;
+        let failures: Vec<String> = results.into_iter().filter_map(Result::err).collect();
+        assert!(failures.is_empty(), "{:#?}", failures);
+    }
+
+    /// Reads the rows of `table` back and compares them with the `rows`
+    /// loaded (see [`verify_table`]).
+    async fn read_back(
+        config: &ConnectionConfig,
+        table: &str,
+        rows: u64,
+        expected: impl FnMut(u64) -> TokenRow<'static>,
+    ) -> Result<()> {
+        let (mut client, _) = connect(config).await?;
+        verify_table(&mut client, table, rows, expected).await?;
+        client.close().await?;
+        Ok(())
+    }
+
+    /// The server of the tests: the one [`connection_config`] names when a
+    /// profile or `SQL_AUTH_CONN_STRING` is configured, or else the
+    /// [mock server](crate::mock_server::shared).
+    fn test_config() -> Result<ConnectionConfig> {
+        if connection_config_from_env()?.is_some()
+            || std::env::var_os("SQL_AUTH_CONN_STRING").is_some()
+        {
+            return connection_config();
+        }
+        crate::mock_server::shared()?.config()
+    }
+
+    /// Runs `test` with the tables of `tables.sql` it loads, which are
+    /// released however it ends.
+    async fn with_tables(config: &ConnectionConfig, tables: &[&str], test: impl std::future::Future<Output = Result<()>>) -> Result<()> {
+        let tables = TestTables::acquire(config, tables).await?;
+        let result = test.await;
+        tables.release().await?;
+        result
+    }
+
+    #[test]
+    fn test_parse_tables_sql() {
+        let tables = parse_tables(TABLES_SQL).unwrap();
+        assert_eq!(tables.len(), TABLES_SQL.matches("create table").count());
+        assert!(tables.iter().all(|table| table.schema.as_deref() == Some("dbo")));
+
+        let float = tables.iter().find(|table| table.name == "random_float").unwrap();
+        assert_eq!(
+            float.create_table_sql("bulk_test"),
+            "CREATE TABLE [bulk_test].[random_float] (\n    a_float_column float,\n    a_float_precision_column float(3)\n)"
+        );
+
+        let sql = "-- Test tables\nCREATE TABLE [my table] (id int, amount decimal(10, 2)); \nGO\ncreate table s.t\n(\n    a int -- (\n)\n";
+        let tables = parse_tables(sql).unwrap();
+        assert_eq!(tables[0], TableDefinition {
+            schema: None,
+            name: "my table".to_owned(),
+            columns: "id int, amount decimal(10, 2)".to_owned(),
+        });
+        assert_eq!((tables[1].schema.as_deref(), tables[1].name.as_str(), tables[1].columns.as_str()), (Some("s"), "t", "a int"));
+
+        assert!(matches!(parse_tables("create table t (a int)\ndrop table u"), Err(BulkError::Config(_))));
+        assert!(matches!(parse_tables("create table t (a int"), Err(BulkError::Config(_))));
+        let fixtures = Fixtures::new("bulk_test", parse_tables(TABLES_SQL).unwrap());
+        assert_eq!(fixtures.table("random_bit"), "[bulk_test].[random_bit]");
+    }
+
+    #[runtime::test]
+    async fn test_fixtures_refuse_schemas_they_did_not_create() {
+        let server = MockServer::start().unwrap();
+        let (mut client, _) = connect(&server.config().unwrap()).await.unwrap();
+        let tables = parse_tables("CREATE TABLE dbo.fruit (name varchar(10))").unwrap();
+
+        let dbo = Fixtures::new("dbo", tables.clone());
+        assert!(matches!(dbo.create(&mut client).await, Err(BulkError::Config(_))));
+        assert!(matches!(dbo.drop(&mut client).await, Err(BulkError::Config(_))));
+
+        client.execute("CREATE SCHEMA kept", &[]).await.unwrap();
+        let kept = Fixtures::new("kept", tables.clone());
+        assert!(matches!(kept.create(&mut client).await, Err(BulkError::Config(_))));
+        assert!(matches!(kept.drop(&mut client).await, Err(BulkError::Config(_))));
+
+        // A schema of the fixtures is theirs to create again, as after a run
+        // that stopped halfway, and to drop.
+        let fresh = Fixtures::new("fresh", tables);
+        fresh.create(&mut client).await.unwrap();
+        fresh.create(&mut client).await.unwrap();
+        fresh.drop(&mut client).await.unwrap();
+        fresh.drop(&mut client).await.unwrap();
+        assert!(!server.statements().iter().any(|sql| sql.contains("[dbo]") || sql.contains("[kept]")));
+    }
+
+    #[test]
+    fn test_tds_version_from_product_version() {
+        let server = ServerInfo::from_product_version("9.00.5000.00");
+        assert_eq!(server.tds_version, TdsVersion::Tds72);
+
+        let server = ServerInfo::from_product_version("10.50.6000.34");
+        assert_eq!(server.tds_version, TdsVersion::Tds73);
+
+        let server = ServerInfo::from_product_version("16.0.1000.6");
+        assert_eq!(server.major_version, 16);
+        assert_eq!(server.tds_version, TdsVersion::Tds74);
+    }
+
+    #[test]
+    fn test_adapt_date_time_values_for_tds72() {
+        let date = NaiveDate::from_ymd_opt(2023, 6, 30).unwrap();
+        let adapted = adapt_value(date.into_sql(), TdsVersion::Tds72);
+        assert!(matches!(adapted, ColumnData::DateTime(Some(_))));
+        assert_eq!(
+            NaiveDateTime::from_sql(&adapted).unwrap(),
+            date.and_hms_opt(0, 0, 0)
+        );
+
+        let time = NaiveTime::from_hms_milli_opt(10, 15, 30, 250).unwrap();
+        let adapted = adapt_value(time.into_sql(), TdsVersion::Tds72);
+        assert_eq!(adapted, ColumnData::String(Some("10:15:30.250".into())));
+
+        let offset = FixedOffset::east_opt(2 * 3600).unwrap();
+        let dto = offset.with_ymd_and_hms(2023, 6, 30, 12, 0, 0).unwrap();
+        let adapted = adapt_value(dto.into_sql(), TdsVersion::Tds72);
+        assert_eq!(
+            adapted,
+            ColumnData::String(Some("2023-06-30 12:00:00 +02:00".into()))
+        );
+
+        // Servers with TDS 7.3 or later receive the values unchanged.
+        let adapted = adapt_value(time.into_sql(), TdsVersion::Tds74);
+        assert_eq!(adapted, time.into_sql());
+    }
+
+    #[test]
+    fn test_classify_server_errors() {
+        let error = BulkError::from_server(2627, 1, 14, "Violation of PRIMARY KEY constraint");
+        assert!(matches!(error, BulkError::DuplicateKey { .. }));
+        assert_eq!(error.is_retryable(), false);
+
+        let error = BulkError::from_server(8152, 14, 16, "String or binary data would be truncated.");
+        assert!(matches!(error, BulkError::Truncation { .. }));
+
+        let error = BulkError::from_server(1205, 51, 13, "Transaction was deadlocked");
+        assert!(matches!(error, BulkError::Deadlock { .. }));
+        assert_eq!(error.is_retryable(), true);
+
+        let error = BulkError::from_server(18456, 1, 14, "Login failed for user 'developer'.");
+        assert!(matches!(error, BulkError::LoginFailed { .. }));
+        assert_eq!(error.number(), Some(18456));
+
+        let error: BulkError = std::io::Error::from(std::io::ErrorKind::ConnectionReset).into();
+        assert_eq!(error.is_retryable(), true);
+    }
+
+    #[test]
+    fn test_error_context() {
+        let result: std::result::Result<(), BulkError> =
+            Err(BulkError::from_server(2628, 1, 16, "String or binary data would be truncated."));
+        let error = result
+            .in_column("a_char_column")
+            .at_row(41)
+            .in_table("random_string")
+            .unwrap_err();
+
+        assert_eq!(
+            error.context(),
+            Some(&ErrorContext {
+                table: Some("random_string".to_owned()),
+                row: Some(41),
+                column: Some("a_char_column".to_owned()),
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            "data would be truncated (table `random_string`, row 41, column `a_char_column`): \
+             String or binary data would be truncated."
+        );
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            jitter: 0.5,
+            ..Default::default()
+        };
+
+        assert_eq!(policy.base_backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.base_backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.base_backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.base_backoff(4), Duration::from_millis(500));
+
+        for attempt in 1..10 {
+            let backoff = policy.backoff(attempt);
+            let base = policy.base_backoff(attempt);
+            assert!(backoff >= base && backoff <= base.mul_f64(1.5));
+        }
+    }
+
+    #[runtime::test]
+    async fn test_retry_transient_errors() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+
+        // A deadlock is retried until the operation succeeds.
+        let attempts = AtomicU32::new(0);
+        let result = policy
+            .run(|attempt| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if attempt < 3 {
+                        Err(BulkError::from_server(1205, 51, 13, "Transaction was deadlocked"))
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        // A duplicated key fails at once.
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = policy
+            .run(|_| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err(BulkError::from_server(2627, 1, 14, "Violation of PRIMARY KEY")) }
+            })
+            .await;
+        assert!(matches!(result, Err(BulkError::DuplicateKey { .. })));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // A database that cannot be opened stays so.
+        assert!(!BulkError::from_server(4060, 11, 1, "Cannot open database").is_retryable());
+    }
+
+    /// Answers SQL Server Browser requests for a single instance like the
+    /// service on port 1434 does.
+    async fn spawn_browser_stand_in(instance: &'static str, tcp_port: u16) -> u16 {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+
+        runtime::spawn(async move {
+            let mut buffer = [0u8; 512];
+            while let Ok((len, peer)) = socket.recv_from(&mut buffer).await {
+                let requested = String::from_utf8_lossy(&buffer[1..len - 1]).to_string();
+                if buffer[0] != 0x04 || !requested.eq_ignore_ascii_case(instance) {
+                    continue;
+                }
+
+                let data = format!(
+                    "ServerName;LOCALHOST;InstanceName;{};IsClustered;No;Version;16.0.1000.6;tcp;{};;",
+                    instance, tcp_port
+                );
+                let mut response = vec![0x05];
+                response.extend_from_slice(&(data.len() as u16).to_le_bytes());
+                response.extend_from_slice(data.as_bytes());
+                socket.send_to(&response, peer).await.unwrap();
+            }
+        });
+
+        port
+    }
+
+    #[runtime::test]
+    async fn test_resolve_named_instance() {
+        let browser_port = spawn_browser_stand_in("SQL2022D", 22828).await;
+
+        let port = resolve_instance("127.0.0.1", "sql2022d", browser_port, Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert_eq!(port, 22828);
+
+        let result =
+            resolve_instance("127.0.0.1", "SQLEXPRESS", browser_port, Duration::from_millis(200))
+                .await;
+        assert!(matches!(result, Err(BulkError::Io { .. })));
+        assert_eq!(result.unwrap_err().is_retryable(), true);
+    }
+
+    #[runtime::test]
+    async fn test_connection_config_resolves_instance_port() {
+        let browser_port = spawn_browser_stand_in("SQL2022D", 22828).await;
+
+        let mut config = ConnectionConfig::from_ado_string(
+            "server=tcp:127.0.0.1\\sql2022d;database=DestinationDB;user=developer;password=developer",
+        )
+        .unwrap();
+        assert_eq!(
+            config.server,
+            ServerAddress {
+                host: "127.0.0.1".to_owned(),
+                instance: Some("sql2022d".to_owned()),
+                port: None,
+            }
+        );
+
+        config.browser_port = browser_port;
+        assert_eq!(config.resolve().await.unwrap().get_addr(), "127.0.0.1:22828");
+
+        // An explicit port wins over SQL Server Browser.
+        let config = ConnectionConfig::from_ado_string(
+            "server=tcp:127.0.0.1\\sql2022d,1500;user=developer;password=developer",
+        )
+        .unwrap();
+        assert_eq!(config.resolve().await.unwrap().get_addr(), "127.0.0.1:1500");
+    }
+
+    #[test]
+    fn test_load_connection_profiles() {
+        let vars = |name: &str| match name {
+            "BULK_TEST_PROFILE_PASSWORD" => Some("s3cret".to_owned()),
+            "BULK_TEST_PROFILE_PORT" => Some("14330".to_owned()),
+            _ => None,
+        };
+
+        let profiles = Profiles::parse(
+            r#"
+            default = "dev"
+
+            [profiles.dev]
+            host = "localhost"
+            instance = "sql2022d"
+            database = "DestinationDB"
+            auth = { method = "sql_server", user = "developer", password = "${BULK_TEST_PROFILE_PASSWORD}" }
+            tls = { encrypt = "optional", trust_server_certificate = true }
+
+            [profiles.local]
+            host = "${BULK_TEST_PROFILE_HOST:-127.0.0.1}"
+            port = "${BULK_TEST_PROFILE_PORT}"
+            packet_size = 8192
+            "#,
+        )
+        .unwrap();
+
+        let dev = profiles.get_with(None, &vars).unwrap();
+        assert_eq!(dev.instance.as_deref(), Some("sql2022d"));
+        assert!(matches!(
+            &dev.auth,
+            Auth::SqlServer { user, password: SecretSource::Value(password) }
+                if user == "developer" && password.expose() == "s3cret"
+        ));
+        let config = dev.connection_config().unwrap();
+        assert_eq!(config.server.instance.as_deref(), Some("sql2022d"));
+        assert_eq!(config.server.port, None);
+
+        let local = profiles.get_with(Some("local"), &vars).unwrap();
+        assert_eq!(local.host, "127.0.0.1");
+        assert_eq!(local.port, Some(14330));
+        assert!(matches!(local.connection_config(), Err(BulkError::Config(_))));
+
+        // Passwords and users stay strings even when they look like numbers
+        // or booleans.
+        let vars = |name: &str| match name {
+            "BULK_TEST_PROFILE_PASSWORD" => Some("12345678".to_owned()),
+            "BULK_TEST_PROFILE_USER" => Some("true".to_owned()),
+            "BULK_TEST_PROFILE_TRUST" => Some("true".to_owned()),
+            _ => None,
+        };
+        let profiles = Profiles::parse(
+            r#"
+            [profiles.dev]
+            host = "localhost"
+            auth = { method = "sql_server", user = "${BULK_TEST_PROFILE_USER}", password = "${BULK_TEST_PROFILE_PASSWORD}" }
+            tls = { trust_server_certificate = "${BULK_TEST_PROFILE_TRUST}" }
+            "#,
+        )
+        .unwrap();
+        let dev = profiles.get_with(Some("dev"), &vars).unwrap();
+        assert!(matches!(
+            &dev.auth,
+            Auth::SqlServer { user, password: SecretSource::Value(password) }
+                if user == "true" && password.expose() == "12345678"
+        ));
+        assert!(dev.tls.trust_server_certificate);
+
+        assert!(profiles.get_with(Some("staging"), &vars).is_err());
+        let profiles = Profiles::parse("[profiles.dev]\nhost = \"${BULK_TEST_PROFILE_UNSET}\"");
+        assert!(profiles.unwrap().get_with(Some("dev"), &vars).is_err());
+    }
+
+    #[test]
+    fn test_example_profiles_file() {
+        let vars = |name: &str| (name == "SQL_PASSWORD").then(|| "developer".to_owned());
+
+        let profiles = Profiles::load("bulkload.example.toml").unwrap();
+        let profile = profiles.get_with(None, &vars).unwrap();
+        let config = profile.connection_config_with(&vars).unwrap();
+        assert!(matches!(profile.connection_config_with(&|_| None), Err(BulkError::Config(_))));
+        assert_eq!(config.server.host, "localhost");
+    }
+
+    #[test]
+    fn test_secret_is_never_printed() {
+        let secret = Secret::new("hunter2-secret");
+        assert_eq!(secret.expose(), "hunter2-secret");
+        assert_eq!(format!("{:?}", secret), "Secret(***)");
+        assert_eq!(secret.to_string(), "***");
+
+        // Known secrets are scrubbed wherever they appear.
+        assert_eq!(
+            redact("login with hunter2-secret failed"),
+            "login with *** failed"
+        );
+        let error = BulkError::from_server(18456, 1, 14, "Login failed: hunter2-secret");
+        assert_eq!(error.to_string().matches("hunter2-secret").count(), 0);
+
+        // So are passwords of a few characters.
+        let secret = Secret::new("pa55w0rd");
+        let error = BulkError::from_server(18456, 1, 14, "Login failed for user 'sa' with pa55w0rd.");
+        assert_eq!(error.to_string().matches("pa55w0rd").count(), 0);
+        assert!(format!("{:?}", error).contains("***"));
+        assert_eq!(redact(secret.expose()), "***");
+
+        // Secrets of up to three characters are only scrubbed from connection
+        // strings.
+        let secret = Secret::new("q7z");
+        assert_eq!(redact("the q7z logged in"), "the q7z logged in");
+        assert_eq!(
+            redact(&format!("user=sa;password={}", secret.expose())),
+            "user=sa;password=***"
+        );
+    }
+
+    #[test]
+    fn test_redact_connection_strings() {
+        assert_eq!(
+            redact("server=tcp:localhost;user=sa;password=p@ss;database=db"),
+            "server=tcp:localhost;user=sa;password=***;database=db"
+        );
+        assert_eq!(
+            redact("Server=.;PWD='a;b';Trust=true"),
+            "Server=.;PWD=***;Trust=true"
+        );
+        assert_eq!(redact("password="), "password=");
+        assert_eq!(redact("mypassword=visible"), "mypassword=visible");
+
+        let config = ConnectionConfig::from_ado_string(
+            "server=tcp:localhost,1433;user=sa;password=Plain-Text-Pass",
+        )
+        .unwrap();
+        assert!(!format!("{:?}", config).contains("Plain-Text-Pass"));
+
+        let password = Secret::new("kept-apart-pass");
+        let config = ConnectionConfig::from_ado_string_with_password(
+            "server=tcp:localhost;user=sa",
+            &password,
+        )
+        .unwrap();
+        assert!(!format!("{:?}", config).contains("kept-apart-pass"));
+    }
+
+    #[test]
+    fn test_secret_sources() {
+        let vars = |name: &str| (name == "BULK_TEST_SECRET").then(|| "from-env".to_owned());
+        let source: SecretSource = toml::Value::from(toml::toml! { env = "BULK_TEST_SECRET" })
+            .try_into()
+            .unwrap();
+        assert_eq!(source.resolve_with(&vars).unwrap().expose(), "from-env");
+
+        let path = std::env::temp_dir().join("bulk_test_secret_file");
+        std::fs::write(&path, "from-file\n").unwrap();
+        let source = SecretSource::File { file: path.clone() };
+        assert_eq!(source.resolve().unwrap().expose(), "from-file");
+        std::fs::remove_file(path).unwrap();
+
+        if cfg!(unix) {
+            let source = SecretSource::Command {
+                command: "echo from-command".to_owned(),
+            };
+            assert_eq!(source.resolve().unwrap().expose(), "from-command");
+            let source = SecretSource::Command {
+                command: "exit 3".to_owned(),
+            };
+            assert!(matches!(source.resolve(), Err(BulkError::Config(_))));
+        }
+
+        let source = SecretSource::Env {
+            env: "BULK_TEST_SECRET_UNSET".to_owned(),
+        };
+        assert!(matches!(source.resolve_with(&vars), Err(BulkError::Config(_))));
+        assert!(matches!(source.resolve(), Err(BulkError::Config(_))));
+    }
+
+    #[test]
+    fn test_tls_settings_from_connection_string() {
+        let pin = "ab:CD:ef:01:23:45:67:89:ab:cd:ef:01:23:45:67:89:ab:cd:ef:01:23:45:67:89:ab:cd:ef:01:23:45:67:89";
+        let tls = TlsSettings::from_ado_string(&format!(
+            "server=tcp:10.0.0.5,1433;encrypt=true;HostNameInCertificate=sql.test;CertificateThumbprint={}",
+            pin
+        ))
+        .unwrap();
+        assert_eq!(tls.encrypt, Encryption::Required);
+        assert_eq!(tls.hostname.as_deref(), Some("sql.test"));
+        assert_eq!(tls.pinned_certificates, vec![pin.parse::<Thumbprint>().unwrap()]);
+        assert_eq!(
+            tls.pinned_certificates[0].to_string(),
+            "ABCDEF0123456789ABCDEF0123456789ABCDEF0123456789ABCDEF0123456789"
+        );
+        assert_eq!(tls.encrypted_by_crate(), true);
+
+        let tls = TlsSettings::from_ado_string("server=.;encrypt=DANGER_PLAINTEXT").unwrap();
+        assert_eq!(tls.encrypt, Encryption::Off);
+        let tls = TlsSettings::from_ado_string("server=.;TrustServerCertificate=true").unwrap();
+        assert_eq!(tls.encrypt, Encryption::Optional);
+        assert_eq!(tls.encrypted_by_crate(), false);
+
+        assert!("ABCDEF".parse::<Thumbprint>().is_err());
+        assert!(pin.replace('a', "x").parse::<Thumbprint>().is_err());
+        assert!(matches!(
+            ConnectionConfig::from_ado_string(&format!(
+                "server=tcp:localhost;TrustServerCertificate=true;CertificateThumbprint={}",
+                pin
+            )),
+            Err(BulkError::Config(_))
+        ));
+        assert!(matches!(
+            ConnectionConfig::from_ado_string(
+                "server=tcp:localhost;encrypt=DANGER_PLAINTEXT;HostNameInCertificate=sql.test"
+            ),
+            Err(BulkError::Config(_))
+        ));
+    }
+
+    fn read_tds_message(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+        let mut message = Vec::new();
+        loop {
+            let mut header = [0; 8];
+            stream.read_exact(&mut header)?;
+            let mut payload = vec![0; u16::from_be_bytes([header[2], header[3]]) as usize - 8];
+            stream.read_exact(&mut payload)?;
+            message.extend_from_slice(&payload);
+            if header[1] & 0x01 != 0 {
+                return Ok(message);
+            }
+        }
+    }
+
+    fn write_tds_packet(stream: &mut TcpStream, packet_type: u8, payload: &[u8]) {
+        let length = (payload.len() + 8) as u16;
+        let mut packet = vec![packet_type, 0x01];
+        packet.extend_from_slice(&length.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 1, 0]);
+        packet.extend_from_slice(payload);
+        let _ = stream.write_all(&packet);
+    }
+
+    /// A server that answers PRELOGIN and runs the TLS handshake wrapped in
+    /// TDS packets like SQL Server, with the given certificate. The type of
+    /// the first packet it decrypts after the handshake is sent to the
+    /// receiver before the connection is closed.
+    fn spawn_tls_stand_in(
+        chain: Vec<rustls::Certificate>,
+        key: rustls::PrivateKey,
+    ) -> (u16, std::sync::mpsc::Receiver<u8>) {
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(chain, key)
+            .unwrap();
+        let config = Arc::new(config);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (packets, received) = std::sync::mpsc::channel();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut tls = rustls::ServerConnection::new(config.clone()).unwrap();
+
+                // VERSION and ENCRYPTION options, encryption on.
+                let prelogin = [
+                    0x00, 0, 11, 0, 6, 0x01, 0, 17, 0, 1, 0xff, 16, 0, 0, 0, 0, 0, 0x01,
+                ];
+                if read_tds_message(&mut stream).is_err() {
+                    continue;
+                }
+                write_tds_packet(&mut stream, 0x04, &prelogin);
+
+                while tls.is_handshaking() {
+                    if tls.wants_write() {
+                        let mut records = Vec::new();
+                        while tls.wants_write() {
+                            tls.write_tls(&mut records).unwrap();
+                        }
+                        write_tds_packet(&mut stream, 0x12, &records);
+                    } else {
+                        let Ok(message) = read_tds_message(&mut stream) else { break };
+                        let mut message = std::io::Cursor::new(message);
+                        while (message.position() as usize) < message.get_ref().len() {
+                            tls.read_tls(&mut message).unwrap();
+                        }
+                        if tls.process_new_packets().is_err() {
+                            break;
+                        }
+                    }
+                }
+
+                // After the handshake, TLS records are sent as they are.
+                let mut packet_type = [0];
+                while !tls.is_handshaking() {
+                    while tls.wants_write() {
+                        if tls.write_tls(&mut stream).is_err() {
+                            break;
+                        }
+                    }
+                    match tls.reader().read(&mut packet_type) {
+                        Ok(1) => {
+                            let _ = packets.send(packet_type[0]);
+                            break;
+                        }
+                        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                        _ => break,
+                    }
+                    if !matches!(tls.read_tls(&mut stream), Ok(1..)) {
+                        break;
+                    }
+                    if tls.process_new_packets().is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        (port, received)
+    }
+
+    #[runtime::test]
+    async fn test_tls_stream_validates_certificates() {
+        let mut ca_params = rcgen::CertificateParams::new(Vec::new());
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+        let leaf = rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec![
+            "sql.test".to_owned(),
+        ]))
+        .unwrap();
+        let leaf_der = leaf.serialize_der_with_signer(&ca).unwrap();
+        let ca_path = std::env::temp_dir().join("bulk_test_tls_ca.pem");
+        std::fs::write(&ca_path, ca.serialize_pem().unwrap()).unwrap();
+
+        let (port, received) = spawn_tls_stand_in(
+            vec![rustls::Certificate(leaf_der.clone())],
+            rustls::PrivateKey(leaf.serialize_private_key_der()),
+        );
+        let addr = format!("127.0.0.1:{}", port);
+        let settings = |ca: bool, hostname: Option<&str>, pin: Option<Thumbprint>| TlsSettings {
+            encrypt: Encryption::Required,
+            ca_certificate: ca.then(|| ca_path.clone()),
+            hostname: hostname.map(str::to_owned),
+            pinned_certificates: pin.into_iter().collect(),
+            ..Default::default()
+        };
+
+        // Pinned self-signed certificates need no CA.
+        let pin = Thumbprint::of(&leaf_der);
+        TlsStream::connect(&addr, "127.0.0.1", &settings(false, None, Some(pin)))
+            .await
+            .unwrap();
+        let other = Thumbprint::of(b"another certificate");
+        let result =
+            TlsStream::connect(&addr, "127.0.0.1", &settings(false, None, Some(other)))
+                .await;
+        assert!(matches!(result, Err(BulkError::Tls(message)) if message.contains(&pin.to_string())));
+
+        // The CA validates the certificate for the overridden host name only.
+        TlsStream::connect(&addr, "127.0.0.1", &settings(true, Some("sql.test"), None))
+            .await
+            .unwrap();
+        let result =
+            TlsStream::connect(&addr, "127.0.0.1", &settings(true, None, None)).await;
+        assert!(matches!(result, Err(BulkError::Tls(_))));
+        let result =
+            TlsStream::connect(&addr, "127.0.0.1", &settings(true, Some("other.test"), None))
+                .await;
+        assert!(matches!(result, Err(BulkError::Tls(_))));
+        TlsStream::connect(&addr, "127.0.0.1", &settings(true, Some("sql.test"), Some(pin)))
+            .await
+            .unwrap();
+
+
+        // The certificate is validated on the connection that carries the
+        // login, which Tiberius sends in the clear over the encrypted stream.
+        let login_with = |options: &str| {
+            ConnectionConfig::from_ado_string(&format!(
+                "server=tcp:127.0.0.1,{};user=sa;password=Not-Sent-In-Clear;{}",
+                port, options
+            ))
+            .unwrap()
+        };
+        while received.try_recv().is_ok() {}
+        let config = login_with(&format!("encrypt=true;CertificateThumbprint={}", pin));
+        assert!(connect(&config).await.is_err());
+        assert_eq!(received.recv_timeout(Duration::from_secs(5)), Ok(0x10));
+
+        let config = login_with(&format!("CertificateThumbprint={}", other));
+        assert!(matches!(connect(&config).await, Err(BulkError::Tls(_))));
+        let config = login_with(&format!(
+            "HostNameInCertificate=other.test;TrustServerCertificateCA={}",
+            ca_path.display()
+        ));
+        assert!(matches!(connect(&config).await, Err(BulkError::Tls(_))));
+        assert!(received.try_recv().is_err());
+
+        std::fs::remove_file(ca_path).unwrap();
+    }
+
+    #[test]
+    fn test_blocking_loader_reports_connection_errors() {
+        // Nothing listens on the port of a socket that was just closed.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = ConnectionConfig::from_ado_string(&format!(
+            "server=tcp:127.0.0.1,{};user=developer;password=developer",
+            port
+        ))
+        .unwrap();
+        let options = BulkOptions {
+            retry: RetryPolicy::never(),
+            ..Default::default()
+        };
+
+        let result = blocking::BulkLoader::connect(config, "random_integer", options);
+        assert!(matches!(result, Err(BulkError::Io { .. })));
+    }
+
+    #[runtime::test]
+    async fn test_mock_server_round_trip() {
+        let server = MockServer::start().unwrap();
+        let (mut client, info) = connect(&server.config().unwrap()).await.unwrap();
+        assert_eq!(info.product_version, MOCK_PRODUCT_VERSION);
+
+        client
+            .execute("CREATE TABLE fruit (id int IDENTITY, name char(10) NOT NULL, price float, picked datetime2(3))", &[])
+            .await
+            .unwrap();
+        client
+            .execute("INSERT INTO fruit (name, price) VALUES (@P1, @P2), ('lime', NULL)", &[&"kiwi", &1.25f64])
+            .await
+            .unwrap();
+
+        let picked = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap().and_hms_milli_opt(8, 30, 0, 123).unwrap();
+        let mut bulk = client.bulk_insert("fruit").await.unwrap();
+        let mut row = tiberius::TokenRow::new();
+        row.push("mango".into_sql());
+        row.push(0.5f64.into_sql());
+        row.push(picked.into_sql());
+        bulk.send(row).await.unwrap();
+        assert_eq!(bulk.finalize().await.unwrap().total(), 1);
+
+        // `char` values are padded and identities numbered as in SQL Server.
+        let rows = server.rows("dbo.fruit").unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0][0], ColumnData::I32(Some(1)));
+        assert_eq!(rows[1][1], ColumnData::String(Some("lime      ".into())));
+        assert_eq!(rows[1][2], ColumnData::F64(None));
+        assert_eq!(rows[2][0], ColumnData::I32(Some(3)));
+
+        let read = client
+            .simple_query("SELECT * FROM [dbo].[fruit]")
+            .await
+            .unwrap()
+            .into_first_result()
+            .await
+            .unwrap();
+        assert_eq!(read[0].get::<&str, _>("name"), Some("kiwi      "));
+        assert_eq!(read[0].get::<f64, _>("price"), Some(1.25));
+        assert_eq!(read[2].get::<NaiveDateTime, _>("picked"), Some(picked));
+
+        let schema = TableSchema::load(&mut client, "fruit").await.unwrap();
+        assert_eq!(schema.columns[0].identity, true);
+        assert_eq!(schema.columns[1].sql_type, SqlType::Char(10));
+        assert_eq!(schema.columns[3].sql_type, SqlType::DateTime2(3));
+
+        let result = client.execute("INSERT INTO fruit (price) VALUES (2)", &[]).await;
+        assert!(matches!(result, Err(tiberius::error::Error::Server(e)) if e.code() == 515));
+        let result = client.execute("INSERT INTO fruit (name) VALUES ('passion fruit')", &[]).await;
+        assert!(matches!(result, Err(tiberius::error::Error::Server(e)) if e.code() == 2628));
+        assert_eq!(server.row_count("fruit"), Some(3));
+    }
+
+    #[runtime::test]
+    async fn test_mock_server_injected_errors() {
+        let server = MockServer::start().unwrap();
+        let (mut client, _) = connect(&server.config().unwrap()).await.unwrap();
+        client.execute("CREATE TABLE numbers (n int)", &[]).await.unwrap();
+
+        server.fail_statement("insert into numbers", MockError::new(50001, "no numbers today"));
+        let result = client
+            .execute("INSERT INTO numbers VALUES (1)", &[])
+            .await
+            .map_err(BulkError::from);
+        assert!(matches!(result, Err(BulkError::Server { number: 50001, .. })));
+        client.execute("INSERT INTO numbers VALUES (1)", &[]).await.unwrap();
+        client.close().await.unwrap();
+
+        // Batches are not retried unless asked.
+        assert_eq!(BulkOptions::default().batch_retry.max_attempts, 1);
+
+        // A deadlock is retried once the whole batch has been sent.
+        let options = BulkOptions {
+            batch_size: 100,
+            batch_retry: RetryPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::from_millis(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        server.fail_bulk_load("numbers", MockError::deadlock());
+        let mut loader = BulkLoader::connect(server.config().unwrap(), "numbers", options.clone())
+            .await
+            .unwrap();
+        for n in 0..150i32 {
+            loader.send_row(n.into_row()).await.unwrap();
+        }
+        let report = loader.finish().await.unwrap();
+        assert_eq!((report.rows, report.batches, report.retries), (150, 2, 1));
+        assert_eq!(server.row_count("numbers"), Some(151));
+
+        // Every attempt of a batch, reconnections included, counts against
+        // the same budget.
+        for _ in 0..3 {
+            server.fail_bulk_load("numbers", MockError::deadlock());
+        }
+        let mut loader = BulkLoader::connect(server.config().unwrap(), "numbers", options.clone())
+            .await
+            .unwrap();
+        loader.send_row(1i32.into_row()).await.unwrap();
+        assert!(matches!(loader.finish().await, Err(BulkError::Deadlock { .. })));
+        assert_eq!(server.row_count("numbers"), Some(151));
+
+        server.fail_bulk_load("numbers", MockError::deadlock());
+        let options = BulkOptions {
+            batch_retry: RetryPolicy::never(),
+            ..options
+        };
+        let mut loader = BulkLoader::connect(server.config().unwrap(), "numbers", options)
+            .await
+            .unwrap();
+        loader.send_row(1i32.into_row()).await.unwrap();
+        assert!(matches!(loader.finish().await, Err(BulkError::Deadlock { .. })));
+        assert_eq!(server.row_count("numbers"), Some(151));
+    }
+
+    #[test]
+    fn test_same_value() {
+        let same = |expected: ColumnData<'static>, actual: ColumnData<'static>, sql_type| {
+            same_value(&expected, &actual, &sql_type)
+        };
+        let at = |ms: u32, ns: u32| {
+            NaiveDate::from_ymd_opt(2022, 8, 1)
+                .unwrap()
+                .and_hms_nano_opt(10, 0, 0, ms * 1_000_000 + ns)
+                .unwrap()
+        };
+
+        // `datetime` keeps 1/300 of a second: .004 is stored as .003.
+        let stored = to_datetime(at(3, 333_333).into_sql());
+        assert!(same(at(4, 0).into_sql(), stored.clone(), SqlType::DateTime));
+        assert!(!same(at(6, 0).into_sql(), stored, SqlType::DateTime));
+        assert!(same(at(0, 999).into_sql(), to_datetime(at(0, 0).into_sql()), SqlType::DateTime));
+        let minute = NaiveDate::from_ymd_opt(2022, 8, 1).unwrap().and_hms_opt(10, 1, 0).unwrap();
+        assert!(same(
+            NaiveDate::from_ymd_opt(2022, 8, 1).unwrap().and_hms_opt(10, 0, 45).unwrap().into_sql(),
+            convert(Value::DateTime(minute), &SqlType::SmallDateTime, BinaryEncoding::Hex).unwrap(),
+            SqlType::SmallDateTime
+        ));
+        // Times are rounded to the scale of the column.
+        let time = NaiveTime::from_hms_micro_opt(1, 2, 3, 456_789).unwrap();
+        assert!(same(
+            time.into_sql(),
+            ColumnData::Time(Some(tiberius::time::Time::new(3723457, 3))),
+            SqlType::Time(3)
+        ));
+
+        // `real` keeps single precision, `float` does not.
+        assert!(same(ColumnData::F64(Some(0.1)), ColumnData::F32(Some(0.1)), SqlType::Real));
+        assert!(!same(ColumnData::F32(Some(0.1)), ColumnData::F64(Some(0.1)), SqlType::Float));
+        assert!(same(ColumnData::I32(Some(1316)), ColumnData::F64(Some(1316.0)), SqlType::SmallMoney));
+        assert!(same(
+            ColumnData::F64(Some(701321588.25051)),
+            ColumnData::F64(Some(701321588.2505)),
+            SqlType::Money
+        ));
+
+        // `char`, `nchar` and `binary` values are padded.
+        assert!(same("kiwi".into_sql(), "kiwi  ".into_sql(), SqlType::Char(6)));
+        assert!(!same("kiwi".into_sql(), "kiwi".into_sql(), SqlType::Char(6)));
+        assert!(same("CD 💿".into_sql(), "CD 💿 ".into_sql(), SqlType::NChar(6)));
+        assert!(same(vec![10u8].into_sql(), vec![10u8, 0].into_sql(), SqlType::Binary(2)));
+        assert!(!same("kiwi".into_sql(), "kiwi ".into_sql(), SqlType::VarChar(Some(6))));
+
+        assert!(same(
+            tiberius::xml::XmlData::new("<a>\n    <b x=\"1\">2</b>\n</a>").into_sql(),
+            tiberius::xml::XmlData::new("<a><b x=\"1\">2</b></a>").into_sql(),
+            SqlType::Xml
+        ));
+        assert!(same(ColumnData::I32(None), ColumnData::String(None), SqlType::NVarChar(None)));
+        assert!(!same(ColumnData::I32(None), "".into_sql(), SqlType::NVarChar(None)));
+    }
+
+    #[test]
+    fn test_mock_server_rounds_values_by_rules_of_its_own() {
+        let at = |m: u32, s: u32, nano: u32| {
+            NaiveDate::from_ymd_opt(2022, 8, 1)
+                .unwrap()
+                .and_hms_nano_opt(10, m, s, nano)
+                .unwrap()
+        };
+        let time = |nano: u32| NaiveTime::from_hms_nano_opt(1, 2, 3, nano).unwrap();
+        let small = |value: NaiveDateTime| {
+            convert(Value::DateTime(value), &SqlType::SmallDateTime, BinaryEncoding::Hex).unwrap()
+        };
+
+        // Values, and what a rounding mistake would store instead of them.
+        let cases = [
+            // .006 is 1.8 ticks of 1/300 of a second, stored as .00667.
+            (at(0, 0, 6_000_000).into_sql(), SqlType::DateTime, to_datetime(at(0, 0, 3_333_333).into_sql())),
+            (at(0, 30, 0).into_sql(), SqlType::SmallDateTime, small(at(0, 0, 0))),
+            (at(0, 29, 997_000_000).into_sql(), SqlType::SmallDateTime, small(at(1, 0, 0))),
+            (ColumnData::F64(Some(0.1)), SqlType::Real, ColumnData::F64(Some(0.1))),
+            (time(456_500_000).into_sql(), SqlType::Time(3), ColumnData::Time(Some(tiberius::time::Time::new(3723456, 3)))),
+            (at(0, 0, 125_000_000).into_sql(), SqlType::DateTime2(2), at(0, 0, 120_000_000).into_sql()),
+            ("kiwi".into_sql(), SqlType::Char(6), "kiwi".into_sql()),
+            (vec![10u8].into_sql(), SqlType::Binary(2), vec![10u8].into_sql()),
+        ];
+        for (value, sql_type, mistake) in cases {
+            let stored = crate::mock_server::store(value.clone(), &sql_type).unwrap();
+            assert_eq!(stored, stored_value(value.clone(), &sql_type).unwrap(), "{:?} as {}", value, sql_type);
+            assert!(same_value(&value, &stored, &sql_type));
+
+            // Stored by the mock server, the mistake fails the verification;
+            // expected by the verification, it is not what the mock stores.
+            assert!(!same_value(&value, &mistake, &sql_type), "{:?} as {}", value, sql_type);
+            assert_ne!(mistake, stored);
+        }
+    }
+
+    #[runtime::test]
+    async fn test_verify_table_reports_mismatches() {
+        let server = MockServer::start().unwrap();
+        let (mut client, _) = connect(&server.config().unwrap()).await.unwrap();
+        client
+            .execute("CREATE TABLE fruit (id int IDENTITY, name varchar(10), weight real)", &[])
+            .await
+            .unwrap();
+        client
+            .execute("INSERT INTO fruit (name, weight) VALUES ('kiwi', 0.1), ('lime', 0.2), ('mango', 0.3)", &[])
+            .await
+            .unwrap();
+
+        let loaded = [("kiwi", 0.1), ("lime", 0.2), ("mango", 0.3)];
+        verify_table(&mut client, "fruit", 3, |i| loaded[i as usize].into_row())
+            .await
+            .unwrap();
+        // Rows are matched whatever order they are read back in, but each as
+        // many times as it was loaded.
+        verify_table(&mut client, "fruit", 3, |i| loaded[2 - i as usize].into_row())
+            .await
+            .unwrap();
+        let twice = [("kiwi", 0.1), ("kiwi", 0.1), ("mango", 0.3)];
+        assert!(verify_table(&mut client, "fruit", 3, |i| twice[i as usize].into_row())
+            .await
+            .is_err());
+
+        let loaded = [("kiwi", 0.1), ("lemon", 0.2), ("mango", 0.25)];
+        let error = verify_table(&mut client, "fruit", 3, |i| loaded[i as usize].into_row())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "values differ from those loaded (table `fruit`): 2 rows differ, first loaded \
+             but not read back: row 1 (\"lemon\", 0.2), row 2 (\"mango\", 0.25); \
+             first read back but not loaded: (\"lime\", 0.2), (\"mango\", 0.3)"
+        );
+
+        let error = verify_table(&mut client, "fruit", 4, |i| loaded[i as usize % 3].into_row())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, BulkError::Mismatch { .. }));
+        assert!(error.to_string().ends_with("4 rows were loaded, 3 were read back"));
+    }
+
+    fn column(name: &str, sql_type: SqlType) -> Column {
+        Column {
+            name: name.to_owned(),
+            sql_type,
+            nullable: true,
+            identity: false,
+            computed: false,
+        }
+    }
+
+    #[test]
+    fn test_sql_types_from_sys_columns() {
+        let types = [
+            (("int", 4, 10, 0), SqlType::Int, "int"),
+            (("numeric", 9, 10, 6), SqlType::Decimal { precision: 10, scale: 6 }, "decimal(10,6)"),
+            (("nchar", 28, 0, 0), SqlType::NChar(14), "nchar(14)"),
+            (("nvarchar", -1, 0, 0), SqlType::NVarChar(None), "nvarchar(max)"),
+            (("varbinary", 2900, 0, 0), SqlType::VarBinary(Some(2900)), "varbinary(2900)"),
+            (("datetime2", 8, 27, 7), SqlType::DateTime2(7), "datetime2(7)"),
+            (("timestamp", 8, 0, 0), SqlType::RowVersion, "rowversion"),
+            (("geography", -1, 0, 0), SqlType::Other("geography".to_owned()), "geography"),
+        ];
+
+        for ((name, max_length, precision, scale), sql_type, declaration) in types {
+            let parsed = SqlType::from_sys_columns(name, max_length, precision, scale);
+            assert_eq!(parsed, sql_type);
+            assert_eq!(parsed.to_string(), declaration);
+        }
+
+        assert_eq!(SqlType::Money.is_bulk_loadable(), false);
+        assert_eq!(SqlType::NVarChar(None).is_bulk_loadable(), true);
+    }
+
+    #[test]
+    fn test_table_schema_insertable_columns() {
+        let mut id = column("id", SqlType::Int);
+        id.identity = true;
+        let schema = TableSchema {
+            table: "random_money".to_owned(),
+            columns: vec![
+                id,
+                column("a_money_column", SqlType::Money),
+                column("version", SqlType::RowVersion),
+            ],
+        };
+
+        let names: Vec<_> = schema.insertable_columns().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["a_money_column"]);
+
+        let error = schema.check_bulk_loadable().unwrap_err();
+        assert_eq!(
+            error.context().unwrap().column.as_deref(),
+            Some("a_money_column")
+        );
+        assert!(RowGenerator::new(&schema, 0).is_err());
+    }
+
+    #[test]
+    fn test_quote_object_name() {
+        assert_eq!(quote_object_name("random_integer").unwrap(), "[random_integer]");
+        assert_eq!(quote_object_name(" dbo.[order details] ").unwrap(), "[dbo].[order details]");
+        assert_eq!(quote_object_name("\"sales\".[a]]b]").unwrap(), "[sales].[a]]b]");
+        assert!(quote_object_name("t; DROP TABLE users").is_err());
+        assert!(quote_object_name("a.b.c.d.e").is_err());
+        assert!(quote_object_name("").is_err());
+        assert_eq!(quote_object_name("[a]]]").unwrap(), "[a]]]");
+        assert_eq!(quote_object_name("[a]]b]").unwrap(), "[a]]b]");
+        assert_eq!(quote_object_name("\"a\"\"b\"").unwrap(), "[a\"b]");
+        assert_eq!(quote_object_name("[a]]].\"b\"\".c\"").unwrap(), "[a]]].[b\".c]");
+        assert_eq!(unquote("[a]]]"), "a]");
+        assert_eq!(unquote("[a]]b]"), "a]b");
+        assert_eq!(unquote("\"a\"\"b\""), "a\"b");
+    }
+
+    #[test]
+    fn test_generate_rows() {
+        let mut id = column("id", SqlType::BigInt);
+        id.identity = true;
+        let schema = TableSchema {
+            table: "random_precision_decimal".to_owned(),
+            columns: vec![
+                id,
+                column("a_decimal_column", SqlType::Decimal { precision: 5, scale: 4 }),
+                column("a_char_column", SqlType::Char(21)),
+                column("a_datetime_column", SqlType::DateTime),
+                column("a_guid_column", SqlType::UniqueIdentifier),
+            ],
+        };
+
+        let mut generator = RowGenerator::new(&schema, 42).unwrap();
+        let row: Vec<_> = generator.next_row().into_iter().collect();
+        assert_eq!(row.len(), 4);
+        assert!(matches!(&row[0], ColumnData::Numeric(Some(n)) if n.scale() == 4 && n.value().abs() < 100_000));
+        assert!(matches!(&row[1], ColumnData::String(Some(s)) if s.len() == 21));
+        assert!(matches!(&row[2], ColumnData::DateTime(Some(_))));
+        assert!(matches!(&row[3], ColumnData::Guid(Some(_))));
+
+        // The same seed generates the same rows.
+        let mut again = RowGenerator::new(&schema, 42).unwrap();
+        assert_eq!(format!("{:?}", again.next_row()), format!("{:?}", row_of(row)));
+
+        let mut nulls = RowGenerator::new(&schema, 7).unwrap().null_ratio(1.0);
+        assert!(nulls.next_row().into_iter().all(|value| format!("{:?}", value).ends_with("(None)")));
+    }
+
+    #[test]
+    fn test_parse_text_values() {
+        let hex = BinaryEncoding::Hex;
+        let parse_ok = |text: &str, sql_type: SqlType| parse(text, &sql_type, hex).unwrap();
+
+        assert_eq!(parse_ok("yes", SqlType::Bit), ColumnData::Bit(Some(true)));
+        assert_eq!(parse_ok(" 255 ", SqlType::TinyInt), ColumnData::U8(Some(255)));
+        assert_eq!(parse_ok("-32768", SqlType::SmallInt), ColumnData::I16(Some(-32768)));
+        let error = parse("256", &SqlType::TinyInt, hex).unwrap_err();
+        assert!(error.to_string().contains("out of range for tinyint"));
+        assert!(parse("12a", &SqlType::Int, hex).is_err());
+
+        // Decimals are rounded half away from zero to the scale of the column.
+        let decimal = |text: &str| match parse(text, &SqlType::Decimal { precision: 5, scale: 4 }, hex) {
+            Ok(ColumnData::Numeric(Some(n))) => Some((n.value(), n.scale())),
+            _ => None,
+        };
+        assert_eq!(decimal("3.14159"), Some((31416, 4)));
+        assert_eq!(decimal("-0.00005"), Some((-1, 4)));
+        assert_eq!(decimal("2"), Some((20000, 4)));
+        assert_eq!(decimal("1.5e-3"), Some((15, 4)));
+        assert_eq!(decimal("12.5"), None);
+        assert_eq!(decimal("1.2.3"), None);
+
+        assert_eq!(
+            parse_ok("{6F9619FF-8B86-D011-B42D-00C04FC964FF}", SqlType::UniqueIdentifier),
+            ColumnData::Guid(Some(tiberius::Uuid::from_u128(0x6F9619FF_8B86_D011_B42D_00C04FC964FF)))
+        );
+        assert_eq!(parse_ok("2023-04-05", SqlType::Date), NaiveDate::from_ymd_opt(2023, 4, 5).into_sql());
+        assert!(matches!(parse_ok("2023-04-05 10:11:12.5", SqlType::DateTime), ColumnData::DateTime(Some(_))));
+        assert!(matches!(parse_ok("2023-04-05T10:11:12.1234567", SqlType::DateTime2(7)), ColumnData::DateTime2(Some(_))));
+        assert!(parse("1700-01-01", &SqlType::DateTime, hex).is_err());
+        assert_eq!(
+            parse_ok("1900-01-02 00:01:30", SqlType::SmallDateTime),
+            ColumnData::SmallDateTime(Some(tiberius::time::SmallDateTime::new(1, 2)))
+        );
+        assert!(matches!(parse_ok("2023-04-05T10:11:12+02:00", SqlType::DateTimeOffset(7)), ColumnData::DateTimeOffset(Some(_))));
+        assert!(parse("2023-04-05T10:11:12", &SqlType::DateTimeOffset(7), hex).is_err());
+
+        let bytes = |value: ColumnData<'static>| match value {
+            ColumnData::Binary(Some(bytes)) => bytes.into_owned(),
+            _ => panic!("not binary"),
+        };
+        assert_eq!(bytes(parse_ok("0xCAFE", SqlType::VarBinary(None))), [0xCA, 0xFE]);
+        assert_eq!(bytes(parse_ok("cafe", SqlType::Binary(2))), [0xCA, 0xFE]);
+        assert_eq!(bytes(parse("yv4=", &SqlType::VarBinary(Some(2)), BinaryEncoding::Base64).unwrap()), [0xCA, 0xFE]);
+        assert!(parse("0xCAFE00", &SqlType::Binary(2), hex).is_err());
+        assert!(parse("0xCAF", &SqlType::VarBinary(None), hex).is_err());
+
+        assert!(parse("abc", &SqlType::VarChar(Some(3)), hex).is_ok());
+        assert!(parse("abcd", &SqlType::NChar(3), hex).is_err());
+
+        // Values of other kinds go through their text.
+        assert_eq!(convert(Value::Int(7), &SqlType::BigInt, hex).unwrap(), ColumnData::I64(Some(7)));
+        assert_eq!(convert(Value::Bool(true), &SqlType::Bit, hex).unwrap(), ColumnData::Bit(Some(true)));
+        assert_eq!(convert(Value::Null, &SqlType::Int, hex).unwrap(), ColumnData::I32(None));
+        assert!(convert(Value::Float(f64::NAN), &SqlType::Float, hex).is_err());
+        assert!(convert(Value::Bytes(vec![1]), &SqlType::Int, hex).is_err());
+    }
+
+    fn csv_test_schema() -> TableSchema {
+        let mut id = column("id", SqlType::Int);
+        id.identity = true;
+        let mut code = column("code", SqlType::VarChar(Some(10)));
+        code.nullable = false;
+        TableSchema {
+            table: "csv_test".to_owned(),
+            columns: vec![
+                id,
+                code,
+                column("amount", SqlType::Decimal { precision: 10, scale: 2 }),
+                column("flag", SqlType::Bit),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_column_map() {
+        let schema = csv_test_schema();
+        let fields = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        let options = IngestOptions::default();
+
+        // Names are matched without regard to case, in any order.
+        let map = ColumnMap::new(&schema, Some(&fields(&["FLAG", "Code"])), &options).unwrap();
+        assert_eq!(map.mapped_columns().collect::<Vec<_>>(), ["code", "flag"]);
+        let row: Vec<_> = map
+            .row(vec![Value::Text("0".into()), Value::Text("A1".into())])
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(row[0], ColumnData::String(Some("A1".into())));
+        assert_eq!(row[1], ColumnData::Numeric(None));
+        assert_eq!(row[2], ColumnData::Bit(Some(false)));
+
+        let error = map.row(vec![Value::Text("1".into()), Value::Null]).unwrap_err();
+        assert_eq!(error.context().unwrap().column.as_deref(), Some("code"));
+        let error = map.row(vec![Value::Text("maybe".into()), Value::Text("A1".into())]).unwrap_err();
+        assert_eq!(error.context().unwrap().column.as_deref(), Some("flag"));
+
+        let renamed = IngestOptions {
+            rename: vec![("product".to_owned(), "code".to_owned())],
+            ..Default::default()
+        };
+        assert!(ColumnMap::new(&schema, Some(&fields(&["product"])), &renamed).is_ok());
+
+        let extra = fields(&["code", "comment"]);
+        assert!(matches!(ColumnMap::new(&schema, Some(&extra), &options), Err(BulkError::Config(_))));
+        let ignore = IngestOptions {
+            ignore_extra_fields: true,
+            ..Default::default()
+        };
+        assert!(ColumnMap::new(&schema, Some(&extra), &ignore).is_ok());
+
+        // A column that does not allow NULLs needs a field.
+        let error = ColumnMap::new(&schema, Some(&fields(&["amount"])), &options).unwrap_err();
+        assert_eq!(error.context().unwrap().column.as_deref(), Some("code"));
+
+        // Without field names the fields are loaded by position.
+        let map = ColumnMap::new(&schema, None, &options).unwrap();
+        assert_eq!(map.mapped_columns().count(), 3);
+    }
+
+    #[test]
+    fn test_convert_server_values() {
+        // Values read from SQL Server keep their types on the way to the
+        // columns of another table.
+        let numeric = |value, scale| ColumnData::Numeric(Some(tiberius::numeric::Numeric::new_with_scale(value, scale)));
+        let decimal = SqlType::Decimal { precision: 10, scale: 2 };
+        assert_eq!(convert_column(numeric(123456, 3), &decimal).unwrap(), numeric(12346, 2));
+        assert_eq!(convert_column(ColumnData::U8(Some(7)), &SqlType::BigInt).unwrap(), ColumnData::I64(Some(7)));
+        assert_eq!(convert_column(ColumnData::I64(Some(70_000)), &SqlType::SmallInt).unwrap_err().to_string(), "conversion error: `70000` is out of range for smallint");
+        assert_eq!(convert_column(ColumnData::F32(Some(0.1)), &SqlType::Real).unwrap(), ColumnData::F32(Some(0.1)));
+        assert_eq!(convert_column(ColumnData::F64(Some(0.25)), &SqlType::Float).unwrap(), ColumnData::F64(Some(0.25)));
+        let at = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap().and_hms_milli_opt(8, 15, 30, 123).unwrap();
+        let legacy = to_datetime(at.into_sql());
+        assert_eq!(convert_column(legacy.clone(), &SqlType::DateTime).unwrap(), legacy);
+        assert_eq!(convert_column(at.into_sql(), &SqlType::Date).unwrap(), at.date().into_sql());
+        let offset = FixedOffset::east_opt(3600).unwrap().from_local_datetime(&at).unwrap();
+        assert_eq!(convert_column(offset.into_sql(), &SqlType::DateTimeOffset(7)).unwrap(), offset.into_sql());
+        assert_eq!(convert_column(ColumnData::String(Some("A1".into())), &SqlType::NVarChar(Some(2))).unwrap(), ColumnData::String(Some("A1".into())));
+        assert_eq!(convert_column(ColumnData::I32(None), &SqlType::VarChar(None)).unwrap(), ColumnData::String(None));
+        assert_eq!(column_value(numeric(-5, 2)), Value::Text("-0.05".into()));
+        assert_eq!(column_value(ColumnData::Bit(Some(true))), Value::Bool(true));
+
+        // Rows are matched with the columns like the records of a file.
+        let schema = csv_test_schema();
+        let fields = vec!["flag".to_owned(), "code".to_owned(), "amount".to_owned()];
+        let map = ColumnMap::new(&schema, Some(&fields), &IngestOptions::default()).unwrap();
+        let row: Vec<_> = map
+            .column_row(vec![ColumnData::Bit(Some(true)), ColumnData::String(Some("A1".into())), numeric(150, 2)])
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(row, [ColumnData::String(Some("A1".into())), numeric(150, 2), ColumnData::Bit(Some(true))]);
+        let error = map.column_row(vec![ColumnData::Bit(None), ColumnData::String(None), numeric(1, 0)]).unwrap_err();
+        assert_eq!(error.context().unwrap().column.as_deref(), Some("code"));
+    }
+
+    #[test]
+    fn test_export_rows() {
+        use crate::export::*;
+        use crate::source::parquet::ParquetSource;
+
+        let mut id = column("id", SqlType::Int);
+        id.nullable = false;
+        let schema = TableSchema {
+            table: "export_test".to_owned(),
+            columns: vec![
+                id,
+                column("amount", SqlType::Decimal { precision: 10, scale: 3 }),
+                column("price", SqlType::Money),
+                column("at", SqlType::DateTimeOffset(7)),
+                column("blob", SqlType::VarBinary(None)),
+                column("guid", SqlType::UniqueIdentifier),
+                column("flag", SqlType::Bit),
+                column("name", SqlType::NVarChar(Some(10))),
+                column("day", SqlType::Date),
+            ],
+        };
+        let guid = tiberius::Uuid::from_u128(0x6F9619FF_8B86_D011_B42D_00C04FC964FF);
+        let at = FixedOffset::east_opt(5400).unwrap().with_ymd_and_hms(2024, 1, 31, 13, 30, 0).unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        let numeric = |value| ColumnData::Numeric(Some(tiberius::numeric::Numeric::new_with_scale(value, 3)));
+        let rows = || vec![
+            vec![
+                ColumnData::I32(Some(1)), numeric(-12345), ColumnData::F64(Some(1.5)), at.into_sql(), ColumnData::Binary(Some(vec![0xCA, 0xFE].into())),
+                ColumnData::Guid(Some(guid)), ColumnData::Bit(Some(true)), ColumnData::String(Some("a,\"b\"".into())), day.into_sql(),
+            ],
+            vec![
+                ColumnData::I32(Some(2)), ColumnData::Numeric(None), ColumnData::F64(None), ColumnData::DateTimeOffset(None), ColumnData::Binary(None),
+                ColumnData::Guid(None), ColumnData::Bit(None), ColumnData::String(None), ColumnData::Date(None),
+            ],
+        ];
+
+        // CSV as SQL Server writes values as text, which loads back.
+        let options = ExportOptions { null_marker: "NULL".to_owned(), binary_encoding: BinaryEncoding::Base64, ..Default::default() };
+        let mut writer = CsvWriter::new(Vec::new(), &schema, &options);
+        for row in rows() {
+            writer.write_row(row).unwrap();
+        }
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "id,amount,price,at,blob,guid,flag,name,day\n\
+             1,-12.345,1.5,2024-01-31 13:30:00 +01:30,yv4=,6F9619FF-8B86-D011-B42D-00C04FC964FF,1,\"a,\"\"b\"\"\",2024-02-29\n\
+             2,NULL,NULL,NULL,NULL,NULL,NULL,NULL,NULL\n"
+        );
+        let error = CsvWriter::new(Vec::new(), &schema, &options).write_row(vec![ColumnData::I32(Some(1))]).unwrap_err();
+        assert!(matches!(error, BulkError::Config(_)));
+
+        // JSON Lines keep numbers as numbers, with all the digits of decimals.
+        let mut writer = JsonlWriter::new(Vec::new(), &schema, &ExportOptions::default());
+        for row in rows() {
+            writer.write_row(row).unwrap();
+        }
+        let jsonl = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            jsonl.lines().next().unwrap(),
+            r#"{"id":1,"amount":-12.345,"price":1.5,"at":"2024-01-31 13:30:00 +01:30","blob":"CAFE","guid":"6F9619FF-8B86-D011-B42D-00C04FC964FF","flag":true,"name":"a,\"b\"","day":"2024-02-29"}"#
+        );
+        let fields: Vec<String> = schema.columns.iter().map(|c| c.name.clone()).collect();
+        let mut source = JsonlSource::from_reader(jsonl.as_bytes(), &fields).unwrap();
+        // Money columns are not bulk loaded.
+        let mut loadable = schema.clone();
+        loadable.columns.retain(|column| column.sql_type != SqlType::Money);
+        let options = IngestOptions { ignore_extra_fields: true, ..Default::default() };
+        let map = ColumnMap::new(&loadable, source.fields(), &options).unwrap();
+        let loaded: Vec<_> = map.row(source.next_record().unwrap().unwrap()).unwrap().into_iter().collect();
+        assert_eq!(loaded[1], numeric(-12345));
+        assert_eq!(loaded[2], at.into_sql());
+        assert_eq!(loaded[3], ColumnData::Binary(Some(vec![0xCA, 0xFE].into())));
+
+        // Parquet keeps the types: decimals, money as decimals, UTC instants
+        // and UUIDs.
+        let mut writer = ParquetWriter::with_batch_size(Vec::new(), &schema, 1).unwrap();
+        for row in rows() {
+            writer.write_row(row).unwrap();
+        }
+        let parquet = writer.into_inner().unwrap();
+        let mut source = ParquetSource::from_reader(bytes::Bytes::from(parquet), 10).unwrap();
+        assert_eq!(source.fields().unwrap(), fields);
+        assert_eq!(source.next_record().unwrap().unwrap(), [
+            Value::Int(1), Value::Text("-12.345".into()), Value::Text("1.5000".into()),
+            Value::DateTimeOffset(at.with_timezone(&FixedOffset::east_opt(0).unwrap())), Value::Bytes(vec![0xCA, 0xFE]),
+            Value::Bytes(guid.as_bytes().to_vec()), Value::Bool(true), Value::Text("a,\"b\"".into()), Value::Date(day),
+        ]);
+        assert!(source.next_record().unwrap().unwrap()[1..].iter().all(|value| *value == Value::Null));
+        assert_eq!(source.next_record().unwrap(), None);
+        assert_eq!(arrow_type(&SqlType::DateTime2(7)), arrow_schema::DataType::Timestamp(arrow_schema::TimeUnit::Nanosecond, None));
+
+        // The file is flushed when it is finished, and failing to flush fails
+        // the export.
+        struct FullDisk;
+        impl Write for FullDisk {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Err(std::io::Error::other("no space left on device"))
+            }
+        }
+        let mut writer = ParquetWriter::new(FullDisk, &schema).unwrap();
+        writer.write_row(rows().remove(0)).unwrap();
+        assert!(matches!(writer.finish(), Err(BulkError::Io { .. })));
+    }
+
+    #[test]
+    fn test_infer_schema() {
+        use crate::infer::*;
+
+        let data = "id,big,price,code,name,active,guid,day,at,at_tz,ratio,mixed,empty\n\
+                    1,3000000000,12.50,00123,Zoë,true,6F9619FF-8B86-D011-B42D-00C04FC964FF,2024-01-31,2024-01-31 10:00:00.123,2024-01-31T10:00:00+02:00,1e-3,7,\n\
+                    2,4,-3.125,00042,Bob,FALSE,6F9619FF-8B86-D011-B42D-00C04FC964FE,2024-02-01,2024-02-01,2024-02-01 10:00:00.5 +00:00,2,x,\n";
+        let mut source = CsvSource::from_reader(data.as_bytes(), &CsvOptions::default()).unwrap();
+        let schema = infer_schema(&mut source, "dbo.feed", &InferOptions::default()).unwrap();
+        let types: Vec<String> = schema.columns.iter().map(|c| c.sql_type.to_string()).collect();
+        assert_eq!(types, [
+            "int", "bigint", "decimal(5,3)", "varchar(5)", "nvarchar(3)", "bit", "uniqueidentifier", "date", "datetime2(3)",
+            "datetimeoffset(1)", "float", "varchar(1)", "nvarchar(max)",
+        ]);
+        assert!(schema.columns[..12].iter().all(|column| !column.nullable));
+        assert!(schema.columns[12].nullable);
+        assert!(schema.create_table_sql().unwrap().starts_with("CREATE TABLE [dbo].[feed] (\n    [id] int NOT NULL,\n"));
+        let mut injected = schema.clone();
+        injected.table = "feed; DROP TABLE users".to_owned();
+        assert!(matches!(injected.create_table_sql(), Err(BulkError::Config(_))));
+
+        // Only the sample counts.
+        let mut source = CsvSource::from_reader(data.as_bytes(), &CsvOptions::default()).unwrap();
+        let schema = infer_schema(&mut source, "feed", &InferOptions { sample_rows: Some(1) }).unwrap();
+        assert_eq!(schema.columns[1].sql_type, SqlType::BigInt);
+        assert_eq!(schema.columns[2].sql_type, SqlType::Decimal { precision: 4, scale: 2 });
+        assert_eq!(schema.columns[11].sql_type, SqlType::Int);
+
+        // Keys missing from some objects are nullable; JSON numbers keep
+        // their digits.
+        let data = "{\"id\":1,\"amount\":1.5,\"tags\":[\"a\"]}\n\n{\"id\":2,\"extra\":\"hé\",\"amount\":100}\n";
+        let fields = sample_fields(data.as_bytes(), None).unwrap();
+        assert_eq!(fields, ["id", "amount", "tags", "extra"]);
+        let mut source = JsonlSource::from_reader(data.as_bytes(), &fields).unwrap();
+        let schema = infer_schema(&mut source, "feed", &InferOptions::default()).unwrap();
+        let columns: Vec<(String, bool)> = schema.columns.iter().map(|c| (c.sql_type.to_string(), c.nullable)).collect();
+        assert_eq!(columns, [
+            ("int".to_owned(), false), ("decimal(4,1)".to_owned(), false), ("varchar(5)".to_owned(), true), ("nvarchar(2)".to_owned(), true),
+        ]);
+        assert_eq!(sample_fields(data.as_bytes(), Some(1)).unwrap(), ["id", "amount", "tags"]);
+
+        // Typed values keep their types; fields are named by position
+        // without a header.
+        let options = CsvOptions { has_header: false, ..Default::default() };
+        let mut source = CsvSource::from_reader("".as_bytes(), &options).unwrap();
+        assert!(matches!(infer_schema(&mut source, "feed", &InferOptions::default()), Err(BulkError::Config(_))));
+        let mut source = CsvSource::from_reader("12:30,0x1F\n12:30:00.25,0xABCD\n".as_bytes(), &options).unwrap();
+        let schema = infer_schema(&mut source, "feed", &InferOptions::default()).unwrap();
+        assert_eq!(schema.columns[0].name, "column1");
+        assert_eq!(schema.columns[0].sql_type, SqlType::Time(2));
+        assert_eq!(schema.columns[1].sql_type, SqlType::VarChar(Some(6)));
+    }
+
+    #[test]
+    fn test_csv_source() {
+        let options = CsvOptions {
+            delimiter: b';',
+            quote: b'\'',
+            escape: Some(b'\\'),
+            null_markers: vec!["NULL".to_owned()],
+            ..Default::default()
+        };
+        let data = "code;amount;flag\n'a;\\'b';12.345;1\nc;NULL;\nd;1\ne;;0\n";
+        let mut source = CsvSource::from_reader(data.as_bytes(), &options).unwrap();
+        assert_eq!(source.fields().unwrap(), ["code", "amount", "flag"]);
+
+        let text = |s: &str| Value::Text(s.to_owned());
+        assert_eq!(source.next_record().unwrap().unwrap(), [text("a;'b"), text("12.345"), text("1")]);
+        assert_eq!(source.next_record().unwrap().unwrap(), [text("c"), Value::Null, text("")]);
+        // A malformed record is an error of its own.
+        assert!(matches!(source.next_record(), Err(BulkError::Conversion { .. })));
+        assert_eq!(source.next_record().unwrap().unwrap(), [text("e"), text(""), text("0")]);
+        assert_eq!(source.next_record().unwrap(), None);
+
+        let schema = csv_test_schema();
+        let mut source = CsvSource::from_reader("x,,1\n".as_bytes(), &CsvOptions {
+            has_header: false,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(source.fields(), None);
+        let map = ColumnMap::new(&schema, source.fields(), &IngestOptions::default()).unwrap();
+        let row: Vec<_> = map.row(source.next_record().unwrap().unwrap()).unwrap().into_iter().collect();
+        assert_eq!(row, [ColumnData::String(Some("x".into())), ColumnData::Numeric(None), ColumnData::Bit(Some(true))]);
+    }
+
+    #[test]
+    fn test_json_paths() {
+        let document: serde_json::Value =
+            serde_json::from_str(r#"{"device": {"id": 7, "tags": ["a", "b"]}, "name": "x"}"#).unwrap();
+        let find = |path: &str| path.parse::<JsonPath>().unwrap().find(&document).cloned();
+
+        assert_eq!(find("$.device.id"), Some(serde_json::json!(7)));
+        assert_eq!(find("device.tags[1]"), Some(serde_json::json!("b")));
+        assert_eq!(find("name"), Some(serde_json::json!("x")));
+        assert_eq!(find("$.device.serial"), None);
+        assert_eq!(find("$.name[0]"), None);
+        assert!("$.device..id".parse::<JsonPath>().is_err());
+        assert!("$.tags[x]".parse::<JsonPath>().is_err());
+    }
+
+    #[test]
+    fn test_jsonl_source() {
+        let schema = TableSchema {
+            table: "readings".to_owned(),
+            columns: vec![
+                column("device_id", SqlType::BigInt),
+                column("amount", SqlType::Decimal { precision: 38, scale: 20 }),
+                column("payload", SqlType::NVarChar(None)),
+                column("ok", SqlType::Bit),
+            ],
+        };
+        let rename = vec![("$.device.id".to_owned(), "device_id".to_owned())];
+        let fields = default_fields(&schema, &rename).unwrap();
+        let names: Vec<_> = fields.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["amount", "payload", "ok", "$.device.id"]);
+
+        let data = concat!(
+            r#"{"device": {"id": 7}, "amount": 1.23456789012345678901, "payload": {"a": [1, 2]}, "ok": true}"#,
+            "\n\n",
+            "{not json}\n",
+            r#"{"device": {}, "amount": null}"#,
+            "\n",
+        );
+        let mut source = JsonlSource::with_paths(data.as_bytes(), fields);
+        let options = IngestOptions {
+            rename,
+            ..Default::default()
+        };
+        let map = ColumnMap::new(&schema, source.fields(), &options).unwrap();
+
+        let row: Vec<_> = map.row(source.next_record().unwrap().unwrap()).unwrap().into_iter().collect();
+        assert_eq!(row[0], ColumnData::I64(Some(7)));
+        // Decimals keep all their digits, objects are stored as JSON text.
+        assert!(matches!(&row[1], ColumnData::Numeric(Some(n)) if n.value() == 123456789012345678901 && n.scale() == 20));
+        assert_eq!(row[2], ColumnData::String(Some(r#"{"a":[1,2]}"#.into())));
+        assert_eq!(row[3], ColumnData::Bit(Some(true)));
+
+        // Blank lines are skipped, invalid lines are errors of their own.
+        assert!(matches!(source.next_record(), Err(BulkError::Conversion { .. })));
+        assert_eq!(source.next_record().unwrap().unwrap(), vec![Value::Null; 4]);
+        assert_eq!(source.next_record().unwrap(), None);
+
+        // Columns are read from keys of their name, dots and brackets too.
+        let schema = TableSchema {
+            table: "readings".to_owned(),
+            columns: vec![column("a.b", SqlType::BigInt), column("x[0]", SqlType::BigInt)],
+        };
+        let fields = default_fields(&schema, &[]).unwrap();
+        let data = r#"{"a.b": 1, "a": {"b": 2}, "x[0]": 3, "x": [4]}"#;
+        let mut source = JsonlSource::with_paths(data.as_bytes(), fields);
+        assert_eq!(source.fields().unwrap(), ["a.b", "x[0]"]);
+        assert_eq!(source.next_record().unwrap().unwrap(), [Value::Int(1), Value::Int(3)]);
+    }
+
+    #[test]
+    fn test_parquet_source() {
+        use arrow_array::{
+            ArrayRef, BinaryArray, Date32Array, Decimal128Array, FixedSizeBinaryArray, Int32Array,
+            RecordBatch, StringArray, TimestampMicrosecondArray, TimestampMillisecondArray,
+        };
+
+        let guid = tiberius::Uuid::from_u128(0x6F9619FF_8B86_D011_B42D_00C04FC964FF);
+        let columns: Vec<(&str, ArrayRef)> = vec![
+            ("id", Arc::new(Int32Array::from(vec![Some(1), Some(2), None]))),
+            ("amount", Arc::new(Decimal128Array::from(vec![12345, -5, 0]).with_precision_and_scale(10, 3).unwrap())),
+            ("name", Arc::new(StringArray::from(vec![Some("a"), None, Some("c")]))),
+            ("guid", Arc::new(FixedSizeBinaryArray::try_from_iter([guid.as_bytes(); 3].into_iter()).unwrap())),
+            ("day", Arc::new(Date32Array::from(vec![19_000, 0, 1]))),
+            ("at", Arc::new(TimestampMicrosecondArray::from(vec![0, 1_500_000, 2]).with_timezone("+02:00"))),
+            ("local", Arc::new(TimestampMillisecondArray::from(vec![0, 1_500, 2]))),
+            ("blob", Arc::new(BinaryArray::from(vec![&b"\xCA\xFE"[..], b"", b"x"]))),
+        ];
+        let batch = RecordBatch::try_from_iter(columns).unwrap();
+
+        let path = std::env::temp_dir().join("bulk_test_source.parquet");
+        let mut writer =
+            ::parquet::arrow::ArrowWriter::try_new(std::fs::File::create(&path).unwrap(), batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        // Batches of two rows, so the last row comes from a second batch.
+        let mut source =
+            crate::source::parquet::ParquetSource::from_reader(std::fs::File::open(&path).unwrap(), 2).unwrap();
+        assert_eq!(source.fields().unwrap(), ["id", "amount", "name", "guid", "day", "at", "local", "blob"]);
+
+        let schema = TableSchema {
+            table: "parquet_test".to_owned(),
+            columns: vec![
+                column("id", SqlType::BigInt),
+                column("amount", SqlType::Decimal { precision: 10, scale: 2 }),
+                column("name", SqlType::NVarChar(Some(10))),
+                column("guid", SqlType::UniqueIdentifier),
+                column("day", SqlType::Date),
+                column("at", SqlType::DateTimeOffset(7)),
+                column("local", SqlType::DateTime2(7)),
+                column("blob", SqlType::VarBinary(None)),
+            ],
+        };
+        let map = ColumnMap::new(&schema, source.fields(), &IngestOptions::default()).unwrap();
+
+        let record = source.next_record().unwrap().unwrap();
+        assert_eq!(record[0], Value::Int(1));
+        assert_eq!(record[1], Value::Text("12.345".to_owned()));
+        let offset = FixedOffset::east_opt(2 * 3600).unwrap();
+        assert_eq!(record[5], Value::DateTimeOffset(offset.with_ymd_and_hms(1970, 1, 1, 2, 0, 0).unwrap()));
+        let row: Vec<_> = map.row(record).unwrap().into_iter().collect();
+        assert_eq!(row[0], ColumnData::I64(Some(1)));
+        assert!(matches!(&row[1], ColumnData::Numeric(Some(n)) if n.value() == 1235 && n.scale() == 2));
+        assert_eq!(row[3], ColumnData::Guid(Some(guid)));
+        assert_eq!(row[4], NaiveDate::from_ymd_opt(2022, 1, 8).into_sql());
+        assert!(matches!(row[5], ColumnData::DateTimeOffset(Some(_))));
+        assert_eq!(row[6], NaiveDate::from_ymd_opt(1970, 1, 1).unwrap().and_hms_opt(0, 0, 0).into_sql());
+        assert_eq!(row[7], ColumnData::Binary(Some(vec![0xCA, 0xFE].into())));
+
+        let record = source.next_record().unwrap().unwrap();
+        assert_eq!(record[2], Value::Null);
+        assert_eq!(record[6], Value::DateTime(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap().and_hms_milli_opt(0, 0, 1, 500).unwrap()));
+        let record = source.next_record().unwrap().unwrap();
+        assert_eq!(record[0], Value::Null);
+        assert_eq!(source.next_record().unwrap(), None);
+
+        // Compressed files are decompressed in memory.
+        let gz_path = std::env::temp_dir().join("bulk_test_source.parquet.gz");
+        let mut gzip = flate2::write::GzEncoder::new(std::fs::File::create(&gz_path).unwrap(), flate2::Compression::fast());
+        gzip.write_all(&std::fs::read(&path).unwrap()).unwrap();
+        gzip.finish().unwrap();
+        let mut source = crate::source::parquet::ParquetSource::from_path(&gz_path).unwrap();
+        assert_eq!(std::iter::from_fn(|| source.next_record().unwrap()).count(), 3);
+        std::fs::remove_file(gz_path).unwrap();
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_record_batch_rows() {
+        use arrow_array::{ArrayRef, Decimal128Array, Int64Array, RecordBatch, StringArray};
+        use arrow_schema::DataType;
+
+        let mut id = column("id", SqlType::Int);
+        id.identity = true;
+        let mut code = column("code", SqlType::VarChar(Some(10)));
+        code.nullable = false;
+        let schema = TableSchema {
+            table: "arrow_test".to_owned(),
+            columns: vec![
+                id,
+                code,
+                column("amount", SqlType::Decimal { precision: 10, scale: 2 }),
+                column("small", SqlType::TinyInt),
+                column("note", SqlType::NVarChar(None)),
+            ],
+        };
+
+        let columns: Vec<(&str, ArrayRef)> = vec![
+            ("CODE", Arc::new(StringArray::from(vec![Some("a"), None]))),
+            ("amount", Arc::new(Decimal128Array::from(vec![12345, -12345]).with_precision_and_scale(10, 3).unwrap())),
+            ("small", Arc::new(Int64Array::from(vec![255, 256]))),
+        ];
+        let batch = RecordBatch::try_from_iter(columns).unwrap();
+        let columns = check_schema(&schema, &batch.schema(), &IngestOptions::default()).unwrap();
+        let zones = time_zones(&batch.schema()).unwrap();
+
+        let row: Vec<_> = token_row(&columns, &zones, &batch, 0).unwrap().into_iter().collect();
+        assert_eq!(row[0], ColumnData::String(Some("a".into())));
+        assert!(matches!(&row[1], ColumnData::Numeric(Some(n)) if n.value() == 1235 && n.scale() == 2));
+        assert_eq!(row[2], ColumnData::U8(Some(255)));
+        assert_eq!(row[3], ColumnData::String(None));
+
+        // NULL in a column that does not allow NULLs.
+        let error = token_row(&columns, &zones, &batch, 1).unwrap_err();
+        assert_eq!(error.context().unwrap().column.as_deref(), Some("code"));
+
+        // Types that cannot be loaded are found before any row is converted.
+        let wrong = arrow_schema::Schema::new(vec![
+            arrow_schema::Field::new("code", DataType::Utf8, false),
+            arrow_schema::Field::new("small", DataType::Date32, true),
+        ]);
+        let error = check_schema(&schema, &wrong, &IngestOptions::default()).unwrap_err();
+        assert_eq!(error.context().unwrap().column.as_deref(), Some("small"));
+
+        assert!(is_compatible(&DataType::Utf8, &SqlType::UniqueIdentifier));
+        assert!(is_compatible(&DataType::FixedSizeBinary(16), &SqlType::UniqueIdentifier));
+        assert!(!is_compatible(&DataType::Float64, &SqlType::Int));
+        assert!(is_compatible(&DataType::Timestamp(arrow_schema::TimeUnit::Microsecond, Some("UTC".into())), &SqlType::DateTimeOffset(7)));
+        assert!(!is_compatible(&DataType::Timestamp(arrow_schema::TimeUnit::Microsecond, None), &SqlType::DateTimeOffset(7)));
+
+        // Numbers and dates in columns of other types are converted without
+        // text, to what their text gives.
+        let noon = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap().and_hms_micro_opt(12, 0, 30, 3500).unwrap();
+        let typed = [
+            (Value::Int(1), SqlType::Bit),
+            (Value::Int(16_777_217), SqlType::Real),
+            (Value::Int(i64::MAX), SqlType::Float),
+            (Value::Int(-123), SqlType::Decimal { precision: 5, scale: 2 }),
+            (Value::Date(noon.date()), SqlType::DateTime),
+            (Value::Date(noon.date()), SqlType::DateTime2(7)),
+            (Value::DateTime(noon), SqlType::DateTime),
+            (Value::DateTime(noon), SqlType::SmallDateTime),
+        ];
+        for (value, sql_type) in typed {
+            let text = parse(&value.to_string(), &sql_type, BinaryEncoding::Hex).unwrap();
+            assert_eq!(convert(value, &sql_type, BinaryEncoding::Hex).unwrap(), text, "{}", sql_type);
+        }
+        assert!(convert(Value::Int(1000), &SqlType::Decimal { precision: 5, scale: 2 }, BinaryEncoding::Hex).is_err());
+        assert!(convert(Value::Int(2), &SqlType::Bit, BinaryEncoding::Hex).is_err());
+        assert!(convert(Value::DateTime(NaiveDate::from_ymd_opt(1700, 1, 1).unwrap().and_time(NaiveTime::MIN)), &SqlType::DateTime, BinaryEncoding::Hex).is_err());
+        assert!(convert(Value::Float(1e39), &SqlType::Real, BinaryEncoding::Hex).is_err());
+
+        // Decimals become the float nearest to them, with or without text.
+        for (value, scale) in [(1, 1), (-12345, 3), (1 << 60, 2), (i128::MAX, 30), (7, -3)] {
+            for sql_type in [SqlType::Float, SqlType::Real] {
+                let text = match scale {
+                    -3 => format!("{}e3", value),
+                    _ => decimal_text(value, scale as u8),
+                };
+                let nearest = parse(&text, &sql_type, BinaryEncoding::Hex).unwrap();
+                assert_eq!(decimal_value(value, scale, &sql_type).unwrap(), nearest, "{} {}", text, sql_type);
+            }
+        }
+
+        // The time zone of a timestamp is parsed once for the field.
+        let timestamps = arrow_array::TimestampSecondArray::from(vec![0]).with_timezone("Europe/Paris");
+        let zones = time_zones(&arrow_schema::Schema::new(vec![arrow_schema::Field::new("at", arrow_array::Array::data_type(&timestamps).clone(), true)])).unwrap();
+        let value = arrow_value(&timestamps, zones[0].as_ref(), 0).unwrap();
+        assert_eq!(value, Value::DateTimeOffset(FixedOffset::east_opt(3600).unwrap().with_ymd_and_hms(1970, 1, 1, 1, 0, 0).unwrap()));
+        assert_eq!(arrow_value(&timestamps, None, 0).unwrap(), value);
+        let unknown = arrow_schema::Field::new("at", DataType::Timestamp(arrow_schema::TimeUnit::Second, Some("Nowhere/Else".into())), true);
+        let error = time_zones(&arrow_schema::Schema::new(vec![unknown])).unwrap_err();
+        assert_eq!(error.context().unwrap().column.as_deref(), Some("at"));
+    }
+
+    fn bcp_test_schema() -> TableSchema {
+        let mut id = column("id", SqlType::Int);
+        id.identity = true;
+        id.nullable = false;
+        let mut name = column("name", SqlType::VarChar(Some(20)));
+        name.nullable = false;
+        TableSchema {
+            table: "bcp_test".to_owned(),
+            columns: vec![
+                id,
+                name,
+                column("note", SqlType::NVarChar(None)),
+                column("amount", SqlType::Decimal { precision: 10, scale: 2 }),
+                column("at", SqlType::DateTime2(3)),
+                column("flag", SqlType::Bit),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_bcp_format_files() {
+        let non_xml = "14.0\r\n\
+            3\r\n\
+            1       SQLCHAR       0       12      \"\\t\"     1     id       \"\"\r\n\
+            2       SQLNCHAR      2       40      \"\"       0     skipped  SQL_Latin1_General_CP1_CI_AS\r\n\
+            3       SQLCHAR       0       20      \"\\r\\n\"   2     name     \"\"\r\n";
+        let format: FormatFile = non_xml.parse().unwrap();
+        assert_eq!(format.fields.len(), 3);
+        assert_eq!(format.fields[0].terminator, "\t");
+        assert_eq!(format.fields[1].host_type, HostType::NChar);
+        assert_eq!(format.fields[1].prefix_length, 2);
+        assert_eq!(format.fields[1].column, 0);
+        assert_eq!(format.fields[2].terminator, "\r\n");
+        assert_eq!(format.fields[2].name, "name");
+
+        // What is written reads back the same.
+        assert_eq!(format.to_string().parse::<FormatFile>().unwrap(), format);
+        let mut spaced = format.clone();
+        spaced.fields[0].name = "order id".to_owned();
+        spaced.fields[1].name = String::new();
+        spaced.fields[2].name = "say \"hi\"".to_owned();
+        assert!(spaced.to_string().contains("\"order id\""));
+        assert_eq!(spaced.to_string().parse::<FormatFile>().unwrap(), spaced);
+        let mut long = format.clone();
+        long.fields[0].name = "a_column_name_of_more_than_thirty_characters".to_owned();
+        long.fields[0].terminator = "<end of the field>\t\r\n".repeat(2);
+        assert_eq!(long.to_string().parse::<FormatFile>().unwrap(), long);
+        assert!("14.0\n2\n1 SQLCHAR 0 12 \"\\t\" 1 id \"\"\n".parse::<FormatFile>().is_err());
+        assert!("14.0\n1\n1 SQLSTRING 0 12 \"\" 1 id \"\"\n".parse::<FormatFile>().is_err());
+
+        let xml = r#"<?xml version="1.0"?>
+            <BCPFORMAT xmlns="http://schemas.microsoft.com/sqlserver/2004/bulkload/format"
+                       xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+              <RECORD>
+                <FIELD ID="1" xsi:type="NativeFixed" LENGTH="4"/>
+                <FIELD ID="2" xsi:type="CharTerm" TERMINATOR="\t" MAX_LENGTH="20"/>
+                <FIELD ID="3" xsi:type="NCharPrefix" PREFIX_LENGTH="8"/>
+              </RECORD>
+              <ROW>
+                <COLUMN SOURCE="1" NAME="id" xsi:type="SQLINT"/>
+                <COLUMN SOURCE="3" NAME="note" xsi:type="SQLNVARCHAR"/>
+              </ROW>
+            </BCPFORMAT>"#;
+        let format: FormatFile = xml.parse().unwrap();
+        assert_eq!(format.fields[0].host_type, HostType::Int);
+        assert_eq!(format.fields[0].length, 4);
+        assert_eq!(format.fields[0].column, 1);
+        assert_eq!(format.fields[1].host_type, HostType::Char);
+        assert_eq!(format.fields[1].terminator, "\t");
+        assert_eq!(format.fields[1].column, 0);
+        assert_eq!(format.fields[2].prefix_length, 8);
+        assert_eq!(format.fields[2].column, 2);
+        assert_eq!(format.fields[2].name, "note");
+
+        let native = FormatFile::for_table(&bcp_test_schema(), BcpMode::Native);
+        let layout: Vec<_> = native.fields.iter().map(|field| (field.host_type, field.prefix_length, field.length)).collect();
+        assert_eq!(layout, [
+            (HostType::Int, 0, 4),
+            (HostType::Char, 2, 20),
+            (HostType::NChar, 8, 0),
+            (HostType::Decimal, 1, 19),
+            (HostType::DateTime2, 1, 7),
+            (HostType::Bit, 1, 1),
+        ]);
+        let wide = FormatFile::for_table(&bcp_test_schema(), BcpMode::WideNative);
+        assert_eq!((wide.fields[1].host_type, wide.fields[1].length), (HostType::NChar, 40));
+    }
+
+    #[test]
+    fn test_bcp_native_files() {
+        let schema = bcp_test_schema();
+        let at = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap().and_hms_milli_opt(13, 45, 30, 125).unwrap();
+        let records = vec![
+            vec![
+                Value::Int(1),
+                Value::Text("first".into()),
+                Value::Text("ünïcode".into()),
+                Value::Text("-12.345".into()),
+                Value::DateTime(at),
+                Value::Bool(true),
+            ],
+            vec![Value::Int(2), Value::Text(String::new()), Value::Null, Value::Null, Value::Null, Value::Null],
+        ];
+
+        for mode in [BcpMode::Native, BcpMode::WideNative, BcpMode::Character] {
+            let mut writer = BcpWriter::new(Vec::new(), &schema, mode);
+            for record in &records {
+                writer.write_record(record.clone()).unwrap();
+            }
+            let format = writer.format_file().clone();
+            let file = writer.finish().unwrap();
+
+            // Identity values are written but not loaded.
+            let mut source = BcpSource::from_reader(file.as_slice(), &format, &schema).unwrap();
+            assert_eq!(source.fields().unwrap(), ["name", "note", "amount", "at", "flag"]);
+            let first = source.next_record().unwrap().unwrap();
+            let second = source.next_record().unwrap().unwrap();
+            assert!(source.next_record().unwrap().is_none());
+
+            assert_eq!(first[0], Value::Text("first".into()));
+            assert_eq!(first[1], Value::Text("ünïcode".into()));
+            assert_eq!(first[2], Value::Text("-12.35".into()));
+            assert_eq!(convert(first[3].clone(), &SqlType::DateTime2(3), BinaryEncoding::Hex).unwrap(), at.into_sql());
+            assert_eq!(convert(first[4].clone(), &SqlType::Bit, BinaryEncoding::Hex).unwrap(), ColumnData::Bit(Some(true)));
+            assert_eq!(second, [Value::Text(String::new()), Value::Null, Value::Null, Value::Null, Value::Null]);
+        }
+
+        // The bytes bcp writes: a NOT NULL int without prefix, varchar with a
+        // two byte length and NULL as a length of all ones.
+        let mut writer = BcpWriter::new(Vec::new(), &schema, BcpMode::Native);
+        writer.write_record(records[1].clone()).unwrap();
+        let file = writer.finish().unwrap();
+        assert_eq!(file, [2, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+
+        // NULL in a column that does not allow NULLs, and a record that
+        // ends early.
+        let mut writer = BcpWriter::new(Vec::new(), &schema, BcpMode::Native);
+        let error = writer.write_record(vec![Value::Int(3), Value::Null, Value::Null, Value::Null, Value::Null, Value::Null]).unwrap_err();
+        assert_eq!(error.context().unwrap().column.as_deref(), Some("name"));
+        let format = FormatFile::for_table(&schema, BcpMode::Native);
+        let mut source = BcpSource::from_reader(&[1u8, 0, 0, 0, 5, 0, b'a'][..], &format, &schema).unwrap();
+        assert!(matches!(source.next_record(), Err(BulkError::Conversion { .. })));
+
+        // Native data that starts like gzip is read as it is, unless its
+        // extension says otherwise.
+        let mut writer = BcpWriter::new(Vec::new(), &schema, BcpMode::Native);
+        writer.write_record(vec![Value::Int(35615), Value::Text("gz".into()), Value::Null, Value::Null, Value::Null, Value::Null]).unwrap();
+        let file = writer.finish().unwrap();
+        assert_eq!(Compression::detect(&file), Compression::Gzip);
+        let path = std::env::temp_dir().join("bulk_test_native_magic.dat");
+        std::fs::write(&path, &file).unwrap();
+        let mut source = BcpSource::from_path(&path, &format, &schema).unwrap();
+        assert_eq!(source.next_record().unwrap().unwrap()[0], Value::Text("gz".into()));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_bcp_character_files() {
+        let schema = bcp_test_schema();
+        let format = FormatFile::for_table(&schema, BcpMode::Character);
+
+        // Rows written by bcp on Linux end in \n, and the last row may go
+        // without; empty fields are NULL and a NUL character is empty text.
+        let file = "7\tabc\t\t1.50\t2024-01-02 03:04:05.678\t1\r\n8\t\0\tx y\t\t\t0\n9\tlast\t\t\t\t1";
+        let mut source = BcpSource::from_reader(file.as_bytes(), &format, &schema).unwrap();
+        let records: Vec<_> = std::iter::from_fn(|| source.next_record().unwrap()).collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0][0], Value::Text("abc".into()));
+        assert_eq!(records[0][1], Value::Null);
+        assert_eq!(records[0][3], Value::Text("2024-01-02 03:04:05.678".into()));
+        assert_eq!(records[1][0], Value::Text(String::new()));
+        assert_eq!(records[1][1], Value::Text("x y".into()));
+        assert_eq!(records[1][4], Value::Text("0".into()));
+        assert_eq!(records[2][3], Value::Null);
+        assert_eq!(records[2][4], Value::Text("1".into()));
+
+        let too_few = "7\tabc\n";
+        let mut source = BcpSource::from_reader(too_few.as_bytes(), &format, &schema).unwrap();
+        assert!(matches!(source.next_record(), Err(BulkError::Conversion { .. })));
+
+        let mut wide = FormatFile::for_table(&schema, BcpMode::Character);
+        wide.fields[5].column = 9;
+        assert!(matches!(BcpSource::from_reader(&b""[..], &wide, &schema), Err(BulkError::Config(_))));
+    }
+
+    #[test]
+    fn test_fixed_width_source() {
+        let layout: FixedWidthLayout = r#"
+            [[fields]]
+            name = "code"
+            start = 1
+            length = 6
+            [[fields]]
+            name = "amount"
+            start = 7
+            length = 7
+            type = "number"
+            decimals = 2
+            [[fields]]
+            name = "balance"
+            start = 14
+            length = 5
+            type = "zoned"
+            decimals = 1
+            [[fields]]
+            name = "opened"
+            start = 19
+            length = 8
+            type = "date"
+        "#
+        .parse()
+        .unwrap();
+
+        // Lines shorter than the layout are padded, blank fields are NULL,
+        // and a bad field rejects its record only.
+        let file = "AB12  0012345 123}20240131\r\n\n  x   -0000050012A00000000\nbad   12x4567\nshort\n";
+        let mut source = FixedWidthSource::from_reader(file.as_bytes(), &layout).unwrap();
+        assert_eq!(source.fields().unwrap(), ["code", "amount", "balance", "opened"]);
+        let date = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let text = |s: &str| Value::Text(s.to_owned());
+        assert_eq!(source.next_record().unwrap().unwrap(), [text("AB12"), text("123.45"), text("-123.0"), Value::Date(date)]);
+        assert_eq!(source.next_record().unwrap().unwrap(), [text("x"), text("-0.05"), text("12.1"), Value::Null]);
+        let error = source.next_record().unwrap_err();
+        assert_eq!(error.context().unwrap().column.as_deref(), Some("amount"));
+        assert_eq!(source.next_record().unwrap().unwrap(), [text("short"), Value::Null, Value::Null, Value::Null]);
+        assert_eq!(source.next_record().unwrap(), None);
+
+        // EBCDIC records of a fixed length with packed and binary numbers.
+        let layout: FixedWidthLayout = r#"
+            record_length = 10
+            encoding = "ebcdic"
+            [[fields]]
+            name = "name"
+            start = 1
+            length = 4
+            [[fields]]
+            name = "total"
+            start = 5
+            length = 3
+            type = "packed"
+            decimals = 2
+            [[fields]]
+            name = "count"
+            start = 8
+            length = 2
+            type = "binary"
+            [[fields]]
+            name = "sign"
+            start = 10
+            length = 1
+            type = "zoned"
+        "#
+        .parse()
+        .unwrap();
+        let file = [
+            0xC1, 0x81, 0xF1, 0x40, 0x12, 0x34, 0x5D, 0xFF, 0xFE, 0xD3,
+            0x40, 0x40, 0x40, 0x40, 0x40, 0x40, 0x40, 0x00, 0x07, 0xC0,
+            0x40, 0x40, 0x40, 0x40, 0x00, 0x00, 0x0C, 0x40, 0x40, 0xC0,
+            0xC1, 0xC1,
+        ];
+        let mut source = FixedWidthSource::from_reader(&file[..], &layout).unwrap();
+        assert_eq!(source.next_record().unwrap().unwrap(), [text("Aa1"), text("-123.45"), Value::Int(-2), Value::Int(-3)]);
+        assert_eq!(source.next_record().unwrap().unwrap(), [Value::Null, Value::Null, Value::Int(7), Value::Int(0)]);
+        // A binary number may have the bytes of spaces.
+        assert_eq!(source.next_record().unwrap().unwrap(), [Value::Null, text("0.00"), Value::Int(0x4040), Value::Int(0)]);
+        assert!(matches!(source.next_record(), Err(BulkError::Conversion { .. })));
+        assert_eq!(source.next_record().unwrap(), None);
+
+        let overlapping = "record_length = 4\n[[fields]]\nname = \"a\"\nstart = 3\nlength = 4\n";
+        assert!(matches!(overlapping.parse::<FixedWidthLayout>(), Err(BulkError::Config(_))));
+        let packed_lines = "[[fields]]\nname = \"a\"\nstart = 1\nlength = 4\ntype = \"packed\"\n";
+        assert!(matches!(packed_lines.parse::<FixedWidthLayout>(), Err(BulkError::Config(_))));
+
+        // Records of a fixed length may start like gzip and are read as they are.
+        let layout: FixedWidthLayout = "record_length = 2\n[[fields]]\nname = \"a\"\nstart = 1\nlength = 2\ntype = \"binary\"\n".parse().unwrap();
+        let path = std::env::temp_dir().join("bulk_test_fixed_magic.dat");
+        std::fs::write(&path, [0x1F, 0x8B]).unwrap();
+        let mut source = FixedWidthSource::from_path(&path, &layout).unwrap();
+        assert_eq!(source.next_record().unwrap().unwrap(), [Value::Int(0x1F8B)]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_decompress_input_files() {
+        let data = "code,amount\nA1,1.50\nB2,\n";
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gzip.write_all(data.as_bytes()).unwrap();
+        let mut xz = liblzma::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(data.as_bytes()).unwrap();
+        let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        bzip2.write_all(data.as_bytes()).unwrap();
+        let files = [
+            ("gz", gzip.finish().unwrap(), Compression::Gzip),
+            ("zst", zstd::encode_all(data.as_bytes(), 3).unwrap(), Compression::Zstd),
+            ("bz2", bzip2.finish().unwrap(), Compression::Bzip2),
+            ("xz", xz.finish().unwrap(), Compression::Xz),
+        ];
+
+        for (extension, compressed, compression) in files {
+            assert_eq!(Compression::detect(&compressed), compression);
+
+            // The compression is told by the first bytes, whatever the name.
+            let path = std::env::temp_dir().join(format!("bulk_test_decompress_{}.csv", extension));
+            std::fs::write(&path, &compressed).unwrap();
+            let mut source = CsvSource::from_path(&path, &CsvOptions::default()).unwrap();
+            assert_eq!(source.fields().unwrap(), ["code", "amount"]);
+            assert_eq!(source.next_record().unwrap().unwrap(), [Value::Text("A1".into()), Value::Text("1.50".into())]);
+            assert_eq!(source.next_record().unwrap().unwrap(), [Value::Text("B2".into()), Value::Null]);
+            assert_eq!(source.next_record().unwrap(), None);
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        // Concatenated streams are read as one.
+        let mut first = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        first.write_all(b"{\"code\": \"A1\"}\n").unwrap();
+        let mut second = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        second.write_all(b"{\"code\": \"B2\"}\n").unwrap();
+        let mut both = first.finish().unwrap();
+        both.extend(second.finish().unwrap());
+        let mut source = JsonlSource::from_reader(decompress(both.as_slice()).unwrap(), &["code".to_owned()]).unwrap();
+        assert_eq!(std::iter::from_fn(|| source.next_record().unwrap()).count(), 2);
+
+        // Files that are not compressed are read as they are, unless their
+        // extension says otherwise.
+        assert_eq!(Compression::detect(b"code,amount"), Compression::None);
+        let path = std::env::temp_dir().join("bulk_test_not_compressed.csv.gz");
+        std::fs::write(&path, data).unwrap();
+        assert_eq!(Compression::of_file(&path).unwrap(), Compression::Gzip);
+        assert!(CsvSource::from_path(&path, &CsvOptions::default()).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(uncompressed_path(std::path::Path::new("data.jsonl.zst")), std::path::Path::new("data.jsonl"));
+        assert_eq!(uncompressed_path(std::path::Path::new("data.csv")), std::path::Path::new("data.csv"));
+    }
+
+    #[test]
+    fn test_excel_source() {
+        use rust_xlsxwriter::{ExcelDateTime, Format, Workbook};
+
+        let mut workbook = Workbook::new();
+        workbook.add_worksheet().set_name("Notes").unwrap().write_string(0, 0, "not loaded").unwrap();
+        let sheet = workbook.add_worksheet().set_name("Orders").unwrap();
+        let date = Format::new().set_num_format("yyyy-mm-dd");
+        let time = Format::new().set_num_format("hh:mm");
+        sheet.write_string(0, 0, "Orders of the month").unwrap();
+        for (column, name) in ["id", "amount", "ordered", "at"].into_iter().enumerate() {
+            sheet.write_string(2, 1 + column as u16, name).unwrap();
+        }
+        sheet.write_number(3, 1, 1).unwrap();
+        sheet.write_number(3, 2, 12.5).unwrap();
+        sheet.write_datetime_with_format(3, 3, ExcelDateTime::from_ymd(2024, 1, 31).unwrap(), &date).unwrap();
+        sheet.write_datetime_with_format(3, 4, ExcelDateTime::from_hms(13, 30, 0).unwrap(), &time).unwrap();
+        sheet.write_number(4, 1, 2).unwrap();
+        sheet.write_number(4, 2, 3).unwrap();
+        sheet.write_datetime_with_format(4, 3, ExcelDateTime::from_ymd(2024, 2, 29).unwrap().and_hms_milli(8, 15, 30, 250).unwrap(), &date).unwrap();
+        sheet.write_number(6, 1, 3).unwrap();
+        sheet.write_string(6, 2, "n/a").unwrap();
+        sheet.write_boolean(6, 4, true).unwrap();
+        let path = std::env::temp_dir().join("bulk_test_source.xlsx");
+        std::fs::write(&path, workbook.save_to_buffer().unwrap()).unwrap();
+
+        // The header is the first row of the range; whole numbers are
+        // integers, dates are dates and times of day are times. Empty rows
+        // are skipped.
+        let options = ExcelOptions { sheet: Some("Orders".to_owned()), range: Some("B3".parse().unwrap()), has_header: true };
+        let mut source = ExcelSource::from_path(&path, &options).unwrap();
+        assert_eq!(source.fields().unwrap(), ["id", "amount", "ordered", "at"]);
+        let ordered = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let at = NaiveTime::from_hms_opt(13, 30, 0).unwrap();
+        assert_eq!(source.next_record().unwrap().unwrap(), [Value::Int(1), Value::Float(12.5), Value::DateTime(ordered), Value::Time(at)]);
+        let ordered = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap().and_hms_milli_opt(8, 15, 30, 250).unwrap();
+        assert_eq!(source.next_record().unwrap().unwrap(), [Value::Int(2), Value::Int(3), Value::DateTime(ordered), Value::Null]);
+        assert_eq!(source.next_record().unwrap().unwrap(), [Value::Int(3), Value::Text("n/a".into()), Value::Null, Value::Bool(true)]);
+        assert_eq!(source.next_record().unwrap(), None);
+
+        // Numbers and dates are converted to the types of the columns.
+        assert_eq!(convert(Value::DateTime(ordered), &SqlType::Date, BinaryEncoding::Hex).unwrap(), ordered.date().into_sql());
+        assert_eq!(
+            convert(Value::Float(12.5), &SqlType::Decimal { precision: 5, scale: 2 }, BinaryEncoding::Hex).unwrap(),
+            ColumnData::Numeric(Some(tiberius::numeric::Numeric::new_with_scale(1250, 2)))
+        );
+
+        // Without a header, a closed range of the first sheet is matched
+        // with the columns by position.
+        let options = ExcelOptions { sheet: None, range: Some("A1:B2".parse().unwrap()), has_header: false };
+        let mut source = ExcelSource::from_path(&path, &options).unwrap();
+        assert_eq!(source.fields(), None);
+        assert_eq!(source.next_record().unwrap().unwrap(), [Value::Text("not loaded".into()), Value::Null]);
+        assert_eq!(source.next_record().unwrap(), None);
+
+        let options = ExcelOptions { sheet: Some("Missing".to_owned()), ..Default::default() };
+        assert!(matches!(ExcelSource::from_path(&path, &options), Err(BulkError::Config(_))));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!("$AA$10:AB12".parse::<CellRange>().unwrap(), CellRange { start: (9, 26), end: Some((11, 27)) });
+        assert!("B2:A1".parse::<CellRange>().is_err());
+        assert!("12".parse::<CellRange>().is_err());
+    }
+
+    #[test]
+    fn test_sqlite_source() {
+        let path = std::env::temp_dir().join("bulk_test_source.sqlite");
+        let _ = std::fs::remove_file(&path);
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection.execute_batch(r#"
+            CREATE TABLE orders (id INTEGER PRIMARY KEY, code VARCHAR(10) NOT NULL, amount DECIMAL(10,2), ordered DATETIME, shipped BOOLEAN, photo BLOB, note, ratio DOUBLE PRECISION);
+            CREATE TABLE "order ""lines""" (qty INT);
+            CREATE TABLE "odd]. name" ("x""y]" INT NOT NULL, "z;--" TEXT);
+            INSERT INTO "odd]. name" VALUES (7, 'z');
+            INSERT INTO orders VALUES (1, 'A1', 12.5, '2024-01-31 13:30:00', 1, x'CAFE', 'first', 0.25);
+            INSERT INTO orders (id, code) VALUES (2, 'B2');
+        "#).unwrap();
+        drop(connection);
+
+        let database = SqliteDatabase::open(&path).unwrap();
+        assert_eq!(database.tables().unwrap(), ["odd]. name", "order \"lines\"", "orders"]);
+
+        // Declared types keep their meaning; other columns are mapped by
+        // their affinity.
+        let schema = database.schema("orders").unwrap();
+        assert_eq!(
+            schema.create_table_sql().unwrap(),
+            "CREATE TABLE [orders] (\n    [id] bigint NOT NULL,\n    [code] nvarchar(10) NOT NULL,\n    [amount] decimal(10,2) NULL,\n    [ordered] datetime2(7) NULL,\n    \
+             [shipped] bit NULL,\n    [photo] varbinary(max) NULL,\n    [note] nvarchar(max) NULL,\n    [ratio] float NULL\n)"
+        );
+        assert_eq!(database.schema("order \"lines\"").unwrap().columns[0].sql_type, SqlType::BigInt);
+        // Names are delimited in SQL Server and in SQLite, whatever they hold.
+        let odd = database.schema("odd]. name").unwrap();
+        assert_eq!(
+            odd.create_table_sql().unwrap(),
+            "CREATE TABLE [odd]]. name] (\n    [x\"y]]] bigint NOT NULL,\n    [z;--] nvarchar(max) NULL\n)"
+        );
+        let mut source = database.source("odd]. name").unwrap();
+        assert_eq!(source.fields().unwrap(), ["x\"y]", "z;--"]);
+        assert_eq!(source.next_record().unwrap().unwrap(), [Value::Int(7), Value::Text("z".into())]);
+        assert_eq!(crate::source::sqlite::sql_type("smallint"), SqlType::BigInt);
+        assert!(matches!(database.schema("missing"), Err(BulkError::Config(_))));
+        assert_eq!(crate::source::sqlite::sql_type("unsigned big int"), SqlType::BigInt);
+        assert_eq!(crate::source::sqlite::sql_type("NUMERIC"), SqlType::Float);
+
+        let mut source = database.source("orders").unwrap();
+        assert_eq!(source.fields().unwrap(), ["id", "code", "amount", "ordered", "shipped", "photo", "note", "ratio"]);
+        let record = source.next_record().unwrap().unwrap();
+        assert_eq!(record, [
+            Value::Int(1), Value::Text("A1".into()), Value::Float(12.5), Value::Text("2024-01-31 13:30:00".into()),
+            Value::Int(1), Value::Bytes(vec![0xCA, 0xFE]), Value::Text("first".into()), Value::Float(0.25),
+        ]);
+        let map = ColumnMap::new(&schema, source.fields(), &IngestOptions::default()).unwrap();
+        let at = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap().and_hms_opt(13, 30, 0).unwrap();
+        let row = map.row(record).unwrap().into_iter().collect::<Vec<_>>();
+        assert_eq!(row[3], at.into_sql());
+        assert_eq!(row[4], ColumnData::Bit(Some(true)));
+        assert_eq!(source.next_record().unwrap().unwrap()[2], Value::Null);
+        assert_eq!(source.next_record().unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn row_of(values: Vec<ColumnData<'static>>) -> tiberius::TokenRow<'static> {
+        let mut row = tiberius::TokenRow::with_capacity(values.len());
+        for value in values {
+            row.push(value);
+        }
+        row
+    }
 }
@@ -0,0 +1,2319 @@
+use crate::{
+    connection::ConnectionConfig,
+    convert::{convert, convert_column, BinaryEncoding, Value},
+    error::Result,
+    fixtures::{closing_parenthesis, keyword, parse_tables},
+    schema::{identifier, object_name, quote_name, Column, SqlType},
+    verify::is_null,
+};
+use bytes::BufMut;
+use encoding_rs::WINDOWS_1252;
+use once_cell::sync::OnceCell;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    io::{self, BufReader, Read, Write},
+    mem,
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+};
+use tiberius::{
+    numeric::Numeric,
+    time::{Date, DateTime, DateTime2, DateTimeOffset, SmallDateTime, Time},
+    xml::XmlData,
+    ColumnData, Uuid,
+};
+
+/// The version the mock server reports, that of SQL Server 2022.
+pub const MOCK_PRODUCT_VERSION: &str = "16.0.1000.6";
+
+/// Types of TDS packets.
+const SQL_BATCH: u8 = 0x01;
+const RPC: u8 = 0x03;
+const TABULAR_RESULT: u8 = 0x04;
+const ATTENTION: u8 = 0x06;
+const BULK_LOAD: u8 = 0x07;
+const LOGIN7: u8 = 0x10;
+const PRELOGIN: u8 = 0x12;
+
+const PACKET_HEADER_LEN: usize = 8;
+/// Size of the packets the mock server sends, the default of Tiberius.
+const PACKET_SIZE: usize = 4096;
+/// Status bit of the last packet of a message.
+const END_OF_MESSAGE: u8 = 0x01;
+
+/// Tokens of the messages of the server.
+const COLMETADATA: u8 = 0x81;
+const ERROR: u8 = 0xAA;
+const LOGINACK: u8 = 0xAD;
+const RETURNSTATUS: u8 = 0x79;
+const ROW: u8 = 0xD1;
+const DONE: u8 = 0xFD;
+const DONEPROC: u8 = 0xFE;
+const DONEINPROC: u8 = 0xFF;
+
+/// Status bits of `DONE` tokens.
+const DONE_MORE: u16 = 0x01;
+const DONE_ERROR: u16 = 0x02;
+const DONE_COUNT: u16 = 0x10;
+const DONE_ATTN: u16 = 0x20;
+
+/// Flags of the columns of `COLMETADATA`.
+const COLUMN_NULLABLE: u16 = 0x01;
+const COLUMN_UPDATEABLE: u16 = 0x08;
+const COLUMN_IDENTITY: u16 = 0x20;
+
+/// Data types of `TYPE_INFO`, the ones Tiberius sends and the mock server
+/// stores.
+const NULLTYPE: u8 = 0x1F;
+const IMAGETYPE: u8 = 0x22;
+const TEXTTYPE: u8 = 0x23;
+const GUIDTYPE: u8 = 0x24;
+const INTNTYPE: u8 = 0x26;
+const DATENTYPE: u8 = 0x28;
+const TIMENTYPE: u8 = 0x29;
+const DATETIME2NTYPE: u8 = 0x2A;
+const DATETIMEOFFSETNTYPE: u8 = 0x2B;
+const NTEXTTYPE: u8 = 0x63;
+const BITNTYPE: u8 = 0x68;
+const DECIMALNTYPE: u8 = 0x6A;
+const NUMERICNTYPE: u8 = 0x6C;
+const FLTNTYPE: u8 = 0x6D;
+const MONEYNTYPE: u8 = 0x6E;
+const DATETIMNTYPE: u8 = 0x6F;
+const BIGVARBINARYTYPE: u8 = 0xA5;
+const BIGVARCHARTYPE: u8 = 0xA7;
+const BIGBINARYTYPE: u8 = 0xAD;
+const BIGCHARTYPE: u8 = 0xAF;
+const NVARCHARTYPE: u8 = 0xE7;
+const NCHARTYPE: u8 = 0xEF;
+const XMLTYPE: u8 = 0xF1;
+
+/// Length of the `max` types.
+const MAX_LEN: u32 = 0xFFFF;
+/// `SQL_Latin1_General_CP1_CI_AS`, the collation of every text column.
+const COLLATION: [u8; 5] = [0x09, 0x04, 0xD0, 0x00, 0x34];
+/// Length of a NULL of the partially length-prefixed (`max`) types.
+const PLP_NULL: u64 = u64::MAX;
+/// TDS 7.4, the version of SQL Server 2012 and later.
+const TDS_74: u32 = 0x7400_0004;
+
+/// An error the mock server answers with, as SQL Server would.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MockError {
+    pub number: u32,
+    pub state: u8,
+    /// Severity, 16 for most errors of statements.
+    pub class: u8,
+    pub message: String,
+}
+
+impl MockError {
+    /// An error of severity 16.
+    pub fn new(number: u32, message: impl Into<String>) -> MockError {
+        MockError {
+            number,
+            state: 1,
+            class: 16,
+            message: message.into(),
+        }
+    }
+
+    /// Error 1205: the session was chosen as the victim of a deadlock.
+    pub fn deadlock() -> MockError {
+        MockError {
+            number: 1205,
+            state: 51,
+            class: 13,
+            message: "Transaction (Process ID 51) was deadlocked on lock resources with another \
+                      process and has been chosen as the deadlock victim. Rerun the transaction."
+                .to_owned(),
+        }
+    }
+}
+
+/// What an injected error replaces.
+enum Target {
+    /// The next statement whose text contains this, in lower case.
+    Statement(String),
+    /// The next bulk load into the table with this key.
+    BulkLoad((String, String)),
+}
+
+struct Failure {
+    target: Target,
+    error: MockError,
+}
+
+/// A table of the mock server.
+struct Table {
+    /// The name as in `[bulk_test].[random_bit]`.
+    name: String,
+    columns: Vec<Column>,
+    /// The rows as `ROW` tokens, in the order they were inserted, ready to be
+    /// sent back.
+    rows: Vec<u8>,
+    count: u64,
+    next_identity: i64,
+}
+
+#[derive(Default)]
+struct State {
+    /// Tables by schema and name, in lower case.
+    tables: HashMap<(String, String), Table>,
+    /// Schemas other than `dbo`, in lower case.
+    schemas: HashSet<String>,
+    /// Schemas with an extended property, by name of the property.
+    schema_properties: HashSet<(String, String)>,
+    statements: Vec<String>,
+    failures: Vec<Failure>,
+}
+
+/// A stand-in for SQL Server that runs in the process, so that the tests
+/// need no server.
+///
+/// It speaks enough of TDS for Tiberius: PRELOGIN without encryption,
+/// LOGIN7 with any credentials, SQL batches, `sp_executesql` calls and bulk
+/// loads, and it keeps the rows it receives. It understands the statements
+/// of the crate and its tests: `CREATE TABLE`, `DROP TABLE`,
+/// `TRUNCATE TABLE`, `INSERT INTO ... VALUES`, `INSERT BULK`,
+/// `SELECT [TOP n] * FROM` a table, `SELECT COUNT(*) FROM` a table, the
+/// queries of `sys.columns` and of the version of the server, and the
+/// creation, marking with an extended property and removal of schemas;
+/// anything else fails with error 50000.
+/// Values are converted to the types of the columns, `char`, `nchar` and
+/// `binary` values are padded, and NULLs in `NOT NULL` columns and values
+/// too long for their columns fail like they do in SQL Server.
+///
+/// Errors can be injected with [`MockServer::fail_statement`] and
+/// [`MockServer::fail_bulk_load`].
+pub struct MockServer {
+    port: u16,
+    state: Arc<Mutex<State>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    /// Starts a server on a free port of the loopback interface, with no
+    /// tables. Every connection is served by a thread of its own, so the
+    /// server works with any async runtime.
+    pub fn start() -> Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let state = Arc::new(Mutex::new(State::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let (shared, stop) = (state.clone(), stopped.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else { continue };
+                let state = shared.clone();
+                thread::spawn(move || {
+                    // A client that goes away just ends its session.
+                    let _ = Session::new(stream, state).and_then(Session::run);
+                });
+            }
+        });
+
+        Ok(MockServer {
+            port,
+            state,
+            stopped,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// A connection string for the server, without encryption.
+    pub fn connection_string(&self) -> String {
+        format!(
+            "server=tcp:127.0.0.1,{};database=DestinationDB;user=mock;password=mock;encrypt=DANGER_PLAINTEXT",
+            self.port
+        )
+    }
+
+    pub fn config(&self) -> Result<ConnectionConfig> {
+        ConnectionConfig::from_ado_string(&self.connection_string())
+    }
+
+    /// Makes the next statement whose text contains `containing`, compared
+    /// without regard to case, fail with `error` instead of running.
+    pub fn fail_statement(&self, containing: &str, error: MockError) {
+        self.state().failures.push(Failure {
+            target: Target::Statement(containing.to_lowercase()),
+            error,
+        });
+    }
+
+    /// Makes the next bulk load into `table` fail with `error` once its rows
+    /// have been sent; the rows are not stored.
+    pub fn fail_bulk_load(&self, table: &str, error: MockError) {
+        if let Some(key) = object_key(table) {
+            self.state().failures.push(Failure {
+                target: Target::BulkLoad(key),
+                error,
+            });
+        }
+    }
+
+    /// The SQL statements received so far, in order, as sent.
+    pub fn statements(&self) -> Vec<String> {
+        self.state().statements.clone()
+    }
+
+    /// The number of rows of `table`, or `None` when it does not exist.
+    pub fn row_count(&self, table: &str) -> Option<u64> {
+        let key = object_key(table)?;
+        self.state().tables.get(&key).map(|table| table.count)
+    }
+
+    /// The rows of `table` in the order they were inserted, with the values
+    /// as the server stores them, or `None` when it does not exist.
+    pub fn rows(&self, table: &str) -> Option<Vec<Vec<ColumnData<'static>>>> {
+        let key = object_key(table)?;
+        let state = self.state();
+        let table = state.tables.get(&key)?;
+        let types: Vec<TypeInfo> = table
+            .columns
+            .iter()
+            .map(|column| type_info(&column.sql_type))
+            .collect();
+
+        let mut rows = Vec::with_capacity(table.count as usize);
+        let mut data = table.rows.as_slice();
+        while !data.is_empty() {
+            data.u8().expect("a ROW token");
+            let row = types
+                .iter()
+                .map(|ty| read_value(&mut data, ty))
+                .collect::<io::Result<_>>()
+                .expect("rows stored by the server");
+            rows.push(row);
+        }
+
+        Some(rows)
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wakes up the thread waiting for connections.
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+    }
+}
+
+/// The server the tests run against when no server is configured, started
+/// the first time it is asked for.
+pub fn shared() -> Result<&'static MockServer> {
+    static SHARED: OnceCell<MockServer> = OnceCell::new();
+    SHARED.get_or_try_init(MockServer::start)
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    // A session that panicked leaves the tables as they were.
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The `TYPE_INFO` of a column or a parameter: the type, its length in
+/// bytes, and the precision and scale of decimals, times and timestamps.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct TypeInfo {
+    ty: u8,
+    len: u32,
+    precision: u8,
+    scale: u8,
+}
+
+impl TypeInfo {
+    fn new(ty: u8, len: u32) -> TypeInfo {
+        TypeInfo {
+            ty,
+            len,
+            precision: 0,
+            scale: 0,
+        }
+    }
+
+    fn scaled(ty: u8, scale: u8) -> TypeInfo {
+        TypeInfo {
+            scale,
+            ..TypeInfo::new(ty, 0)
+        }
+    }
+
+    /// Values of `text`, `ntext` and `image`, which come with a text pointer.
+    fn is_text(&self) -> bool {
+        matches!(self.ty, TEXTTYPE | NTEXTTYPE | IMAGETYPE)
+    }
+
+    fn is_plp(&self) -> bool {
+        self.ty == XMLTYPE
+            || (matches!(
+                self.ty,
+                BIGVARCHARTYPE | NVARCHARTYPE | BIGVARBINARYTYPE | BIGCHARTYPE | NCHARTYPE
+            ) && self.len == MAX_LEN)
+    }
+}
+
+/// How the mock server stores a column of type `sql_type`.
+fn type_info(sql_type: &SqlType) -> TypeInfo {
+    let bytes = |len: &Option<u16>| len.map_or(MAX_LEN, u32::from);
+    let chars = |len: &Option<u16>| len.map_or(MAX_LEN, |len| u32::from(len) * 2);
+
+    match sql_type {
+        SqlType::Bit => TypeInfo::new(BITNTYPE, 1),
+        SqlType::TinyInt => TypeInfo::new(INTNTYPE, 1),
+        SqlType::SmallInt => TypeInfo::new(INTNTYPE, 2),
+        SqlType::Int => TypeInfo::new(INTNTYPE, 4),
+        SqlType::BigInt => TypeInfo::new(INTNTYPE, 8),
+        SqlType::Real => TypeInfo::new(FLTNTYPE, 4),
+        SqlType::Float => TypeInfo::new(FLTNTYPE, 8),
+        SqlType::Decimal { precision, scale } => TypeInfo {
+            ty: DECIMALNTYPE,
+            len: decimal_len(*precision).into(),
+            precision: *precision,
+            scale: *scale,
+        },
+        SqlType::Money => TypeInfo::new(MONEYNTYPE, 8),
+        SqlType::SmallMoney => TypeInfo::new(MONEYNTYPE, 4),
+        SqlType::Char(len) => TypeInfo::new(BIGCHARTYPE, (*len).into()),
+        SqlType::VarChar(len) => TypeInfo::new(BIGVARCHARTYPE, bytes(len)),
+        SqlType::NChar(len) => TypeInfo::new(NCHARTYPE, u32::from(*len) * 2),
+        SqlType::NVarChar(len) => TypeInfo::new(NVARCHARTYPE, chars(len)),
+        SqlType::Text => TypeInfo::new(TEXTTYPE, 0x7FFF_FFFF),
+        SqlType::NText => TypeInfo::new(NTEXTTYPE, 0x7FFF_FFFE),
+        SqlType::Image => TypeInfo::new(IMAGETYPE, 0x7FFF_FFFF),
+        SqlType::Binary(len) => TypeInfo::new(BIGBINARYTYPE, (*len).into()),
+        SqlType::VarBinary(len) => TypeInfo::new(BIGVARBINARYTYPE, bytes(len)),
+        SqlType::RowVersion => TypeInfo::new(BIGBINARYTYPE, 8),
+        SqlType::UniqueIdentifier => TypeInfo::new(GUIDTYPE, 16),
+        SqlType::Date => TypeInfo::new(DATENTYPE, 3),
+        SqlType::Time(scale) => TypeInfo::scaled(TIMENTYPE, *scale),
+        SqlType::DateTime => TypeInfo::new(DATETIMNTYPE, 8),
+        SqlType::SmallDateTime => TypeInfo::new(DATETIMNTYPE, 4),
+        SqlType::DateTime2(scale) => TypeInfo::scaled(DATETIME2NTYPE, *scale),
+        SqlType::DateTimeOffset(scale) => TypeInfo::scaled(DATETIMEOFFSETNTYPE, *scale),
+        SqlType::Xml => TypeInfo::new(XMLTYPE, 0),
+        SqlType::Other(_) => TypeInfo::new(NVARCHARTYPE, MAX_LEN),
+    }
+}
+
+/// Bytes of a decimal value of `precision` digits, with its sign.
+fn decimal_len(precision: u8) -> u8 {
+    match precision {
+        0..=9 => 5,
+        10..=19 => 9,
+        20..=28 => 13,
+        _ => 17,
+    }
+}
+
+/// Bytes of a time with `scale` digits of fractions of seconds.
+fn time_len(scale: u8) -> u8 {
+    match scale {
+        0..=2 => 3,
+        3..=4 => 4,
+        _ => 5,
+    }
+}
+
+/// The type name, `max_length`, `precision` and `scale` of a column of
+/// type `sql_type` in `sys.columns`.
+fn sys_column(sql_type: &SqlType) -> (String, i16, u8, u8) {
+    let time_precision = |scale: u8| if scale == 0 { 8 } else { 9 + scale };
+    let length = |len: &Option<u16>| len.map_or(-1, |len| len as i16);
+
+    let (name, max_length, precision, scale) = match sql_type {
+        SqlType::Bit => ("bit", 1, 1, 0),
+        SqlType::TinyInt => ("tinyint", 1, 3, 0),
+        SqlType::SmallInt => ("smallint", 2, 5, 0),
+        SqlType::Int => ("int", 4, 10, 0),
+        SqlType::BigInt => ("bigint", 8, 19, 0),
+        SqlType::Real => ("real", 4, 24, 0),
+        SqlType::Float => ("float", 8, 53, 0),
+        SqlType::Decimal { precision, scale } => (
+            "decimal",
+            decimal_len(*precision).into(),
+            *precision,
+            *scale,
+        ),
+        SqlType::Money => ("money", 8, 19, 4),
+        SqlType::SmallMoney => ("smallmoney", 4, 10, 4),
+        SqlType::Char(len) => ("char", *len as i16, 0, 0),
+        SqlType::VarChar(len) => ("varchar", length(len), 0, 0),
+        SqlType::NChar(len) => ("nchar", *len as i16 * 2, 0, 0),
+        SqlType::NVarChar(len) => ("nvarchar", length(len).max(-1) * 2, 0, 0),
+        SqlType::Text => ("text", 16, 0, 0),
+        SqlType::NText => ("ntext", 16, 0, 0),
+        SqlType::Image => ("image", 16, 0, 0),
+        SqlType::Binary(len) => ("binary", *len as i16, 0, 0),
+        SqlType::VarBinary(len) => ("varbinary", length(len), 0, 0),
+        SqlType::UniqueIdentifier => ("uniqueidentifier", 16, 0, 0),
+        SqlType::Date => ("date", 3, 10, 0),
+        SqlType::Time(scale) => (
+            "time",
+            time_len(*scale).into(),
+            time_precision(*scale),
+            *scale,
+        ),
+        SqlType::DateTime => ("datetime", 8, 23, 3),
+        SqlType::SmallDateTime => ("smalldatetime", 4, 16, 0),
+        SqlType::DateTime2(scale) => (
+            "datetime2",
+            i16::from(time_len(*scale)) + 3,
+            time_precision(*scale) + 11,
+            *scale,
+        ),
+        SqlType::DateTimeOffset(scale) => (
+            "datetimeoffset",
+            i16::from(time_len(*scale)) + 5,
+            time_precision(*scale) + 18,
+            *scale,
+        ),
+        SqlType::Xml => ("xml", -1, 0, 0),
+        SqlType::RowVersion => ("timestamp", 8, 0, 0),
+        SqlType::Other(name) => return (name.clone(), -1, 0, 0),
+    };
+    // `nvarchar(max)` is -1, not -2.
+    let max_length = if max_length < -1 { -1 } else { max_length };
+
+    (name.to_owned(), max_length, precision, scale)
+}
+
+/// A client connection.
+struct Session {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    state: Arc<Mutex<State>>,
+    /// The table of the last `INSERT BULK` statement, which the next bulk
+    /// load message fills.
+    bulk: Option<BulkTarget>,
+    packet_id: u8,
+}
+
+struct BulkTarget {
+    key: (String, String),
+    name: String,
+    columns: Vec<Column>,
+}
+
+/// What a statement returns.
+enum Outcome {
+    /// The number of rows affected, if the statement counts rows.
+    Done(Option<u64>),
+    /// A result set, with its rows as `ROW` tokens.
+    Rows {
+        columns: Vec<Column>,
+        rows: Vec<u8>,
+        count: u64,
+    },
+    Error(MockError),
+}
+
+impl Session {
+    fn new(stream: TcpStream, state: Arc<Mutex<State>>) -> io::Result<Session> {
+        stream.set_nodelay(true)?;
+        Ok(Session {
+            reader: BufReader::with_capacity(64 * 1024, stream.try_clone()?),
+            writer: stream,
+            state,
+            bulk: None,
+            packet_id: 1,
+        })
+    }
+
+    fn run(mut self) -> io::Result<()> {
+        loop {
+            let (packet_type, mut message) = match Message::start(&mut self.reader) {
+                Ok(message) => message,
+                // The client closed the connection.
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+
+            if packet_type == BULK_LOAD {
+                let outcome = bulk_load(&mut message, self.bulk.take(), &self.state)?;
+                self.respond(outcome, false)?;
+                continue;
+            }
+
+            let mut payload = Vec::new();
+            message.read_to_end(&mut payload)?;
+            match packet_type {
+                PRELOGIN => self.send(TABULAR_RESULT, &prelogin())?,
+                LOGIN7 => self.send(TABULAR_RESULT, &login_ack())?,
+                SQL_BATCH => {
+                    let sql = batch_text(&payload)?;
+                    let outcome = self.execute(&sql, &HashMap::new());
+                    self.respond(outcome, false)?;
+                }
+                RPC => {
+                    let outcome = match rpc_request(&payload)? {
+                        Ok((sql, params)) => self.execute(&sql, &params),
+                        Err(error) => Outcome::Error(error),
+                    };
+                    self.respond(outcome, true)?;
+                }
+                ATTENTION => {
+                    let mut done = Vec::new();
+                    put_done(&mut done, DONE, DONE_ATTN, 0);
+                    self.send(TABULAR_RESULT, &done)?;
+                }
+                other => self.respond(
+                    Outcome::Error(MockError::new(
+                        50000,
+                        format!(
+                            "the mock server does not handle packets of type {:#04x}",
+                            other
+                        ),
+                    )),
+                    false,
+                )?,
+            }
+        }
+    }
+
+    /// Runs a statement, unless an error was injected for it.
+    fn execute(&mut self, sql: &str, params: &HashMap<String, ColumnData<'static>>) -> Outcome {
+        let mut state = lock(&self.state);
+        state.statements.push(sql.to_owned());
+
+        let lower = sql.to_lowercase();
+        let injected = state.failures.iter().position(|failure| {
+            matches!(&failure.target, Target::Statement(text) if lower.contains(text.as_str()))
+        });
+        if let Some(i) = injected {
+            return Outcome::Error(state.failures.remove(i).error);
+        }
+
+        match statement(&mut state, sql, params) {
+            Ok(Statement::Outcome(outcome)) => outcome,
+            Ok(Statement::InsertBulk(target)) => {
+                self.bulk = Some(target);
+                Outcome::Done(None)
+            }
+            Err(error) => Outcome::Error(error),
+        }
+    }
+
+    /// Answers a request. The results of RPC requests end like those of a
+    /// stored procedure.
+    fn respond(&mut self, outcome: Outcome, rpc: bool) -> io::Result<()> {
+        let mut response = Response::new(TABULAR_RESULT);
+        let (status, count) = match outcome {
+            Outcome::Error(error) => {
+                put_error(&mut response.buf, &error);
+                let done = if rpc { DONEPROC } else { DONE };
+                put_done(&mut response.buf, done, DONE_ERROR, 0);
+                return response.finish(self);
+            }
+            Outcome::Done(count) => (count.map_or(0, |_| DONE_COUNT), count.unwrap_or(0)),
+            Outcome::Rows {
+                columns,
+                rows,
+                count,
+            } => {
+                put_col_metadata(&mut response.buf, &columns);
+                response.write(self, &rows)?;
+                (DONE_COUNT, count)
+            }
+        };
+
+        if rpc {
+            put_done(&mut response.buf, DONEINPROC, DONE_MORE | status, count);
+            response.buf.put_u8(RETURNSTATUS);
+            response.buf.put_i32_le(0);
+            put_done(&mut response.buf, DONEPROC, 0, 0);
+        } else {
+            put_done(&mut response.buf, DONE, status, count);
+        }
+        response.finish(self)
+    }
+
+    fn send(&mut self, packet_type: u8, payload: &[u8]) -> io::Result<()> {
+        let mut response = Response::new(packet_type);
+        response.buf.extend_from_slice(payload);
+        response.finish(self)
+    }
+
+    fn write_packet(&mut self, packet_type: u8, last: bool, payload: &[u8]) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(PACKET_HEADER_LEN + payload.len());
+        packet.put_u8(packet_type);
+        packet.put_u8(if last { END_OF_MESSAGE } else { 0 });
+        packet.put_u16((PACKET_HEADER_LEN + payload.len()) as u16);
+        packet.put_u16(0);
+        packet.put_u8(self.packet_id);
+        packet.put_u8(0);
+        packet.extend_from_slice(payload);
+        self.packet_id = self.packet_id.wrapping_add(1);
+
+        self.writer.write_all(&packet)
+    }
+}
+
+/// A message of the server, sent in packets of [`PACKET_SIZE`] as it is
+/// written.
+struct Response {
+    packet_type: u8,
+    buf: Vec<u8>,
+}
+
+impl Response {
+    fn new(packet_type: u8) -> Response {
+        Response {
+            packet_type,
+            buf: Vec::with_capacity(PACKET_SIZE),
+        }
+    }
+
+    fn write(&mut self, session: &mut Session, bytes: &[u8]) -> io::Result<()> {
+        let payload = PACKET_SIZE - PACKET_HEADER_LEN;
+        for chunk in bytes.chunks(payload) {
+            self.buf.extend_from_slice(chunk);
+            while self.buf.len() > payload {
+                let rest = self.buf.split_off(payload);
+                let full = mem::replace(&mut self.buf, rest);
+                session.write_packet(self.packet_type, false, &full)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self, session: &mut Session) -> io::Result<()> {
+        let payload = PACKET_SIZE - PACKET_HEADER_LEN;
+        while self.buf.len() > payload {
+            let rest = self.buf.split_off(payload);
+            session.write_packet(self.packet_type, false, &self.buf)?;
+            self.buf = rest;
+        }
+        session.write_packet(self.packet_type, true, &self.buf)
+    }
+}
+
+/// The payload of the packets of a message of the client, read as one
+/// stream.
+struct Message<'a> {
+    reader: &'a mut BufReader<TcpStream>,
+    /// Bytes left in the current packet.
+    left: usize,
+    last: bool,
+}
+
+impl<'a> Message<'a> {
+    /// Reads the header of the first packet of the next message, and
+    /// returns the type of the message with it.
+    fn start(reader: &'a mut BufReader<TcpStream>) -> io::Result<(u8, Message<'a>)> {
+        let (packet_type, last, left) = packet_header(reader)?;
+        Ok((packet_type, Message { reader, left, last }))
+    }
+}
+
+impl Read for Message<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.left == 0 {
+            if self.last || buf.is_empty() {
+                return Ok(0);
+            }
+            let (_, last, left) = packet_header(self.reader)?;
+            self.left = left;
+            self.last = last;
+        }
+
+        let len = buf.len().min(self.left);
+        let read = self.reader.read(&mut buf[..len])?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.left -= read;
+        Ok(read)
+    }
+}
+
+/// The type of a packet, whether it is the last of its message and the
+/// length of its payload.
+fn packet_header(reader: &mut impl Read) -> io::Result<(u8, bool, usize)> {
+    let mut header = [0; PACKET_HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+    let payload = len
+        .checked_sub(PACKET_HEADER_LEN)
+        .ok_or_else(|| invalid_data("packet shorter than its header"))?;
+
+    Ok((header[0], header[1] & END_OF_MESSAGE != 0, payload))
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Reads the little-endian values of TDS.
+trait ReadTds: Read {
+    fn exact(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![0; len];
+        self.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let mut bytes = [0; 2];
+        self.read_exact(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        self.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        self.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// An unsigned integer of `len` bytes.
+    fn uint(&mut self, len: usize) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        self.read_exact(&mut bytes[..len])?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// A string of UTF-16 code units, of `chars` units.
+    fn utf16(&mut self, chars: usize) -> io::Result<String> {
+        utf16(&self.exact(chars * 2)?)
+    }
+
+    /// `B_VARCHAR`: a string with its length in a byte.
+    fn b_varchar(&mut self) -> io::Result<String> {
+        let chars = self.u8()? as usize;
+        self.utf16(chars)
+    }
+
+    /// `US_VARCHAR`: a string with its length in two bytes.
+    fn us_varchar(&mut self) -> io::Result<String> {
+        let chars = self.u16()? as usize;
+        self.utf16(chars)
+    }
+}
+
+impl<R: Read + ?Sized> ReadTds for R {}
+
+fn utf16(bytes: &[u8]) -> io::Result<String> {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect();
+    String::from_utf16(&units).map_err(|_| invalid_data("invalid UTF-16"))
+}
+
+fn put_utf16(buf: &mut Vec<u8>, s: &str) {
+    for unit in s.encode_utf16() {
+        buf.put_u16_le(unit);
+    }
+}
+
+fn put_b_varchar(buf: &mut Vec<u8>, s: &str) {
+    buf.put_u8(s.encode_utf16().count() as u8);
+    put_utf16(buf, s);
+}
+
+fn put_us_varchar(buf: &mut Vec<u8>, s: &str) {
+    buf.put_u16_le(s.encode_utf16().count() as u16);
+    put_utf16(buf, s);
+}
+
+/// The answer to PRELOGIN: the version of the server, and no encryption.
+fn prelogin() -> Vec<u8> {
+    const PRELOGIN_VERSION: u8 = 0x00;
+    const PRELOGIN_ENCRYPTION: u8 = 0x01;
+    const PRELOGIN_TERMINATOR: u8 = 0xff;
+    const ENCRYPT_NOT_SUP: u8 = 0x02;
+
+    let mut buf = Vec::new();
+    // Each option: its token, and the offset and length of its data.
+    buf.put_u8(PRELOGIN_VERSION);
+    buf.put_u16(11);
+    buf.put_u16(6);
+    buf.put_u8(PRELOGIN_ENCRYPTION);
+    buf.put_u16(17);
+    buf.put_u16(1);
+    buf.put_u8(PRELOGIN_TERMINATOR);
+    // 16.0.1000, sub-build 6.
+    buf.extend_from_slice(&[16, 0, 0x03, 0xE8, 0, 6]);
+    buf.put_u8(ENCRYPT_NOT_SUP);
+    buf
+}
+
+/// The answer to LOGIN7, whatever the credentials.
+fn login_ack() -> Vec<u8> {
+    const PROGRAM: &str = "Mock SQL Server";
+
+    let mut buf = Vec::new();
+    buf.put_u8(LOGINACK);
+    buf.put_u16_le((1 + 4 + 1 + PROGRAM.len() * 2 + 4) as u16);
+    // SQL_TSQL
+    buf.put_u8(1);
+    buf.put_u32(TDS_74);
+    put_b_varchar(&mut buf, PROGRAM);
+    buf.extend_from_slice(&[16, 0, 0x03, 0xE8]);
+    put_done(&mut buf, DONE, 0, 0);
+    buf
+}
+
+fn put_done(buf: &mut Vec<u8>, token: u8, status: u16, count: u64) {
+    buf.put_u8(token);
+    buf.put_u16_le(status);
+    buf.put_u16_le(0);
+    buf.put_u64_le(count);
+}
+
+fn put_error(buf: &mut Vec<u8>, error: &MockError) {
+    const SERVER: &str = "mock";
+
+    let mut token = Vec::new();
+    token.put_u32_le(error.number);
+    token.put_u8(error.state);
+    token.put_u8(error.class);
+    put_us_varchar(&mut token, &error.message);
+    put_b_varchar(&mut token, SERVER);
+    put_b_varchar(&mut token, "");
+    token.put_u32_le(1);
+
+    buf.put_u8(ERROR);
+    buf.put_u16_le(token.len() as u16);
+    buf.extend_from_slice(&token);
+}
+
+fn put_col_metadata(buf: &mut Vec<u8>, columns: &[Column]) {
+    buf.put_u8(COLMETADATA);
+    buf.put_u16_le(columns.len() as u16);
+    for column in columns {
+        let mut flags = 0;
+        if column.nullable {
+            flags |= COLUMN_NULLABLE;
+        }
+        if column.is_insertable() {
+            flags |= COLUMN_UPDATEABLE;
+        }
+        if column.identity {
+            flags |= COLUMN_IDENTITY;
+        }
+
+        // User type.
+        buf.put_u32_le(0);
+        buf.put_u16_le(flags);
+        let ty = type_info(&column.sql_type);
+        put_type_info(buf, &ty);
+        if ty.is_text() {
+            // The name of the table, left out.
+            buf.put_u8(0);
+        }
+        put_b_varchar(buf, &column.name);
+    }
+}
+
+fn put_type_info(buf: &mut Vec<u8>, ty: &TypeInfo) {
+    buf.put_u8(ty.ty);
+    match ty.ty {
+        BITNTYPE | INTNTYPE | FLTNTYPE | MONEYNTYPE | GUIDTYPE | DATETIMNTYPE => {
+            buf.put_u8(ty.len as u8)
+        }
+        DECIMALNTYPE | NUMERICNTYPE => {
+            buf.put_u8(ty.len as u8);
+            buf.put_u8(ty.precision);
+            buf.put_u8(ty.scale);
+        }
+        TIMENTYPE | DATETIME2NTYPE | DATETIMEOFFSETNTYPE => buf.put_u8(ty.scale),
+        BIGCHARTYPE | BIGVARCHARTYPE | NCHARTYPE | NVARCHARTYPE => {
+            buf.put_u16_le(ty.len as u16);
+            buf.extend_from_slice(&COLLATION);
+        }
+        BIGBINARYTYPE | BIGVARBINARYTYPE => buf.put_u16_le(ty.len as u16),
+        TEXTTYPE | NTEXTTYPE => {
+            buf.put_u32_le(ty.len);
+            buf.extend_from_slice(&COLLATION);
+        }
+        IMAGETYPE => buf.put_u32_le(ty.len),
+        // No schema collection.
+        XMLTYPE => buf.put_u8(0),
+        _ => {}
+    }
+}
+
+fn read_type_info(r: &mut impl Read) -> io::Result<TypeInfo> {
+    let ty = r.u8()?;
+    let mut info = TypeInfo::new(ty, 0);
+    match ty {
+        NULLTYPE => {}
+        BITNTYPE | INTNTYPE | FLTNTYPE | MONEYNTYPE | GUIDTYPE | DATETIMNTYPE => {
+            info.len = r.u8()?.into()
+        }
+        DECIMALNTYPE | NUMERICNTYPE => {
+            info.len = r.u8()?.into();
+            info.precision = r.u8()?;
+            info.scale = r.u8()?;
+        }
+        DATENTYPE => info.len = 3,
+        TIMENTYPE | DATETIME2NTYPE | DATETIMEOFFSETNTYPE => info.scale = r.u8()?,
+        BIGCHARTYPE | BIGVARCHARTYPE | NCHARTYPE | NVARCHARTYPE => {
+            info.len = r.u16()?.into();
+            r.exact(COLLATION.len())?;
+        }
+        BIGBINARYTYPE | BIGVARBINARYTYPE => info.len = r.u16()?.into(),
+        TEXTTYPE | NTEXTTYPE => {
+            info.len = r.u32()?;
+            r.exact(COLLATION.len())?;
+        }
+        IMAGETYPE => info.len = r.u32()?,
+        XMLTYPE => {
+            if r.u8()? == 1 {
+                r.b_varchar()?;
+                r.b_varchar()?;
+                r.us_varchar()?;
+            }
+        }
+        other => {
+            return Err(invalid_data(format!(
+                "unsupported data type {:#04x}",
+                other
+            )))
+        }
+    }
+
+    Ok(info)
+}
+
+/// A NULL of the variant Tiberius reads for values of type `ty`.
+fn null_value(ty: &TypeInfo) -> ColumnData<'static> {
+    match (ty.ty, ty.len) {
+        (BITNTYPE, _) => ColumnData::Bit(None),
+        (INTNTYPE, 1) => ColumnData::U8(None),
+        (INTNTYPE, 2) => ColumnData::I16(None),
+        (INTNTYPE, 4) => ColumnData::I32(None),
+        (INTNTYPE, _) => ColumnData::I64(None),
+        (FLTNTYPE, 4) => ColumnData::F32(None),
+        (FLTNTYPE, _) | (MONEYNTYPE, _) => ColumnData::F64(None),
+        (GUIDTYPE, _) => ColumnData::Guid(None),
+        (DATETIMNTYPE, 4) => ColumnData::SmallDateTime(None),
+        (DATETIMNTYPE, _) => ColumnData::DateTime(None),
+        (DECIMALNTYPE | NUMERICNTYPE, _) => ColumnData::Numeric(None),
+        (DATENTYPE, _) => ColumnData::Date(None),
+        (TIMENTYPE, _) => ColumnData::Time(None),
+        (DATETIME2NTYPE, _) => ColumnData::DateTime2(None),
+        (DATETIMEOFFSETNTYPE, _) => ColumnData::DateTimeOffset(None),
+        (BIGCHARTYPE | BIGVARCHARTYPE | NCHARTYPE | NVARCHARTYPE | TEXTTYPE | NTEXTTYPE, _) => {
+            ColumnData::String(None)
+        }
+        (BIGBINARYTYPE | BIGVARBINARYTYPE | IMAGETYPE, _) => ColumnData::Binary(None),
+        (XMLTYPE, _) => ColumnData::Xml(None),
+        _ => ColumnData::I32(None),
+    }
+}
+
+/// Reads a value of type `ty`.
+fn read_value(r: &mut impl Read, ty: &TypeInfo) -> io::Result<ColumnData<'static>> {
+    let text = |bytes: Option<Vec<u8>>| bytes.map(|bytes| decode_char(&bytes));
+    let unicode = |bytes: Option<Vec<u8>>| bytes.map(|bytes| utf16(&bytes)).transpose();
+
+    let value = match ty.ty {
+        NULLTYPE => ColumnData::I32(None),
+        BIGCHARTYPE | BIGVARCHARTYPE | TEXTTYPE => {
+            ColumnData::String(text(read_bytes(r, ty)?).map(Cow::Owned))
+        }
+        NCHARTYPE | NVARCHARTYPE | NTEXTTYPE => {
+            ColumnData::String(unicode(read_bytes(r, ty)?)?.map(Cow::Owned))
+        }
+        BIGBINARYTYPE | BIGVARBINARYTYPE | IMAGETYPE => {
+            ColumnData::Binary(read_bytes(r, ty)?.map(Cow::Owned))
+        }
+        XMLTYPE => {
+            ColumnData::Xml(unicode(read_bytes(r, ty)?)?.map(|xml| Cow::Owned(XmlData::new(xml))))
+        }
+        _ => {
+            let len = r.u8()?;
+            if len == 0 {
+                return Ok(null_value(ty));
+            }
+            read_fixed(r, ty, len)?
+        }
+    };
+
+    Ok(value)
+}
+
+/// Reads a value other than NULL of `len` bytes of a type with a length
+/// in a byte.
+fn read_fixed(r: &mut impl Read, ty: &TypeInfo, len: u8) -> io::Result<ColumnData<'static>> {
+    let wrong_length = || invalid_data(format!("{} bytes for type {:#04x}", len, ty.ty));
+
+    let value = match (ty.ty, len) {
+        (BITNTYPE, 1) => ColumnData::Bit(Some(r.u8()? != 0)),
+        (INTNTYPE, 1) => ColumnData::U8(Some(r.u8()?)),
+        (INTNTYPE, 2) => ColumnData::I16(Some(r.u16()? as i16)),
+        (INTNTYPE, 4) => ColumnData::I32(Some(r.u32()? as i32)),
+        (INTNTYPE, 8) => ColumnData::I64(Some(r.u64()? as i64)),
+        (FLTNTYPE, 4) => ColumnData::F32(Some(f32::from_bits(r.u32()?))),
+        (FLTNTYPE, 8) => ColumnData::F64(Some(f64::from_bits(r.u64()?))),
+        (MONEYNTYPE, 4) => ColumnData::F64(Some(r.u32()? as i32 as f64 / 1e4)),
+        (MONEYNTYPE, 8) => {
+            let high = r.u32()? as i32 as i64;
+            let low = r.u32()? as i64;
+            ColumnData::F64(Some(((high << 32) | low) as f64 / 1e4))
+        }
+        (GUIDTYPE, 16) => {
+            let bytes: [u8; 16] = r.exact(16)?.try_into().expect("16 bytes");
+            ColumnData::Guid(Some(Uuid::from_bytes_le(bytes)))
+        }
+        (DATETIMNTYPE, 8) => {
+            let days = r.u32()? as i32;
+            ColumnData::DateTime(Some(DateTime::new(days, r.u32()?)))
+        }
+        (DATETIMNTYPE, 4) => {
+            let days = r.u16()?;
+            ColumnData::SmallDateTime(Some(SmallDateTime::new(days, r.u16()?)))
+        }
+        (DECIMALNTYPE | NUMERICNTYPE, 5 | 9 | 13 | 17) => {
+            let positive = r.u8()? == 1;
+            let mut magnitude = [0; 16];
+            r.read_exact(&mut magnitude[..len as usize - 1])?;
+            let magnitude = i128::from_le_bytes(magnitude);
+            let value = if positive { magnitude } else { -magnitude };
+            ColumnData::Numeric(Some(Numeric::new_with_scale(value, ty.scale)))
+        }
+        (DATENTYPE, 3) => ColumnData::Date(Some(Date::new(r.uint(3)? as u32))),
+        (TIMENTYPE, 3..=5) => ColumnData::Time(Some(Time::new(r.uint(len.into())?, ty.scale))),
+        (DATETIME2NTYPE, 6..=8) => ColumnData::DateTime2(Some(read_datetime2(r, ty, len - 3)?)),
+        (DATETIMEOFFSETNTYPE, 8..=10) => {
+            let datetime2 = read_datetime2(r, ty, len - 5)?;
+            let offset = r.u16()? as i16;
+            ColumnData::DateTimeOffset(Some(DateTimeOffset::new(datetime2, offset)))
+        }
+        _ => return Err(wrong_length()),
+    };
+
+    Ok(value)
+}
+
+fn read_datetime2(r: &mut impl Read, ty: &TypeInfo, time_len: u8) -> io::Result<DateTime2> {
+    let time = Time::new(r.uint(time_len.into())?, ty.scale);
+    let date = Date::new(r.uint(3)? as u32);
+    Ok(DateTime2::new(date, time))
+}
+
+/// Reads the bytes of a value of a string or binary type, or `None` for
+/// NULL.
+fn read_bytes(r: &mut impl Read, ty: &TypeInfo) -> io::Result<Option<Vec<u8>>> {
+    if ty.is_text() {
+        let pointer = r.u8()? as usize;
+        if pointer == 0 {
+            return Ok(None);
+        }
+        // The text pointer and its timestamp.
+        r.exact(pointer + 8)?;
+        let len = r.u32()? as usize;
+        return r.exact(len).map(Some);
+    }
+
+    if ty.is_plp() {
+        if r.u64()? == PLP_NULL {
+            return Ok(None);
+        }
+        let mut bytes = Vec::new();
+        loop {
+            let chunk = r.u32()? as usize;
+            if chunk == 0 {
+                return Ok(Some(bytes));
+            }
+            bytes.extend_from_slice(&r.exact(chunk)?);
+        }
+    }
+
+    match r.u16()? {
+        0xFFFF => Ok(None),
+        len => r.exact(len.into()).map(Some),
+    }
+}
+
+/// Writes `value`, already converted to the variant of `ty`.
+fn put_value(buf: &mut Vec<u8>, ty: &TypeInfo, value: &ColumnData<'_>) -> Result<(), MockError> {
+    if is_null(value) {
+        match ty.ty {
+            NULLTYPE => {}
+            _ if ty.is_text() => buf.put_u8(0),
+            _ if ty.is_plp() => buf.put_u64_le(PLP_NULL),
+            BIGCHARTYPE | BIGVARCHARTYPE | NCHARTYPE | NVARCHARTYPE | BIGBINARYTYPE
+            | BIGVARBINARYTYPE => buf.put_u16_le(0xFFFF),
+            _ => buf.put_u8(0),
+        }
+        return Ok(());
+    }
+
+    match (ty.ty, value) {
+        (BITNTYPE, ColumnData::Bit(Some(value))) => {
+            buf.put_u8(1);
+            buf.put_u8(*value as u8);
+        }
+        (INTNTYPE, ColumnData::U8(Some(value))) => {
+            buf.put_u8(1);
+            buf.put_u8(*value);
+        }
+        (INTNTYPE, ColumnData::I16(Some(value))) => {
+            buf.put_u8(2);
+            buf.put_i16_le(*value);
+        }
+        (INTNTYPE, ColumnData::I32(Some(value))) => {
+            buf.put_u8(4);
+            buf.put_i32_le(*value);
+        }
+        (INTNTYPE, ColumnData::I64(Some(value))) => {
+            buf.put_u8(8);
+            buf.put_i64_le(*value);
+        }
+        (FLTNTYPE, ColumnData::F32(Some(value))) => {
+            buf.put_u8(4);
+            buf.put_f32_le(*value);
+        }
+        (FLTNTYPE, ColumnData::F64(Some(value))) => {
+            buf.put_u8(8);
+            buf.put_f64_le(*value);
+        }
+        (MONEYNTYPE, ColumnData::F64(Some(value))) => {
+            let units = (value * 1e4).round() as i64;
+            if ty.len == 4 {
+                buf.put_u8(4);
+                buf.put_i32_le(units as i32);
+            } else {
+                buf.put_u8(8);
+                buf.put_i32_le((units >> 32) as i32);
+                buf.put_u32_le(units as u32);
+            }
+        }
+        (DECIMALNTYPE | NUMERICNTYPE, ColumnData::Numeric(Some(value))) => {
+            let len = decimal_len(ty.precision);
+            buf.put_u8(len);
+            buf.put_u8((value.value() >= 0) as u8);
+            let magnitude = value.value().unsigned_abs().to_le_bytes();
+            buf.extend_from_slice(&magnitude[..len as usize - 1]);
+        }
+        (GUIDTYPE, ColumnData::Guid(Some(value))) => {
+            buf.put_u8(16);
+            buf.extend_from_slice(&value.to_bytes_le());
+        }
+        (DATETIMNTYPE, ColumnData::DateTime(Some(value))) => {
+            buf.put_u8(8);
+            buf.put_i32_le(value.days());
+            buf.put_u32_le(value.seconds_fragments());
+        }
+        (DATETIMNTYPE, ColumnData::SmallDateTime(Some(value))) => {
+            buf.put_u8(4);
+            buf.put_u16_le(value.days());
+            buf.put_u16_le(value.seconds_fragments());
+        }
+        (DATENTYPE, ColumnData::Date(Some(value))) => {
+            buf.put_u8(3);
+            put_date(buf, *value);
+        }
+        (TIMENTYPE, ColumnData::Time(Some(value))) => {
+            buf.put_u8(time_len(ty.scale));
+            put_time(buf, *value, ty.scale);
+        }
+        (DATETIME2NTYPE, ColumnData::DateTime2(Some(value))) => {
+            buf.put_u8(time_len(ty.scale) + 3);
+            put_time(buf, value.time(), ty.scale);
+            put_date(buf, value.date());
+        }
+        (DATETIMEOFFSETNTYPE, ColumnData::DateTimeOffset(Some(value))) => {
+            buf.put_u8(time_len(ty.scale) + 5);
+            put_time(buf, value.datetime2().time(), ty.scale);
+            put_date(buf, value.datetime2().date());
+            buf.put_i16_le(value.offset());
+        }
+        (BIGCHARTYPE | BIGVARCHARTYPE | TEXTTYPE, ColumnData::String(Some(value))) => {
+            put_bytes(buf, ty, &encode_char(value))
+        }
+        (NCHARTYPE | NVARCHARTYPE | NTEXTTYPE, ColumnData::String(Some(value))) => {
+            let mut bytes = Vec::with_capacity(value.len() * 2);
+            put_utf16(&mut bytes, value);
+            put_bytes(buf, ty, &bytes)
+        }
+        (BIGBINARYTYPE | BIGVARBINARYTYPE | IMAGETYPE, ColumnData::Binary(Some(value))) => {
+            put_bytes(buf, ty, value)
+        }
+        (XMLTYPE, ColumnData::Xml(Some(value))) => {
+            let mut bytes = Vec::new();
+            put_utf16(&mut bytes, value.as_ref().as_ref());
+            put_bytes(buf, ty, &bytes)
+        }
+        (_, value) => {
+            return Err(MockError::new(
+                50000,
+                format!(
+                    "the mock server cannot store {:?} as type {:#04x}",
+                    value, ty.ty
+                ),
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+fn put_date(buf: &mut Vec<u8>, date: Date) {
+    buf.extend_from_slice(&date.days().to_le_bytes()[..3]);
+}
+
+/// Writes `time` with `scale` digits of fractions of seconds, rounded.
+fn put_time(buf: &mut Vec<u8>, time: Time, scale: u8) {
+    let increments = round_time(time, scale).increments();
+    buf.extend_from_slice(&increments.to_le_bytes()[..time_len(scale).into()]);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, ty: &TypeInfo, bytes: &[u8]) {
+    if ty.is_text() {
+        // A text pointer and its timestamp, which nobody follows.
+        buf.put_u8(16);
+        buf.extend_from_slice(&[0; 16 + 8]);
+        buf.put_u32_le(bytes.len() as u32);
+    } else if ty.is_plp() {
+        buf.put_u64_le(bytes.len() as u64);
+        if !bytes.is_empty() {
+            buf.put_u32_le(bytes.len() as u32);
+        }
+    } else {
+        buf.put_u16_le(bytes.len() as u16);
+    }
+    buf.extend_from_slice(bytes);
+    if ty.is_plp() {
+        buf.put_u32_le(0);
+    }
+}
+
+/// `s` in Windows-1252, the code page of the collation, with `?` for the
+/// characters it does not have, as SQL Server does.
+fn encode_char(s: &str) -> Vec<u8> {
+    if s.is_ascii() {
+        return s.as_bytes().to_vec();
+    }
+
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut buf = [0; 4];
+    for c in s.chars() {
+        let (encoded, _, unmappable) = WINDOWS_1252.encode(c.encode_utf8(&mut buf));
+        match unmappable {
+            true => bytes.push(b'?'),
+            false => bytes.extend_from_slice(&encoded),
+        }
+    }
+    bytes
+}
+
+fn decode_char(bytes: &[u8]) -> String {
+    WINDOWS_1252
+        .decode_without_bom_handling(bytes)
+        .0
+        .into_owned()
+}
+
+/// The text of a SQL batch.
+fn batch_text(payload: &[u8]) -> io::Result<String> {
+    let mut r = payload;
+    skip_all_headers(&mut r)?;
+    utf16(r)
+}
+
+fn skip_all_headers(r: &mut &[u8]) -> io::Result<()> {
+    let len = r.u32()? as usize;
+    r.exact(len.saturating_sub(4))?;
+    Ok(())
+}
+
+/// The statement and the parameters of a call of `sp_executesql`, or the
+/// error for calls of other procedures.
+#[allow(clippy::type_complexity)]
+fn rpc_request(
+    payload: &[u8],
+) -> io::Result<Result<(String, HashMap<String, ColumnData<'static>>), MockError>> {
+    /// `sp_executesql` among the procedures called by number.
+    const SP_EXECUTESQL: u16 = 10;
+
+    let mut r = payload;
+    skip_all_headers(&mut r)?;
+    let procedure = match r.u16()? {
+        0xFFFF => r.u16()?,
+        chars => {
+            let name = r.utf16(chars.into())?;
+            return Ok(Err(MockError::new(
+                2812,
+                format!("Could not find stored procedure '{}'.", name),
+            )));
+        }
+    };
+    if procedure != SP_EXECUTESQL {
+        return Ok(Err(MockError::new(
+            2812,
+            format!("Could not find stored procedure number {}.", procedure),
+        )));
+    }
+    // Option flags.
+    r.u16()?;
+
+    let mut values = Vec::new();
+    while !r.is_empty() {
+        let name = r.b_varchar()?;
+        // Status flags.
+        r.u8()?;
+        let ty = read_type_info(&mut r)?;
+        values.push((name, read_value(&mut r, &ty)?));
+    }
+
+    let mut values = values.into_iter();
+    let sql = match values.next() {
+        Some((_, ColumnData::String(Some(sql)))) => sql.into_owned(),
+        _ => return Err(invalid_data("sp_executesql without a statement")),
+    };
+    // The declarations of the parameters.
+    values.next();
+
+    Ok(Ok((sql, values.collect())))
+}
+
+/// What the mock server makes of a statement.
+enum Statement {
+    Outcome(Outcome),
+    /// `INSERT BULK`, whose rows come in the next message.
+    InsertBulk(BulkTarget),
+}
+
+type Params = HashMap<String, ColumnData<'static>>;
+
+fn statement(state: &mut State, sql: &str, params: &Params) -> Result<Statement, MockError> {
+    let text = sql.trim().trim_end_matches(';').trim_end();
+    let lower = text.to_lowercase();
+    let done = |count| Ok(Statement::Outcome(Outcome::Done(count)));
+
+    if lower.contains("serverproperty('productversion')") {
+        let columns = vec![result_column("", SqlType::NVarChar(Some(128)))];
+        let row = [ColumnData::String(Some(MOCK_PRODUCT_VERSION.into()))];
+        return rows(columns, &[row]);
+    }
+    if lower.contains("from sys.columns") {
+        return sys_columns(state, params);
+    }
+    if lower.contains("schema_id(@p1)") {
+        let param = |name: &str| match params.get(name) {
+            Some(ColumnData::String(Some(value))) => Ok(value.to_lowercase()),
+            _ => Err(MockError::new(
+                137,
+                format!("Must declare the scalar variable \"{}\".", name),
+            )),
+        };
+        let schema = param("@P1")?;
+
+        // Whether the schema is missing (0), has the property @P2 (1), or
+        // has not (2).
+        if lower.contains("sys.extended_properties") {
+            let property = (schema.clone(), param("@P2")?);
+            let found = match state.schemas.contains(&schema) || schema == "dbo" {
+                false => 0,
+                true if state.schema_properties.contains(&property) => 1,
+                true => 2,
+            };
+            let columns = vec![result_column("", SqlType::Int)];
+            return rows(columns, &[[ColumnData::I32(Some(found))]]);
+        }
+        if lower.contains("create schema") && schema != "dbo" {
+            state.schemas.insert(schema.clone());
+            if lower.contains("sp_addextendedproperty") {
+                state.schema_properties.insert((schema, param("@P2")?));
+            }
+        } else if lower.contains("drop schema") {
+            state.schemas.remove(&schema);
+            state
+                .schema_properties
+                .retain(|(marked, _)| *marked != schema);
+        }
+        return done(None);
+    }
+
+    if let Some(rest) = keyword(text, "SELECT") {
+        return select(state, rest.trim_start());
+    }
+    if let Some(rest) = keyword(text, "CREATE") {
+        if let Some(rest) = keyword(rest.trim_start(), "SCHEMA") {
+            let (parts, _) = object_name(rest.trim_start()).ok_or_else(|| unsupported(sql))?;
+            state.schemas.insert(parts[parts.len() - 1].to_lowercase());
+            return done(None);
+        }
+        create_table(state, text)?;
+        return done(None);
+    }
+    if let Some(rest) = keyword(text, "DROP") {
+        let rest = keyword(rest.trim_start(), "TABLE").ok_or_else(|| unsupported(sql))?;
+        let (rest, if_exists) = match keyword(rest.trim_start(), "IF") {
+            Some(rest) => (
+                keyword(rest.trim_start(), "EXISTS").ok_or_else(|| unsupported(sql))?,
+                true,
+            ),
+            None => (rest, false),
+        };
+        let (key, name) = table_name(rest.trim_start()).ok_or_else(|| unsupported(sql))?;
+        if state.tables.remove(&key).is_none() && !if_exists {
+            return Err(MockError::new(
+                3701,
+                format!(
+                    "Cannot drop the table '{}', because it does not exist or you do not have permission.",
+                    name
+                ),
+            ));
+        }
+        return done(None);
+    }
+    if let Some(rest) = keyword(text, "TRUNCATE") {
+        let rest = keyword(rest.trim_start(), "TABLE").ok_or_else(|| unsupported(sql))?;
+        let (key, name) = table_name(rest.trim_start()).ok_or_else(|| unsupported(sql))?;
+        let table = state
+            .tables
+            .get_mut(&key)
+            .ok_or_else(|| invalid_object(&name))?;
+        table.rows = Vec::new();
+        table.count = 0;
+        return done(None);
+    }
+    if let Some(rest) = keyword(text, "INSERT") {
+        let rest = rest.trim_start();
+        if let Some(rest) = keyword(rest, "BULK") {
+            let (key, name) = table_name(rest.trim_start()).ok_or_else(|| unsupported(sql))?;
+            let table = state
+                .tables
+                .get(&key)
+                .ok_or_else(|| invalid_object(&name))?;
+            return Ok(Statement::InsertBulk(BulkTarget {
+                key,
+                name: table.name.clone(),
+                columns: table.columns.clone(),
+            }));
+        }
+        let rest = keyword(rest, "INTO").unwrap_or(rest).trim_start();
+        let count =
+            insert(state, rest, params).map_err(|e| e.unwrap_or_else(|| unsupported(sql)))?;
+        return done(Some(count));
+    }
+
+    Err(unsupported(sql))
+}
+
+fn unsupported(sql: &str) -> MockError {
+    MockError::new(
+        50000,
+        format!("the mock server does not run `{}`", sql.trim()),
+    )
+}
+
+fn invalid_object(name: &str) -> MockError {
+    MockError::new(208, format!("Invalid object name '{}'.", name))
+}
+
+fn result_column(name: &str, sql_type: SqlType) -> Column {
+    Column {
+        name: name.to_owned(),
+        sql_type,
+        nullable: true,
+        identity: false,
+        computed: false,
+    }
+}
+
+/// A result set of `rows`, converted to the types of `columns`.
+fn rows<R: AsRef<[ColumnData<'static>]>>(
+    columns: Vec<Column>,
+    values: &[R],
+) -> Result<Statement, MockError> {
+    let mut rows = Vec::new();
+    for row in values {
+        put_row(&mut rows, &columns, row.as_ref())?;
+    }
+    Ok(Statement::Outcome(Outcome::Rows {
+        columns,
+        rows,
+        count: values.len() as u64,
+    }))
+}
+
+fn put_row(
+    buf: &mut Vec<u8>,
+    columns: &[Column],
+    values: &[ColumnData<'_>],
+) -> Result<(), MockError> {
+    buf.put_u8(ROW);
+    for (column, value) in columns.iter().zip(values) {
+        put_value(buf, &type_info(&column.sql_type), value)?;
+    }
+    Ok(())
+}
+
+/// `SELECT [TOP n] * FROM table` and `SELECT COUNT(*) FROM table`.
+fn select(state: &State, rest: &str) -> Result<Statement, MockError> {
+    let unsupported = || unsupported(&format!("SELECT {}", rest));
+
+    let (rest, count) = match rest
+        .get(..8)
+        .filter(|start| start.eq_ignore_ascii_case("COUNT(*)"))
+    {
+        Some(_) => (&rest[8..], true),
+        None => (rest, false),
+    };
+    let (rest, top) = match keyword(rest, "TOP") {
+        Some(rest) => {
+            let rest = rest.trim_start();
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let top: u64 = rest[..digits].parse().map_err(|_| unsupported())?;
+            (rest[digits..].trim_start(), Some(top))
+        }
+        None => (rest, None),
+    };
+    let rest = match count {
+        true => rest,
+        false => rest.strip_prefix('*').ok_or_else(unsupported)?,
+    };
+    let rest = keyword(rest.trim_start(), "FROM").ok_or_else(unsupported)?;
+    let (key, name) = table_name(rest.trim_start()).ok_or_else(unsupported)?;
+    let table = state
+        .tables
+        .get(&key)
+        .ok_or_else(|| invalid_object(&name))?;
+
+    if count {
+        let columns = vec![result_column("", SqlType::Int)];
+        return rows(columns, &[[ColumnData::I32(Some(table.count as i32))]]);
+    }
+
+    let (rows, count) = match top {
+        Some(top) if top < table.count => {
+            let types: Vec<TypeInfo> = table
+                .columns
+                .iter()
+                .map(|column| type_info(&column.sql_type))
+                .collect();
+            let mut data = table.rows.as_slice();
+            for _ in 0..top {
+                data.u8().expect("a ROW token");
+                for ty in &types {
+                    read_value(&mut data, ty).expect("rows stored by the server");
+                }
+            }
+            let len = table.rows.len() - data.len();
+            (table.rows[..len].to_vec(), top)
+        }
+        _ => (table.rows.clone(), table.count),
+    };
+
+    Ok(Statement::Outcome(Outcome::Rows {
+        columns: table.columns.clone(),
+        rows,
+        count,
+    }))
+}
+
+/// The columns of the table `@P1`, as the query of `TableSchema::load`
+/// reads them from `sys.columns`.
+fn sys_columns(state: &State, params: &Params) -> Result<Statement, MockError> {
+    let columns = vec![
+        result_column("name", SqlType::NVarChar(Some(128))),
+        result_column("", SqlType::NVarChar(Some(128))),
+        result_column("max_length", SqlType::SmallInt),
+        result_column("precision", SqlType::TinyInt),
+        result_column("scale", SqlType::TinyInt),
+        result_column("is_nullable", SqlType::Bit),
+        result_column("is_identity", SqlType::Bit),
+        result_column("is_computed", SqlType::Bit),
+    ];
+
+    let table = match params.get("@P1") {
+        Some(ColumnData::String(Some(name))) => {
+            object_key(name).and_then(|key| state.tables.get(&key))
+        }
+        _ => None,
+    };
+    let values: Vec<Vec<ColumnData<'static>>> = table
+        .map(|table| table.columns.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|column| {
+            let (type_name, max_length, precision, scale) = sys_column(&column.sql_type);
+            vec![
+                ColumnData::String(Some(column.name.clone().into())),
+                ColumnData::String(Some(type_name.into())),
+                ColumnData::I16(Some(max_length)),
+                ColumnData::U8(Some(precision)),
+                ColumnData::U8(Some(scale)),
+                ColumnData::Bit(Some(column.nullable)),
+                ColumnData::Bit(Some(column.identity)),
+                ColumnData::Bit(Some(column.computed)),
+            ]
+        })
+        .collect();
+
+    rows(columns, &values)
+}
+
+fn create_table(state: &mut State, sql: &str) -> Result<(), MockError> {
+    let definition = match parse_tables(sql) {
+        Ok(tables) if tables.len() == 1 => tables.into_iter().next().expect("one table"),
+        _ => return Err(unsupported(sql)),
+    };
+    let schema = definition.schema.unwrap_or_else(|| "dbo".to_owned());
+    let key = (schema.to_lowercase(), definition.name.to_lowercase());
+
+    if key.0 != "dbo" && !state.schemas.contains(&key.0) {
+        return Err(MockError::new(
+            2760,
+            format!(
+                "The specified schema name \"{}\" either does not exist or you do not have permission to use it.",
+                schema
+            ),
+        ));
+    }
+    if state.tables.contains_key(&key) {
+        return Err(MockError::new(
+            2714,
+            format!(
+                "There is already an object named '{}' in the database.",
+                definition.name
+            ),
+        ));
+    }
+
+    let columns = parse_columns(&definition.columns).ok_or_else(|| unsupported(sql))??;
+    state.tables.insert(
+        key,
+        Table {
+            name: format!("{}.{}", quote_name(&schema), quote_name(&definition.name)),
+            columns,
+            rows: Vec::new(),
+            count: 0,
+            next_identity: 1,
+        },
+    );
+
+    Ok(())
+}
+
+/// The columns of the text between the parentheses of `CREATE TABLE`, or
+/// `None` when the mock server does not understand it. Table constraints
+/// are left out.
+fn parse_columns(text: &str) -> Option<Result<Vec<Column>, MockError>> {
+    let mut columns = Vec::new();
+    for item in split_top_level(text) {
+        let item = item.trim();
+        if item.is_empty()
+            || [
+                "CONSTRAINT",
+                "PRIMARY",
+                "UNIQUE",
+                "FOREIGN",
+                "CHECK",
+                "INDEX",
+            ]
+            .iter()
+            .any(|word| keyword(item, word).is_some())
+        {
+            continue;
+        }
+
+        let (name, rest) = identifier(item)?;
+        let rest = rest.trim_start();
+        let type_end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let (type_name, mut rest) = rest.split_at(type_end);
+        let mut args = Vec::new();
+        if rest.trim_start().starts_with('(') {
+            rest = rest.trim_start();
+            let close = closing_parenthesis(rest, 0)?;
+            args = rest[1..close].split(',').map(str::trim).collect();
+            rest = &rest[close + 1..];
+        }
+        let Some(sql_type) = sql_type(type_name, &args) else {
+            return Some(Err(MockError::new(
+                2715,
+                format!(
+                    "Column, parameter, or variable #{}: Cannot find data type {}.",
+                    columns.len() + 1,
+                    type_name
+                ),
+            )));
+        };
+
+        let rest = rest.to_uppercase();
+        let identity = rest.contains("IDENTITY");
+        columns.push(Column {
+            name,
+            sql_type,
+            nullable: !(identity || rest.contains("NOT NULL") || rest.contains("PRIMARY KEY")),
+            identity,
+            computed: false,
+        });
+    }
+
+    Some(Ok(columns))
+}
+
+/// The type of a column declared as `name(args)`, with the defaults of SQL
+/// Server for the arguments left out.
+fn sql_type(name: &str, args: &[&str]) -> Option<SqlType> {
+    let number = |i: usize, default: u16| -> Option<u16> {
+        match args.get(i) {
+            Some(arg) => arg.parse().ok(),
+            None => Some(default),
+        }
+    };
+    // `max`, or a length that defaults to 1.
+    let length = || match args.first() {
+        Some(arg) if arg.eq_ignore_ascii_case("max") => Some(None),
+        _ => number(0, 1).map(Some),
+    };
+    let scale = || {
+        number(0, 7)
+            .filter(|scale| *scale <= 7)
+            .map(|scale| scale as u8)
+    };
+
+    let sql_type = match name.to_lowercase().as_str() {
+        "bit" => SqlType::Bit,
+        "tinyint" => SqlType::TinyInt,
+        "smallint" => SqlType::SmallInt,
+        "int" | "integer" => SqlType::Int,
+        "bigint" => SqlType::BigInt,
+        "real" => SqlType::Real,
+        // `float(1)` to `float(24)` are `real`.
+        "float" => match number(0, 53)? {
+            1..=24 => SqlType::Real,
+            _ => SqlType::Float,
+        },
+        "decimal" | "dec" | "numeric" => SqlType::Decimal {
+            precision: number(0, 18)? as u8,
+            scale: number(1, 0)? as u8,
+        },
+        "money" => SqlType::Money,
+        "smallmoney" => SqlType::SmallMoney,
+        "char" | "character" => SqlType::Char(length()??),
+        "varchar" => SqlType::VarChar(length()?),
+        "nchar" => SqlType::NChar(length()??),
+        "nvarchar" | "sysname" => SqlType::NVarChar(length()?),
+        "text" => SqlType::Text,
+        "ntext" => SqlType::NText,
+        "binary" => SqlType::Binary(length()??),
+        "varbinary" => SqlType::VarBinary(length()?),
+        "image" => SqlType::Image,
+        "uniqueidentifier" => SqlType::UniqueIdentifier,
+        "date" => SqlType::Date,
+        "time" => SqlType::Time(scale()?),
+        "datetime" => SqlType::DateTime,
+        "smalldatetime" => SqlType::SmallDateTime,
+        "datetime2" => SqlType::DateTime2(scale()?),
+        "datetimeoffset" => SqlType::DateTimeOffset(scale()?),
+        "xml" => SqlType::Xml,
+        "rowversion" | "timestamp" => SqlType::RowVersion,
+        _ => return None,
+    };
+
+    Some(sql_type)
+}
+
+/// `INSERT INTO table [(columns)] VALUES (values)[, (values)...]` with
+/// parameters, NULLs and literals as values. `Err(None)` when the mock
+/// server does not understand the statement.
+fn insert(state: &mut State, rest: &str, params: &Params) -> Result<u64, Option<MockError>> {
+    let (key, name) = table_name(rest).ok_or(None)?;
+    let (_, rest) = object_name(rest).ok_or(None)?;
+    let table = state
+        .tables
+        .get_mut(&key)
+        .ok_or_else(|| invalid_object(&name))?;
+
+    let mut rest = rest.trim_start();
+    let targets: Vec<usize> = if rest.starts_with('(') {
+        let close = closing_parenthesis(rest, 0).ok_or(None)?;
+        let names = split_top_level(&rest[1..close]);
+        rest = rest[close + 1..].trim_start();
+        names
+            .iter()
+            .map(|name| {
+                let (name, _) = identifier(name.trim()).ok_or(None)?;
+                table
+                    .columns
+                    .iter()
+                    .position(|column| column.name.eq_ignore_ascii_case(&name))
+                    .ok_or_else(|| {
+                        Some(MockError::new(
+                            207,
+                            format!("Invalid column name '{}'.", name),
+                        ))
+                    })
+            })
+            .collect::<Result<_, _>>()?
+    } else {
+        (0..table.columns.len())
+            .filter(|i| table.columns[*i].is_insertable())
+            .collect()
+    };
+
+    let mut rest = keyword(rest, "VALUES").ok_or(None)?.trim_start();
+    let mut data = Vec::new();
+    let mut count = 0;
+    loop {
+        let close = closing_parenthesis(rest, 0)
+            .filter(|_| rest.starts_with('('))
+            .ok_or(None)?;
+        let values = split_top_level(&rest[1..close]);
+        if values.len() != targets.len() {
+            return Err(Some(MockError::new(
+                110,
+                "There are fewer columns in the INSERT statement than values specified in the VALUES clause.",
+            )));
+        }
+
+        let mut row = new_row(table);
+        for (i, value) in targets.iter().zip(&values) {
+            let column = &table.columns[*i];
+            let value = match literal(value.trim(), params) {
+                Some(Ok(value)) => coerce(value, column, &table.name)?,
+                // Strings too long for their column are truncation errors.
+                Some(Err(Value::Text(text)))
+                    if matches!(column.sql_type.null(), ColumnData::String(_)) =>
+                {
+                    coerce(ColumnData::String(Some(text.into())), column, &table.name)?
+                }
+                Some(Err(value)) => convert(
+                    value,
+                    &full_precision(&column.sql_type),
+                    BinaryEncoding::Hex,
+                )
+                .map_err(|e| conversion_error(&e.to_string()))
+                .and_then(|value| store(value, &column.sql_type))?,
+                None => return Err(None),
+            };
+            row[*i] = value;
+        }
+        check_nulls(table, &row)?;
+        put_row(&mut data, &table.columns, &row)?;
+        count += 1;
+
+        rest = rest[close + 1..].trim_start();
+        match rest.strip_prefix(',') {
+            Some(next) => rest = next.trim_start(),
+            None if rest.is_empty() => break,
+            None => return Err(None),
+        }
+    }
+
+    table.rows.extend_from_slice(&data);
+    table.count += count;
+    Ok(count)
+}
+
+/// A value of `VALUES`: a parameter, or the text of a literal to convert.
+#[allow(clippy::type_complexity)]
+fn literal(value: &str, params: &Params) -> Option<Result<ColumnData<'static>, Value>> {
+    if value.starts_with('@') {
+        return params
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(value))
+            .map(|(_, value)| Ok(value.clone()));
+    }
+    if value.eq_ignore_ascii_case("NULL") {
+        return Some(Err(Value::Null));
+    }
+
+    let quoted = value.strip_prefix(['N', 'n']).unwrap_or(value);
+    if let Some(text) = quoted
+        .strip_prefix('\'')
+        .and_then(|text| text.strip_suffix('\''))
+    {
+        return Some(Err(Value::Text(text.replace("''", "'"))));
+    }
+    Some(Err(Value::Text(value.to_owned())))
+}
+
+/// A row of NULLs, with the next value of the identity column.
+fn new_row(table: &mut Table) -> Vec<ColumnData<'static>> {
+    table
+        .columns
+        .iter()
+        .map(|column| match column.identity {
+            true => {
+                let value = Value::Int(table.next_identity);
+                table.next_identity += 1;
+                convert(value, &column.sql_type, BinaryEncoding::Hex)
+                    .unwrap_or_else(|_| column.sql_type.null())
+            }
+            false => column.sql_type.null(),
+        })
+        .collect()
+}
+
+fn check_nulls(table: &Table, row: &[ColumnData<'_>]) -> Result<(), MockError> {
+    match table
+        .columns
+        .iter()
+        .zip(row)
+        .find(|(column, value)| !column.nullable && is_null(value))
+    {
+        Some((column, _)) => Err(MockError::new(
+            515,
+            format!(
+                "Cannot insert the value NULL into column '{}', table '{}'; column does not allow nulls. INSERT fails.",
+                column.name, table.name
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Converts a value to the type of `column`, as SQL Server does when it
+/// stores it.
+fn coerce(
+    value: ColumnData<'static>,
+    column: &Column,
+    table: &str,
+) -> Result<ColumnData<'static>, MockError> {
+    if is_null(&value) {
+        return Ok(column.sql_type.null());
+    }
+
+    let too_long = match (&value, &column.sql_type) {
+        (ColumnData::String(Some(s)), SqlType::Char(len) | SqlType::VarChar(Some(len))) => {
+            encode_char(s).len() > *len as usize
+        }
+        (ColumnData::String(Some(s)), SqlType::NChar(len) | SqlType::NVarChar(Some(len))) => {
+            s.encode_utf16().count() > *len as usize
+        }
+        (ColumnData::Binary(Some(bytes)), SqlType::Binary(len) | SqlType::VarBinary(Some(len))) => {
+            bytes.len() > *len as usize
+        }
+        _ => false,
+    };
+    if too_long {
+        return Err(MockError::new(
+            2628,
+            format!(
+                "String or binary data would be truncated in table '{}', column '{}'.",
+                table, column.name
+            ),
+        ));
+    }
+
+    store(value, &column.sql_type)
+}
+
+/// `value` as SQL Server stores it in a column of type `sql_type`. It is
+/// converted by the crate to the type at full precision, and then rounded
+/// and padded by rules of the mock server's own, so that a mistake in the
+/// rules of [`stored_value`](crate::verify::stored_value), which the tests
+/// check the stored values with, does not hide the same mistake here:
+/// `datetime` values are rounded to the nearest 1/300 of a second,
+/// `smalldatetime` values to the nearest minute, `real` values to the
+/// nearest single-precision float, times to the scale of the column, and
+/// `char`, `nchar` and `binary` values are padded to the length of the
+/// column.
+pub(crate) fn store(
+    value: ColumnData<'static>,
+    sql_type: &SqlType,
+) -> Result<ColumnData<'static>, MockError> {
+    if is_null(&value) {
+        return Ok(sql_type.null());
+    }
+
+    let value = match (value, sql_type) {
+        (value @ ColumnData::DateTime(Some(_)), SqlType::DateTime)
+        | (value @ ColumnData::SmallDateTime(Some(_)), SqlType::SmallDateTime) => return Ok(value),
+        (value, _) => convert_column(value, &full_precision(sql_type))
+            .map_err(|e| conversion_error(&e.to_string()))?,
+    };
+
+    let stored = match (value, sql_type) {
+        (ColumnData::F64(Some(value)), SqlType::Real) => match value as f32 {
+            single if single.is_infinite() && value.is_finite() => {
+                return Err(conversion_error(&format!(
+                    "{} is out of range for real",
+                    value
+                )))
+            }
+            single => ColumnData::F32(Some(single)),
+        },
+        (ColumnData::DateTime2(Some(value)), SqlType::DateTime) => {
+            ColumnData::DateTime(Some(datetime(value)?))
+        }
+        (ColumnData::DateTime2(Some(value)), SqlType::SmallDateTime) => {
+            ColumnData::SmallDateTime(Some(small_datetime(value)?))
+        }
+        (ColumnData::Time(Some(time)), SqlType::Time(scale)) => {
+            ColumnData::Time(Some(round_time(time, *scale)))
+        }
+        (ColumnData::DateTime2(Some(value)), SqlType::DateTime2(scale)) => {
+            ColumnData::DateTime2(Some(DateTime2::new(
+                value.date(),
+                round_time(value.time(), *scale),
+            )))
+        }
+        (ColumnData::DateTimeOffset(Some(value)), SqlType::DateTimeOffset(scale)) => {
+            let local = value.datetime2();
+            let local = DateTime2::new(local.date(), round_time(local.time(), *scale));
+            ColumnData::DateTimeOffset(Some(DateTimeOffset::new(local, value.offset())))
+        }
+        (ColumnData::String(Some(text)), SqlType::Char(len)) => {
+            let mut text = text.into_owned();
+            while text.chars().count() < *len as usize {
+                text.push(' ');
+            }
+            ColumnData::String(Some(Cow::Owned(text)))
+        }
+        (ColumnData::String(Some(text)), SqlType::NChar(len)) => {
+            let mut text = text.into_owned();
+            while text.encode_utf16().count() < *len as usize {
+                text.push(' ');
+            }
+            ColumnData::String(Some(Cow::Owned(text)))
+        }
+        (ColumnData::Binary(Some(bytes)), SqlType::Binary(len)) => {
+            let mut bytes = bytes.into_owned();
+            if bytes.len() < *len as usize {
+                bytes.resize(*len as usize, 0);
+            }
+            ColumnData::Binary(Some(Cow::Owned(bytes)))
+        }
+        (value, _) => value,
+    };
+
+    Ok(stored)
+}
+
+/// The type that [`store`] converts values to before rounding them: that of
+/// `sql_type` with 100 ns times and double-precision floats.
+fn full_precision(sql_type: &SqlType) -> SqlType {
+    match sql_type {
+        SqlType::Real => SqlType::Float,
+        SqlType::DateTime | SqlType::SmallDateTime | SqlType::DateTime2(_) => SqlType::DateTime2(7),
+        SqlType::Time(_) => SqlType::Time(7),
+        SqlType::DateTimeOffset(_) => SqlType::DateTimeOffset(7),
+        other => other.clone(),
+    }
+}
+
+/// Days from 0001-01-01, where `date` counts from, to 1900-01-01, where
+/// `datetime` and `smalldatetime` count from.
+const DAYS_BEFORE_1900: i64 = 693_595;
+
+/// Time of a day in units of 1/300 of a second.
+const DATETIME_TICKS_PER_DAY: u64 = 300 * 86_400;
+
+/// `value` in whole days and 1/300 of a second since 1900-01-01, rounded to
+/// the nearest tick.
+fn datetime(value: DateTime2) -> Result<DateTime, MockError> {
+    let hundred_ns = round_time(value.time(), 7).increments();
+    let ticks = (hundred_ns * 300 + 5_000_000) / 10_000_000;
+    let days =
+        i64::from(value.date().days()) - DAYS_BEFORE_1900 + (ticks / DATETIME_TICKS_PER_DAY) as i64;
+
+    // From 1753-01-01 to 9999-12-31.
+    match i32::try_from(days) {
+        Ok(days) if (-53_690..=2_958_463).contains(&days) => {
+            Ok(DateTime::new(days, (ticks % DATETIME_TICKS_PER_DAY) as u32))
+        }
+        _ => Err(conversion_error("the value is out of range for datetime")),
+    }
+}
+
+/// `value` in whole days and minutes since 1900-01-01, rounded up from 30
+/// seconds; 29.997 seconds, the last `datetime` before, round down.
+fn small_datetime(value: DateTime2) -> Result<SmallDateTime, MockError> {
+    let seconds = round_time(value.time(), 7).increments() / 10_000_000;
+    let minutes = (seconds + 30) / 60;
+    let days = i64::from(value.date().days()) - DAYS_BEFORE_1900 + (minutes / 1440) as i64;
+
+    // From 1900-01-01 to 2079-06-06.
+    match u16::try_from(days) {
+        Ok(days) => Ok(SmallDateTime::new(days, (minutes % 1440) as u16)),
+        Err(_) => Err(conversion_error(
+            "the value is out of range for smalldatetime",
+        )),
+    }
+}
+
+/// `time` with `scale` digits of fractions of seconds, halves rounded up.
+fn round_time(time: Time, scale: u8) -> Time {
+    let from = 10u128.pow(time.scale().into());
+    let to = 10u128.pow(scale.into());
+    let increments = (u128::from(time.increments()) * to * 2 + from) / (from * 2);
+
+    Time::new(increments as u64, scale)
+}
+
+fn conversion_error(message: &str) -> MockError {
+    MockError::new(8114, format!("Error converting data type: {}", message))
+}
+
+/// Reads the rows of a bulk load into `target` and stores them, unless a
+/// row fails or an error was injected for the load.
+fn bulk_load(
+    message: &mut impl Read,
+    target: Option<BulkTarget>,
+    state: &Mutex<State>,
+) -> io::Result<Outcome> {
+    let Some(target) = target else {
+        io::copy(message, &mut io::sink())?;
+        return Ok(Outcome::Error(MockError::new(
+            4804,
+            "While reading current row from host, a premature end-of-message was encountered",
+        )));
+    };
+
+    if message.u8()? != COLMETADATA {
+        return Err(invalid_data("a bulk load without COLMETADATA"));
+    }
+    let count = message.u16()?;
+    let mut sent = Vec::with_capacity(count.into());
+    for _ in 0..count {
+        // User type and flags.
+        message.u32()?;
+        message.u16()?;
+        let ty = read_type_info(message)?;
+        if ty.is_text() {
+            for _ in 0..message.u8()? {
+                message.us_varchar()?;
+            }
+        }
+        let name = message.b_varchar()?;
+        let position = target
+            .columns
+            .iter()
+            .position(|column| column.name.eq_ignore_ascii_case(&name));
+        sent.push((ty, position));
+    }
+
+    let mut table = Table {
+        name: target.name.clone(),
+        columns: target.columns.clone(),
+        rows: Vec::new(),
+        count: 0,
+        next_identity: lock(state)
+            .tables
+            .get(&target.key)
+            .map_or(1, |table| table.next_identity),
+    };
+    let mut failed = None;
+    loop {
+        match message.u8()? {
+            ROW => {
+                let mut row = new_row(&mut table);
+                for (ty, position) in &sent {
+                    let value = read_value(message, ty)?;
+                    if let (Some(i), None) = (position, &failed) {
+                        match coerce(value, &table.columns[*i], &table.name) {
+                            Ok(value) => row[*i] = value,
+                            Err(error) => failed = Some(error),
+                        }
+                    }
+                }
+                if failed.is_none() {
+                    failed = check_nulls(&table, &row)
+                        .and_then(|_| put_row(&mut table.rows, &table.columns, &row))
+                        .err();
+                    table.count += 1;
+                }
+            }
+            DONE => {
+                message.exact(12)?;
+                break;
+            }
+            other => return Err(invalid_data(format!("unexpected token {:#04x}", other))),
+        }
+    }
+    io::copy(message, &mut io::sink())?;
+
+    let mut state = lock(state);
+    let injected = state
+        .failures
+        .iter()
+        .position(|failure| matches!(&failure.target, Target::BulkLoad(key) if *key == target.key));
+    if let Some(i) = injected {
+        return Ok(Outcome::Error(state.failures.remove(i).error));
+    }
+    if let Some(error) = failed {
+        return Ok(Outcome::Error(error));
+    }
+    let Some(stored) = state.tables.get_mut(&target.key) else {
+        return Ok(Outcome::Error(invalid_object(&target.name)));
+    };
+    stored.rows.extend_from_slice(&table.rows);
+    stored.count += table.count;
+    stored.next_identity = table.next_identity;
+
+    Ok(Outcome::Done(Some(table.count)))
+}
+
+/// Splits `text` at the commas outside parentheses and quotes.
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let (mut depth, mut quote, mut start) = (0, None, 0);
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                items.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&text[start..]);
+    items
+}
+
+/// The schema and the name of the table `s` starts with, in lower case,
+/// and the name as written.
+fn table_name(s: &str) -> Option<((String, String), String)> {
+    let (parts, rest) = object_name(s)?;
+    let written = s[..s.len() - rest.len()].to_owned();
+    let name = parts[parts.len() - 1].to_lowercase();
+    let schema = match parts.len() {
+        1 => "dbo".to_owned(),
+        len => parts[len - 2].to_lowercase(),
+    };
+    Some(((schema, name), written))
+}
+
+/// The schema and the name of `table`, in lower case.
+fn object_key(table: &str) -> Option<(String, String)> {
+    table_name(table.trim()).map(|(key, _)| key)
+}
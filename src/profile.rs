@@ -0,0 +1,315 @@
+use crate::{
+    browser::{DEFAULT_BROWSER_TIMEOUT, SQL_BROWSER_PORT},
+    connection::{ConnectionConfig, ServerAddress},
+    error::{BulkError, Result},
+    secret::SecretSource,
+    tls::TlsSettings,
+};
+use serde::Deserialize;
+use std::{collections::BTreeMap, env, fs, path::Path};
+use tiberius::{AuthMethod, Config};
+
+/// Configuration file read when `BULK_CONFIG` is not set.
+pub const DEFAULT_CONFIG_FILE: &str = "bulkload.toml";
+
+/// The only packet size Tiberius asks for during login.
+const TDS_PACKET_SIZE: u32 = 4096;
+
+/// Settings that are numbers or booleans, which may be given as a string
+/// made of a single variable. Other values, such as passwords, stay strings
+/// whatever they look like.
+const TYPED_KEYS: &[&str] = &["port", "packet_size", "trust_server_certificate"];
+
+/// Looks up the value of a variable referenced by a profile, like
+/// [`env_var`] does in the environment of the process.
+pub type Vars<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// The value of the environment variable `name`, if it is set and valid
+/// Unicode.
+pub fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+/// A configuration file with named connection profiles:
+///
+/// ```toml
+/// default = "dev"
+///
+/// [profiles.dev]
+/// host = "localhost"
+/// instance = "sql2022d"
+/// database = "DestinationDB"
+/// auth = { method = "sql_server", user = "developer", password = "${SQL_PASSWORD}" }
+/// tls = { encrypt = "optional", trust_server_certificate = true }
+/// ```
+///
+/// Every string may reference environment variables as `${NAME}` or
+/// `${NAME:-default}`; for the numeric and boolean settings (`port`,
+/// `packet_size` and `trust_server_certificate`), a string made of a single
+/// variable is read as a number or a boolean.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profiles {
+    /// Profile used when none is selected by name.
+    pub default: Option<String>,
+    /// Profiles are only interpolated and validated when they are selected,
+    /// so variables of other profiles do not need to be set.
+    #[serde(default)]
+    profiles: BTreeMap<String, toml::Value>,
+}
+
+/// Settings to connect to one server.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub host: String,
+    pub instance: Option<String>,
+    pub port: Option<u16>,
+    pub database: Option<String>,
+    #[serde(default)]
+    pub auth: Auth,
+    #[serde(default)]
+    pub tls: TlsSettings,
+    /// Size of the TDS packets. Tiberius only supports 4096 bytes.
+    pub packet_size: Option<u32>,
+    pub application_name: Option<String>,
+}
+
+/// How to log in to the server.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Auth {
+    /// SQL Server authentication with a login and a password.
+    SqlServer {
+        user: String,
+        password: SecretSource,
+    },
+    /// No credentials.
+    #[default]
+    None,
+}
+
+impl Profiles {
+    /// Reads a configuration file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Profiles> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| BulkError::Config(format!("cannot read {}: {}", path.display(), e)))?;
+
+        Profiles::parse(&text).map_err(|e| match e {
+            BulkError::Config(message) => {
+                BulkError::Config(format!("{}: {}", path.display(), message))
+            }
+            e => e,
+        })
+    }
+
+    /// Parses the text of a configuration file.
+    pub fn parse(text: &str) -> Result<Profiles> {
+        toml::from_str(text).map_err(|e| BulkError::Config(e.to_string()))
+    }
+
+    /// Names of the profiles in the file.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(|name| name.as_str())
+    }
+
+    /// The profile called `name`, or the default profile when `name` is
+    /// `None`, with its environment variables expanded.
+    pub fn get(&self, name: Option<&str>) -> Result<Profile> {
+        self.get_with(name, &env_var)
+    }
+
+    /// The profile called `name` like [`Profiles::get`], with the variables
+    /// looked up with `vars` instead of in the environment.
+    pub fn get_with(&self, name: Option<&str>, vars: Vars<'_>) -> Result<Profile> {
+        let name = name.or(self.default.as_deref()).ok_or_else(|| {
+            BulkError::Config("no profile selected and no default profile".to_owned())
+        })?;
+
+        let mut value = self
+            .profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| BulkError::Config(format!("unknown profile: {}", name)))?;
+        interpolate(name, &mut value, vars).map_err(|e| match e {
+            BulkError::Config(message) => {
+                BulkError::Config(format!("profile {}: {}", name, message))
+            }
+            e => e,
+        })?;
+
+        value
+            .try_into()
+            .map_err(|e: toml::de::Error| BulkError::Config(format!("profile {}: {}", name, e)))
+    }
+}
+
+impl Profile {
+    /// Builds the configuration of the connection factory.
+    pub fn connection_config(&self) -> Result<ConnectionConfig> {
+        self.connection_config_with(&env_var)
+    }
+
+    /// Builds the configuration of the connection factory like
+    /// [`Profile::connection_config`], with the password variables looked up
+    /// with `vars` instead of in the environment.
+    pub fn connection_config_with(&self, vars: Vars<'_>) -> Result<ConnectionConfig> {
+        if let Some(size) = self.packet_size {
+            if size != TDS_PACKET_SIZE {
+                return Err(BulkError::Config(format!(
+                    "packet_size {} is not supported, Tiberius always uses {} bytes",
+                    size, TDS_PACKET_SIZE
+                )));
+            }
+        }
+
+        let mut config = Config::new();
+        config.host(&self.host);
+        if let Some(port) = self.port {
+            config.port(port);
+        }
+        if let Some(instance) = &self.instance {
+            config.instance_name(instance);
+        }
+        if let Some(database) = &self.database {
+            config.database(database);
+        }
+        if let Some(name) = &self.application_name {
+            config.application_name(name);
+        }
+
+        config.authentication(match &self.auth {
+            Auth::SqlServer { user, password } => {
+                AuthMethod::sql_server(user, password.resolve_with(vars)?.expose())
+            }
+            Auth::None => AuthMethod::None,
+        });
+
+        self.tls.apply(&mut config)?;
+
+        Ok(ConnectionConfig {
+            config,
+            server: ServerAddress {
+                host: self.host.clone(),
+                instance: self.instance.clone(),
+                port: self.port,
+            },
+            browser_port: SQL_BROWSER_PORT,
+            browser_timeout: DEFAULT_BROWSER_TIMEOUT,
+            tls: self.tls.clone(),
+        })
+    }
+}
+
+/// The connection selected through the environment: the profile named by
+/// `BULK_PROFILE`, or the default profile, of the file named by `BULK_CONFIG`
+/// (`bulkload.toml` by default). Returns `None` when no profile is selected
+/// and there is no configuration file.
+pub fn connection_config_from_env() -> Result<Option<ConnectionConfig>> {
+    let path = env::var("BULK_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_FILE.to_owned());
+    let name = env::var("BULK_PROFILE").ok();
+
+    if name.is_none() && !Path::new(&path).exists() {
+        return Ok(None);
+    }
+
+    let profiles = Profiles::load(&path)?;
+    let profile = profiles.get(name.as_deref())?;
+
+    profile.connection_config().map(Some)
+}
+
+/// Expands the variables of the strings of `value`, the value of `key`.
+fn interpolate(key: &str, value: &mut toml::Value, vars: Vars<'_>) -> Result<()> {
+    match value {
+        toml::Value::String(s) => {
+            let expanded = expand_vars(s, vars)?;
+            *value = match TYPED_KEYS.contains(&key) {
+                true => typed_value(s, expanded),
+                false => toml::Value::String(expanded),
+            };
+        }
+        toml::Value::Array(values) => {
+            for value in values {
+                interpolate(key, value, vars)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                interpolate(key, value, vars)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// A string made of a single variable becomes a number or a boolean when
+/// its value looks like one, so that `port = "${PORT}"` works. Only values
+/// of `TYPED_KEYS` are read this way.
+fn typed_value(original: &str, expanded: String) -> toml::Value {
+    let single_variable = original.starts_with("${")
+        && original.ends_with('}')
+        && original.matches("${").count() == 1;
+
+    if single_variable {
+        if let Ok(number) = expanded.parse::<i64>() {
+            return toml::Value::Integer(number);
+        }
+        if let Ok(boolean) = expanded.parse::<bool>() {
+            return toml::Value::Boolean(boolean);
+        }
+    }
+
+    toml::Value::String(expanded)
+}
+
+/// Replaces `${NAME}` and `${NAME:-default}` with the value of the
+/// environment variable `NAME`. `$$` stands for a literal `$`.
+pub fn expand_env(text: &str) -> Result<String> {
+    expand_vars(text, &env_var)
+}
+
+/// Replaces variables like [`expand_env`], looking them up with `vars`.
+pub fn expand_vars(text: &str, vars: Vars<'_>) -> Result<String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("$$") {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| BulkError::Config(format!("unterminated variable in `{}`", text)))?;
+            let (name, default) = match after[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&after[..end], None),
+            };
+
+            match (vars(name), default) {
+                (Some(value), _) => expanded.push_str(&value),
+                (None, Some(default)) => expanded.push_str(default),
+                (None, None) => {
+                    return Err(BulkError::Config(format!(
+                        "environment variable {} is not set",
+                        name
+                    )))
+                }
+            }
+            rest = &after[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
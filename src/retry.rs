@@ -0,0 +1,94 @@
+use crate::{
+    error::{BulkError, Result},
+    runtime,
+    secret::redact,
+};
+use std::{future::Future, time::Duration};
+
+/// How often and how long to wait before an operation that failed with a
+/// transient error is tried again.
+///
+/// The wait grows exponentially from `initial_backoff` up to `max_backoff`,
+/// and a random part of up to `jitter` (a fraction of the wait) is added so
+/// that clients failing at the same time do not retry at the same time.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    pub jitter: f64,
+    /// Decides whether an error is worth another attempt.
+    pub is_retryable: fn(&BulkError) -> bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.2,
+            is_retryable: BulkError::is_retryable,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that gives up after the first failure.
+    pub fn never() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// True, if the attempt number `attempt` (starting at 1) failed with an
+    /// error that should be retried.
+    pub fn should_retry(&self, attempt: u32, error: &BulkError) -> bool {
+        attempt < self.max_attempts && (self.is_retryable)(error)
+    }
+
+    /// The time to wait after the attempt number `attempt` (starting at 1)
+    /// failed, without jitter.
+    pub fn base_backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(32) as i32;
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+
+        Duration::from_secs_f64(backoff.min(self.max_backoff.as_secs_f64()))
+    }
+
+    /// The time to wait after the attempt number `attempt` (starting at 1)
+    /// failed, with jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let base = self.base_backoff(attempt);
+        base + base.mul_f64(self.jitter.max(0.0) * fastrand::f64())
+    }
+
+    /// Runs `operation` until it succeeds, fails with an error that cannot
+    /// be retried or runs out of attempts. The operation receives the number
+    /// of the attempt, starting at 1.
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation(attempt).await {
+                Err(e) if self.should_retry(attempt, &e) => {
+                    println!(
+                        "Attempt {} failed, retrying: {}",
+                        attempt,
+                        redact(&e.to_string())
+                    );
+                    runtime::sleep(self.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
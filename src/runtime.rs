@@ -0,0 +1,134 @@
+// The async runtime the crate runs on, chosen with the `rt-async-std`
+// (default) or `rt-tokio` feature. When both are enabled, Tokio is used.
+// Tiberius works with the `futures` I/O traits, so Tokio sockets are wrapped
+// with the `tokio-util` compatibility layer.
+
+use std::{future::Future, io, time::Duration};
+
+#[cfg(not(any(feature = "rt-async-std", feature = "rt-tokio")))]
+compile_error!("enable the `rt-async-std` or the `rt-tokio` feature");
+
+#[cfg(all(feature = "rt-async-std", not(feature = "rt-tokio")))]
+mod imp {
+    use super::*;
+
+    pub use async_std::net::{TcpStream, UdpSocket};
+    pub use async_std::test;
+
+    pub async fn connect_tcp(addr: &str) -> io::Result<TcpStream> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(stream)
+    }
+
+    pub async fn sleep(duration: Duration) {
+        async_std::task::sleep(duration).await
+    }
+
+    pub async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+        async_std::future::timeout(duration, future).await.ok()
+    }
+
+    pub fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        async_std::task::spawn(future);
+    }
+
+    /// async-std runs its own executor, so blocking on it needs no state.
+    pub struct Runtime;
+
+    impl Runtime {
+        pub fn new() -> io::Result<Runtime> {
+            Ok(Runtime)
+        }
+
+        pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+            async_std::task::block_on(future)
+        }
+    }
+}
+
+#[cfg(feature = "rt-tokio")]
+mod imp {
+    use super::*;
+    use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+    pub use tokio::net::UdpSocket;
+    pub use tokio::test;
+
+    /// A Tokio TCP stream implementing the `futures` I/O traits.
+    pub type TcpStream = Compat<tokio::net::TcpStream>;
+
+    pub async fn connect_tcp(addr: &str) -> io::Result<TcpStream> {
+        let stream = tokio::net::TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(stream.compat())
+    }
+
+    pub async fn sleep(duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+
+    pub async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+        tokio::time::timeout(duration, future).await.ok()
+    }
+
+    pub fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(future);
+    }
+
+    pub struct Runtime(tokio::runtime::Runtime);
+
+    impl Runtime {
+        pub fn new() -> io::Result<Runtime> {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map(Runtime)
+        }
+
+        pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+            self.0.block_on(future)
+        }
+    }
+}
+
+/// Runs an async test on the runtime.
+pub use imp::test;
+/// Runs futures to completion on the calling thread, for the blocking API.
+/// It must not be used from async code.
+pub use imp::Runtime;
+/// A TCP stream of the runtime, usable with Tiberius.
+pub use imp::TcpStream;
+/// A UDP socket of the runtime.
+pub use imp::UdpSocket;
+
+/// Connects to `addr` with Nagle's algorithm disabled, as the TDS packets
+/// are written whole.
+pub async fn connect_tcp(addr: &str) -> io::Result<TcpStream> {
+    imp::connect_tcp(addr).await
+}
+
+/// Waits for `duration` without blocking the runtime.
+pub async fn sleep(duration: Duration) {
+    imp::sleep(duration).await
+}
+
+/// The output of `future`, or `None` if it did not complete within
+/// `duration`.
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    imp::timeout(duration, future).await
+}
+
+/// Runs `future` in the background.
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    imp::spawn(future)
+}
@@ -0,0 +1,436 @@
+use crate::{
+    connection::SqlClient,
+    error::{BulkError, Result, ResultExt},
+};
+use std::{borrow::Cow, fmt};
+use tiberius::{xml::XmlData, ColumnData};
+
+/// The type of a column as declared in SQL Server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SqlType {
+    Bit,
+    TinyInt,
+    SmallInt,
+    Int,
+    BigInt,
+    Real,
+    Float,
+    /// `decimal` and `numeric`.
+    Decimal {
+        precision: u8,
+        scale: u8,
+    },
+    Money,
+    SmallMoney,
+    /// Length in characters.
+    Char(u16),
+    /// Length in characters, `None` for `max`.
+    VarChar(Option<u16>),
+    NChar(u16),
+    NVarChar(Option<u16>),
+    Text,
+    NText,
+    /// Length in bytes.
+    Binary(u16),
+    /// Length in bytes, `None` for `max`.
+    VarBinary(Option<u16>),
+    Image,
+    UniqueIdentifier,
+    Date,
+    /// Fractional second precision.
+    Time(u8),
+    DateTime,
+    SmallDateTime,
+    DateTime2(u8),
+    DateTimeOffset(u8),
+    Xml,
+    /// `rowversion` (`timestamp`), set by the server.
+    RowVersion,
+    /// Types the crate does not handle, by name.
+    Other(String),
+}
+
+impl SqlType {
+    /// The type of a column of `sys.columns`, from the name of its system
+    /// type, its length in bytes (-1 for `max`), its precision and its scale.
+    pub fn from_sys_columns(name: &str, max_length: i16, precision: u8, scale: u8) -> SqlType {
+        let bytes = u16::try_from(max_length).ok();
+        let chars = bytes.map(|len| len / 2);
+
+        match name.to_lowercase().as_str() {
+            "bit" => SqlType::Bit,
+            "tinyint" => SqlType::TinyInt,
+            "smallint" => SqlType::SmallInt,
+            "int" => SqlType::Int,
+            "bigint" => SqlType::BigInt,
+            "real" => SqlType::Real,
+            "float" => SqlType::Float,
+            "decimal" | "numeric" => SqlType::Decimal { precision, scale },
+            "money" => SqlType::Money,
+            "smallmoney" => SqlType::SmallMoney,
+            "char" => SqlType::Char(bytes.unwrap_or_default()),
+            "varchar" => SqlType::VarChar(bytes),
+            "nchar" => SqlType::NChar(chars.unwrap_or_default()),
+            "nvarchar" | "sysname" => SqlType::NVarChar(chars),
+            "text" => SqlType::Text,
+            "ntext" => SqlType::NText,
+            "binary" => SqlType::Binary(bytes.unwrap_or_default()),
+            "varbinary" => SqlType::VarBinary(bytes),
+            "image" => SqlType::Image,
+            "uniqueidentifier" => SqlType::UniqueIdentifier,
+            "date" => SqlType::Date,
+            "time" => SqlType::Time(scale),
+            "datetime" => SqlType::DateTime,
+            "smalldatetime" => SqlType::SmallDateTime,
+            "datetime2" => SqlType::DateTime2(scale),
+            "datetimeoffset" => SqlType::DateTimeOffset(scale),
+            "xml" => SqlType::Xml,
+            "timestamp" | "rowversion" => SqlType::RowVersion,
+            other => SqlType::Other(other.to_owned()),
+        }
+    }
+
+    /// True, if Tiberius can send values of this type in a bulk load.
+    /// `money`, `text`, `ntext` and `image` columns have to be filled with
+    /// `INSERT` statements instead.
+    pub fn is_bulk_loadable(&self) -> bool {
+        !matches!(
+            self,
+            SqlType::Money
+                | SqlType::SmallMoney
+                | SqlType::Text
+                | SqlType::NText
+                | SqlType::Image
+                | SqlType::RowVersion
+                | SqlType::Other(_)
+        )
+    }
+
+    /// A NULL of the variant a bulk load expects for this type.
+    pub fn null(&self) -> ColumnData<'static> {
+        match self {
+            SqlType::Bit => ColumnData::Bit(None),
+            SqlType::TinyInt => ColumnData::U8(None),
+            SqlType::SmallInt => ColumnData::I16(None),
+            SqlType::Int => ColumnData::I32(None),
+            SqlType::BigInt => ColumnData::I64(None),
+            SqlType::Real => ColumnData::F32(None),
+            SqlType::Float | SqlType::Money | SqlType::SmallMoney => ColumnData::F64(None),
+            SqlType::Decimal { .. } => ColumnData::Numeric(None),
+            SqlType::Char(_)
+            | SqlType::VarChar(_)
+            | SqlType::NChar(_)
+            | SqlType::NVarChar(_)
+            | SqlType::Text
+            | SqlType::NText
+            | SqlType::Other(_) => ColumnData::String(None),
+            SqlType::Binary(_) | SqlType::VarBinary(_) | SqlType::Image | SqlType::RowVersion => {
+                ColumnData::Binary(None)
+            }
+            SqlType::UniqueIdentifier => ColumnData::Guid(None),
+            SqlType::Date => ColumnData::Date(None),
+            SqlType::Time(_) => ColumnData::Time(None),
+            SqlType::DateTime => ColumnData::DateTime(None),
+            SqlType::SmallDateTime => ColumnData::SmallDateTime(None),
+            SqlType::DateTime2(_) => ColumnData::DateTime2(None),
+            SqlType::DateTimeOffset(_) => ColumnData::DateTimeOffset(None),
+            SqlType::Xml => ColumnData::Xml(None::<Cow<'static, XmlData>>),
+        }
+    }
+}
+
+impl fmt::Display for SqlType {
+    /// Writes the type as in a `CREATE TABLE` statement.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let length = |len: &Option<u16>| match len {
+            Some(len) => len.to_string(),
+            None => "max".to_owned(),
+        };
+
+        match self {
+            SqlType::Bit => write!(f, "bit"),
+            SqlType::TinyInt => write!(f, "tinyint"),
+            SqlType::SmallInt => write!(f, "smallint"),
+            SqlType::Int => write!(f, "int"),
+            SqlType::BigInt => write!(f, "bigint"),
+            SqlType::Real => write!(f, "real"),
+            SqlType::Float => write!(f, "float"),
+            SqlType::Decimal { precision, scale } => write!(f, "decimal({},{})", precision, scale),
+            SqlType::Money => write!(f, "money"),
+            SqlType::SmallMoney => write!(f, "smallmoney"),
+            SqlType::Char(len) => write!(f, "char({})", len),
+            SqlType::VarChar(len) => write!(f, "varchar({})", length(len)),
+            SqlType::NChar(len) => write!(f, "nchar({})", len),
+            SqlType::NVarChar(len) => write!(f, "nvarchar({})", length(len)),
+            SqlType::Text => write!(f, "text"),
+            SqlType::NText => write!(f, "ntext"),
+            SqlType::Binary(len) => write!(f, "binary({})", len),
+            SqlType::VarBinary(len) => write!(f, "varbinary({})", length(len)),
+            SqlType::Image => write!(f, "image"),
+            SqlType::UniqueIdentifier => write!(f, "uniqueidentifier"),
+            SqlType::Date => write!(f, "date"),
+            SqlType::Time(scale) => write!(f, "time({})", scale),
+            SqlType::DateTime => write!(f, "datetime"),
+            SqlType::SmallDateTime => write!(f, "smalldatetime"),
+            SqlType::DateTime2(scale) => write!(f, "datetime2({})", scale),
+            SqlType::DateTimeOffset(scale) => write!(f, "datetimeoffset({})", scale),
+            SqlType::Xml => write!(f, "xml"),
+            SqlType::RowVersion => write!(f, "rowversion"),
+            SqlType::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// A column of a table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    pub sql_type: SqlType,
+    pub nullable: bool,
+    pub identity: bool,
+    pub computed: bool,
+}
+
+impl Column {
+    /// True, if the column takes part in a bulk load. Like `INSERT BULK`,
+    /// Tiberius leaves out identity, computed and `rowversion` columns.
+    pub fn is_insertable(&self) -> bool {
+        !self.identity && !self.computed && self.sql_type != SqlType::RowVersion
+    }
+}
+
+/// The columns of a table, in the order of their declaration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableSchema {
+    pub table: String,
+    pub columns: Vec<Column>,
+}
+
+impl TableSchema {
+    /// Reads the columns of `table` from `sys.columns`.
+    pub async fn load(client: &mut SqlClient, table: &str) -> Result<TableSchema> {
+        let rows = client
+            .query(
+                "SELECT c.name, TYPE_NAME(c.system_type_id), c.max_length, c.precision, c.scale, \
+                 c.is_nullable, c.is_identity, c.is_computed \
+                 FROM sys.columns c WHERE c.object_id = OBJECT_ID(@P1) ORDER BY c.column_id",
+                &[&table],
+            )
+            .await
+            .in_table(table)?
+            .into_first_result()
+            .await
+            .in_table(table)?;
+
+        let columns = rows
+            .iter()
+            .map(|row| {
+                let name: &str = row.try_get(0)?.unwrap_or_default();
+                let type_name: &str = row.try_get(1)?.unwrap_or_default();
+                let max_length: i16 = row.try_get(2)?.unwrap_or_default();
+                let precision: u8 = row.try_get(3)?.unwrap_or_default();
+                let scale: u8 = row.try_get(4)?.unwrap_or_default();
+
+                Ok(Column {
+                    name: name.to_owned(),
+                    sql_type: SqlType::from_sys_columns(type_name, max_length, precision, scale),
+                    nullable: row.try_get(5)?.unwrap_or(true),
+                    identity: row.try_get(6)?.unwrap_or(false),
+                    computed: row.try_get(7)?.unwrap_or(false),
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .in_table(table)?;
+
+        if columns.is_empty() {
+            return Err(BulkError::Config(format!("table {} does not exist", table)));
+        }
+
+        Ok(TableSchema {
+            table: table.to_owned(),
+            columns,
+        })
+    }
+
+    /// The columns of the first result set of `query`, as SQL Server
+    /// describes them without running the query. The schema is named after
+    /// the query; columns without a name are named after their position, as
+    /// in `column3`.
+    pub async fn describe(client: &mut SqlClient, query: &str) -> Result<TableSchema> {
+        let rows = client
+            .query(
+                "SELECT name, TYPE_NAME(system_type_id), max_length, precision, scale, \
+                 is_nullable, is_identity_column, is_computed_column, column_ordinal, error_message \
+                 FROM sys.dm_exec_describe_first_result_set(@P1, NULL, 0) ORDER BY column_ordinal",
+                &[&query],
+            )
+            .await?
+            .into_first_result()
+            .await?;
+
+        let columns = rows
+            .iter()
+            .map(|row| {
+                if let Some(message) = row.try_get::<&str, _>(9)? {
+                    return Err(BulkError::Config(format!(
+                        "the query cannot be described: {}",
+                        message
+                    )));
+                }
+                let ordinal: i32 = row.try_get(8)?.unwrap_or_default();
+                let type_name: &str = row.try_get(1)?.unwrap_or_default();
+                let max_length: i16 = row.try_get(2)?.unwrap_or_default();
+                let precision: u8 = row.try_get(3)?.unwrap_or_default();
+                let scale: u8 = row.try_get(4)?.unwrap_or_default();
+
+                Ok(Column {
+                    name: row
+                        .try_get::<&str, _>(0)?
+                        .map_or_else(|| format!("column{}", ordinal), str::to_owned),
+                    sql_type: SqlType::from_sys_columns(type_name, max_length, precision, scale),
+                    nullable: row.try_get(5)?.unwrap_or(true),
+                    identity: row.try_get(6)?.unwrap_or(false),
+                    computed: row.try_get(7)?.unwrap_or(false),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if columns.is_empty() {
+            return Err(BulkError::Config(format!(
+                "the query returns no rows: {}",
+                query
+            )));
+        }
+
+        Ok(TableSchema {
+            table: query.to_owned(),
+            columns,
+        })
+    }
+
+    /// The `CREATE TABLE` statement of the table, with the columns in their
+    /// order, types and nullability. The name of the table may be qualified
+    /// with a schema; every part of it is delimited.
+    pub fn create_table_sql(&self) -> Result<String> {
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| {
+                format!(
+                    "    {} {}{}{}",
+                    quote_name(&column.name),
+                    column.sql_type,
+                    if column.identity { " IDENTITY" } else { "" },
+                    if column.nullable {
+                        " NULL"
+                    } else {
+                        " NOT NULL"
+                    }
+                )
+            })
+            .collect();
+
+        Ok(format!(
+            "CREATE TABLE {} (\n{}\n)",
+            quote_object_name(&self.table)?,
+            columns.join(",\n")
+        ))
+    }
+
+    /// The columns a bulk load sends values for.
+    pub fn insertable_columns(&self) -> impl Iterator<Item = &Column> {
+        self.columns.iter().filter(|column| column.is_insertable())
+    }
+
+    /// Fails with the first insertable column whose type cannot be bulk
+    /// loaded.
+    pub fn check_bulk_loadable(&self) -> Result<()> {
+        match self
+            .insertable_columns()
+            .find(|column| !column.sql_type.is_bulk_loadable())
+        {
+            Some(column) => Err(BulkError::conversion(format!(
+                "columns of type {} cannot be bulk loaded",
+                column.sql_type
+            ))
+            .with_table(&self.table)
+            .with_column(&column.name)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `name` as a delimited identifier, as in `[order details]`.
+pub fn quote_name(name: &str) -> String {
+    format!("[{}]", name.replace(']', "]]"))
+}
+
+/// `name`, a table or another object named with up to four parts, with
+/// every part delimited, as in `[dbo].[order details]`. Parts may be given
+/// delimited or not.
+pub fn quote_object_name(name: &str) -> Result<String> {
+    let invalid = || BulkError::Config(format!("invalid object name: {}", name));
+    let (parts, rest) = object_name(name.trim()).ok_or_else(invalid)?;
+    if !rest.trim().is_empty() || parts.len() > 4 {
+        return Err(invalid());
+    }
+
+    let parts: Vec<String> = parts.iter().map(|part| quote_name(part)).collect();
+    Ok(parts.join("."))
+}
+
+/// The identifier `s` starts with, without brackets or quotes, and the
+/// rest of `s`.
+pub(crate) fn identifier(s: &str) -> Option<(String, &str)> {
+    let end = match s.chars().next()? {
+        '[' => delimited_end(s, ']')?,
+        '"' => delimited_end(s, '"')?,
+        _ => s
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '@' | '#' | '$')))
+            .unwrap_or(s.len()),
+    };
+    if end == 0 {
+        return None;
+    }
+    Some((unquote(&s[..end]), &s[end..]))
+}
+
+/// The length of the delimited identifier `s` starts with, up to the first
+/// `close` that is not doubled.
+fn delimited_end(s: &str, close: char) -> Option<usize> {
+    let mut end = 1;
+    loop {
+        end += s[end..].find(close)? + 1;
+        if !s[end..].starts_with(close) {
+            return Some(end);
+        }
+        end += 1;
+    }
+}
+
+/// The parts of the object name `s` starts with, as in `[bulk_test].[t]`,
+/// and the rest of `s`.
+pub(crate) fn object_name(s: &str) -> Option<(Vec<String>, &str)> {
+    let mut parts = Vec::new();
+    let mut rest = s;
+    loop {
+        let (part, after) = identifier(rest)?;
+        parts.push(part);
+        match after.strip_prefix('.') {
+            Some(after) => rest = after,
+            None => return Some((parts, after)),
+        }
+    }
+}
+
+/// `name` without the brackets or quotes around it.
+pub(crate) fn unquote(name: &str) -> String {
+    let name = name.trim();
+    let delimited = |open: char, close: char| {
+        let inner = name.strip_prefix(open)?.strip_suffix(close)?;
+        Some(inner.replace(&format!("{}{}", close, close), &close.to_string()))
+    };
+    delimited('[', ']')
+        .or_else(|| delimited('"', '"'))
+        .unwrap_or_else(|| name.to_owned())
+}
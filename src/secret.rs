@@ -0,0 +1,238 @@
+use crate::{
+    error::{BulkError, Result},
+    profile::{env_var, Vars},
+};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::{env, fmt, fs, path::PathBuf, process::Command, sync::RwLock};
+
+/// What secrets are replaced with in logs, errors and reports.
+pub const REDACTED: &str = "***";
+
+/// Values of every secret created so far, scrubbed by [`redact`].
+static KNOWN_SECRETS: Lazy<RwLock<Vec<String>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Keys of connection strings whose values are secret.
+const SECRET_KEYS: &[&str] = &["password", "pwd"];
+
+/// Secrets shorter than this many characters are only scrubbed from the
+/// values of connection string keys: scrubbing every occurrence of two or
+/// three characters would garble unrelated text.
+pub const MIN_REDACTED_LEN: usize = 4;
+
+/// A password or another credential. It is shown as `***` in debug output
+/// and its value is scrubbed by [`redact`] from every text the crate prints
+/// or returns, if it has at least [`MIN_REDACTED_LEN`] characters.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Secret {
+        let value = value.into();
+
+        if value.chars().count() >= MIN_REDACTED_LEN {
+            let mut secrets = KNOWN_SECRETS.write().unwrap_or_else(|e| e.into_inner());
+            if !secrets.contains(&value) {
+                secrets.push(value.clone());
+                // Longer secrets first, in case one contains another.
+                secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+            }
+        }
+
+        Secret(value)
+    }
+
+    /// The value of the secret, to hand it over to the driver.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret::new(value)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Where to read a secret from. In a configuration file:
+///
+/// ```toml
+/// password = "literal value"
+/// password = { env = "SQL_PASSWORD" }
+/// password = { file = "/run/secrets/sql_password" }
+/// password = { command = "pass show sql/dev" }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum SecretSource {
+    Value(Secret),
+    Env { env: String },
+    File { file: PathBuf },
+    Command { command: String },
+}
+
+impl SecretSource {
+    /// Reads the secret. Trailing line breaks of files and command output
+    /// are removed.
+    pub fn resolve(&self) -> Result<Secret> {
+        self.resolve_with(&env_var)
+    }
+
+    /// Reads the secret like [`SecretSource::resolve`], looking variables up
+    /// with `vars` instead of in the environment.
+    pub fn resolve_with(&self, vars: Vars<'_>) -> Result<Secret> {
+        match self {
+            SecretSource::Value(secret) => Ok(secret.clone()),
+            SecretSource::Env { env } => vars(env).map(Secret::new).ok_or_else(|| {
+                BulkError::Config(format!("environment variable {} is not set", env))
+            }),
+            SecretSource::File { file } => fs::read_to_string(file)
+                .map(|value| Secret::new(trim_line_break(value)))
+                .map_err(|e| {
+                    BulkError::Config(format!("cannot read secret file {}: {}", file.display(), e))
+                }),
+            SecretSource::Command { command } => {
+                let output = shell(command)
+                    .output()
+                    .map_err(|e| BulkError::Config(format!("cannot run secret command: {}", e)))?;
+
+                if !output.status.success() {
+                    return Err(BulkError::Config(format!(
+                        "secret command failed with {}",
+                        output.status
+                    )));
+                }
+
+                let value = String::from_utf8(output.stdout).map_err(|_| {
+                    BulkError::Config("secret command printed invalid UTF-8".to_owned())
+                })?;
+                Ok(Secret::new(trim_line_break(value)))
+            }
+        }
+    }
+}
+
+/// The password of the connection string, taken from the first of these
+/// environment variables that is set: `SQL_AUTH_PASSWORD` (the password),
+/// `SQL_AUTH_PASSWORD_FILE` (a file holding it) or `SQL_AUTH_PASSWORD_COMMAND`
+/// (a command printing it).
+pub fn password_from_env() -> Result<Option<Secret>> {
+    let source = if env::var_os("SQL_AUTH_PASSWORD").is_some() {
+        SecretSource::Env {
+            env: "SQL_AUTH_PASSWORD".to_owned(),
+        }
+    } else if let Ok(file) = env::var("SQL_AUTH_PASSWORD_FILE") {
+        SecretSource::File { file: file.into() }
+    } else if let Ok(command) = env::var("SQL_AUTH_PASSWORD_COMMAND") {
+        SecretSource::Command { command }
+    } else {
+        return Ok(None);
+    };
+
+    source.resolve().map(Some)
+}
+
+/// Replaces the values of known secrets of at least [`MIN_REDACTED_LEN`]
+/// characters, and of `password=` and `pwd=` keys of connection strings,
+/// with `***`.
+pub fn redact(text: &str) -> String {
+    let mut redacted = text.to_owned();
+
+    for secret in KNOWN_SECRETS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+    {
+        if redacted.contains(secret.as_str()) {
+            redacted = redacted.replace(secret.as_str(), REDACTED);
+        }
+    }
+
+    redact_connection_string(&redacted)
+}
+
+fn redact_connection_string(text: &str) -> String {
+    let lowercase = text.to_ascii_lowercase();
+    let mut redacted = String::with_capacity(text.len());
+    let mut position = 0;
+
+    while position < text.len() {
+        let found = SECRET_KEYS
+            .iter()
+            .filter_map(|key| {
+                lowercase[position..]
+                    .find(&format!("{}=", key))
+                    .map(|start| (position + start, key.len() + 1))
+            })
+            .filter(|(start, _)| is_key_start(&lowercase, *start))
+            .min();
+
+        let Some((start, key_len)) = found else {
+            break;
+        };
+
+        let value_start = start + key_len;
+        let value_end = value_end(text, value_start);
+        redacted.push_str(&text[position..value_start]);
+        if value_end > value_start {
+            redacted.push_str(REDACTED);
+        }
+        position = value_end;
+    }
+
+    redacted.push_str(&text[position..]);
+    redacted
+}
+
+/// A key starts the text or follows a separator, so `mypassword=` is not
+/// mistaken for `password=`.
+fn is_key_start(text: &str, start: usize) -> bool {
+    text[..start]
+        .chars()
+        .next_back()
+        .is_none_or(|c| c == ';' || c.is_whitespace())
+}
+
+/// Values end at the next `;`, or at the closing quote of quoted values.
+fn value_end(text: &str, start: usize) -> usize {
+    let rest = &text[start..];
+
+    match rest.chars().next() {
+        Some(quote @ ('"' | '\'')) => rest[1..]
+            .find(quote)
+            .map_or(text.len(), |end| start + end + 2),
+        _ => rest.find(';').map_or(text.len(), |end| start + end),
+    }
+}
+
+fn trim_line_break(mut value: String) -> String {
+    while value.ends_with('\n') || value.ends_with('\r') {
+        value.pop();
+    }
+    value
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
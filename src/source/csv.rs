@@ -0,0 +1,128 @@
+use super::Source;
+use crate::{
+    compression::{self, InputFile},
+    convert::Value,
+    error::{BulkError, Result},
+};
+use csv::{ReaderBuilder, StringRecord, Trim};
+use std::{io::Read, path::Path};
+
+/// Options of a [`CsvSource`].
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    pub quote: u8,
+    /// Escapes quotes in quoted fields; `None` when quotes are doubled.
+    pub escape: Option<u8>,
+    /// The first record names the fields. Without a header the fields are
+    /// matched with the columns of the table by position.
+    pub has_header: bool,
+    /// Fields read as NULL.
+    pub null_markers: Vec<String>,
+    /// Remove the spaces around fields and field names.
+    pub trim: bool,
+}
+
+impl Default for CsvOptions {
+    /// Comma separated fields with a header, where empty fields are NULL.
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            has_header: true,
+            null_markers: vec![String::new()],
+            trim: false,
+        }
+    }
+}
+
+/// Reads the records of a CSV file as text.
+pub struct CsvSource<R> {
+    reader: csv::Reader<R>,
+    fields: Option<Vec<String>>,
+    null_markers: Vec<String>,
+    record: StringRecord,
+}
+
+impl CsvSource<InputFile> {
+    /// Reads a CSV file, decompressing it if it is compressed.
+    pub fn from_path(path: impl AsRef<Path>, options: &CsvOptions) -> Result<CsvSource<InputFile>> {
+        CsvSource::from_reader(compression::open(path)?, options)
+    }
+}
+
+impl<R: Read> CsvSource<R> {
+    /// Reads CSV from `reader`, starting with the header if there is one.
+    pub fn from_reader(reader: R, options: &CsvOptions) -> Result<CsvSource<R>> {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(options.delimiter)
+            .quote(options.quote)
+            .escape(options.escape)
+            .double_quote(options.escape.is_none())
+            .has_headers(options.has_header)
+            .trim(if options.trim { Trim::All } else { Trim::None })
+            .from_reader(reader);
+
+        let fields = match options.has_header {
+            true => Some(
+                reader
+                    .headers()
+                    .map_err(csv_error)?
+                    .iter()
+                    .map(str::to_owned)
+                    .collect(),
+            ),
+            false => None,
+        };
+
+        Ok(CsvSource {
+            reader,
+            fields,
+            null_markers: options.null_markers.clone(),
+            record: StringRecord::new(),
+        })
+    }
+}
+
+impl<R: Read> Source for CsvSource<R> {
+    fn fields(&self) -> Option<&[String]> {
+        self.fields.as_deref()
+    }
+
+    fn next_record(&mut self) -> Result<Option<Vec<Value>>> {
+        if !self
+            .reader
+            .read_record(&mut self.record)
+            .map_err(csv_error)?
+        {
+            return Ok(None);
+        }
+
+        let values = self
+            .record
+            .iter()
+            .map(
+                |field| match self.null_markers.iter().any(|null| null == field) {
+                    true => Value::Null,
+                    false => Value::Text(field.to_owned()),
+                },
+            )
+            .collect();
+
+        Ok(Some(values))
+    }
+}
+
+/// Read errors fail the load; malformed records are conversion errors, so
+/// that they can be rejected.
+fn csv_error(error: csv::Error) -> BulkError {
+    if !error.is_io_error() {
+        return BulkError::conversion(error.to_string());
+    }
+
+    match error.into_kind() {
+        csv::ErrorKind::Io(e) => e.into(),
+        kind => BulkError::conversion(format!("{:?}", kind)),
+    }
+}
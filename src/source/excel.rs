@@ -0,0 +1,274 @@
+use super::Source;
+use crate::{
+    compression,
+    convert::Value,
+    error::{BulkError, Result},
+};
+use calamine::{CellErrorType, Data, ExcelDateTime, Range, Reader, Sheets};
+use chrono::NaiveTime;
+use std::{
+    io::{self, Cursor, Read, Seek},
+    path::Path,
+    str::FromStr,
+};
+
+/// A rectangle of cells, as in `B2:F100`, or every used cell from a first
+/// one, as in `B2`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellRange {
+    /// The first cell, as zero-based `(row, column)`.
+    pub start: (u32, u32),
+    /// The last cell, or `None` for the last used row and column.
+    pub end: Option<(u32, u32)>,
+}
+
+impl FromStr for CellRange {
+    type Err = BulkError;
+
+    fn from_str(s: &str) -> Result<CellRange> {
+        let invalid = || BulkError::Config(format!("invalid cell range: {}", s));
+        let (start, end) = match s.split_once(':') {
+            Some((start, end)) => (start, Some(end)),
+            None => (s, None),
+        };
+        let start = cell(start).ok_or_else(invalid)?;
+        let end = end.map(|end| cell(end).ok_or_else(invalid)).transpose()?;
+        if end.is_some_and(|end| end.0 < start.0 || end.1 < start.1) {
+            return Err(invalid());
+        }
+
+        Ok(CellRange { start, end })
+    }
+}
+
+/// The zero-based `(row, column)` of a cell reference such as `AB12`.
+fn cell(reference: &str) -> Option<(u32, u32)> {
+    let reference = reference.trim().replace('$', "");
+    let digits = reference.find(|c: char| c.is_ascii_digit())?;
+    let (letters, digits) = reference.split_at(digits);
+    if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let column = letters.chars().try_fold(0u32, |column, c| {
+        let digit = c.to_ascii_uppercase() as u32 - 'A' as u32 + 1;
+        column.checked_mul(26)?.checked_add(digit)
+    })?;
+    let row: u32 = digits.parse().ok()?;
+    if row == 0 {
+        return None;
+    }
+
+    Some((row - 1, column - 1))
+}
+
+/// The letters of a zero-based column, as in `AB`.
+fn column_letters(mut column: u32) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.push(b'A' + (column % 26) as u8);
+        if column < 26 {
+            break;
+        }
+        column = column / 26 - 1;
+    }
+    letters.reverse();
+    String::from_utf8(letters).expect("ASCII letters")
+}
+
+/// Which cells of a workbook are loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExcelOptions {
+    /// The sheet to read, or `None` for the first one.
+    pub sheet: Option<String>,
+    /// The cells to read, or `None` for every used cell of the sheet.
+    pub range: Option<CellRange>,
+    /// The first row of the range holds the names of the fields. Without
+    /// it, fields are matched with columns by position.
+    pub has_header: bool,
+}
+
+impl Default for ExcelOptions {
+    fn default() -> ExcelOptions {
+        ExcelOptions {
+            sheet: None,
+            range: None,
+            has_header: true,
+        }
+    }
+}
+
+/// Reads the rows of a sheet of an Excel workbook, `.xlsx`, `.xlsm`,
+/// `.xlsb` or `.xls`, or of an OpenDocument spreadsheet.
+///
+/// Cells are read as:
+///
+/// - numbers as integers when they are whole, or else as floats, which
+///   `decimal` columns take with the digits Excel shows;
+/// - dates and times, which Excel stores as serial numbers of days, as
+///   dates and times, in the 1900 or 1904 date system of the workbook;
+/// - text and booleans as themselves, and empty cells as NULL.
+///
+/// A cell with an error such as `#N/A` rejects its row. Rows of empty cells
+/// are skipped. The sheet is read into memory when the source is opened.
+pub struct ExcelSource {
+    cells: Range<Data>,
+    fields: Option<Vec<String>>,
+    row: usize,
+}
+
+impl ExcelSource {
+    /// Reads a workbook file, decompressing it if it is compressed.
+    pub fn from_path(path: impl AsRef<Path>, options: &ExcelOptions) -> Result<ExcelSource> {
+        ExcelSource::from_reader(Cursor::new(compression::read_all(path)?), options)
+    }
+
+    /// Reads the workbook of `reader`, whose format is told by its content.
+    pub fn from_reader<R: Read + Seek + Clone>(
+        reader: R,
+        options: &ExcelOptions,
+    ) -> Result<ExcelSource> {
+        let mut workbook = calamine::open_workbook_auto_from_rs(reader).map_err(read_error)?;
+        let sheet = match &options.sheet {
+            Some(sheet) => sheet.clone(),
+            None => workbook
+                .sheet_names()
+                .into_iter()
+                .next()
+                .ok_or_else(|| BulkError::Config("the workbook has no sheets".to_owned()))?,
+        };
+        let cells = sheet_cells(&mut workbook, &sheet)?;
+        let cells = match options.range {
+            Some(CellRange { start, end }) => {
+                let end = end.unwrap_or_else(|| {
+                    let used = cells.end().unwrap_or_default();
+                    (used.0.max(start.0), used.1.max(start.1))
+                });
+                cells.range(start, end)
+            }
+            None => cells,
+        };
+
+        let mut source = ExcelSource {
+            cells,
+            fields: None,
+            row: 0,
+        };
+        if options.has_header {
+            source.fields = Some(source.header());
+            source.row = 1;
+        }
+
+        Ok(source)
+    }
+
+    /// The names of the fields in the first row. A cell without a name is
+    /// named after its column, as in `C`.
+    fn header(&self) -> Vec<String> {
+        let first_column = self.cells.start().map_or(0, |start| start.1);
+        (0..self.cells.width())
+            .map(|column| match self.cells.get((0, column)) {
+                Some(Data::Empty) | None => column_letters(first_column + column as u32),
+                Some(cell) => cell.to_string().trim().to_owned(),
+            })
+            .collect()
+    }
+}
+
+impl Source for ExcelSource {
+    fn fields(&self) -> Option<&[String]> {
+        self.fields.as_deref()
+    }
+
+    fn next_record(&mut self) -> Result<Option<Vec<Value>>> {
+        let (height, width) = self.cells.get_size();
+        let cells = loop {
+            if self.row >= height {
+                return Ok(None);
+            }
+            let row = self.row;
+            self.row += 1;
+            let cells: Vec<&Data> = (0..width)
+                .map(|column| self.cells.get((row, column)).unwrap_or(&Data::Empty))
+                .collect();
+            if !cells.iter().all(|cell| matches!(cell, Data::Empty)) {
+                break cells;
+            }
+        };
+
+        cells
+            .into_iter()
+            .map(cell_value)
+            .collect::<Result<_>>()
+            .map(Some)
+    }
+}
+
+fn sheet_cells<R: Read + Seek>(workbook: &mut Sheets<R>, sheet: &str) -> Result<Range<Data>> {
+    if !workbook.sheet_names().iter().any(|name| name == sheet) {
+        return Err(BulkError::Config(format!(
+            "the workbook has no sheet {}",
+            sheet
+        )));
+    }
+    workbook.worksheet_range(sheet).map_err(read_error)
+}
+
+fn cell_value(cell: &Data) -> Result<Value> {
+    let value = match cell {
+        Data::Empty => Value::Null,
+        Data::Int(value) => Value::Int(*value),
+        // Every number of an .xlsx is a float.
+        Data::Float(value) if value.fract() == 0.0 && value.abs() < 2f64.powi(53) => {
+            Value::Int(*value as i64)
+        }
+        Data::Float(value) => Value::Float(*value),
+        Data::String(text) => Value::Text(text.clone()),
+        Data::Bool(value) => Value::Bool(*value),
+        Data::DateTime(value) => excel_datetime(value)?,
+        Data::DateTimeIso(text) | Data::DurationIso(text) => Value::Text(text.clone()),
+        Data::Error(error) => return Err(cell_error(error)),
+    };
+
+    Ok(value)
+}
+
+/// A date or time cell of Excel, a serial number of days since the epoch
+/// of the 1900 date system or of the 1904 one of workbooks made on old
+/// Macs. Excel counts a 29 February 1900 that never was, so serial numbers
+/// before it are read as the dates Excel shows. Times without a date, whose
+/// serial number is less than a day, are read as times.
+fn excel_datetime(value: &ExcelDateTime) -> Result<Value> {
+    let serial = value.as_f64();
+    if value.is_duration() || (0.0..1.0).contains(&serial) {
+        let time = serial_time(serial).ok_or_else(|| {
+            BulkError::conversion(format!("`{}` is not a valid time of day", serial))
+        })?;
+        return Ok(Value::Time(time));
+    }
+
+    value
+        .as_datetime()
+        .map(Value::DateTime)
+        .ok_or_else(|| BulkError::conversion(format!("`{}` is not a valid Excel date", serial)))
+}
+
+/// The time of day of the fraction of a day `serial`, to the millisecond
+/// Excel keeps.
+fn serial_time(serial: f64) -> Option<NaiveTime> {
+    let millis = (serial * 86_400_000.0).round();
+    if !(0.0..86_400_000.0).contains(&millis) {
+        return None;
+    }
+    let millis = millis as u32;
+    NaiveTime::from_num_seconds_from_midnight_opt(millis / 1000, millis % 1000 * 1_000_000)
+}
+
+fn cell_error(error: &CellErrorType) -> BulkError {
+    BulkError::conversion(format!("the cell holds the error {}", error))
+}
+
+/// Failing to read the workbook ends the load; it is no error of a record.
+fn read_error(error: impl std::error::Error + Send + Sync + 'static) -> BulkError {
+    io::Error::new(io::ErrorKind::InvalidData, error).into()
+}
@@ -0,0 +1,495 @@
+use super::Source;
+use crate::{
+    compression::{self, InputFile},
+    convert::{decimal_text, Value},
+    error::{BulkError, Result, ResultExt},
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::Deserialize;
+use std::{
+    fs,
+    io::{BufRead, Read},
+    path::Path,
+    str::FromStr,
+};
+
+/// Format of `date` fields without a format of their own.
+const DEFAULT_DATE_FORMAT: &str = "%Y%m%d";
+
+/// Format of `timestamp` fields without a format of their own, that of DB2
+/// timestamps such as `2024-01-31-13.45.30.123456`.
+const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d-%H.%M.%S%.f";
+
+/// The characters of the bytes of EBCDIC code page 037.
+const CP037: [char; 256] = [
+    '\u{00}', '\u{01}', '\u{02}', '\u{03}', '\u{9C}', '\u{09}', '\u{86}', '\u{7F}', '\u{97}',
+    '\u{8D}', '\u{8E}', '\u{0B}', '\u{0C}', '\u{0D}', '\u{0E}', '\u{0F}', '\u{10}', '\u{11}',
+    '\u{12}', '\u{13}', '\u{9D}', '\u{85}', '\u{08}', '\u{87}', '\u{18}', '\u{19}', '\u{92}',
+    '\u{8F}', '\u{1C}', '\u{1D}', '\u{1E}', '\u{1F}', '\u{80}', '\u{81}', '\u{82}', '\u{83}',
+    '\u{84}', '\u{0A}', '\u{17}', '\u{1B}', '\u{88}', '\u{89}', '\u{8A}', '\u{8B}', '\u{8C}',
+    '\u{05}', '\u{06}', '\u{07}', '\u{90}', '\u{91}', '\u{16}', '\u{93}', '\u{94}', '\u{95}',
+    '\u{96}', '\u{04}', '\u{98}', '\u{99}', '\u{9A}', '\u{9B}', '\u{14}', '\u{15}', '\u{9E}',
+    '\u{1A}', ' ', '\u{A0}', '\u{E2}', '\u{E4}', '\u{E0}', '\u{E1}', '\u{E3}', '\u{E5}', '\u{E7}',
+    '\u{F1}', '\u{A2}', '.', '<', '(', '+', '|', '&', '\u{E9}', '\u{EA}', '\u{EB}', '\u{E8}',
+    '\u{ED}', '\u{EE}', '\u{EF}', '\u{EC}', '\u{DF}', '!', '$', '*', ')', ';', '\u{AC}', '-', '/',
+    '\u{C2}', '\u{C4}', '\u{C0}', '\u{C1}', '\u{C3}', '\u{C5}', '\u{C7}', '\u{D1}', '\u{A6}', ',',
+    '%', '_', '>', '?', '\u{F8}', '\u{C9}', '\u{CA}', '\u{CB}', '\u{C8}', '\u{CD}', '\u{CE}',
+    '\u{CF}', '\u{CC}', '`', ':', '#', '@', '\u{27}', '=', '"', '\u{D8}', 'a', 'b', 'c', 'd', 'e',
+    'f', 'g', 'h', 'i', '\u{AB}', '\u{BB}', '\u{F0}', '\u{FD}', '\u{FE}', '\u{B1}', '\u{B0}', 'j',
+    'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', '\u{AA}', '\u{BA}', '\u{E6}', '\u{B8}', '\u{C6}',
+    '\u{A4}', '\u{B5}', '~', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '\u{A1}', '\u{BF}', '\u{D0}',
+    '\u{DD}', '\u{DE}', '\u{AE}', '^', '\u{A3}', '\u{A5}', '\u{B7}', '\u{A9}', '\u{A7}', '\u{B6}',
+    '\u{BC}', '\u{BD}', '\u{BE}', '[', ']', '\u{AF}', '\u{A8}', '\u{B4}', '\u{D7}', '{', 'A', 'B',
+    'C', 'D', 'E', 'F', 'G', 'H', 'I', '\u{AD}', '\u{F4}', '\u{F6}', '\u{F2}', '\u{F3}', '\u{F5}',
+    '}', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', '\u{B9}', '\u{FB}', '\u{FC}', '\u{F9}',
+    '\u{FA}', '\u{FF}', '\u{5C}', '\u{F7}', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '\u{B2}',
+    '\u{D4}', '\u{D6}', '\u{D2}', '\u{D3}', '\u{D5}', '0', '1', '2', '3', '4', '5', '6', '7', '8',
+    '9', '\u{B3}', '\u{DB}', '\u{DC}', '\u{D9}', '\u{DA}', '\u{9F}',
+];
+
+/// The layout of the records of a fixed-width file, read from TOML:
+///
+/// ```toml
+/// record_length = 42 # without it, records are lines
+/// encoding = "ebcdic"
+///
+/// [[fields]]
+/// name = "account"
+/// start = 1
+/// length = 10
+///
+/// [[fields]]
+/// name = "balance"
+/// start = 11
+/// length = 9
+/// type = "zoned"
+/// decimals = 2
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct FixedWidthLayout {
+    /// Bytes of every record. Records of files without one are lines, and
+    /// lines shorter than the layout are read as if padded with spaces.
+    pub record_length: Option<usize>,
+    #[serde(default)]
+    pub encoding: TextEncoding,
+    pub fields: Vec<FixedField>,
+}
+
+/// A field of the records of a fixed-width file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct FixedField {
+    pub name: String,
+    /// Position of the first byte of the field in the record, from 1.
+    pub start: usize,
+    /// Bytes of the field.
+    pub length: usize,
+    #[serde(default, rename = "type")]
+    pub field_type: FieldType,
+    /// Implied decimal places of numbers without a decimal point: `12345`
+    /// with 2 decimals is `123.45`.
+    #[serde(default)]
+    pub decimals: u8,
+    /// The spaces removed from the text of the field.
+    #[serde(default)]
+    pub trim: TrimRule,
+    /// The chrono format of `date` and `timestamp` fields.
+    pub format: Option<String>,
+}
+
+/// How the bytes of a field are read.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    /// Text, converted to the type of the column it is loaded into.
+    #[default]
+    Text,
+    /// Digits with an optional leading or trailing sign and decimal point.
+    Number,
+    /// Zoned decimal: digits whose last one carries the sign, as in
+    /// `PIC S9(7)V99`. In text the sign is overpunched: `{` and `A` to `I`
+    /// are positive, `}` and `J` to `R` negative.
+    Zoned,
+    /// Packed decimal (`COMP-3`): two digits a byte and the sign in the last
+    /// half byte.
+    Packed,
+    /// Big-endian two's complement integer (`COMP`) of up to 8 bytes.
+    Binary,
+    Date,
+    Timestamp,
+}
+
+/// The spaces removed from the text of a field.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrimRule {
+    None,
+    Left,
+    Right,
+    #[default]
+    Both,
+}
+
+/// The encoding of the text of a fixed-width file.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum TextEncoding {
+    #[default]
+    #[serde(rename = "utf-8", alias = "utf8")]
+    Utf8,
+    /// EBCDIC code page 037, that of mainframes in the US.
+    #[serde(rename = "ebcdic", alias = "cp037")]
+    Ebcdic,
+}
+
+impl TextEncoding {
+    /// The byte of a space.
+    fn space(self) -> u8 {
+        match self {
+            TextEncoding::Utf8 => b' ',
+            TextEncoding::Ebcdic => 0x40,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> Result<String> {
+        match self {
+            TextEncoding::Utf8 => String::from_utf8(bytes.to_vec())
+                .map_err(|_| BulkError::conversion("the text is no UTF-8")),
+            TextEncoding::Ebcdic => Ok(bytes.iter().map(|b| CP037[*b as usize]).collect()),
+        }
+    }
+}
+
+impl FixedWidthLayout {
+    /// Reads a layout file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<FixedWidthLayout> {
+        let path = path.as_ref();
+        fs::read_to_string(path)?.parse().map_err(|e| match e {
+            BulkError::Config(message) => {
+                BulkError::Config(format!("{}: {}", path.display(), message))
+            }
+            e => e,
+        })
+    }
+
+    /// The names of the fields.
+    pub fn field_names(&self) -> Vec<String> {
+        self.fields.iter().map(|field| field.name.clone()).collect()
+    }
+
+    /// Fails with the first field that does not fit in the records or whose
+    /// settings do not go together.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |field: &FixedField, what: &str| {
+            BulkError::Config(format!("field {} of the layout {}", field.name, what))
+        };
+
+        if self.fields.is_empty() {
+            return Err(BulkError::Config("the layout has no fields".to_owned()));
+        }
+        if self.record_length == Some(0) {
+            return Err(BulkError::Config(
+                "the record length of the layout is 0".to_owned(),
+            ));
+        }
+
+        for field in &self.fields {
+            if field.start == 0 || field.length == 0 {
+                return Err(invalid(field, "starts at 0 or is empty"));
+            }
+            if let Some(record_length) = self.record_length {
+                if field.start + field.length - 1 > record_length {
+                    return Err(invalid(field, "ends after the end of the record"));
+                }
+            }
+            if field.decimals > 38 {
+                return Err(invalid(field, "has more than 38 decimals"));
+            }
+
+            match field.field_type {
+                // Binary data may hold newline bytes.
+                FieldType::Packed | FieldType::Binary if self.record_length.is_none() => {
+                    return Err(invalid(field, "is binary, which needs a record length"))
+                }
+                FieldType::Binary if field.length > 8 => {
+                    return Err(invalid(field, "is a binary integer of more than 8 bytes"))
+                }
+                FieldType::Date | FieldType::Timestamp => {}
+                _ if field.format.is_some() => {
+                    return Err(invalid(field, "has a format but is no date or timestamp"))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for FixedWidthLayout {
+    type Err = BulkError;
+
+    /// Reads and validates the TOML of a layout.
+    fn from_str(s: &str) -> Result<FixedWidthLayout> {
+        let layout: FixedWidthLayout =
+            toml::from_str(s).map_err(|e| BulkError::Config(e.to_string()))?;
+        layout.validate()?;
+        Ok(layout)
+    }
+}
+
+/// Reads the records of a fixed-width file, such as a mainframe extract,
+/// with a value per field of its layout.
+///
+/// Fields of only spaces are NULL, except binary ones, whose bytes are a
+/// number whatever they are, and so are dates and timestamps of only zeros,
+/// as mainframes write missing dates. A field that cannot be read
+/// fails its record, and reading goes on with the next record.
+pub struct FixedWidthSource<R> {
+    reader: R,
+    layout: FixedWidthLayout,
+    fields: Vec<String>,
+    record: Vec<u8>,
+}
+
+impl FixedWidthSource<InputFile> {
+    /// Reads a fixed-width file, decompressing it if it is compressed. Files
+    /// of records of a fixed length may hold binary fields, which may start
+    /// with any bytes, so only their extension tells their compression.
+    pub fn from_path(
+        path: impl AsRef<Path>,
+        layout: &FixedWidthLayout,
+    ) -> Result<FixedWidthSource<InputFile>> {
+        let file = match layout.record_length {
+            Some(_) => compression::open_by_extension(path)?,
+            None => compression::open(path)?,
+        };
+        FixedWidthSource::from_reader(file, layout)
+    }
+}
+
+impl<R: BufRead> FixedWidthSource<R> {
+    /// Reads the records of `reader` laid out as in `layout`.
+    pub fn from_reader(reader: R, layout: &FixedWidthLayout) -> Result<FixedWidthSource<R>> {
+        layout.validate()?;
+
+        Ok(FixedWidthSource {
+            reader,
+            fields: layout.field_names(),
+            layout: layout.clone(),
+            record: Vec::new(),
+        })
+    }
+
+    /// Reads the next record into `self.record`; false at the end.
+    fn read_record(&mut self) -> Result<bool> {
+        self.record.clear();
+
+        if let Some(len) = self.layout.record_length {
+            (&mut self.reader)
+                .take(len as u64)
+                .read_to_end(&mut self.record)?;
+            return match self.record.len() {
+                0 => Ok(false),
+                n if n < len => Err(BulkError::conversion("the file ends within a record")),
+                _ => Ok(true),
+            };
+        }
+
+        // Blank lines are no records.
+        loop {
+            self.record.clear();
+            if self.reader.read_until(b'\n', &mut self.record)? == 0 {
+                return Ok(false);
+            }
+            if self.record.ends_with(b"\n") {
+                self.record.pop();
+                if self.record.ends_with(b"\r") {
+                    self.record.pop();
+                }
+            }
+            if !self.record.is_empty() {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Source for FixedWidthSource<R> {
+    fn fields(&self) -> Option<&[String]> {
+        Some(&self.fields)
+    }
+
+    fn next_record(&mut self) -> Result<Option<Vec<Value>>> {
+        if !self.read_record()? {
+            return Ok(None);
+        }
+
+        let encoding = self.layout.encoding;
+        let space = encoding.space();
+        let mut bytes = Vec::new();
+        let mut values = Vec::with_capacity(self.layout.fields.len());
+        for field in &self.layout.fields {
+            // Short lines are read as if padded with spaces.
+            let start = (field.start - 1).min(self.record.len());
+            let end = (start + field.length).min(self.record.len());
+            bytes.clear();
+            bytes.extend_from_slice(&self.record[start..end]);
+            bytes.resize(field.length, space);
+
+            values.push(field_value(field, &bytes, encoding).in_column(&field.name)?);
+        }
+
+        Ok(Some(values))
+    }
+}
+
+/// Reads the bytes of `field`.
+fn field_value(field: &FixedField, bytes: &[u8], encoding: TextEncoding) -> Result<Value> {
+    let blank = bytes.iter().all(|b| *b == encoding.space());
+    match field.field_type {
+        // Spaces are no packed number, but how a missing one is written.
+        FieldType::Packed if blank => return Ok(Value::Null),
+        FieldType::Packed => return packed(bytes).map(|value| number(value, field.decimals)),
+        FieldType::Binary => {
+            // Sign extended from the first byte.
+            let fill = if bytes[0] & 0x80 != 0 { 0xFF } else { 0 };
+            let mut be = [fill; 8];
+            be[8 - bytes.len()..].copy_from_slice(bytes);
+            return Ok(number(i64::from_be_bytes(be).into(), field.decimals));
+        }
+        _ if blank => return Ok(Value::Null),
+        _ => {}
+    }
+
+    let text = encoding.decode(bytes)?;
+    let text = match field.trim {
+        TrimRule::None => text.as_str(),
+        TrimRule::Left => text.trim_start_matches(' '),
+        TrimRule::Right => text.trim_end_matches(' '),
+        TrimRule::Both => text.trim_matches(' '),
+    };
+    let invalid = || {
+        BulkError::conversion(format!(
+            "`{}` is no valid {:?} field",
+            text, field.field_type
+        ))
+    };
+
+    let value = match field.field_type {
+        FieldType::Text => Value::Text(text.to_owned()),
+        FieldType::Number => parse_number(text, field.decimals).ok_or_else(invalid)?,
+        FieldType::Zoned => number(zoned(text.trim()).ok_or_else(invalid)?, field.decimals),
+        FieldType::Date | FieldType::Timestamp if text.trim().bytes().all(|b| b == b'0') => {
+            Value::Null
+        }
+        FieldType::Date => {
+            let format = field.format.as_deref().unwrap_or(DEFAULT_DATE_FORMAT);
+            Value::Date(NaiveDate::parse_from_str(text.trim(), format).map_err(|_| invalid())?)
+        }
+        FieldType::Timestamp => {
+            let format = field.format.as_deref().unwrap_or(DEFAULT_TIMESTAMP_FORMAT);
+            Value::DateTime(
+                NaiveDateTime::parse_from_str(text.trim(), format).map_err(|_| invalid())?,
+            )
+        }
+        FieldType::Packed | FieldType::Binary => unreachable!("read above"),
+    };
+
+    Ok(value)
+}
+
+/// The unscaled `value` with `decimals` implied decimal places; decimals are
+/// read as text, which keeps all their digits.
+fn number(value: i128, decimals: u8) -> Value {
+    match (decimals, i64::try_from(value)) {
+        (0, Ok(value)) => Value::Int(value),
+        _ => Value::Text(decimal_text(value, decimals)),
+    }
+}
+
+/// Digits with an optional leading or trailing sign. Numbers with a decimal
+/// point are read as written, others with `decimals` implied decimals.
+fn parse_number(text: &str, decimals: u8) -> Option<Value> {
+    let text = text.trim();
+    let (negative, unsigned) = if let Some(rest) = text.strip_prefix('-') {
+        (true, rest)
+    } else if let Some(rest) = text.strip_suffix('-') {
+        (true, rest)
+    } else {
+        let rest = text.strip_prefix('+').unwrap_or(text);
+        (false, rest.strip_suffix('+').unwrap_or(rest))
+    };
+    let (integer, fraction) = match unsigned.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (unsigned, None),
+    };
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if integer.is_empty() && fraction.is_none_or(str::is_empty)
+        || !digits(integer)
+        || !fraction.is_none_or(digits)
+    {
+        return None;
+    }
+
+    let sign = if negative { "-" } else { "" };
+    match fraction {
+        Some(fraction) => Some(Value::Text(format!("{}{}.{}", sign, integer, fraction))),
+        None => {
+            let value: i128 = unsigned.parse().ok()?;
+            Some(number(if negative { -value } else { value }, decimals))
+        }
+    }
+}
+
+/// The value of zoned decimal text, whose last character may carry the sign.
+fn zoned(text: &str) -> Option<i128> {
+    let mut chars = text.chars();
+    let (last, negative) = match chars.next_back()? {
+        c @ '0'..='9' => (c as u8 - b'0', false),
+        '{' => (0, false),
+        c @ 'A'..='I' => (c as u8 - b'A' + 1, false),
+        '}' => (0, true),
+        c @ 'J'..='R' => (c as u8 - b'J' + 1, true),
+        _ => return None,
+    };
+    let digits = chars.as_str();
+    if !digits.bytes().all(|b| b.is_ascii_digit()) || digits.len() >= 38 {
+        return None;
+    }
+
+    let value = digits
+        .bytes()
+        .fold(0i128, |value, b| value * 10 + i128::from(b - b'0'))
+        * 10
+        + i128::from(last);
+    Some(if negative { -value } else { value })
+}
+
+/// The value of packed decimal bytes: a digit every half byte and the sign
+/// in the last one, where `D` and `B` are negative.
+fn packed(bytes: &[u8]) -> Result<i128> {
+    let invalid =
+        || BulkError::conversion(format!("{} bytes are no valid packed decimal", bytes.len()));
+    if bytes.len() > 19 {
+        return Err(invalid());
+    }
+
+    let mut value = 0i128;
+    let nibbles = bytes.iter().flat_map(|b| [b >> 4, b & 0x0F]);
+    for (i, nibble) in nibbles.enumerate() {
+        if i == bytes.len() * 2 - 1 {
+            return match nibble {
+                0xA | 0xC | 0xE | 0xF => Ok(value),
+                0xB | 0xD => Ok(-value),
+                _ => Err(invalid()),
+            };
+        }
+        if nibble > 9 {
+            return Err(invalid());
+        }
+        value = value * 10 + i128::from(nibble);
+    }
+
+    Err(invalid())
+}
@@ -0,0 +1,214 @@
+use super::Source;
+use crate::{
+    compression::{self, InputFile},
+    convert::Value,
+    error::{BulkError, Result},
+    schema::TableSchema,
+};
+use std::{io::BufRead, path::Path, str::FromStr};
+
+/// The location of a value in a JSON document, as in `$.device.id`,
+/// `$.tags[0]` or just `name` for a top-level key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonPath(Vec<Step>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    /// The path of the top-level key `name`, which may hold any character,
+    /// dots and brackets too.
+    pub fn key(name: impl Into<String>) -> JsonPath {
+        JsonPath(vec![Step::Key(name.into())])
+    }
+
+    /// The value at the path, if there is one.
+    pub fn find<'a>(&self, document: &'a serde_json::Value) -> Option<&'a serde_json::Value> {
+        self.0.iter().try_fold(document, |value, step| match step {
+            Step::Key(key) => value.get(key),
+            Step::Index(index) => value.get(index),
+        })
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = BulkError;
+
+    fn from_str(s: &str) -> Result<JsonPath> {
+        let invalid = || BulkError::Config(format!("invalid JSON path: {}", s));
+        // A path without `$` starts with a key.
+        let path = match s.strip_prefix('$') {
+            Some(rest) => rest.to_owned(),
+            None => format!(".{}", s),
+        };
+
+        let mut steps = Vec::new();
+        let mut rest = path.as_str();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    return Err(invalid());
+                }
+                steps.push(Step::Key(after[..end].to_owned()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let (index, after) = after.split_once(']').ok_or_else(invalid)?;
+                steps.push(Step::Index(index.parse().map_err(|_| invalid())?));
+                rest = after;
+            } else {
+                return Err(invalid());
+            }
+        }
+
+        Ok(JsonPath(steps))
+    }
+}
+
+/// Reads JSON Lines: one JSON object per line. Every field is a path into
+/// the objects; objects and arrays are read as their JSON text, so they can
+/// be stored in `nvarchar(max)` columns.
+pub struct JsonlSource<R> {
+    reader: R,
+    fields: Vec<String>,
+    paths: Vec<JsonPath>,
+    line: Vec<u8>,
+}
+
+impl JsonlSource<InputFile> {
+    /// Reads a JSON Lines file, decompressing it if it is compressed.
+    pub fn from_path(path: impl AsRef<Path>, fields: &[String]) -> Result<JsonlSource<InputFile>> {
+        JsonlSource::from_reader(compression::open(path)?, fields)
+    }
+}
+
+impl<R: BufRead> JsonlSource<R> {
+    /// Reads the values at the `fields`, which are [`JsonPath`]s, of the
+    /// objects of `reader`.
+    pub fn from_reader(reader: R, fields: &[String]) -> Result<JsonlSource<R>> {
+        let fields = fields
+            .iter()
+            .map(|field| Ok((field.clone(), field.parse()?)))
+            .collect::<Result<_>>()?;
+        Ok(JsonlSource::with_paths(reader, fields))
+    }
+
+    /// Reads the values of the objects of `reader` at the paths of the
+    /// `fields`, which are named like the paths themselves need not be.
+    pub fn with_paths(reader: R, fields: Vec<(String, JsonPath)>) -> JsonlSource<R> {
+        let (fields, paths) = fields.into_iter().unzip();
+        JsonlSource {
+            reader,
+            fields,
+            paths,
+            line: Vec::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Source for JsonlSource<R> {
+    fn fields(&self) -> Option<&[String]> {
+        Some(&self.fields)
+    }
+
+    fn next_record(&mut self) -> Result<Option<Vec<Value>>> {
+        // Blank lines are no records.
+        loop {
+            self.line.clear();
+            if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(None);
+            }
+            if !self.line.trim_ascii().is_empty() {
+                break;
+            }
+        }
+
+        let document: serde_json::Value = serde_json::from_slice(&self.line)
+            .map_err(|e| BulkError::conversion(format!("invalid JSON: {}", e)))?;
+        if !document.is_object() {
+            return Err(BulkError::conversion("the line is no JSON object"));
+        }
+
+        let values = self
+            .paths
+            .iter()
+            .map(|path| path.find(&document).map_or(Value::Null, json_value))
+            .collect();
+
+        Ok(Some(values))
+    }
+}
+
+/// The fields, with their paths, that load every insertable column of
+/// `schema` from the top-level key of its name, except for the columns
+/// `rename` loads from another field. Column names are keys as they are,
+/// even if they hold dots or brackets.
+pub fn default_fields(
+    schema: &TableSchema,
+    rename: &[(String, String)],
+) -> Result<Vec<(String, JsonPath)>> {
+    let renamed = |name: &str| {
+        rename
+            .iter()
+            .any(|(_, column)| column.eq_ignore_ascii_case(name))
+    };
+
+    schema
+        .insertable_columns()
+        .filter(|column| !renamed(&column.name))
+        .map(|column| Ok((column.name.clone(), JsonPath::key(&column.name))))
+        .chain(
+            rename
+                .iter()
+                .map(|(field, _)| Ok((field.clone(), field.parse()?))),
+        )
+        .collect()
+}
+
+/// The top-level keys of the objects on the first `records` lines of
+/// `reader`, in the order they first appear, such as to read a file whose
+/// columns are not known yet. Blank lines and lines that are no JSON object
+/// are skipped.
+pub fn sample_fields<R: BufRead>(reader: R, records: Option<u64>) -> Result<Vec<String>> {
+    let mut fields: Vec<String> = Vec::new();
+    let mut read = 0;
+
+    for line in reader.lines() {
+        if records.is_some_and(|records| read >= records) {
+            break;
+        }
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        read += 1;
+
+        if let Ok(serde_json::Value::Object(object)) = serde_json::from_str(&line) {
+            for key in object.keys() {
+                if !fields.contains(key) {
+                    fields.push(key.clone());
+                }
+            }
+        }
+    }
+
+    Ok(fields)
+}
+
+/// Numbers that are no `i64` are read as their text, which keeps all the
+/// digits of decimals.
+fn json_value(value: &serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(value) => Value::Bool(*value),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(value) => Value::Int(value),
+            None => Value::Text(number.to_string()),
+        },
+        serde_json::Value::String(text) => Value::Text(text.clone()),
+        other => Value::Text(other.to_string()),
+    }
+}
@@ -0,0 +1,232 @@
+use crate::{
+    bulk::BulkLoader,
+    convert::{convert, convert_column, BinaryEncoding, Value},
+    error::{BulkError, Result, ResultExt},
+    schema::{Column, TableSchema},
+};
+use std::mem;
+use tiberius::{ColumnData, TokenRow};
+
+pub mod csv;
+pub mod excel;
+pub mod fixed;
+pub mod jsonl;
+pub mod parquet;
+pub mod sqlite;
+
+/// A file read record by record for a bulk load.
+pub trait Source {
+    /// The names of the fields of the records, or `None` when the fields are
+    /// matched with the columns of the table by position.
+    fn fields(&self) -> Option<&[String]>;
+
+    /// The next record, with a value per field, or `None` at the end. A
+    /// record that cannot be read fails with a conversion error, and reading
+    /// goes on with the record after it.
+    fn next_record(&mut self) -> Result<Option<Vec<Value>>>;
+}
+
+/// How the records of a [`Source`] are loaded into a table.
+#[derive(Clone, Debug, Default)]
+pub struct IngestOptions {
+    /// Fields loaded into a column of another name, as `(field, column)`.
+    /// Other fields are loaded into the column of their own name; names are
+    /// compared without regard to case.
+    pub rename: Vec<(String, String)>,
+    /// Skip fields without a column instead of failing.
+    pub ignore_extra_fields: bool,
+    /// How binary values are written in text fields.
+    pub binary_encoding: BinaryEncoding,
+    /// Records that may be rejected before the load fails.
+    pub max_errors: u64,
+}
+
+/// What happened to the records of a source.
+#[derive(Debug, Default)]
+pub struct IngestReport {
+    /// Records read, including rejected ones.
+    pub records: u64,
+    /// Records that could not be read or converted, with the position of
+    /// the record as the row of their context.
+    pub rejected: Vec<BulkError>,
+}
+
+/// Matches the fields of a source with the insertable columns of a table
+/// and converts records to rows.
+#[derive(Clone, Debug)]
+pub struct ColumnMap {
+    /// Every insertable column with the position of its field. Columns
+    /// without a field are loaded with NULL.
+    columns: Vec<(Column, Option<usize>)>,
+    binary_encoding: BinaryEncoding,
+}
+
+impl ColumnMap {
+    /// Matches `fields` with the columns of `schema` by name, or by position
+    /// when the source has no field names.
+    pub fn new(
+        schema: &TableSchema,
+        fields: Option<&[String]>,
+        options: &IngestOptions,
+    ) -> Result<ColumnMap> {
+        schema.check_bulk_loadable()?;
+        let columns: Vec<Column> = schema.insertable_columns().cloned().collect();
+
+        let positions = match fields {
+            None => (0..columns.len()).map(Some).collect(),
+            Some(fields) => {
+                let mut positions = vec![None; columns.len()];
+                for (i, field) in fields.iter().enumerate() {
+                    let name = options
+                        .rename
+                        .iter()
+                        .find(|(from, _)| from.eq_ignore_ascii_case(field))
+                        .map_or(field, |(_, to)| to);
+
+                    match columns
+                        .iter()
+                        .position(|column| column.name.eq_ignore_ascii_case(name))
+                    {
+                        Some(c) if positions[c].is_some() => {
+                            return Err(BulkError::Config(format!(
+                                "several fields are loaded into column {} of table {}",
+                                columns[c].name, schema.table
+                            )))
+                        }
+                        Some(c) => positions[c] = Some(i),
+                        None if options.ignore_extra_fields => {}
+                        None => {
+                            return Err(BulkError::Config(format!(
+                                "table {} has no column for field {}",
+                                schema.table, field
+                            )))
+                        }
+                    }
+                }
+                positions
+            }
+        };
+
+        let columns: Vec<_> = columns.into_iter().zip(positions).collect();
+        if let Some((column, _)) = columns
+            .iter()
+            .find(|(column, position)| position.is_none() && !column.nullable)
+        {
+            return Err(
+                BulkError::conversion("no field for a column that does not allow NULLs")
+                    .with_table(&schema.table)
+                    .with_column(&column.name),
+            );
+        }
+
+        Ok(ColumnMap {
+            columns,
+            binary_encoding: options.binary_encoding,
+        })
+    }
+
+    /// Every insertable column with the position of its field.
+    pub(crate) fn columns(&self) -> &[(Column, Option<usize>)] {
+        &self.columns
+    }
+
+    pub(crate) fn binary_encoding(&self) -> BinaryEncoding {
+        self.binary_encoding
+    }
+
+    /// The names of the columns that get a value from the source.
+    pub fn mapped_columns(&self) -> impl Iterator<Item = &str> {
+        self.columns
+            .iter()
+            .filter(|(_, position)| position.is_some())
+            .map(|(column, _)| column.name.as_str())
+    }
+
+    /// Converts a record to a row with a value for every insertable column.
+    pub fn row(&self, mut values: Vec<Value>) -> Result<TokenRow<'static>> {
+        let mut row = TokenRow::with_capacity(self.columns.len());
+
+        for (column, position) in &self.columns {
+            let value = position
+                .and_then(|i| values.get_mut(i))
+                .map_or(Value::Null, |value| mem::replace(value, Value::Null));
+            if value == Value::Null && !column.nullable {
+                return Err(
+                    BulkError::conversion("NULL in a column that does not allow NULLs")
+                        .with_column(&column.name),
+                );
+            }
+
+            row.push(
+                convert(value, &column.sql_type, self.binary_encoding).in_column(&column.name)?,
+            );
+        }
+
+        Ok(row)
+    }
+
+    /// Converts a row read from SQL Server, with a value per field, to a row
+    /// with a value for every insertable column, like [`ColumnMap::row`] but
+    /// with [`convert_column`], so values keep their types on the way.
+    pub fn column_row(&self, mut values: Vec<ColumnData<'static>>) -> Result<TokenRow<'static>> {
+        let mut row = TokenRow::with_capacity(self.columns.len());
+
+        for (column, position) in &self.columns {
+            let null = column.sql_type.null();
+            let value = match position.and_then(|i| values.get_mut(i)) {
+                Some(value) => convert_column(mem::replace(value, null.clone()), &column.sql_type)
+                    .in_column(&column.name)?,
+                None => null.clone(),
+            };
+            if value == null && !column.nullable {
+                return Err(
+                    BulkError::conversion("NULL in a column that does not allow NULLs")
+                        .with_column(&column.name),
+                );
+            }
+
+            row.push(value);
+        }
+
+        Ok(row)
+    }
+}
+
+/// Loads the records of `source` into the table of `schema` with `loader`,
+/// which is left open for the caller to finish.
+///
+/// Records that cannot be read or converted are rejected and reported
+/// until more than `max_errors` were rejected; then the load fails with the
+/// last rejection. Any other error fails the load at once.
+pub async fn ingest<S: Source + ?Sized>(
+    source: &mut S,
+    schema: &TableSchema,
+    loader: &mut BulkLoader,
+    options: &IngestOptions,
+) -> Result<IngestReport> {
+    let columns = ColumnMap::new(schema, source.fields(), options)?;
+    let mut report = IngestReport::default();
+
+    loop {
+        let row = match source.next_record() {
+            Ok(None) => break,
+            Ok(Some(values)) => columns.row(values),
+            Err(e) => Err(e),
+        };
+        let record = report.records;
+        report.records += 1;
+
+        match row.in_table(&schema.table).at_row(record) {
+            Ok(row) => loader.send_row(row).await?,
+            Err(e @ BulkError::Conversion { .. }) => {
+                if report.rejected.len() as u64 >= options.max_errors {
+                    return Err(e);
+                }
+                report.rejected.push(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(report)
+}
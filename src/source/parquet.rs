@@ -0,0 +1,118 @@
+use super::Source;
+use crate::{
+    arrow::{arrow_value, time_zones},
+    compression::{self, Compression},
+    convert::Value,
+    error::{BulkError, Result},
+};
+use arrow_array::{timezone::Tz, RecordBatch};
+use bytes::Bytes;
+use parquet::{
+    arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder},
+    file::reader::ChunkReader,
+};
+use std::{fs::File, io, path::Path};
+
+/// Rows decoded at a time; the memory a load needs grows with it.
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// Reads the rows of a Parquet file, a batch of rows at a time, so that the
+/// file never has to fit in memory.
+///
+/// The fields are the top-level columns of the file. Their Arrow types are
+/// read as:
+///
+/// - integers, floats and booleans as numbers;
+/// - `DECIMAL` as text, which keeps all the digits;
+/// - `STRING` as text, `BINARY` as bytes and `UUID` as 16 bytes, which
+///   `uniqueidentifier` columns take;
+/// - `DATE`, `TIME` and `TIMESTAMP` of any unit as dates and times.
+///   Timestamps adjusted to UTC are read with the offset of their time zone,
+///   which is parsed once when the file is opened;
+/// - anything else, such as lists and structs, as text.
+pub struct ParquetSource {
+    reader: ParquetRecordBatchReader,
+    fields: Vec<String>,
+    time_zones: Vec<Option<Tz>>,
+    batch: Option<RecordBatch>,
+    row: usize,
+}
+
+impl ParquetSource {
+    /// Reads a Parquet file. Reading needs random access, so a compressed
+    /// file is decompressed in memory rather than read a batch at a time.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<ParquetSource> {
+        let path = path.as_ref();
+        match Compression::of_file(path)? {
+            Compression::None => ParquetSource::from_reader(File::open(path)?, DEFAULT_BATCH_SIZE),
+            _ => ParquetSource::from_reader(
+                Bytes::from(compression::read_all(path)?),
+                DEFAULT_BATCH_SIZE,
+            ),
+        }
+    }
+
+    /// Reads the Parquet file of `reader`, `batch_size` rows at a time.
+    pub fn from_reader<R: ChunkReader + 'static>(
+        reader: R,
+        batch_size: usize,
+    ) -> Result<ParquetSource> {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(reader).map_err(read_error)?;
+        let fields = builder
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect();
+        let time_zones = time_zones(builder.schema())?;
+        let reader = builder
+            .with_batch_size(batch_size.max(1))
+            .build()
+            .map_err(read_error)?;
+
+        Ok(ParquetSource {
+            reader,
+            fields,
+            time_zones,
+            batch: None,
+            row: 0,
+        })
+    }
+}
+
+impl Source for ParquetSource {
+    fn fields(&self) -> Option<&[String]> {
+        Some(&self.fields)
+    }
+
+    fn next_record(&mut self) -> Result<Option<Vec<Value>>> {
+        while self
+            .batch
+            .as_ref()
+            .is_none_or(|batch| self.row >= batch.num_rows())
+        {
+            match self.reader.next() {
+                Some(batch) => self.batch = Some(batch.map_err(read_error)?),
+                None => return Ok(None),
+            }
+            self.row = 0;
+        }
+
+        let batch = self.batch.as_ref().expect("read above");
+        let row = self.row;
+        self.row += 1;
+
+        batch
+            .columns()
+            .iter()
+            .zip(&self.time_zones)
+            .map(|(column, time_zone)| arrow_value(column.as_ref(), time_zone.as_ref(), row))
+            .collect::<Result<_>>()
+            .map(Some)
+    }
+}
+
+/// Failing to read the file ends the load; it is no error of a record.
+fn read_error(error: impl std::error::Error + Send + Sync + 'static) -> BulkError {
+    io::Error::new(io::ErrorKind::InvalidData, error).into()
+}
@@ -0,0 +1,236 @@
+use super::Source;
+use crate::{
+    convert::Value,
+    error::{BulkError, Result},
+    schema::{quote_name, Column, SqlType, TableSchema},
+};
+use rusqlite::{types::ValueRef, Connection, OpenFlags};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread,
+};
+
+/// Rows read ahead of the load; the memory a load needs grows with it.
+const READ_AHEAD: usize = 1024;
+
+/// A SQLite database file, opened read-only, whose tables are loaded into
+/// SQL Server.
+pub struct SqliteDatabase {
+    connection: Connection,
+    path: PathBuf,
+}
+
+impl SqliteDatabase {
+    /// Opens a database file; it is never written to.
+    pub fn open(path: impl AsRef<Path>) -> Result<SqliteDatabase> {
+        let path = path.as_ref().to_owned();
+        Ok(SqliteDatabase {
+            connection: open(&path)?,
+            path,
+        })
+    }
+
+    /// The names of the tables of the database, without the internal tables
+    /// of SQLite.
+    pub fn tables(&self) -> Result<Vec<String>> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT name FROM sqlite_schema \
+                 WHERE type = 'table' AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' ORDER BY name",
+            )
+            .map_err(read_error)?;
+        let tables = statement
+            .query_map([], |row| row.get(0))
+            .map_err(read_error)?
+            .collect::<rusqlite::Result<_>>()
+            .map_err(read_error)?;
+        Ok(tables)
+    }
+
+    /// The columns of `table` with the SQL Server types that hold their
+    /// values, as [`sql_type`] maps them. The schema is that of a SQL Server
+    /// table of the same name, so [`TableSchema::create_table_sql`] writes
+    /// the statement that creates it.
+    pub fn schema(&self, table: &str) -> Result<TableSchema> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT name, type, \"notnull\", pk FROM pragma_table_info(?1) ORDER BY cid")
+            .map_err(read_error)?;
+        let columns: Vec<Column> = statement
+            .query_map([table], |row| {
+                let declared: String = row.get(1)?;
+                let not_null: bool = row.get(2)?;
+                let primary_key: i64 = row.get(3)?;
+                Ok(Column {
+                    name: row.get(0)?,
+                    sql_type: sql_type(&declared),
+                    // Keys of SQL Server are never NULL.
+                    nullable: !not_null && primary_key == 0,
+                    identity: false,
+                    computed: false,
+                })
+            })
+            .map_err(read_error)?
+            .collect::<rusqlite::Result<_>>()
+            .map_err(read_error)?;
+
+        if columns.is_empty() {
+            return Err(BulkError::Config(format!("table {} does not exist", table)));
+        }
+
+        Ok(TableSchema {
+            table: quote_name(table),
+            columns,
+        })
+    }
+
+    /// Reads the rows of `table`.
+    pub fn source(&self, table: &str) -> Result<SqliteSource> {
+        let schema = self.schema(table)?;
+        let fields: Vec<String> = schema.columns.into_iter().map(|c| c.name).collect();
+        let query = format!(
+            "SELECT {} FROM {}",
+            fields
+                .iter()
+                .map(|field| quote_identifier(field))
+                .collect::<Vec<_>>()
+                .join(", "),
+            quote_identifier(table)
+        );
+
+        let (sender, rows) = mpsc::sync_channel(READ_AHEAD);
+        let path = self.path.clone();
+        thread::spawn(move || {
+            if let Err(error) = read_rows(&path, &query, &sender) {
+                // The source may be gone already.
+                let _ = sender.send(Err(error));
+            }
+        });
+
+        Ok(SqliteSource { fields, rows })
+    }
+}
+
+/// Reads the rows of a table of a SQLite database. The fields are the
+/// columns of the table.
+///
+/// Values are read with their storage class: integers and reals as numbers,
+/// text as text and blobs as bytes. Dates, which SQLite stores as text in ISO
+/// 8601 form, are parsed for date and time columns like text from a CSV
+/// file. Rows are read on a thread of their own, a few ahead of the load.
+pub struct SqliteSource {
+    fields: Vec<String>,
+    rows: Receiver<Result<Vec<Value>>>,
+}
+
+impl Source for SqliteSource {
+    fn fields(&self) -> Option<&[String]> {
+        Some(&self.fields)
+    }
+
+    fn next_record(&mut self) -> Result<Option<Vec<Value>>> {
+        // The reading thread hangs up at the end of the table.
+        self.rows.recv().ok().transpose()
+    }
+}
+
+/// The SQL Server type of a column of SQLite declared as `declared`.
+///
+/// Common type names keep their meaning, such as `varchar(20)`,
+/// `decimal(10,2)`, `boolean`, `date` or `datetime`. Other names are mapped
+/// by the affinity SQLite gives them: `INTEGER` to `bigint`, as any column
+/// with it, such as `int` or `smallint`, holds 64-bit integers, `TEXT` to
+/// `nvarchar(max)`, `BLOB` to `varbinary(max)`, and `REAL` and `NUMERIC` to
+/// `float`. Columns without a type may hold values of any kind and are
+/// `nvarchar(max)`. Text of SQLite is Unicode, so it is stored in `nvarchar`
+/// columns.
+pub fn sql_type(declared: &str) -> SqlType {
+    let declared = declared.trim().to_uppercase();
+    let (name, arguments) = match declared.split_once('(') {
+        Some((name, arguments)) => (name.trim(), arguments.trim_end_matches(')')),
+        None => (declared.as_str(), ""),
+    };
+    let arguments: Vec<u16> = arguments
+        .split(',')
+        .filter_map(|argument| argument.trim().parse().ok())
+        .collect();
+    let length = arguments
+        .first()
+        .copied()
+        .filter(|len| (1..=4000).contains(len));
+
+    match name {
+        "BOOLEAN" | "BOOL" | "BIT" => SqlType::Bit,
+        "DECIMAL" | "NUMERIC" if !arguments.is_empty() => {
+            let precision = arguments[0].clamp(1, 38) as u8;
+            let scale = arguments
+                .get(1)
+                .map_or(0, |scale| (*scale as u8).min(precision));
+            SqlType::Decimal { precision, scale }
+        }
+        "CHAR" | "CHARACTER" | "NCHAR" | "NATIVE CHARACTER" | "VARCHAR" | "NVARCHAR"
+        | "VARYING CHARACTER" => SqlType::NVarChar(length),
+        "DATE" => SqlType::Date,
+        "TIME" => SqlType::Time(7),
+        "DATETIME" | "TIMESTAMP" => SqlType::DateTime2(7),
+        "UUID" | "GUID" | "UNIQUEIDENTIFIER" => SqlType::UniqueIdentifier,
+        // The rules of https://www.sqlite.org/datatype3.html#determination_of_column_affinity
+        _ if name.contains("INT") => SqlType::BigInt,
+        _ if name.is_empty() || ["CHAR", "CLOB", "TEXT"].iter().any(|s| name.contains(s)) => {
+            SqlType::NVarChar(None)
+        }
+        _ if name.contains("BLOB") => SqlType::VarBinary(None),
+        _ => SqlType::Float,
+    }
+}
+
+fn open(path: &Path) -> Result<Connection> {
+    Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(read_error)
+}
+
+fn read_rows(path: &Path, query: &str, sender: &SyncSender<Result<Vec<Value>>>) -> Result<()> {
+    let connection = open(path)?;
+    let mut statement = connection.prepare(query).map_err(read_error)?;
+    let columns = statement.column_count();
+    let mut rows = statement.query([]).map_err(read_error)?;
+
+    while let Some(row) = rows.next().map_err(read_error)? {
+        let values = (0..columns)
+            .map(|i| row.get_ref(i).map(sqlite_value))
+            .collect::<rusqlite::Result<_>>()
+            .map_err(read_error)?;
+        if sender.send(Ok(values)).is_err() {
+            // The load stopped reading.
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn sqlite_value(value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(value) => Value::Int(value),
+        ValueRef::Real(value) => Value::Float(value),
+        ValueRef::Text(text) => Value::Text(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(bytes) => Value::Bytes(bytes.to_vec()),
+    }
+}
+
+/// `name` as an identifier of SQLite, as in `"order details"`.
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Failing to read the database ends the load; it is no error of a record.
+fn read_error(error: rusqlite::Error) -> BulkError {
+    io::Error::new(io::ErrorKind::InvalidData, error).into()
+}
@@ -1,666 +1,805 @@
-use anyhow::Ok;
-use async_std::net::TcpStream;
-use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use crate::{
+    blocking,
+    bulk::{BulkLoader, BulkOptions},
+    connection::{connect, ConnectionConfig},
+    error::{Result, ResultExt},
+    profile::connection_config_from_env,
+    secret::password_from_env,
+    tds::{adapt_row, to_datetime, Param},
+};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
 use once_cell::sync::Lazy;
-use std::{env, borrow::Cow};
+use std::{borrow::Cow, env};
 use tiberius::{
-    Client, ColumnData,Config, IntoRow, 
     numeric::{BigDecimal, BigInt},
-    ToSql, TokenRow,
+    ColumnData, IntoRow, IntoSql, TokenRow,
 };
 
 static SQL_AUTH_CONN_STR_PORT: Lazy<String> = Lazy::new(|| {
     env::var("SQL_AUTH_CONN_STRING").unwrap_or_else(|_| {
-        "server=tcp:localhost\\sql2022d,22828;database=DestinationDB;user=developer;password=developer;TrustServerCertificate=true".to_owned()
+        "server=tcp:localhost\\sql2022d;database=DestinationDB;user=developer;TrustServerCertificate=true".to_owned()
     })
 });
 
-/// Connect to a SQL Server instance using the hostname and port number.
-pub async fn connect_through_port() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-
-    // Create a `TCPStream` from the `async-std` library with
-    // a address that contains the hostname/IP and port number.
-    let tcp = TcpStream::connect(config.get_addr()).await?;
+/// The connection profile selected with `BULK_PROFILE`, or the connection
+/// string in `SQL_AUTH_CONN_STRING` when no profile is configured. The
+/// password of the connection string can be kept out of it in
+/// `SQL_AUTH_PASSWORD`, `SQL_AUTH_PASSWORD_FILE` or `SQL_AUTH_PASSWORD_COMMAND`.
+pub fn connection_config() -> Result<ConnectionConfig> {
+    if let Some(config) = connection_config_from_env()? {
+        return Ok(config);
+    }
 
-    tcp.set_nodelay(true)?;
+    match password_from_env()? {
+        Some(password) => {
+            ConnectionConfig::from_ado_string_with_password(&SQL_AUTH_CONN_STR_PORT, &password)
+        }
+        None => ConnectionConfig::from_ado_string(&SQL_AUTH_CONN_STR_PORT),
+    }
+}
 
-    // Connect to SQL Server
-    let client = Client::connect(config, tcp).await?;
-    println!("Successfully connected to server.");
+/// Connect to a SQL Server instance using the hostname and port number.
+///
+/// When the connection string names an instance without a port, the port is
+/// asked to the SQL Server Browser service of the host.
+pub async fn connect_through_port(config: &ConnectionConfig) -> Result<()> {
+    // Connect to SQL Server and detect the TDS version negotiated
+    // with the server.
+    let (client, server) = connect(config).await?;
+    println!(
+        "Successfully connected to server {} using {}.",
+        server.product_version, server.tds_version
+    );
 
     client.close().await?;
 
     Ok(())
 }
 
-pub async fn bulk_insert_bit_column() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
-
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_bit").await?;
+pub async fn bulk_insert_bit_column(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
 
     println!("Start loading data");
 
     for i in 0..100000 {
-        let bit = match i % 2 {
-            0 => Some(false),
-            _ => Some(true),
-        };
-        let new_row = (bit).into_row();
-
-        result.send(new_row).await?;
+        result
+            .send(bit_row(i))
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
     }
 
-    let res = result.finalize().await?;
+    let res = result.finalize().await.in_table(table)?;
     println!("Result: {:?}", res);
 
     Ok(())
 }
 
-pub async fn bulk_insert_float_real_column() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
+/// The row loaded at position `i` of the bit table.
+pub fn bit_row(i: i32) -> TokenRow<'static> {
+    let bit = match i % 2 {
+        0 => Some(false),
+        _ => Some(true),
+    };
+    (bit).into_row()
+}
 
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_float").await?;
+pub async fn bulk_insert_float_real_column(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
 
     println!("Start loading data");
 
     for i in 0..1000000 {
-        // This data type of mapped to a `float` column
-        let default_float_number = Some(i as f64);
-
-        // This data type of mapped to a `float(n)` column
-        // where `n` is the number of digits.
-        let precision_float_number = Some(i as f32);
-
-        // The `real` data type of SQL Server is not supported yet
-
-        let new_row = (default_float_number, precision_float_number).into_row();
-
-        result.send(new_row).await?;
+        result
+            .send(float_row(i))
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
     }
 
-    let res = result.finalize().await?;
+    let res = result.finalize().await.in_table(table)?;
     println!("Result: {:?}", res);
 
     Ok(())
 }
 
-pub async fn bulk_insert_integer_column() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
+/// The row loaded at position `i` of the float table.
+pub fn float_row(i: i32) -> TokenRow<'static> {
+    // This data type of mapped to a `float` column
+    let default_float_number = Some(i as f64);
 
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_integer").await?;
+    // This data type of mapped to a `float(n)` column
+    // where `n` is the number of digits.
+    let precision_float_number = Some(i as f32);
 
-    println!("Start loading data");
+    // The `real` data type of SQL Server is not supported yet
 
-    for i in 0..1000000 {
-        let tinyint_number = Some(i as u8);
-        let smallint_number = Some(i as i16);
-        let int_number = Some(i as i32);
-        let bigint_number = Some(i as i64);
+    (default_float_number, precision_float_number).into_row()
+}
+
+pub async fn bulk_insert_integer_column(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
 
-        let new_row = (tinyint_number, smallint_number, int_number, bigint_number).into_row();
+    println!("Start loading data");
 
-        result.send(new_row).await?;
+    for i in 0..1000000 {
+        result
+            .send(integer_row(i))
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
     }
 
-    let res = result.finalize().await?;
+    let res = result.finalize().await.in_table(table)?;
     println!("Result: {:?}", res);
 
     Ok(())
 }
 
-#[cfg(feature = "use_tds73")]
-pub async fn bulk_insert_decimal_column() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
+/// The row loaded at position `i` of the integer table.
+pub fn integer_row(i: i32) -> TokenRow<'static> {
+    let tinyint_number = Some(i as u8);
+    let smallint_number = Some(i as i16);
+    let int_number = Some(i);
+    let bigint_number = Some(i as i64);
 
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_decimal").await?;
+    (tinyint_number, smallint_number, int_number, bigint_number).into_row()
+}
+
+/// Load the integer table in batches with `BulkLoader`, each batch
+/// committed on its own.
+pub async fn bulk_insert_integer_column_in_batches(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let options = BulkOptions {
+        batch_size: 50000,
+        ..Default::default()
+    };
+    let mut loader = BulkLoader::connect(config.clone(), table, options).await?;
 
     println!("Start loading data");
 
     for i in 0..1000000 {
-        let numeric = BigDecimal::from(i);
-        let decimal = BigDecimal::from(i);
+        loader.send_row(integer_row(i)).await?;
+    }
 
-        let numeric_number = Some(numeric);
-        let decimal_number = Some(decimal);
+    let report = loader.finish().await?;
+    println!("Result: {}", report);
+
+    Ok(())
+}
 
-        // `numeric(p,s)` and `decimal(p,s)` with specific precision
-        // and scale are not supported yet
+/// Loads the same rows as [`bulk_insert_integer_column_in_batches`] without
+/// async code.
+pub fn bulk_insert_integer_column_blocking(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let options = BulkOptions {
+        batch_size: 50000,
+        ..Default::default()
+    };
+    let mut loader = blocking::BulkLoader::connect(config.clone(), table, options)?;
 
-        let new_row = (numeric_number, decimal_number).into_row();
+    println!("Start loading data");
 
-        result.send(new_row).await?;
+    for i in 0..1000000 {
+        loader.send_row(integer_row(i))?;
     }
 
-    let res = result.finalize().await?;
-    println!("Result: {:?}", res);
+    let report = loader.finish()?;
+    println!("Result: {}", report);
 
     Ok(())
 }
 
-pub async fn bulk_insert_char_column() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
-
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_string").await?;
+pub async fn bulk_insert_decimal_column(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
 
     println!("Start loading data");
 
     for i in 0..1000000 {
-        let char = Cow::from(format!("Hard disk : {0}", i));
-        let nchar = Cow::from(format!("CD 💿: {0}", i));
-        let varchar = Cow::from("Floppy: ".to_string() + i.to_string().as_str());
-        let varchar_max = Cow::from(format!("Floppy {0} is no longer used nowadays.", i));
-        let nvarchar = Cow::from(format!("SSD 🗄️: {0}", i));
-        let nvarchar_max = Cow::from(format!(
-            "Solid State Drive {0} is faster than hard disk 💽 {1}",
-            i, i
-        ));
-
-        let char_string = Some(char);
-        let nchar_string = Some(nchar);
-        let varchar_string = Some(varchar);
-        let varchar_max_string = Some(varchar_max);
-        let nvarchar_string = Some(nvarchar);
-        let nvarchar_max_string = Some(nvarchar_max);
-
-        let new_row = (
-            char_string,
-            nchar_string,
-            varchar_string,
-            varchar_max_string,
-            nvarchar_string,
-            nvarchar_max_string,
-        )
-            .into_row();
-
-        result.send(new_row).await?;
+        result
+            .send(decimal_row(i))
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
     }
 
-    let res = result.finalize().await?;
+    let res = result.finalize().await.in_table(table)?;
     println!("Result: {:?}", res);
 
     Ok(())
 }
 
-pub async fn bulk_insert_binary_column() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
+/// The row loaded at position `i` of the decimal table.
+pub fn decimal_row(i: i32) -> TokenRow<'static> {
+    let numeric = BigDecimal::from(i);
+    let decimal = BigDecimal::from(i);
+
+    let numeric_number = Some(numeric);
+    let decimal_number = Some(decimal);
+
+    // `numeric(p,s)` and `decimal(p,s)` with specific precision
+    // and scale are not supported yet
 
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_binary").await?;
+    (numeric_number, decimal_number).into_row()
+}
+
+pub async fn bulk_insert_char_column(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
 
     println!("Start loading data");
 
     for i in 0..1000000 {
-        let binary: &[u8] = &[10];
-        let binary_specific: &[u8] = &[10, 20];
-        let varbinary = "T".as_bytes().to_owned();
-        let varbinary_specific = format!("There is varbinary data in the row: {0}", i)
-            .as_bytes()
-            .to_owned();
-        let varbinary_max = (i as i32).to_le_bytes().to_vec();
-
-        let binary_data = Some(binary);
-        let binary_specific_data = Some(binary_specific);
-        let varbinary_data = Some(varbinary);
-        let varbinary_specific_data = Some(varbinary_specific);
-        let varbinary_max_data = Some(varbinary_max);
-
-        let new_row = (
-            binary_data,
-            binary_specific_data,
-            varbinary_data,
-            varbinary_specific_data,
-            varbinary_max_data,
-        )
-            .into_row();
-
-        result.send(new_row).await?;
+        result
+            .send(char_row(i))
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
     }
 
-    let res = result.finalize().await?;
+    let res = result.finalize().await.in_table(table)?;
     println!("Result: {:?}", res);
 
     Ok(())
 }
 
-pub async fn bulk_insert_uniqueidentifier_column() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
+/// The row loaded at position `i` of the string table. `char` and `nchar`
+/// values come back padded with spaces.
+pub fn char_row(i: i32) -> TokenRow<'static> {
+    let char = Cow::from(format!("Hard disk : {0}", i));
+    let nchar = Cow::from(format!("CD 💿: {0}", i));
+    let varchar = Cow::from("Floppy: ".to_string() + i.to_string().as_str());
+    let varchar_max = Cow::from(format!("Floppy {0} is no longer used nowadays.", i));
+    let nvarchar = Cow::from(format!("SSD 🗄️: {0}", i));
+    let nvarchar_max = Cow::from(format!(
+        "Solid State Drive {0} is faster than hard disk 💽 {1}",
+        i, i
+    ));
+
+    let char_string = Some(char);
+    let nchar_string = Some(nchar);
+    let varchar_string = Some(varchar);
+    let varchar_max_string = Some(varchar_max);
+    let nvarchar_string = Some(nvarchar);
+    let nvarchar_max_string = Some(nvarchar_max);
+
+    (
+        char_string,
+        nchar_string,
+        varchar_string,
+        varchar_max_string,
+        nvarchar_string,
+        nvarchar_max_string,
+    )
+        .into_row()
+}
 
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_guid").await?;
+pub async fn bulk_insert_binary_column(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
 
     println!("Start loading data");
 
-    for i in 0..1000000 {
-        let seed = 0xbf3ba35b44de49dc86eaf1b5c69bcc8e ^ i;
-        let new_uuid = tiberius::Uuid::from_u128(seed);
+    for i in 0..1000000i32 {
+        result
+            .send(binary_row(i))
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
+    }
+
+    let res = result.finalize().await.in_table(table)?;
+    println!("Result: {:?}", res);
 
-        // You can also use other functions like such as `parse_str` like this:
-        // ```
-        // tiberius::Uuid::parse_str("ad93ed82e692479785198ff01e32d9ae").unwrap();
-        // ```
-        // or `from_u128` like this:
-        // ```
-        // let number = 561de8c7a4e611ee8728c4b53ac415ddu128;
-        // tiberius::Uuid::from_u128(number);
-        // ```
+    Ok(())
+}
 
-        let uuid_value = Some(new_uuid);
+/// The row loaded at position `i` of the binary table. `binary` values come
+/// back padded with zeros.
+pub fn binary_row(i: i32) -> TokenRow<'static> {
+    let binary: &[u8] = &[10];
+    let binary_specific: &[u8] = &[10, 20];
+    let varbinary = "T".as_bytes().to_owned();
+    let varbinary_specific = format!("There is varbinary data in the row: {0}", i)
+        .as_bytes()
+        .to_owned();
+    let varbinary_max = i.to_le_bytes().to_vec();
+
+    let binary_data = Some(binary);
+    let binary_specific_data = Some(binary_specific);
+    let varbinary_data = Some(varbinary);
+    let varbinary_specific_data = Some(varbinary_specific);
+    let varbinary_max_data = Some(varbinary_max);
+
+    (
+        binary_data,
+        binary_specific_data,
+        varbinary_data,
+        varbinary_specific_data,
+        varbinary_max_data,
+    )
+        .into_row()
+}
 
-        let new_row = (uuid_value).into_row();
+pub async fn bulk_insert_uniqueidentifier_column(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
+
+    println!("Start loading data");
 
-        result.send(new_row).await?;
+    for i in 0..1000000 {
+        result
+            .send(guid_row(i))
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
     }
 
-    let res = result.finalize().await?;
+    let res = result.finalize().await.in_table(table)?;
     println!("Result: {:?}", res);
 
     Ok(())
 }
 
-pub async fn insert_money() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
+/// The row loaded at position `i` of the uniqueidentifier table.
+pub fn guid_row(i: u128) -> TokenRow<'static> {
+    let seed = 0xbf3ba35b44de49dc86eaf1b5c69bcc8e ^ i;
+    let new_uuid = tiberius::Uuid::from_u128(seed);
+
+    // You can also use other functions like such as `parse_str` like this:
+    // ```
+    // tiberius::Uuid::parse_str("ad93ed82e692479785198ff01e32d9ae").unwrap();
+    // ```
+    // or `from_u128` like this:
+    // ```
+    // let number = 561de8c7a4e611ee8728c4b53ac415ddu128;
+    // tiberius::Uuid::from_u128(number);
+    // ```
 
-    let tcp = TcpStream::connect(config.get_addr()).await?;
+    let uuid_value = Some(new_uuid);
 
-    tcp.set_nodelay(true)?;
+    (uuid_value).into_row()
+}
 
-    let mut client = Client::connect(config, tcp).await?;
+pub async fn insert_money(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
 
+    let (smallmoney, money) = money_values();
     let result = client
         .execute(
-            "INSERT INTO random_money (a_smallmoney_column, a_money_column) VALUES (@P1, @P2)",
-            &[&1316i32, &701321588.2505f64],
+            format!(
+                "INSERT INTO {} (a_smallmoney_column, a_money_column) VALUES (@P1, @P2)",
+                table
+            ),
+            &[&smallmoney, &money],
         )
         .await?;
 
     println!("Rows affected: {}", result.total());
+
     client.close().await?;
 
     Ok(())
 }
 
-pub async fn insert_text_image() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-
-    let tcp = TcpStream::connect(config.get_addr()).await?;
+/// The values [`insert_money`] inserts.
+pub fn money_values() -> (i32, f64) {
+    (1316, 701321588.2505)
+}
 
-    tcp.set_nodelay(true)?;
+pub async fn insert_text_image(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
 
-    let mut client = Client::connect(config, tcp).await?;
-
-    let text = Cow::from(format!("There is text data in the row"));
-    let ntext = Cow::from(format!("There is ntext data in the row"));
-    let i: &[u8] = &[1, 5];
+    let (text, ntext, i) = text_image_values();
 
     let result = client
         .execute(
-            "INSERT INTO random_text_image (a_text_column, a_ntext_column, a_image_column)
+            format!(
+                "INSERT INTO {} (a_text_column, a_ntext_column, a_image_column)
          VALUES (@P1, @P2, @P3)",
+                table
+            ),
             &[&text, &ntext, &i],
         )
         .await?;
 
     println!("Rows affected: {}", result.total());
+
     client.close().await?;
 
     Ok(())
 }
 
-pub async fn insert_xml() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-
-    let tcp = TcpStream::connect(config.get_addr()).await?;
+/// The values [`insert_text_image`] inserts.
+pub fn text_image_values() -> (&'static str, &'static str, &'static [u8]) {
+    let text = "There is text data in the row";
+    let ntext = "There is ntext data in the row";
+    let i: &[u8] = &[1, 5];
 
-    tcp.set_nodelay(true)?;
+    (text, ntext, i)
+}
 
-    let mut client = Client::connect(config, tcp).await?;
+pub async fn insert_xml(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
 
-    let xml = tiberius::xml::XmlData::new(
-        r#"<calculator>
-    <hex base="16">163</hex>
-    <dec base="10">355</dec>
-    <oct base="8">543</oct>
-    <bin base="2">0001 0110 0011</bin>
-</calculator>"#,
-    );
+    let xml = xml_value();
 
     let result = client
         .execute(
-            "INSERT INTO random_xml (a_xml_column) VALUES (@P1)",
+            format!("INSERT INTO {} (a_xml_column) VALUES (@P1)", table),
             &[&xml],
         )
         .await?;
 
     println!("Rows affected: {}", result.total());
+
     client.close().await?;
 
     Ok(())
 }
 
-pub async fn bulk_insert_datetime_tds72() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
+/// The value [`insert_xml`] inserts. SQL Server drops the whitespace
+/// between the tags.
+pub fn xml_value() -> tiberius::xml::XmlData {
+    tiberius::xml::XmlData::new(
+        r#"<calculator>
+    <hex base="16">163</hex>
+    <dec base="10">355</dec>
+    <oct base="8">543</oct>
+    <bin base="2">0001 0110 0011</bin>
+</calculator>"#,
+    )
+}
 
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_datetime").await?;
+pub async fn bulk_insert_datetime_tds72(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
 
     println!("Start loading data");
 
     for i in 0..1000000 {
-        // Create a date struct using the Chrono library
-        let date = NaiveDate::from_ymd_opt(2022, 8, 1);
-        let time = NaiveTime::from_num_seconds_from_midnight_opt(i % 60, i);
-
-        let mut new_date: Option<NaiveDateTime> = None;
-        if let Some(dt) = date {
-            if let Some(tm) = time {
-                new_date = Some(NaiveDateTime::new(dt, tm));
-            }
-        }
-
-        let new_row = (new_date).into_row();
-
-        result.send(new_row).await?;
+        // A `datetime` column only accepts `datetime` values.
+        let mut new_row = TokenRow::with_capacity(1);
+        new_row.push(to_datetime(datetime_value(i).into_sql()));
+
+        result
+            .send(new_row)
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
     }
 
-    let res = result.finalize().await?;
+    let res = result.finalize().await.in_table(table)?;
     println!("Result: {:?}", res);
 
     Ok(())
 }
 
-pub async fn bulk_insert_datetime2_tds72() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
+/// The value loaded at position `i` of the datetime table. Its nanoseconds
+/// are rounded to 1/300 of a second.
+pub fn datetime_value(i: u32) -> Option<NaiveDateTime> {
+    // Create a date struct using the Chrono library
+    let date = NaiveDate::from_ymd_opt(2022, 8, 1);
+    let time = NaiveTime::from_num_seconds_from_midnight_opt(i % 60, i);
+
+    let mut new_date: Option<NaiveDateTime> = None;
+    if let Some(dt) = date {
+        if let Some(tm) = time {
+            new_date = Some(NaiveDateTime::new(dt, tm));
+        }
+    }
+    new_date
+}
 
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_datetime2").await?;
+pub async fn bulk_insert_datetime2_tds72(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
 
     println!("Start loading data");
 
     for i in 0..1000000 {
-        // Create a date struct using the Chrono library
-        let date = NaiveDate::from_ymd_opt(2022, 8, 1);
-        let time = NaiveTime::from_num_seconds_from_midnight_opt(i % 60, 0);
-
-        let mut new_date: Option<NaiveDateTime> = None;
-        if let Some(dt) = date {
-            if let Some(tm) = time {
-                new_date = Some(NaiveDateTime::new(dt, tm));
-            }
-        }
-
-        let new_row = (new_date).into_row();
-
-        result.send(new_row).await?;
+        // Sent as `datetime`, which TDS 7.2 has, and widened to `datetime2`
+        // by the server.
+        let mut new_row = TokenRow::with_capacity(1);
+        new_row.push(to_datetime(datetime2_value(i).into_sql()));
+
+        result
+            .send(new_row)
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
     }
 
-    let res = result.finalize().await?;
+    let res = result.finalize().await.in_table(table)?;
     println!("Result: {:?}", res);
 
     Ok(())
 }
 
-#[cfg(feature = "use_tds73")]
-pub async fn bulk_insert_datetimeoffset_tds73() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
-
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_datetimeoffset").await?;
-
-    println!("Start loading data");
-
-    for _ in 0..1000000 {
-        let dt = chrono::Utc::now();
+/// The value loaded at position `i` of the datetime2 table.
+pub fn datetime2_value(i: u32) -> Option<NaiveDateTime> {
+    // Create a date struct using the Chrono library
+    let date = NaiveDate::from_ymd_opt(2022, 8, 1);
+    let time = NaiveTime::from_num_seconds_from_midnight_opt(i % 60, 0);
 
-        // Get components
-        let naive_utc = dt.naive_utc();
-        let offset = dt.offset().clone();
+    let mut new_date: Option<NaiveDateTime> = None;
+    if let Some(dt) = date {
+        if let Some(tm) = time {
+            new_date = Some(NaiveDateTime::new(dt, tm));
+        }
+    }
+    new_date
+}
 
-        let dt_new = chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(naive_utc, offset);
+pub async fn bulk_insert_datetimeoffset_tds73(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, server) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
 
-        let new_row = (Some(dt_new)).into_row();
+    println!("Start loading data");
 
-        result.send(new_row).await?;
+    for i in 0..1000000 {
+        result
+            .send(adapt_row(datetimeoffset_row(i), &server))
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
     }
 
-    let res = result.finalize().await?;
+    let res = result.finalize().await.in_table(table)?;
     println!("Result: {:?}", res);
 
     Ok(())
 }
 
-pub async fn bulk_insert_smalldatetime_tds72() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
+/// The row loaded at position `i` of the datetimeoffset table, `i`
+/// microseconds after 2023-10-01.
+pub fn datetimeoffset_row(i: i64) -> TokenRow<'static> {
+    let start = DateTime::from_timestamp(1_696_118_400, 0).unwrap();
+    let dt = start + chrono::Duration::microseconds(i);
 
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_smalldatetime").await?;
+    // Get components
+    let naive_utc = dt.naive_utc();
+    let offset = *dt.offset();
 
-    println!("Start loading data");
+    // `DateTime<Utc>` is sent as `datetime2`, which a `datetimeoffset`
+    // column does not take.
+    let dt_new = chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(naive_utc, offset)
+        .fixed_offset();
 
-    for i in 0..10000 {
-        // Create a date struct using the Chrono library
-        let date = NaiveDate::from_ymd_opt(2023, 10, 1);
-        let time = NaiveTime::from_num_seconds_from_midnight_opt(i % 60, 0);
-
-        let mut new_date: Option<NaiveDateTime> = None;
-        if let Some(dt) = date {
-            if let Some(tm) = time {
-                new_date = Some(NaiveDateTime::new(dt, tm));
-            }
-        }
+    (Some(dt_new)).into_row()
+}
+
+pub async fn bulk_insert_smalldatetime_tds72(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, _) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
 
-        let new_row = (new_date).into_row();
+    println!("Start loading data");
 
-        result.send(new_row).await?;
+    for i in 0..10000 {
+        // Sent as `datetime`, which the server rounds to the minute of a
+        // `smalldatetime`.
+        let mut new_row = TokenRow::with_capacity(1);
+        new_row.push(to_datetime(smalldatetime_value(i).into_sql()));
+
+        result
+            .send(new_row)
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
     }
 
-    let res = result.finalize().await?;
+    let res = result.finalize().await.in_table(table)?;
     println!("Result: {:?}", res);
 
     Ok(())
 }
 
-#[cfg(feature = "use_tds73")]
-pub async fn insert_date() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-
-    tcp.set_nodelay(true)?;
+/// The value loaded at position `i` of the smalldatetime table, which is
+/// rounded to the minute.
+pub fn smalldatetime_value(i: u32) -> Option<NaiveDateTime> {
+    // Create a date struct using the Chrono library
+    let date = NaiveDate::from_ymd_opt(2023, 10, 1);
+    let time = NaiveTime::from_num_seconds_from_midnight_opt(i % 60, 0);
+
+    let mut new_date: Option<NaiveDateTime> = None;
+    if let Some(dt) = date {
+        if let Some(tm) = time {
+            new_date = Some(NaiveDateTime::new(dt, tm));
+        }
+    }
+    new_date
+}
 
-    let mut client = Client::connect(config, tcp).await?;
+pub async fn insert_date(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, server) = connect(config).await?;
 
-    let date = NaiveDate::from_ymd_opt(2023, 06, 30);
+    let date = date_value();
 
     let result = client
         .execute(
-            "INSERT INTO random_date (a_date_column) VALUES (@P1)",
-            &[&date.unwrap()],
+            format!("INSERT INTO {} (a_date_column) VALUES (@P1)", table),
+            &[&Param::new(date.unwrap().into_sql(), &server)],
         )
         .await?;
 
     println!("Rows affected: {}", result.total());
+
     client.close().await?;
 
     Ok(())
 }
 
-#[cfg(feature = "use_tds73")]
-pub async fn bulk_insert_time_tds73() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
+/// The value [`insert_date`] inserts.
+pub fn date_value() -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(2023, 6, 30)
+}
 
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_time").await?;
+pub async fn bulk_insert_time_tds73(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, server) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
 
     println!("Start loading data");
 
     for i in 0..1000000 {
-        let time = chrono::NaiveTime::from_num_seconds_from_midnight_opt(i % 60, 0);
-
-        let new_row = (time).into_row();
-
-        result.send(new_row).await?;
+        result
+            .send(adapt_row(time_row(i), &server))
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
     }
 
-    let res = result.finalize().await?;
+    let res = result.finalize().await.in_table(table)?;
     println!("Result: {:?}", res);
 
     Ok(())
 }
 
-pub async fn insert_datetime_datetime2() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-
-    let tcp = TcpStream::connect(config.get_addr()).await?;
+/// The row loaded at position `i` of the time table.
+pub fn time_row(i: u32) -> TokenRow<'static> {
+    let time = chrono::NaiveTime::from_num_seconds_from_midnight_opt(i % 60, 0);
 
-    tcp.set_nodelay(true)?;
+    (time).into_row()
+}
 
-    let mut client = Client::connect(config, tcp).await?;
+pub async fn insert_datetime_datetime2(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, server) = connect(config).await?;
 
-    let date = NaiveDateTime::from_timestamp_millis(126000);
-    let reg_date = NaiveDateTime::from_timestamp_millis(231688);
+    let (date, reg_date) = datetime_datetime2_values();
 
     let result = client
         .execute(
-            "INSERT INTO random_datetime_datetime2(a_datetime_column, a_datetime2_column)
+            format!(
+                "INSERT INTO {}(a_datetime_column, a_datetime2_column)
          VALUES (@P1, @P2)",
-            &[&date.unwrap(), &reg_date.unwrap()],
+                table
+            ),
+            &[
+                &Param::new(date.unwrap().into_sql(), &server),
+                &Param::new(reg_date.unwrap().into_sql(), &server),
+            ],
         )
         .await?;
 
     println!("Rows affected: {}", result.total());
+
     client.close().await?;
 
     Ok(())
 }
 
-#[cfg(feature = "use_tds73")]
-pub async fn bulk_insert_more_than_10_columns() -> anyhow::Result<()> {
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
-
-    let mut client = Client::connect(config, tcp).await?;
-    let mut result = client.bulk_insert("random_data_several_columns").await?;
-
-    println!("Start loading data");
+/// The values [`insert_datetime_datetime2`] inserts. The `datetime` column
+/// keeps 1/300 of a second.
+pub fn datetime_datetime2_values() -> (Option<NaiveDateTime>, Option<NaiveDateTime>) {
+    let date = DateTime::from_timestamp_millis(126000).map(|dt| dt.naive_utc());
+    let reg_date = DateTime::from_timestamp_millis(231688).map(|dt| dt.naive_utc());
 
-    let mut temporal_i: i32 = 0;
-    let decimal_value = BigDecimal::from(29540577);
-    let numeric_value = BigDecimal::from(30024);
+    (date, reg_date)
+}
 
-    let dt = chrono::Utc::now();
-    let naive_utc = dt.naive_utc();
-    let offset = dt.offset().clone();
+pub async fn bulk_insert_more_than_10_columns(config: &ConnectionConfig, table: &str) -> Result<()> {
+    let (mut client, server) = connect(config).await?;
+    let mut result = client.bulk_insert(table).await.in_table(table)?;
 
-    let today = chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(naive_utc, offset);
+    println!("Start loading data");
 
     for i in 0..1000000 {
-        let mut row = TokenRow::with_capacity(15);
-        temporal_i = i.clone();
-        row.push(ColumnData::Bit(Some(temporal_i % 3 == 0)));
-        row.push(ColumnData::F64(Some((temporal_i + 1) as f64)));
-        row.push(ColumnData::U8(Some(temporal_i as u8)));
-        row.push(ColumnData::I16(Some(
-            (temporal_i - temporal_i + 65535) as i16,
-        )));
-        row.push(ColumnData::I32(Some((temporal_i + 4) as i32)));
-        row.push(ColumnData::I64(Some((temporal_i + 5) as i64)));
-
-        row.push(numeric_value.to_sql());
-        row.push(decimal_value.to_sql());
-
-        let a_char_value = format!("{0} strawberry", i.to_owned());
-        row.push(ColumnData::String(Some(Cow::from(a_char_value))));
-
-        let a_nchar_value = format!("{0} blueberry 🫐", i.to_owned());
-        row.push(ColumnData::String(Some(Cow::from(a_nchar_value))));
-
-        let a_varchar_value = format!("{0} kiwi", i.to_owned());
-        row.push(ColumnData::String(Some(Cow::from(a_varchar_value))));
-
-        let a_nvarchar_value = format!("{0} tangerine 🍊", i.to_owned());
-        row.push(ColumnData::String(Some(Cow::from(a_nvarchar_value))));
-
-        row.push(ColumnData::Binary(Some(Cow::from(
-            (temporal_i as i32).to_le_bytes().to_vec(),
-        ))));
-        row.push(ColumnData::Binary(Some(Cow::from(
-            (temporal_i as i32).to_le_bytes().to_vec(),
-        ))));
-        row.push(ColumnData::Guid(Some(tiberius::Uuid::from_u128(
-            temporal_i as u128,
-        ))));
-
-        row.push(today.to_sql());
-
-        result.send(row).await?;
+        result
+            .send(adapt_row(several_columns_row(i), &server))
+            .await
+            .in_table(table)
+            .at_row(i as u64)?;
     }
 
-    let res = result.finalize().await?;
+    let res = result.finalize().await.in_table(table)?;
     println!("Result: {:?}", res);
 
     Ok(())
 }
 
+/// The row loaded at position `i` of the table of several columns, whose
+/// `datetimeoffset` is 2023-10-01.
+pub fn several_columns_row(i: i32) -> TokenRow<'static> {
+    let decimal_value = BigDecimal::from(29540577);
+    let numeric_value = BigDecimal::from(30024);
 
-#[cfg(feature = "use_tds73")]
-pub async fn insert_precision_decimal()->anyhow::Result<()> {
-    use std::str::FromStr;
-
-    let config = Config::from_ado_string(&SQL_AUTH_CONN_STR_PORT)?;
+    let dt = DateTime::from_timestamp(1_696_118_400, 0).unwrap();
+    let naive_utc = dt.naive_utc();
+    let offset = *dt.offset();
+
+    let today = chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(naive_utc, offset)
+        .fixed_offset();
+
+    let mut row = TokenRow::with_capacity(15);
+    let temporal_i: i32 = i;
+    row.push(ColumnData::Bit(Some(temporal_i % 3 == 0)));
+    row.push(ColumnData::F64(Some((temporal_i + 1) as f64)));
+    row.push(ColumnData::U8(Some(temporal_i as u8)));
+    row.push(ColumnData::I16(Some(65535_u16 as i16)));
+    row.push(ColumnData::I32(Some(temporal_i + 4)));
+    row.push(ColumnData::I64(Some((temporal_i + 5) as i64)));
+
+    row.push(numeric_value.clone().into_sql());
+    row.push(decimal_value.clone().into_sql());
+
+    let a_char_value = format!("{0} strawberry", i.to_owned());
+    row.push(ColumnData::String(Some(Cow::from(a_char_value))));
+
+    let a_nchar_value = format!("{0} blueberry 🫐", i.to_owned());
+    row.push(ColumnData::String(Some(Cow::from(a_nchar_value))));
+
+    let a_varchar_value = format!("{0} kiwi", i.to_owned());
+    row.push(ColumnData::String(Some(Cow::from(a_varchar_value))));
+
+    let a_nvarchar_value = format!("{0} tangerine 🍊", i.to_owned());
+    row.push(ColumnData::String(Some(Cow::from(a_nvarchar_value))));
+
+    row.push(ColumnData::Binary(Some(Cow::from(
+        temporal_i.to_le_bytes().to_vec(),
+    ))));
+    row.push(ColumnData::Binary(Some(Cow::from(
+        temporal_i.to_le_bytes().to_vec(),
+    ))));
+    row.push(ColumnData::Guid(Some(tiberius::Uuid::from_u128(
+        temporal_i as u128,
+    ))));
+
+    row.push(today.into_sql());
+    row
+}
 
-    let tcp = TcpStream::connect(config.get_addr()).await?;
 
-    tcp.set_nodelay(true)?;
+pub async fn insert_precision_decimal(config: &ConnectionConfig, table: &str)->Result<()> {
+    let (mut client, _) = connect(config).await?;
 
-    let mut client = Client::connect(config, tcp).await?;
+    let (numeric, decimal) = precision_decimal_values();
 
-    // Parse a decimal value with a with 6 decimal digits (scale)
-    // This equals to value: 1.690601
-    let numeric=BigDecimal::new( BigInt::parse_bytes(b"1690601", 10).unwrap(), 6);
-    
-    // Parse a hexadecimal value with 4 decimal digits (scale)
-    // This equals to value: 4.3981
-    let decimal=BigDecimal::new( BigInt::parse_bytes(b"ABCD", 16).unwrap(), 4);
-    
     let result = client.execute(
-        "INSERT INTO random_precision_decimal (a_numeric_precision_column, a_decimal_precision_column ) VALUES (@P1, @P2)", 
+        format!(
+            "INSERT INTO {} (a_numeric_precision_column, a_decimal_precision_column ) VALUES (@P1, @P2)",
+            table
+        ), 
         &[&numeric, &decimal],
     ).await?;
     
     
     println!("Rows affected: {}",result.total());
+
     client.close().await?;
 
     Ok(())
+}
+
+/// The values [`insert_precision_decimal`] inserts.
+pub fn precision_decimal_values() -> (BigDecimal, BigDecimal) {
+    // Parse a decimal value with a with 6 decimal digits (scale)
+    // This equals to value: 1.690601
+    let numeric = BigDecimal::new(BigInt::parse_bytes(b"1690601", 10).unwrap(), 6);
+
+    // Parse a hexadecimal value with 4 decimal digits (scale)
+    // This equals to value: 4.3981
+    let decimal = BigDecimal::new(BigInt::parse_bytes(b"ABCD", 16).unwrap(), 4);
+
+    (numeric, decimal)
 }
\ No newline at end of file
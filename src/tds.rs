@@ -0,0 +1,243 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use futures_util::io::{AsyncRead, AsyncWrite};
+use std::{borrow::Cow, fmt};
+use tiberius::{
+    time::{Date, DateTime, DateTime2, DateTimeOffset, Time},
+    Client, ColumnData, ToSql, TokenRow,
+};
+
+/// Days between 0001-01-01 (the epoch of `date`/`datetime2`) and
+/// 1900-01-01 (the epoch of `datetime`).
+const DAYS_FROM_0001_TO_1900: i64 = 693595;
+
+/// TDS protocol versions the crate knows how to talk to.
+///
+/// Tiberius always asks for TDS 7.4 during login and the server answers with
+/// the highest version it supports, so the negotiated version follows from
+/// the major version of the server.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TdsVersion {
+    /// SQL Server 2005.
+    Tds72,
+    /// SQL Server 2008 and 2008 R2.
+    Tds73,
+    /// SQL Server 2012 and later.
+    Tds74,
+}
+
+impl TdsVersion {
+    /// Maps the major version of SQL Server (e.g. `16` for SQL Server 2022)
+    /// to the TDS version negotiated with Tiberius.
+    pub fn from_major_version(major: u32) -> TdsVersion {
+        match major {
+            0..=9 => TdsVersion::Tds72,
+            10 => TdsVersion::Tds73,
+            _ => TdsVersion::Tds74,
+        }
+    }
+
+    /// `date`, `time`, `datetime2` and `datetimeoffset` only exist since TDS 7.3.
+    pub fn supports_date_time_types(self) -> bool {
+        self >= TdsVersion::Tds73
+    }
+}
+
+impl fmt::Display for TdsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TdsVersion::Tds72 => write!(f, "TDS 7.2"),
+            TdsVersion::Tds73 => write!(f, "TDS 7.3"),
+            TdsVersion::Tds74 => write!(f, "TDS 7.4"),
+        }
+    }
+}
+
+/// Version information of the server a client is connected to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerInfo {
+    /// Value of `SERVERPROPERTY('ProductVersion')`, e.g. `16.0.1000.6`.
+    pub product_version: String,
+    /// Major version of SQL Server, e.g. `16` for SQL Server 2022.
+    pub major_version: u32,
+    /// TDS version negotiated during login.
+    pub tds_version: TdsVersion,
+}
+
+impl ServerInfo {
+    /// Builds the server information from a product version string
+    /// like `16.0.1000.6`.
+    pub fn from_product_version(product_version: &str) -> ServerInfo {
+        let major_version = product_version
+            .split('.')
+            .next()
+            .and_then(|major| major.trim().parse().ok())
+            .unwrap_or(0);
+
+        ServerInfo {
+            product_version: product_version.to_owned(),
+            major_version,
+            tds_version: TdsVersion::from_major_version(major_version),
+        }
+    }
+
+    /// Asks the server for its version right after connecting.
+    pub async fn detect<S>(client: &mut Client<S>) -> tiberius::Result<ServerInfo>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let row = client
+            .simple_query("SELECT CAST(SERVERPROPERTY('ProductVersion') AS nvarchar(128))")
+            .await?
+            .into_row()
+            .await?;
+
+        let product_version = row
+            .as_ref()
+            .and_then(|row| row.get::<&str, _>(0))
+            .unwrap_or_default();
+
+        Ok(ServerInfo::from_product_version(product_version))
+    }
+}
+
+/// Rewrites the values of a row that the negotiated TDS version cannot
+/// transport. See [`adapt_value`].
+pub fn adapt_row<'a>(row: TokenRow<'a>, server: &ServerInfo) -> TokenRow<'a> {
+    if server.tds_version.supports_date_time_types() {
+        return row;
+    }
+
+    let mut adapted = TokenRow::with_capacity(row.len());
+    for value in row {
+        adapted.push(adapt_value(value, server.tds_version));
+    }
+
+    adapted
+}
+
+/// Rewrites a value that the negotiated TDS version cannot transport:
+///
+/// - `date` and `datetime2` are sent as `datetime` (see [`to_datetime`]),
+///   losing precision below 1/300 of a second.
+/// - `time` and `datetimeoffset` are sent as strings so the server converts
+///   them to whatever the destination column is.
+pub fn adapt_value(value: ColumnData<'_>, tds_version: TdsVersion) -> ColumnData<'_> {
+    if tds_version.supports_date_time_types() {
+        return value;
+    }
+
+    match value {
+        ColumnData::Date(_) | ColumnData::DateTime2(_) => to_datetime(value),
+        ColumnData::Time(time) => ColumnData::String(time.map(|t| Cow::Owned(format_time(t)))),
+        ColumnData::DateTimeOffset(dto) => {
+            ColumnData::String(dto.map(|dto| Cow::Owned(format_datetimeoffset(dto))))
+        }
+        other => other,
+    }
+}
+
+/// Encodes a `date` or `datetime2` value as `datetime`, the only date and
+/// time type that TDS 7.2 knows about. Values before 1753-01-01 are sent as
+/// strings; other values are returned unchanged.
+pub fn to_datetime(value: ColumnData<'_>) -> ColumnData<'_> {
+    match value {
+        ColumnData::Date(Some(date)) => date_to_datetime(date, Time::new(0, 0))
+            .map(|dt| ColumnData::DateTime(Some(dt)))
+            .unwrap_or_else(|| string_value(format_date(date))),
+        ColumnData::DateTime2(Some(dt2)) => date_to_datetime(dt2.date(), dt2.time())
+            .map(|dt| ColumnData::DateTime(Some(dt)))
+            .unwrap_or_else(|| string_value(format_datetime2(dt2))),
+        ColumnData::Date(None) | ColumnData::DateTime2(None) => ColumnData::DateTime(None),
+        other => other,
+    }
+}
+
+/// A query parameter whose value has been adapted to the negotiated TDS
+/// version with [`adapt_value`].
+#[derive(Debug)]
+pub struct Param<'a>(ColumnData<'a>);
+
+impl<'a> Param<'a> {
+    pub fn new(value: ColumnData<'a>, server: &ServerInfo) -> Param<'a> {
+        Param(adapt_value(value, server.tds_version))
+    }
+}
+
+impl ToSql for Param<'_> {
+    fn to_sql(&self) -> ColumnData<'_> {
+        self.0.clone()
+    }
+}
+
+fn string_value(value: String) -> ColumnData<'static> {
+    ColumnData::String(Some(Cow::Owned(value)))
+}
+
+fn date_to_datetime(date: Date, time: Time) -> Option<DateTime> {
+    let days = date.days() as i64 - DAYS_FROM_0001_TO_1900;
+
+    // `datetime` starts at 1753-01-01.
+    if days < -53690 {
+        return None;
+    }
+
+    // `datetime` counts time in 1/300 of a second.
+    let fragments = (time_nanoseconds(time) as u128 * 300 + 500_000_000) / 1_000_000_000;
+    let (days, fragments) = if fragments >= 300 * 86400 {
+        (days + 1, 0)
+    } else {
+        (days, fragments as u32)
+    };
+
+    Some(DateTime::new(days as i32, fragments))
+}
+
+fn time_nanoseconds(time: Time) -> u64 {
+    time.increments() * 10u64.pow(9 - time.scale() as u32)
+}
+
+fn to_naive_date(date: Date) -> NaiveDate {
+    NaiveDate::from_ymd_opt(1, 1, 1).unwrap() + Duration::days(date.days() as i64)
+}
+
+fn to_naive_time(time: Time) -> NaiveTime {
+    let nanoseconds = time_nanoseconds(time);
+    NaiveTime::from_num_seconds_from_midnight_opt(
+        (nanoseconds / 1_000_000_000) as u32,
+        (nanoseconds % 1_000_000_000) as u32,
+    )
+    .unwrap_or(NaiveTime::MIN)
+}
+
+fn format_date(date: Date) -> String {
+    to_naive_date(date).format("%Y-%m-%d").to_string()
+}
+
+fn format_time(time: Time) -> String {
+    to_naive_time(time).format("%H:%M:%S%.f").to_string()
+}
+
+fn format_datetime2(dt2: DateTime2) -> String {
+    NaiveDateTime::new(to_naive_date(dt2.date()), to_naive_time(dt2.time()))
+        .format("%Y-%m-%d %H:%M:%S%.f")
+        .to_string()
+}
+
+fn format_datetimeoffset(dto: DateTimeOffset) -> String {
+    // The date and time of a `datetimeoffset` are stored in UTC.
+    let utc = NaiveDateTime::new(
+        to_naive_date(dto.datetime2().date()),
+        to_naive_time(dto.datetime2().time()),
+    );
+    let offset = dto.offset() as i64;
+    let local = utc + Duration::minutes(offset);
+    let sign = if offset < 0 { '-' } else { '+' };
+
+    format!(
+        "{} {}{:02}:{:02}",
+        local.format("%Y-%m-%d %H:%M:%S%.f"),
+        sign,
+        offset.abs() / 60,
+        offset.abs() % 60
+    )
+}
@@ -0,0 +1,643 @@
+use crate::{
+    connection::ado_value,
+    error::{BulkError, Result},
+    runtime::{connect_tcp, TcpStream},
+};
+use futures_util::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, ClientConnection, RootCertStore, ServerName,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt, fs,
+    io::{self, Cursor, Read, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::SystemTime,
+};
+use tiberius::{Config, EncryptionLevel};
+
+/// Type of the TDS packets that carry PRELOGIN messages and, wrapped in them,
+/// the TLS handshake.
+const PRELOGIN_PACKET: u8 = 0x12;
+/// Type of the packets of server responses.
+const RESPONSE_PACKET: u8 = 0x04;
+const PACKET_HEADER_LEN: usize = 8;
+/// Status bit of the last packet of a message.
+const END_OF_MESSAGE: u8 = 0x01;
+
+const PRELOGIN_VERSION: u8 = 0x00;
+const PRELOGIN_ENCRYPTION: u8 = 0x01;
+const PRELOGIN_TERMINATOR: u8 = 0xff;
+
+const ENCRYPT_ON: u8 = 0x01;
+const ENCRYPT_NOT_SUP: u8 = 0x02;
+const ENCRYPT_REQ: u8 = 0x03;
+
+/// Size of the reads of TLS records, small enough for the plaintext of one
+/// read to fit in the buffer of rustls.
+const RECORD_READ_SIZE: usize = 8192;
+
+/// Whether to encrypt the connection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encryption {
+    /// Encrypt the whole connection, or fail.
+    Required,
+    /// Encrypt the login packet, and the whole connection if the server
+    /// requires it or the crate validates the certificate (see
+    /// [`TlsSettings`]).
+    #[default]
+    Optional,
+    /// Do not encrypt anything.
+    Off,
+}
+
+impl Encryption {
+    fn level(self) -> EncryptionLevel {
+        match self {
+            Encryption::Required => EncryptionLevel::Required,
+            Encryption::Optional => EncryptionLevel::Off,
+            Encryption::Off => EncryptionLevel::NotSupported,
+        }
+    }
+}
+
+/// The SHA-256 hash of the DER encoding of a certificate, written as 64
+/// hexadecimal digits that may be separated by colons.
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Thumbprint([u8; 32]);
+
+impl Thumbprint {
+    /// The thumbprint of a certificate in DER encoding.
+    pub fn of(der: &[u8]) -> Thumbprint {
+        Thumbprint(Sha256::digest(der).into())
+    }
+}
+
+impl FromStr for Thumbprint {
+    type Err = BulkError;
+
+    fn from_str(s: &str) -> Result<Thumbprint> {
+        let digits: Vec<u8> = s
+            .trim()
+            .bytes()
+            .filter(|b| *b != b':')
+            .map(|b| (b as char).to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .ok_or_else(|| BulkError::Config(format!("invalid SHA-256 thumbprint: {}", s)))?;
+
+        let mut thumbprint = [0; 32];
+        if digits.len() != thumbprint.len() * 2 {
+            return Err(BulkError::Config(format!(
+                "a SHA-256 thumbprint has 64 hexadecimal digits: {}",
+                s
+            )));
+        }
+        for (byte, pair) in thumbprint.iter_mut().zip(digits.chunks(2)) {
+            *byte = pair[0] << 4 | pair[1];
+        }
+
+        Ok(Thumbprint(thumbprint))
+    }
+}
+
+impl TryFrom<String> for Thumbprint {
+    type Error = BulkError;
+
+    fn try_from(s: String) -> Result<Thumbprint> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Thumbprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Thumbprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Thumbprint({})", self)
+    }
+}
+
+/// How the connection is encrypted and how the certificate of the server is
+/// validated.
+///
+/// Tiberius only validates certificates against the system roots or a
+/// single CA certificate, for the host it connects to. When a CA bundle, a
+/// host name or pinned thumbprints are given, the connection is encrypted by
+/// the crate instead: [`TlsStream`] runs the TLS handshake with its own
+/// certificate validation and encrypts the whole connection, login included,
+/// and Tiberius speaks TDS over it without encryption of its own.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    #[serde(default)]
+    pub encrypt: Encryption,
+    /// Accept any certificate of the server.
+    #[serde(default)]
+    pub trust_server_certificate: bool,
+    /// Validate the certificate of the server with the CA certificates of
+    /// this PEM or DER file instead of the system roots.
+    pub ca_certificate: Option<PathBuf>,
+    /// The name the certificate must be issued for, when it differs from the
+    /// host connected to (for example when connecting through an IP address).
+    pub hostname: Option<String>,
+    /// Accept only certificates with one of these SHA-256 thumbprints. Pinned
+    /// certificates need no CA unless `ca_certificate` is given as well.
+    #[serde(default)]
+    pub pinned_certificates: Vec<Thumbprint>,
+}
+
+impl TlsSettings {
+    /// Reads the settings of an ADO.NET connection string: `Encrypt`,
+    /// `TrustServerCertificate`, `TrustServerCertificateCA`,
+    /// `HostNameInCertificate` and `CertificateThumbprint` (a comma separated
+    /// list of SHA-256 thumbprints).
+    pub fn from_ado_string(s: &str) -> Result<TlsSettings> {
+        let encrypt = match ado_value(s, &["encrypt"]).map(|v| v.to_lowercase()) {
+            None => Encryption::Optional,
+            Some(v) if v == "danger_plaintext" => Encryption::Off,
+            Some(v) => match parse_bool(&v) {
+                Some(true) => Encryption::Required,
+                Some(false) => Encryption::Optional,
+                None => return Err(BulkError::Config(format!("invalid encrypt: {}", v))),
+            },
+        };
+        let trust_server_certificate = match ado_value(s, &["trustservercertificate"]) {
+            None => false,
+            Some(v) => parse_bool(v).ok_or_else(|| {
+                BulkError::Config(format!("invalid TrustServerCertificate: {}", v))
+            })?,
+        };
+        let pinned_certificates = ado_value(s, &["certificatethumbprint"])
+            .map(|v| v.split(',').map(str::parse).collect::<Result<_>>())
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(TlsSettings {
+            encrypt,
+            trust_server_certificate,
+            ca_certificate: ado_value(s, &["trustservercertificateca"]).map(PathBuf::from),
+            hostname: ado_value(s, &["hostnameincertificate"]).map(str::to_owned),
+            pinned_certificates,
+        })
+    }
+
+    /// True, if the connection is encrypted by a [`TlsStream`] rather than
+    /// by Tiberius.
+    pub fn encrypted_by_crate(&self) -> bool {
+        self.encrypt != Encryption::Off
+            && !self.trust_server_certificate
+            && (self.ca_certificate.is_some()
+                || self.hostname.is_some()
+                || !self.pinned_certificates.is_empty())
+    }
+
+    /// Sets the encryption and the certificate validation of Tiberius.
+    pub fn apply(&self, config: &mut Config) -> Result<()> {
+        let custom_validation = self.ca_certificate.is_some()
+            || self.hostname.is_some()
+            || !self.pinned_certificates.is_empty();
+
+        if self.trust_server_certificate && custom_validation {
+            return Err(BulkError::Config(
+                "trust_server_certificate cannot be used together with ca_certificate, \
+                 hostname or pinned_certificates"
+                    .to_owned(),
+            ));
+        }
+        if self.encrypt == Encryption::Off && custom_validation {
+            return Err(BulkError::Config(
+                "certificates cannot be validated when encryption is off".to_owned(),
+            ));
+        }
+
+        if self.encrypted_by_crate() {
+            config.encryption(EncryptionLevel::NotSupported);
+            return Ok(());
+        }
+
+        config.encryption(self.encrypt.level());
+        if self.trust_server_certificate {
+            config.trust_cert();
+        }
+
+        Ok(())
+    }
+
+    fn verifier(&self) -> Result<Arc<dyn ServerCertVerifier>> {
+        let roots = match &self.ca_certificate {
+            Some(path) => Some(load_certificates(path)?),
+            None if self.pinned_certificates.is_empty() => Some(native_roots()?),
+            None => None,
+        };
+
+        Ok(Arc::new(Verifier {
+            webpki: roots.map(|roots| WebPkiVerifier::new(roots, None)),
+            pinned: self.pinned_certificates.clone(),
+        }))
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" => Some(true),
+        "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Checks the thumbprint against the pinned ones, and the chain and the
+/// host name against the CA certificates.
+struct Verifier {
+    webpki: Option<WebPkiVerifier>,
+    pinned: Vec<Thumbprint>,
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        if !self.pinned.is_empty() {
+            let thumbprint = Thumbprint::of(&end_entity.0);
+            if !self.pinned.contains(&thumbprint) {
+                return Err(rustls::Error::General(format!(
+                    "the certificate with SHA-256 thumbprint {} is not pinned",
+                    thumbprint
+                )));
+            }
+        }
+
+        match &self.webpki {
+            Some(webpki) => webpki.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            ),
+            None => Ok(ServerCertVerified::assertion()),
+        }
+    }
+}
+
+/// Reads the certificates of a PEM file, or the single certificate of a DER
+/// file.
+fn load_certificates(path: &Path) -> Result<RootCertStore> {
+    let bytes = fs::read(path).map_err(|e| {
+        BulkError::Config(format!(
+            "cannot read CA certificate {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+
+    let certificates = if bytes.starts_with(b"-----") {
+        rustls_pemfile::certs(&mut Cursor::new(&bytes))
+            .map_err(|e| BulkError::Config(format!("{}: {}", path.display(), e)))?
+    } else {
+        vec![bytes]
+    };
+
+    let mut roots = RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(&certificates);
+    if added == 0 {
+        return Err(BulkError::Config(format!(
+            "{} holds no valid CA certificate",
+            path.display()
+        )));
+    }
+
+    Ok(roots)
+}
+
+fn native_roots() -> Result<RootCertStore> {
+    let certificates = rustls_native_certs::load_native_certs()
+        .map_err(|e| BulkError::Tls(format!("cannot load the system certificates: {}", e)))?;
+
+    let mut roots = RootCertStore::empty();
+    for certificate in certificates {
+        // Broken system certificates are skipped, like browsers do.
+        let _ = roots.add(&Certificate(certificate.0));
+    }
+
+    Ok(roots)
+}
+
+/// A connection encrypted by the crate, with the certificate of the server
+/// validated by [`TlsSettings`].
+///
+/// [`TlsStream::connect`] runs the PRELOGIN exchange, asking to encrypt the
+/// whole connection, and the TLS handshake wrapped in PRELOGIN packets like
+/// a login does. The stream then drops the PRELOGIN message Tiberius writes
+/// and answers it with the response of the server, changed to say that the
+/// server does not support encryption, so that Tiberius sends the login and
+/// everything after it in the clear over the encrypted stream.
+pub struct TlsStream {
+    tcp: TcpStream,
+    tls: ClientConnection,
+    /// The PRELOGIN message of Tiberius received so far, until it is whole.
+    prelogin: Option<Vec<u8>>,
+    /// The PRELOGIN response handed to Tiberius.
+    response: Cursor<Vec<u8>>,
+    /// TLS records not written to `tcp` yet.
+    records: Vec<u8>,
+    closing: bool,
+}
+
+impl TlsStream {
+    /// Connects to the server at `addr` and encrypts the connection,
+    /// validating the certificate of the server with `settings`. `host` is
+    /// the name the certificate must be issued for, unless the settings give
+    /// another one.
+    pub async fn connect(addr: &str, host: &str, settings: &TlsSettings) -> Result<TlsStream> {
+        let name = settings.hostname.as_deref().unwrap_or(host);
+        let server_name = ServerName::try_from(name)
+            .map_err(|_| BulkError::Config(format!("invalid host name for TLS: {}", name)))?;
+
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(settings.verifier()?)
+            .with_no_client_auth();
+        let mut tls = ClientConnection::new(Arc::new(config), server_name)
+            .map_err(|e| BulkError::Tls(e.to_string()))?;
+
+        let mut stream = connect_tcp(addr).await?;
+
+        write_packet(&mut stream, 1, &prelogin_message(ENCRYPT_ON)).await?;
+        let mut response = read_message(&mut stream).await?;
+        let offset = prelogin_encryption_offset(&response)?;
+        match response[offset] {
+            ENCRYPT_ON | ENCRYPT_REQ => {}
+            ENCRYPT_NOT_SUP => {
+                return Err(BulkError::Tls(
+                    "the server does not support encryption".to_owned(),
+                ))
+            }
+            _ => {
+                return Err(BulkError::Tls(
+                    "the server does not encrypt the whole connection".to_owned(),
+                ))
+            }
+        }
+        response[offset] = ENCRYPT_NOT_SUP;
+
+        let mut packet_id = 2u8;
+        loop {
+            // The last flight of the client, if any, is still wrapped in a
+            // PRELOGIN packet when the handshake completes.
+            if tls.wants_write() {
+                let mut records = Vec::new();
+                while tls.wants_write() {
+                    tls.write_tls(&mut records)?;
+                }
+                write_packet(&mut stream, packet_id, &records).await?;
+                packet_id = packet_id.wrapping_add(1);
+            } else if tls.is_handshaking() {
+                let message = read_message(&mut stream).await?;
+                let mut message = Cursor::new(message);
+                while (message.position() as usize) < message.get_ref().len() {
+                    tls.read_tls(&mut message)?;
+                    tls.process_new_packets()
+                        .map_err(|e| BulkError::Tls(e.to_string()))?;
+                }
+            } else {
+                break;
+            }
+        }
+
+        Ok(TlsStream {
+            tcp: stream,
+            tls,
+            prelogin: Some(Vec::new()),
+            response: Cursor::new(packet(RESPONSE_PACKET, 1, &response)?),
+            records: Vec::new(),
+            closing: false,
+        })
+    }
+
+    /// Writes the TLS records waiting to be sent.
+    fn poll_write_records(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            if self.records.is_empty() {
+                if !self.tls.wants_write() {
+                    return Poll::Ready(Ok(()));
+                }
+                self.tls.write_tls(&mut self.records)?;
+            }
+
+            let written = ready!(Pin::new(&mut self.tcp).poll_write(cx, &self.records))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.records.drain(..written);
+        }
+    }
+}
+
+impl AsyncRead for TlsStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if (this.response.position() as usize) < this.response.get_ref().len() {
+            return Poll::Ready(this.response.read(buf));
+        }
+
+        loop {
+            match this.tls.reader().read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                result => return Poll::Ready(result),
+            }
+
+            // Alerts and key updates may need an answer; it is sent along
+            // with the next write if the socket is busy.
+            if let Poll::Ready(Err(e)) = this.poll_write_records(cx) {
+                return Poll::Ready(Err(e));
+            }
+
+            let mut incoming = [0; RECORD_READ_SIZE];
+            let read = ready!(Pin::new(&mut this.tcp).poll_read(cx, &mut incoming))?;
+            let mut incoming = &incoming[..read];
+            // Reading no bytes tells rustls the connection was closed.
+            loop {
+                this.tls.read_tls(&mut incoming)?;
+                this.tls
+                    .process_new_packets()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                if incoming.is_empty() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl AsyncWrite for TlsStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if let Some(prelogin) = &mut this.prelogin {
+            prelogin.extend_from_slice(buf);
+            if message_complete(prelogin) {
+                this.prelogin = None;
+            }
+            return Poll::Ready(Ok(buf.len()));
+        }
+
+        ready!(this.poll_write_records(cx))?;
+        let written = this.tls.writer().write(buf)?;
+        if let Poll::Ready(Err(e)) = this.poll_write_records(cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_records(cx))?;
+        Pin::new(&mut this.tcp).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.closing {
+            this.tls.send_close_notify();
+            this.closing = true;
+        }
+        ready!(this.poll_write_records(cx))?;
+        Pin::new(&mut this.tcp).poll_close(cx)
+    }
+}
+
+/// True, if `packets` end with the last packet of a message.
+fn message_complete(packets: &[u8]) -> bool {
+    let mut rest = packets;
+
+    while rest.len() >= PACKET_HEADER_LEN {
+        let length = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+        if length < PACKET_HEADER_LEN || rest.len() < length {
+            return false;
+        }
+        if rest[1] & END_OF_MESSAGE != 0 {
+            return true;
+        }
+        rest = &rest[length..];
+    }
+
+    false
+}
+
+/// A PRELOGIN message with the client version and the encryption option.
+fn prelogin_message(encryption: u8) -> Vec<u8> {
+    // Two options of 5 bytes and the terminator precede the data.
+    let version_offset = 11u16;
+    let encryption_offset = version_offset + 6;
+
+    let mut message = Vec::new();
+    message.push(PRELOGIN_VERSION);
+    message.extend_from_slice(&version_offset.to_be_bytes());
+    message.extend_from_slice(&6u16.to_be_bytes());
+    message.push(PRELOGIN_ENCRYPTION);
+    message.extend_from_slice(&encryption_offset.to_be_bytes());
+    message.extend_from_slice(&1u16.to_be_bytes());
+    message.push(PRELOGIN_TERMINATOR);
+    message.extend_from_slice(&[0; 6]);
+    message.push(encryption);
+
+    message
+}
+
+/// The position of the encryption option of a PRELOGIN response.
+fn prelogin_encryption_offset(message: &[u8]) -> Result<usize> {
+    let invalid = || BulkError::Protocol("invalid PRELOGIN response".to_owned());
+    let mut options = message;
+
+    loop {
+        match options {
+            [PRELOGIN_TERMINATOR, ..] | [] => return Err(invalid()),
+            [token, offset_hi, offset_lo, _, _, rest @ ..] => {
+                if *token == PRELOGIN_ENCRYPTION {
+                    let offset = u16::from_be_bytes([*offset_hi, *offset_lo]) as usize;
+                    return match offset < message.len() {
+                        true => Ok(offset),
+                        false => Err(invalid()),
+                    };
+                }
+                options = rest;
+            }
+            _ => return Err(invalid()),
+        }
+    }
+}
+
+async fn write_packet(stream: &mut TcpStream, packet_id: u8, payload: &[u8]) -> io::Result<()> {
+    stream
+        .write_all(&packet(PRELOGIN_PACKET, packet_id, payload)?)
+        .await?;
+    stream.flush().await
+}
+
+/// A TDS packet holding a whole message.
+fn packet(packet_type: u8, packet_id: u8, payload: &[u8]) -> io::Result<Vec<u8>> {
+    let length = u16::try_from(PACKET_HEADER_LEN + payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "TDS packet too large"))?;
+
+    let mut packet = Vec::with_capacity(length as usize);
+    packet.extend_from_slice(&[packet_type, END_OF_MESSAGE]);
+    packet.extend_from_slice(&length.to_be_bytes());
+    packet.extend_from_slice(&[0, 0, packet_id, 0]);
+    packet.extend_from_slice(payload);
+
+    Ok(packet)
+}
+
+/// Reads the payload of the packets of one message.
+async fn read_message(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut message = Vec::new();
+
+    loop {
+        let mut header = [0; PACKET_HEADER_LEN];
+        stream.read_exact(&mut header).await?;
+
+        let length = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let payload_len = length.checked_sub(PACKET_HEADER_LEN).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "invalid TDS packet length")
+        })?;
+
+        let start = message.len();
+        message.resize(start + payload_len, 0);
+        stream.read_exact(&mut message[start..]).await?;
+
+        if header[1] & END_OF_MESSAGE != 0 {
+            return Ok(message);
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use crate::{
+    connection::SqlClient,
+    convert::{column_text, convert_column},
+    error::{BulkError, Result},
+    schema::{SqlType, TableSchema},
+};
+use futures_util::TryStreamExt;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    mem,
+};
+use tiberius::{
+    time::{DateTime2, DateTimeOffset, Time},
+    ColumnData, TokenRow,
+};
+
+/// Number of differing rows listed in the error of a verification.
+const REPORTED_ROWS: usize = 5;
+
+/// Reads the rows of `table` back and compares them with the rows loaded,
+/// which `expected` gives for every position in the load, with the values
+/// of the insertable columns in order.
+///
+/// Values are compared as SQL Server stores them (see [`same_value`]).
+/// SQL Server reads the rows of a table in no guaranteed order, so rows are
+/// matched whatever their order: every row loaded must be read back as many
+/// times as it was loaded. Fails with [`BulkError::Mismatch`] when the
+/// number of rows differs, or with the first rows loaded that were not read
+/// back and the first rows read back that were not loaded.
+pub async fn verify_table(
+    client: &mut SqlClient,
+    table: &str,
+    rows: u64,
+    mut expected: impl FnMut(u64) -> TokenRow<'static>,
+) -> Result<()> {
+    let schema = TableSchema::load(client, table).await?;
+    let columns: Vec<_> = schema
+        .columns
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| column.is_insertable())
+        .collect();
+    let types: Vec<&SqlType> = columns.iter().map(|(_, column)| &column.sql_type).collect();
+
+    // The rows loaded by the hash of their values as stored, with the
+    // number of rows not read back yet and the position of the first.
+    let mut loaded: HashMap<u64, (u64, u64)> = HashMap::new();
+    let mut missing = Vec::new();
+    for row in 0..rows {
+        let values: Result<Vec<_>> = expected(row)
+            .into_iter()
+            .zip(&types)
+            .map(|(value, sql_type)| stored_value(value, sql_type))
+            .collect();
+        match values {
+            Ok(values) => {
+                loaded
+                    .entry(row_hash(&values, &types))
+                    .or_insert((0, row))
+                    .0 += 1
+            }
+            Err(_) => missing.push(row),
+        }
+    }
+
+    let sql = format!("SELECT * FROM {}", table);
+    let mut stream = client.simple_query(sql).await?.into_row_stream();
+    let (mut read, mut unexpected) = (0, Vec::new());
+    while let Some(row) = stream.try_next().await? {
+        read += 1;
+        let mut values: Vec<ColumnData<'static>> = row.into_iter().collect();
+        let values: Vec<_> = columns
+            .iter()
+            .map(|(i, _)| mem::replace(&mut values[*i], ColumnData::I32(None)))
+            .collect();
+        match loaded.get_mut(&row_hash(&values, &types)) {
+            Some((left, _)) if *left > 0 => *left -= 1,
+            _ if unexpected.len() < REPORTED_ROWS => unexpected.push(describe_row(values)),
+            _ => {}
+        }
+    }
+
+    if read != rows {
+        return Err(BulkError::mismatch(format!(
+            "{} rows were loaded, {} were read back",
+            rows, read
+        ))
+        .with_table(table));
+    }
+
+    let differing = missing.len() as u64 + loaded.values().map(|(left, _)| left).sum::<u64>();
+    if differing > 0 {
+        missing.extend(
+            loaded
+                .values()
+                .filter(|(left, _)| *left > 0)
+                .map(|(_, row)| *row),
+        );
+        missing.sort_unstable();
+        let missing: Vec<String> = missing
+            .into_iter()
+            .take(REPORTED_ROWS)
+            .map(|row| {
+                let values = expected(row)
+                    .into_iter()
+                    .zip(&types)
+                    .map(|(value, sql_type)| describe(stored_value(value, sql_type)));
+                format!("row {} {}", row, values_text(values))
+            })
+            .collect();
+        return Err(BulkError::mismatch(format!(
+            "{} rows differ, first loaded but not read back: {}; \
+             first read back but not loaded: {}",
+            differing,
+            missing.join(", "),
+            unexpected.join(", ")
+        ))
+        .with_table(table));
+    }
+
+    Ok(())
+}
+
+/// A hash of the values of a row as [`same_value`] compares them, so that
+/// rows with the same values have the same hash.
+fn row_hash(values: &[ColumnData<'static>], types: &[&SqlType]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for (value, sql_type) in values.iter().zip(types) {
+        compared_value(value, sql_type).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// What [`same_value`] compares of a value stored in a column of type
+/// `sql_type`.
+fn compared_value(value: &ColumnData<'static>, sql_type: &SqlType) -> String {
+    match (value, sql_type) {
+        (ColumnData::F64(Some(value)), SqlType::Money | SqlType::SmallMoney) => {
+            format!("{}", (value * 1e4).round())
+        }
+        (ColumnData::Xml(Some(xml)), _) => without_whitespace_between_tags(xml.as_ref().as_ref()),
+        _ => format!("{:?}", value),
+    }
+}
+
+/// Whether `actual`, read from a column of type `sql_type`, is the value
+/// SQL Server stores for `expected` (see [`stored_value`]). Money is
+/// compared to the ten-thousandth, as it is stored, and XML without the
+/// whitespace between tags, which SQL Server does not keep.
+pub fn same_value(
+    expected: &ColumnData<'static>,
+    actual: &ColumnData<'static>,
+    sql_type: &SqlType,
+) -> bool {
+    stored_value(expected.clone(), sql_type)
+        .is_ok_and(|stored| compared_value(&stored, sql_type) == compared_value(actual, sql_type))
+}
+
+fn without_whitespace_between_tags(xml: &str) -> String {
+    let mut text = String::with_capacity(xml.len());
+    let mut rest = xml;
+    while let Some(end) = rest.find('>') {
+        text.push_str(&rest[..=end]);
+        rest = &rest[end + 1..];
+        let next = rest.find('<').unwrap_or(rest.len());
+        if !rest[..next].trim().is_empty() {
+            text.push_str(&rest[..next]);
+        }
+        rest = &rest[next..];
+    }
+    text.push_str(rest);
+    text
+}
+
+/// The value SQL Server stores for `value` in a column of type `sql_type`:
+/// converted as a bulk load converts it, with `datetime` values rounded to
+/// 1/300 of a second, `smalldatetime` values to the minute, `real` values
+/// to single precision, times to the scale of the column, and `char`,
+/// `nchar` and `binary` values padded to the length of the column.
+pub fn stored_value(value: ColumnData<'static>, sql_type: &SqlType) -> Result<ColumnData<'static>> {
+    if is_null(&value) {
+        return Ok(sql_type.null());
+    }
+
+    let value = match (convert_column(value, sql_type)?, sql_type) {
+        (ColumnData::Time(Some(time)), SqlType::Time(scale)) => {
+            ColumnData::Time(Some(rescale_time(time, *scale)))
+        }
+        (ColumnData::DateTime2(Some(value)), SqlType::DateTime2(scale)) => {
+            ColumnData::DateTime2(Some(DateTime2::new(
+                value.date(),
+                rescale_time(value.time(), *scale),
+            )))
+        }
+        (ColumnData::DateTimeOffset(Some(value)), SqlType::DateTimeOffset(scale)) => {
+            let datetime2 = value.datetime2();
+            let datetime2 =
+                DateTime2::new(datetime2.date(), rescale_time(datetime2.time(), *scale));
+            ColumnData::DateTimeOffset(Some(DateTimeOffset::new(datetime2, value.offset())))
+        }
+        (ColumnData::String(Some(text)), SqlType::Char(len)) => {
+            let missing = (*len as usize).saturating_sub(text.chars().count());
+            ColumnData::String(Some(Cow::Owned(text.into_owned() + &" ".repeat(missing))))
+        }
+        (ColumnData::String(Some(text)), SqlType::NChar(len)) => {
+            let missing = (*len as usize).saturating_sub(text.encode_utf16().count());
+            ColumnData::String(Some(Cow::Owned(text.into_owned() + &" ".repeat(missing))))
+        }
+        (ColumnData::Binary(Some(bytes)), SqlType::Binary(len)) => {
+            let mut bytes = bytes.into_owned();
+            bytes.resize(bytes.len().max(*len as usize), 0);
+            ColumnData::Binary(Some(Cow::Owned(bytes)))
+        }
+        (value, _) => value,
+    };
+
+    Ok(value)
+}
+
+/// `time` with `scale` digits of fractions of seconds, rounded.
+pub fn rescale_time(time: Time, scale: u8) -> Time {
+    let increments = match scale.cmp(&time.scale()) {
+        std::cmp::Ordering::Equal => return time,
+        std::cmp::Ordering::Greater => time.increments() * 10u64.pow((scale - time.scale()).into()),
+        std::cmp::Ordering::Less => {
+            let divisor = 10u64.pow((time.scale() - scale).into());
+            (time.increments() + divisor / 2) / divisor
+        }
+    };
+
+    Time::new(increments, scale)
+}
+
+/// Whether `value` is a NULL of any type.
+pub fn is_null(value: &ColumnData<'_>) -> bool {
+    match value {
+        ColumnData::U8(value) => value.is_none(),
+        ColumnData::I16(value) => value.is_none(),
+        ColumnData::I32(value) => value.is_none(),
+        ColumnData::I64(value) => value.is_none(),
+        ColumnData::F32(value) => value.is_none(),
+        ColumnData::F64(value) => value.is_none(),
+        ColumnData::Bit(value) => value.is_none(),
+        ColumnData::String(value) => value.is_none(),
+        ColumnData::Guid(value) => value.is_none(),
+        ColumnData::Binary(value) => value.is_none(),
+        ColumnData::Numeric(value) => value.is_none(),
+        ColumnData::Xml(value) => value.is_none(),
+        ColumnData::DateTime(value) => value.is_none(),
+        ColumnData::SmallDateTime(value) => value.is_none(),
+        ColumnData::Time(value) => value.is_none(),
+        ColumnData::Date(value) => value.is_none(),
+        ColumnData::DateTime2(value) => value.is_none(),
+        ColumnData::DateTimeOffset(value) => value.is_none(),
+    }
+}
+
+/// A value for messages, with strings quoted so that padding shows.
+fn describe(value: Result<ColumnData<'static>>) -> String {
+    match value {
+        Ok(ColumnData::String(Some(text))) => format!("{:?}", text),
+        Ok(value) => column_text(&value).unwrap_or_else(|| "NULL".to_owned()),
+        Err(e) => format!("a value that cannot be stored ({})", e),
+    }
+}
+
+/// The values of a row read back, for messages.
+fn describe_row(values: Vec<ColumnData<'static>>) -> String {
+    values_text(values.into_iter().map(|value| describe(Ok(value))))
+}
+
+fn values_text(values: impl Iterator<Item = String>) -> String {
+    format!("({})", values.collect::<Vec<_>>().join(", "))
+}